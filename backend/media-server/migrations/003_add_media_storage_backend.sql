@@ -0,0 +1,2 @@
+-- 记录媒体文件所在的存储后端
+ALTER TABLE media_files ADD COLUMN IF NOT EXISTS storage_backend TEXT NOT NULL DEFAULT 'cos'; -- 'cos', 'local', 's3'
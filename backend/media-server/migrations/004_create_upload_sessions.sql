@@ -0,0 +1,35 @@
+-- 创建断点续传会话表
+CREATE TABLE IF NOT EXISTS upload_sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL,
+    media_id TEXT NOT NULL, -- 上传完成后创建的媒体ID
+    title TEXT,
+    description TEXT,
+    original_filename TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    cos_key TEXT NOT NULL, -- 最终对象键
+    total_size BIGINT NOT NULL,
+    upload_offset BIGINT NOT NULL DEFAULT 0,
+    status TEXT NOT NULL DEFAULT 'uploading', -- 'uploading', 'finalizing', 'completed', 'aborted', 'expired'
+    last_error TEXT, -- 最近一次合并失败的原因
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    CONSTRAINT fk_upload_session_user FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_upload_sessions_user_id ON upload_sessions(user_id);
+CREATE INDEX IF NOT EXISTS idx_upload_sessions_status_expires ON upload_sessions(status, expires_at);
+
+-- 已接收的分块，每个分块作为临时对象保存在存储后端
+CREATE TABLE IF NOT EXISTS upload_session_parts (
+    session_id TEXT NOT NULL,
+    part_offset BIGINT NOT NULL,
+    part_size BIGINT NOT NULL,
+    part_key TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+
+    PRIMARY KEY (session_id, part_offset),
+    CONSTRAINT fk_upload_part_session FOREIGN KEY (session_id) REFERENCES upload_sessions(id) ON DELETE CASCADE
+);
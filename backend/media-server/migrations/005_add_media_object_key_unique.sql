@@ -0,0 +1,13 @@
+-- 用户前缀下的对象只能被一条媒体记录引用，防止并发登记同一对象；
+-- blobs/ 下的共享对象由多条媒体记录引用，不受此限制。
+-- 之前没有校验对象归属，同一对象可能被登记多次，保留最早的记录，删除之后重复登记的记录
+DELETE FROM media_files m
+USING media_files k
+WHERE m.storage_backend = k.storage_backend
+  AND m.cos_key = k.cos_key
+  AND m.cos_key NOT LIKE 'blobs/%'
+  AND (m.created_at, m.id) > (k.created_at, k.id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_media_files_object_key
+    ON media_files(storage_backend, cos_key)
+    WHERE cos_key NOT LIKE 'blobs/%';
@@ -0,0 +1,3 @@
+-- 用户角色：'user' 普通用户，'admin' 管理员
+-- 将用户设为管理员: UPDATE users SET role = 'admin' WHERE username = '...';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS role TEXT NOT NULL DEFAULT 'user';
@@ -0,0 +1,12 @@
+-- 媒体分享：被分享的用户可以查看和下载媒体
+CREATE TABLE IF NOT EXISTS media_shares (
+    media_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+
+    PRIMARY KEY (media_id, user_id),
+    CONSTRAINT fk_share_media FOREIGN KEY (media_id) REFERENCES media_files(id) ON DELETE CASCADE,
+    CONSTRAINT fk_share_user FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_media_shares_user_id ON media_shares(user_id);
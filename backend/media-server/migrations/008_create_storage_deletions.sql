@@ -0,0 +1,16 @@
+-- 存储对象删除队列：删除失败的对象会在后台按退避策略重试，避免对象脱离追踪
+CREATE TABLE IF NOT EXISTS storage_deletions (
+    id TEXT PRIMARY KEY NOT NULL,
+    object_key TEXT NOT NULL,
+    storage_backend TEXT NOT NULL,
+    media_id TEXT, -- 对应的媒体ID（媒体记录可能已删除）
+    status TEXT NOT NULL DEFAULT 'pending', -- 'pending', 'failed'（超过重试次数，需要管理员重新入队）
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_storage_deletions_status_next ON storage_deletions(status, next_attempt_at);
+CREATE INDEX IF NOT EXISTS idx_storage_deletions_object_key ON storage_deletions(object_key);
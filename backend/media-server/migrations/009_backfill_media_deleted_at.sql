@@ -0,0 +1,2 @@
+-- 回收站功能之前软删除的媒体没有删除时间，按最后更新时间补齐，使其能在回收站中列出并按保留期清理
+UPDATE media_files SET deleted_at = updated_at WHERE status = 'deleted' AND deleted_at IS NULL;
@@ -0,0 +1,17 @@
+-- 媒体内容的 SHA-256 摘要，用于识别重复上传
+ALTER TABLE media_files ADD COLUMN IF NOT EXISTS content_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_media_user_content_hash ON media_files(user_id, content_hash) WHERE content_hash IS NOT NULL;
+
+-- 内容相同的媒体共享同一个存储对象，ref_count 为引用该对象的媒体记录数
+CREATE TABLE IF NOT EXISTS media_blobs (
+    object_key TEXT PRIMARY KEY NOT NULL,
+    storage_backend TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    ref_count INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+
+    CONSTRAINT uq_media_blobs_hash UNIQUE (storage_backend, content_hash)
+);
@@ -0,0 +1,3 @@
+-- 用户存储配额，NULL 表示使用默认配额（环境变量 USER_QUOTA_BYTES / USER_QUOTA_ITEMS）
+ALTER TABLE users ADD COLUMN IF NOT EXISTS quota_bytes BIGINT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS quota_items BIGINT;
@@ -0,0 +1,4 @@
+-- 按拍摄时间倒序排序图库（拍摄时间由服务器从 EXIF 提取，保存在 metadata.image.taken_at，没有拍摄时间的排在最后）
+CREATE INDEX IF NOT EXISTS idx_media_files_user_taken_at
+    ON media_files(user_id, (metadata->'image'->>'taken_at') DESC NULLS LAST, created_at DESC)
+    WHERE status IN ('active', 'processing');
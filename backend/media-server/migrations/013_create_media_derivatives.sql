@@ -0,0 +1,17 @@
+-- 媒体的派生文件（缩略图等），媒体彻底删除时在同一事务中登记对象删除
+CREATE TABLE IF NOT EXISTS media_derivatives (
+    id TEXT PRIMARY KEY NOT NULL,
+    media_id TEXT NOT NULL,
+    kind TEXT NOT NULL, -- 'thumbnail'
+    size INTEGER NOT NULL, -- 目标尺寸（最长边像素）
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    content_type TEXT NOT NULL,
+    file_size BIGINT NOT NULL,
+    object_key TEXT NOT NULL,
+    storage_backend TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+
+    CONSTRAINT uq_media_derivatives UNIQUE (media_id, kind, size),
+    CONSTRAINT fk_media_derivatives_media FOREIGN KEY (media_id) REFERENCES media_files(id) ON DELETE CASCADE
+);
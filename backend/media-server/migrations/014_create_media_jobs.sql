@@ -0,0 +1,33 @@
+-- 媒体处理任务队列：元数据提取、缩略图、摘要计算和对象删除等在请求之外由后台工作线程执行
+CREATE TABLE IF NOT EXISTS media_jobs (
+    id TEXT PRIMARY KEY NOT NULL,
+    media_id TEXT, -- 对应的媒体ID（媒体记录可能已删除）
+    job_type TEXT NOT NULL, -- 'extract_metadata', 'generate_thumbnails', 'compute_hash', 'delete_object'
+    payload JSONB NOT NULL DEFAULT '{}',
+    priority INTEGER NOT NULL DEFAULT 0, -- 数值越大越先执行
+    status TEXT NOT NULL DEFAULT 'pending', -- 'pending', 'running', 'succeeded', 'dead'（超过重试次数，需要管理员重新入队）
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL,
+    last_error TEXT,
+    run_at TIMESTAMPTZ NOT NULL, -- 最早执行时间，失败后按退避策略推迟
+    locked_until TIMESTAMPTZ, -- 执行中任务的可见性超时，超时后可被其他工作线程重新领取
+    locked_by TEXT,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_media_jobs_ready ON media_jobs(priority DESC, run_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_media_jobs_running ON media_jobs(locked_until) WHERE status = 'running';
+CREATE INDEX IF NOT EXISTS idx_media_jobs_media_id ON media_jobs(media_id);
+CREATE INDEX IF NOT EXISTS idx_media_jobs_status_finished ON media_jobs(status, finished_at);
+
+-- 同一媒体的同类任务最多一个待执行、一个执行中：登记任务时依赖前者去重，后者防止两个工作线程同时处理同一媒体。
+-- 两种状态分别建索引，执行期间内容被替换时仍可登记新的待执行任务
+CREATE UNIQUE INDEX IF NOT EXISTS uq_media_jobs_pending
+    ON media_jobs(media_id, job_type)
+    WHERE status = 'pending';
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_media_jobs_running
+    ON media_jobs(media_id, job_type)
+    WHERE status = 'running';
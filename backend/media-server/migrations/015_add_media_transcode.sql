@@ -0,0 +1,4 @@
+-- 视频转码（HLS 多码率）的状态，转码结果登记在 media_derivatives
+ALTER TABLE media_files ADD COLUMN IF NOT EXISTS transcode_status TEXT; -- 'pending', 'running', 'ready', 'failed'，不需要转码时为空
+ALTER TABLE media_files ADD COLUMN IF NOT EXISTS transcode_progress REAL; -- 0 到 1
+ALTER TABLE media_files ADD COLUMN IF NOT EXISTS transcode_error TEXT;
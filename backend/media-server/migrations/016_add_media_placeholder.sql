@@ -0,0 +1,3 @@
+-- 图片的占位信息，由元数据提取任务计算，缩略图加载前用于显示模糊预览
+ALTER TABLE media_files ADD COLUMN IF NOT EXISTS blurhash TEXT;
+ALTER TABLE media_files ADD COLUMN IF NOT EXISTS dominant_color TEXT; -- '#rrggbb'
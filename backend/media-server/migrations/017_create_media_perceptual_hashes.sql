@@ -0,0 +1,20 @@
+-- 图片和视频关键帧的感知哈希（64 位 dHash），用于查找相似和可能重复的媒体
+CREATE TABLE IF NOT EXISTS media_perceptual_hashes (
+    media_id TEXT NOT NULL,
+    frame INTEGER NOT NULL, -- 图片为 0，视频为关键帧序号
+    hash BIGINT NOT NULL,
+    -- 哈希按 16 位分为四段。距离不超过 3 的两个哈希至少有一段完全相同（抽屉原理），
+    -- 查找可能重复的媒体时只比较第一帧至少有一段相同的媒体，不再两两比较用户的所有媒体
+    band0 INTEGER GENERATED ALWAYS AS ((hash >> 48) & 65535) STORED,
+    band1 INTEGER GENERATED ALWAYS AS ((hash >> 32) & 65535) STORED,
+    band2 INTEGER GENERATED ALWAYS AS ((hash >> 16) & 65535) STORED,
+    band3 INTEGER GENERATED ALWAYS AS (hash & 65535) STORED,
+
+    CONSTRAINT pk_media_perceptual_hashes PRIMARY KEY (media_id, frame),
+    CONSTRAINT fk_media_perceptual_hashes_media FOREIGN KEY (media_id) REFERENCES media_files(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_media_perceptual_hashes_band0 ON media_perceptual_hashes(band0) WHERE frame = 0;
+CREATE INDEX IF NOT EXISTS idx_media_perceptual_hashes_band1 ON media_perceptual_hashes(band1) WHERE frame = 0;
+CREATE INDEX IF NOT EXISTS idx_media_perceptual_hashes_band2 ON media_perceptual_hashes(band2) WHERE frame = 0;
+CREATE INDEX IF NOT EXISTS idx_media_perceptual_hashes_band3 ON media_perceptual_hashes(band3) WHERE frame = 0;
@@ -6,7 +6,7 @@ use axum::{
     response::Response,
 };
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct AuthUser {
     pub user_id: String,
     pub username: String,
@@ -4,6 +4,12 @@ use sqlx::{FromRow, Pool, Postgres};
 use tracing::info;
 use uuid::Uuid;
 
+/// 普通用户角色
+pub const ROLE_USER: &str = "user";
+
+/// 管理员角色
+pub const ROLE_ADMIN: &str = "admin";
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
     pub id: String,
@@ -13,6 +19,7 @@ pub struct User {
     pub created_at: DateTime<Utc>,
     pub last_login: Option<DateTime<Utc>>,
     pub is_active: bool,
+    pub role: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -41,6 +48,7 @@ pub struct UserInfo {
     pub email: String,
     pub created_at: DateTime<Utc>,
     pub last_login: Option<DateTime<Utc>>,
+    pub role: String,
 }
 
 // 数据库操作函数
@@ -49,7 +57,7 @@ pub struct UserRepository;
 impl UserRepository {
     pub async fn create_user(pool: &Pool<Postgres>, user: &User) -> Result<(), sqlx::Error> {
         sqlx::query(
-            "INSERT INTO users (id, username, email, password_hash, created_at, last_login, is_active) VALUES ($1, $2, $3, $4, $5, $6, $7)"
+            "INSERT INTO users (id, username, email, password_hash, created_at, last_login, is_active, role) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
         )
         .bind(&user.id)
         .bind(&user.username)
@@ -58,6 +66,7 @@ impl UserRepository {
         .bind(&user.created_at)
         .bind(&user.last_login)
         .bind(&user.is_active)
+        .bind(&user.role)
         .execute(pool)
         .await?;
 
@@ -70,7 +79,7 @@ impl UserRepository {
         username: &str,
     ) -> Result<Option<User>, sqlx::Error> {
         let user = sqlx::query_as::<_, User>(
-            "SELECT id, username, email, password_hash, created_at, last_login, is_active FROM users WHERE username = $1"
+            "SELECT id, username, email, password_hash, created_at, last_login, is_active, role FROM users WHERE username = $1"
         )
         .bind(username)
         .fetch_optional(pool)
@@ -81,7 +90,7 @@ impl UserRepository {
 
     pub async fn find_by_id(pool: &Pool<Postgres>, id: &str) -> Result<Option<User>, sqlx::Error> {
         let user = sqlx::query_as::<_, User>(
-            "SELECT id, username, email, password_hash, created_at, last_login, is_active FROM users WHERE id = $1"
+            "SELECT id, username, email, password_hash, created_at, last_login, is_active, role FROM users WHERE id = $1"
         )
         .bind(id)
         .fetch_optional(pool)
@@ -104,6 +113,23 @@ impl UserRepository {
         Ok(())
     }
 
+    /// 查询已启用用户的角色，每次从数据库读取以便角色变更立即生效
+    pub async fn get_role(
+        pool: &Pool<Postgres>,
+        user_id: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar("SELECT role FROM users WHERE id = $1 AND is_active = true")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// 查询用户是否为管理员
+    pub async fn is_admin(pool: &Pool<Postgres>, user_id: &str) -> Result<bool, sqlx::Error> {
+        let role = Self::get_role(pool, user_id).await?;
+        Ok(role.as_deref() == Some(ROLE_ADMIN))
+    }
+
     pub async fn username_exists(
         pool: &Pool<Postgres>,
         username: &str,
@@ -139,6 +165,7 @@ impl User {
             created_at: Utc::now(),
             last_login: None,
             is_active: true,
+            role: ROLE_USER.to_string(),
         })
     }
 
@@ -153,6 +180,7 @@ impl User {
             email: self.email.clone(),
             created_at: self.created_at,
             last_login: self.last_login,
+            role: self.role.clone(),
         }
     }
 }
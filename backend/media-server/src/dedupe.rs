@@ -0,0 +1,433 @@
+//! 基于内容摘要的去重
+//!
+//! 每个存储对象计算 SHA-256 摘要并保存在 `media_files.content_hash`：
+//! - 同一用户再次上传相同内容时直接返回已有的媒体
+//! - 不同用户上传相同内容时共享同一个存储对象，`media_blobs.ref_count` 记录引用数，
+//!   引用数归零时才删除对象
+//!
+//! 用户前缀下的对象可以被该用户的上传凭证覆盖，因此被多个用户共享的对象
+//! 会先复制到只有服务器可以写入的 `blobs/` 前缀
+
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::database::Database;
+use crate::deletion_queue::{delete_or_enqueue, enqueue_deletion};
+use crate::handlers::cos_handlers::is_user_object_key;
+use crate::handlers::media_handlers::{MediaItem, insert_media_item};
+use crate::quota::{QuotaError, check_quota_locked};
+use crate::storage::{ByteStream, SharedStorage, StorageError};
+
+/// 共享对象的前缀
+pub const BLOB_PREFIX: &str = "blobs/";
+
+#[derive(Debug)]
+pub enum DedupeError {
+    Storage(StorageError),
+    Database(sqlx::Error),
+    /// 登记时超出用户的存储配额，附带说明
+    QuotaExceeded(String),
+}
+
+impl fmt::Display for DedupeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DedupeError::Storage(e) => write!(f, "复制共享对象失败: {}", e),
+            DedupeError::Database(e) => write!(f, "登记共享对象失败: {}", e),
+            DedupeError::QuotaExceeded(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for DedupeError {}
+
+/// 媒体记录的对象键唯一约束，见迁移 005
+const OBJECT_KEY_CONSTRAINT: &str = "uq_media_files_object_key";
+
+impl DedupeError {
+    /// 对象已被其他媒体记录引用（并发登记同一对象）
+    pub fn is_object_key_conflict(&self) -> bool {
+        matches!(
+            self,
+            DedupeError::Database(e) if e
+                .as_database_error()
+                .and_then(|e| e.constraint())
+                == Some(OBJECT_KEY_CONSTRAINT)
+        )
+    }
+}
+
+impl From<StorageError> for DedupeError {
+    fn from(e: StorageError) -> Self {
+        DedupeError::Storage(e)
+    }
+}
+
+impl From<sqlx::Error> for DedupeError {
+    fn from(e: sqlx::Error) -> Self {
+        DedupeError::Database(e)
+    }
+}
+
+impl From<QuotaError> for DedupeError {
+    fn from(e: QuotaError) -> Self {
+        match e {
+            QuotaError::Exceeded(message) => DedupeError::QuotaExceeded(message),
+            QuotaError::Database(e) => DedupeError::Database(e),
+        }
+    }
+}
+
+/// 计算字节流的 SHA-256 摘要
+#[derive(Clone, Default)]
+pub struct ContentHasher(Arc<Mutex<Sha256>>);
+
+impl ContentHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 包装字节流，在数据流经时更新摘要
+    pub fn wrap<'a>(&self, stream: ByteStream<'a>) -> ByteStream<'a> {
+        let hasher = self.0.clone();
+        Box::pin(stream.map(move |chunk| {
+            if let Ok(bytes) = &chunk
+                && let Ok(mut hasher) = hasher.lock()
+            {
+                hasher.update(bytes);
+            }
+            chunk
+        }))
+    }
+
+    /// 返回小写十六进制的摘要
+    pub fn finish(&self) -> String {
+        let hasher = self.0.lock().map(|h| h.clone()).unwrap_or_default();
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// 摘要是否为64位十六进制字符串
+pub fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// 读取存储对象并计算摘要
+pub async fn hash_object(storage: &SharedStorage, key: &str) -> Result<String, StorageError> {
+    let mut stream = storage.get_stream(key, None).await?;
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| StorageError::Backend(e.to_string()))?;
+        hasher.update(&chunk);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// 查找用户已有的、内容相同的媒体
+pub async fn find_duplicate(
+    db: &Database,
+    user_id: &str,
+    content_hash: &str,
+) -> Result<Option<MediaItem>, sqlx::Error> {
+    sqlx::query_as::<_, MediaItem>(
+        r#"
+        SELECT * FROM media_files
+        WHERE user_id = $1 AND content_hash = $2 AND status IN ('active', 'processing')
+        ORDER BY created_at
+        LIMIT 1
+        "#,
+    )
+    .bind(user_id)
+    .bind(content_hash)
+    .fetch_optional(&db.pool)
+    .await
+}
+
+/// 新上传对象在登记前的准备结果
+#[derive(Debug, Clone)]
+pub struct PreparedBlob {
+    pub content_hash: String,
+    pub size: i64,
+    /// 本次上传写入的对象
+    pub uploaded_key: String,
+    /// 已有对象需要提升为共享对象时，本次上传复制到的 `blobs/` 对象
+    pub promoted_key: Option<String>,
+}
+
+fn blob_key(content_hash: &str) -> String {
+    // 不直接使用摘要作为对象键，避免通过摘要猜出对象地址
+    format!(
+        "{}{}/{}",
+        BLOB_PREFIX,
+        &content_hash[..2],
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// 对象能否被用户直接引用：用户自己前缀下的对象或共享对象
+fn is_shareable_with(user_id: &str, key: &str) -> bool {
+    key.starts_with(BLOB_PREFIX) || is_user_object_key(user_id, key)
+}
+
+/// 在事务外准备登记：内容相同的对象位于其他用户的前缀下时，将本次上传复制到 `blobs/`
+pub async fn prepare_blob(
+    db: &Database,
+    storage: &SharedStorage,
+    user_id: &str,
+    uploaded_key: &str,
+    content_hash: &str,
+    size: i64,
+) -> Result<PreparedBlob, DedupeError> {
+    let existing: Option<String> = sqlx::query_scalar(
+        "SELECT object_key FROM media_blobs WHERE storage_backend = $1 AND content_hash = $2",
+    )
+    .bind(storage.name())
+    .bind(content_hash)
+    .fetch_optional(&db.pool)
+    .await?;
+
+    let mut prepared = PreparedBlob {
+        content_hash: content_hash.to_string(),
+        size,
+        uploaded_key: uploaded_key.to_string(),
+        promoted_key: None,
+    };
+
+    if let Some(existing) = existing
+        && !is_shareable_with(user_id, &existing)
+    {
+        let key = blob_key(content_hash);
+        let meta = storage.head(uploaded_key).await?;
+        let stream = storage.get_stream(uploaded_key, None).await?;
+        storage
+            .put_stream(&key, stream, meta.content_type.as_deref())
+            .await?;
+        prepared.promoted_key = Some(key);
+    }
+
+    Ok(prepared)
+}
+
+/// 在插入媒体记录的事务中引用共享对象，返回媒体记录应使用的对象键
+///
+/// 已有相同内容的对象时增加其引用数；已有对象位于其他用户前缀下时改为引用提升后的
+/// `blobs/` 对象，并把原有的媒体记录一并迁移过去
+pub async fn acquire_blob(
+    conn: &mut PgConnection,
+    storage: &SharedStorage,
+    user_id: &str,
+    prepared: &PreparedBlob,
+) -> Result<String, sqlx::Error> {
+    let backend = storage.name();
+    let now = chrono::Utc::now();
+
+    let existing: Option<String> = sqlx::query_scalar(
+        "SELECT object_key FROM media_blobs WHERE storage_backend = $1 AND content_hash = $2 FOR UPDATE",
+    )
+    .bind(backend)
+    .bind(&prepared.content_hash)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(existing) = existing else {
+        let key = prepared
+            .promoted_key
+            .clone()
+            .unwrap_or_else(|| prepared.uploaded_key.clone());
+        // 并发登记相同内容时唯一约束冲突，事务失败后由客户端重试
+        sqlx::query(
+            r#"
+            INSERT INTO media_blobs (object_key, storage_backend, content_hash, size, ref_count, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, 1, $5, $5)
+            "#,
+        )
+        .bind(&key)
+        .bind(backend)
+        .bind(&prepared.content_hash)
+        .bind(prepared.size)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+        return Ok(key);
+    };
+
+    if is_shareable_with(user_id, &existing) {
+        sqlx::query(
+            "UPDATE media_blobs SET ref_count = ref_count + 1, updated_at = $1 WHERE object_key = $2",
+        )
+        .bind(now)
+        .bind(&existing)
+        .execute(&mut *conn)
+        .await?;
+        return Ok(existing);
+    }
+
+    let Some(promoted) = prepared.promoted_key.clone() else {
+        // 准备之后共享对象发生了变化，本次上传不参与共享
+        return Ok(prepared.uploaded_key.clone());
+    };
+
+    // 把引用原对象的媒体记录迁移到共享对象，原对象登记删除
+    sqlx::query(
+        "UPDATE media_files SET cos_key = $1, cos_url = $2, updated_at = $3 WHERE cos_key = $4 AND storage_backend = $5",
+    )
+    .bind(&promoted)
+    .bind(storage.object_url(&promoted))
+    .bind(now)
+    .bind(&existing)
+    .bind(backend)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "UPDATE media_blobs SET object_key = $1, ref_count = ref_count + 1, updated_at = $2 WHERE object_key = $3",
+    )
+    .bind(&promoted)
+    .bind(now)
+    .bind(&existing)
+    .execute(&mut *conn)
+    .await?;
+
+    enqueue_deletion(&mut *conn, &existing, backend, None).await?;
+
+    crate::log_with_storage!(info, "对象已提升为共享对象: {} -> {}", existing, promoted);
+    Ok(promoted)
+}
+
+/// 登记完成后清理未被使用的上传对象
+pub async fn discard_unused(
+    db: &Database,
+    storage: &SharedStorage,
+    prepared: &PreparedBlob,
+    used_key: &str,
+) {
+    for key in std::iter::once(&prepared.uploaded_key).chain(prepared.promoted_key.as_ref()) {
+        if key != used_key {
+            delete_or_enqueue(db, storage, key, None).await;
+        }
+    }
+}
+
+/// 在删除媒体记录的事务中释放对象引用，没有其他引用时登记删除，返回删除队列记录的ID
+///
+/// 没有共享记录的对象（去重之前上传的媒体）只在没有其他媒体记录引用时删除
+pub async fn release_blob(
+    conn: &mut PgConnection,
+    object_key: &str,
+    storage_backend: &str,
+    media_id: Option<&str>,
+) -> Result<Option<String>, sqlx::Error> {
+    let remaining: Option<i32> = sqlx::query_scalar(
+        r#"
+        UPDATE media_blobs SET ref_count = ref_count - 1, updated_at = $1
+        WHERE object_key = $2 AND storage_backend = $3
+        RETURNING ref_count
+        "#,
+    )
+    .bind(chrono::Utc::now())
+    .bind(object_key)
+    .bind(storage_backend)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let unreferenced = match remaining {
+        Some(count) if count > 0 => false,
+        Some(_) => {
+            sqlx::query("DELETE FROM media_blobs WHERE object_key = $1")
+                .bind(object_key)
+                .execute(&mut *conn)
+                .await?;
+            true
+        }
+        None => !sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM media_files WHERE cos_key = $1 AND storage_backend = $2)",
+        )
+        .bind(object_key)
+        .bind(storage_backend)
+        .fetch_one(&mut *conn)
+        .await?,
+    };
+
+    if !unreferenced {
+        return Ok(None);
+    }
+
+    enqueue_deletion(&mut *conn, object_key, storage_backend, media_id)
+        .await
+        .map(Some)
+}
+
+/// 登记新媒体的结果
+pub enum Registration {
+    Created(MediaItem),
+    /// 用户已有内容相同的媒体
+    Duplicate(MediaItem),
+}
+
+/// 登记新上传的媒体
+///
+/// 用户已有内容相同的媒体时返回已有媒体，并清理本次上传的对象；
+/// 与其他媒体内容相同时共享存储对象。没有摘要的媒体直接插入。
+/// 插入记录的事务中再次检查配额，超出时返回 [`DedupeError::QuotaExceeded`]
+pub async fn register_media(
+    db: &Database,
+    storage: &SharedStorage,
+    mut media: MediaItem,
+) -> Result<Registration, DedupeError> {
+    let Some(content_hash) = media.content_hash.clone() else {
+        let mut tx = db.pool.begin().await?;
+        check_quota_locked(&mut tx, &media.user_id, media.file_size, 1).await?;
+        insert_media_item(&mut *tx, &media).await?;
+        tx.commit().await?;
+        return Ok(Registration::Created(media));
+    };
+
+    if let Some(existing) = find_duplicate(db, &media.user_id, &content_hash).await? {
+        crate::log_with_storage!(
+            info,
+            "重复上传，返回已有媒体: {} (对象 {})",
+            existing.id,
+            media.cos_key
+        );
+        // 并发登记同一对象时已有媒体引用的就是这个对象，不能删除
+        if existing.cos_key != media.cos_key {
+            delete_or_enqueue(db, storage, &media.cos_key, None).await;
+        }
+        return Ok(Registration::Duplicate(existing));
+    }
+
+    let prepared = prepare_blob(
+        db,
+        storage,
+        &media.user_id,
+        &media.cos_key,
+        &content_hash,
+        media.file_size,
+    )
+    .await?;
+
+    let committed: Result<(), DedupeError> = async {
+        let mut tx = db.pool.begin().await?;
+        check_quota_locked(&mut tx, &media.user_id, media.file_size, 1).await?;
+        let key = acquire_blob(&mut tx, storage, &media.user_id, &prepared).await?;
+        media.cos_url = storage.object_url(&key);
+        media.cos_key = key;
+        insert_media_item(&mut *tx, &media).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+    .await;
+
+    if let Err(e) = committed {
+        if let Some(promoted) = &prepared.promoted_key {
+            delete_or_enqueue(db, storage, promoted, None).await;
+        }
+        return Err(e);
+    }
+
+    discard_unused(db, storage, &prepared, &media.cos_key).await;
+    Ok(Registration::Created(media))
+}
@@ -0,0 +1,311 @@
+//! 存储对象删除队列
+//!
+//! 删除媒体时先在同一事务中写入 `storage_deletions`，再尝试删除存储中的对象。
+//! 删除失败的记录由后台任务按指数退避重试，超过最大次数后标记为 `failed`，
+//! 由管理员排查后重新入队。对象在真正删除之前始终有记录可查
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::database::Database;
+use crate::storage::{self, SharedStorage, StorageError};
+
+/// 自动重试的最大次数
+const MAX_ATTEMPTS: i32 = 10;
+
+/// 首次重试的等待时间（秒），之后每次翻倍
+const BASE_BACKOFF_SECS: i64 = 30;
+
+/// 重试等待时间上限（秒）
+const MAX_BACKOFF_SECS: i64 = 6 * 3600;
+
+/// 后台任务领取记录后的租约时间，租约内其他实例不会重复处理
+const CLAIM_LEASE_SECS: i64 = 5 * 60;
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct StorageDeletion {
+    pub id: String,
+    pub object_key: String,
+    pub storage_backend: String,
+    pub media_id: Option<String>,
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DeletionStats {
+    pub pending: i64,
+    pub failed: i64,
+    /// 已到重试时间、等待后台任务处理的数量
+    pub due: i64,
+    pub oldest_pending_at: Option<DateTime<Utc>>,
+}
+
+/// 将对象加入删除队列，可以在删除媒体记录的事务中调用
+pub async fn enqueue_deletion<'e, E>(
+    executor: E,
+    object_key: &str,
+    storage_backend: &str,
+    media_id: Option<&str>,
+) -> Result<String, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+
+    sqlx::query(
+        r#"
+        INSERT INTO storage_deletions (
+            id, object_key, storage_backend, media_id, status, attempts,
+            next_attempt_at, created_at, updated_at
+        ) VALUES ($1, $2, $3, $4, 'pending', 0, $5, $5, $5)
+        "#,
+    )
+    .bind(&id)
+    .bind(object_key)
+    .bind(storage_backend)
+    .bind(media_id)
+    .bind(now)
+    .execute(executor)
+    .await?;
+
+    Ok(id)
+}
+
+fn backoff(attempts: i32) -> Duration {
+    let secs = BASE_BACKOFF_SECS
+        .saturating_mul(1i64 << attempts.clamp(0, 20))
+        .min(MAX_BACKOFF_SECS);
+    Duration::seconds(secs)
+}
+
+/// 尝试删除一条记录对应的对象，成功后移除记录，失败则安排下一次重试
+///
+/// 返回对象是否已删除
+pub async fn attempt_deletion(
+    db: &Database,
+    storage: &SharedStorage,
+    deletion: &StorageDeletion,
+) -> Result<bool, sqlx::Error> {
+    // 对象可能位于切换前的存储后端，按记录中的后端删除；对象已不存在视为删除成功
+    let result = match storage::for_backend(storage, &deletion.storage_backend) {
+        Ok(backend) => match backend.delete(&deletion.object_key).await {
+            Ok(()) | Err(StorageError::NotFound(_)) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        },
+        Err(e) => Err(format!(
+            "无法访问存储后端 {}: {}",
+            deletion.storage_backend, e
+        )),
+    };
+
+    match result {
+        Ok(()) => {
+            sqlx::query("DELETE FROM storage_deletions WHERE id = $1")
+                .bind(&deletion.id)
+                .execute(&db.pool)
+                .await?;
+            Ok(true)
+        }
+        Err(error) => {
+            let attempts = deletion.attempts + 1;
+            let status = if attempts >= MAX_ATTEMPTS {
+                "failed"
+            } else {
+                "pending"
+            };
+            let now = Utc::now();
+
+            crate::log_with_storage!(
+                warn,
+                "删除存储对象失败 {} (第 {} 次): {}",
+                deletion.object_key,
+                attempts,
+                error
+            );
+
+            sqlx::query(
+                r#"
+                UPDATE storage_deletions
+                SET status = $1, attempts = $2, last_error = $3, next_attempt_at = $4, updated_at = $5
+                WHERE id = $6
+                "#,
+            )
+            .bind(status)
+            .bind(attempts)
+            .bind(&error)
+            .bind(now + backoff(attempts))
+            .bind(now)
+            .bind(&deletion.id)
+            .execute(&db.pool)
+            .await?;
+            Ok(false)
+        }
+    }
+}
+
+/// 立即处理删除队列中的指定记录（例如刚在事务中登记的删除）
+///
+/// 记录不存在或已被其他任务处理时返回 `false`
+pub async fn attempt_queued_deletion(
+    db: &Database,
+    storage: &SharedStorage,
+    deletion_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let deletion = sqlx::query_as::<_, StorageDeletion>(
+        "SELECT * FROM storage_deletions WHERE id = $1 AND status = 'pending'",
+    )
+    .bind(deletion_id)
+    .fetch_optional(&db.pool)
+    .await?;
+
+    match deletion {
+        Some(deletion) => attempt_deletion(db, storage, &deletion).await,
+        None => Ok(false),
+    }
+}
+
+/// 将当前存储后端中的对象加入删除队列并立即尝试删除
+///
+/// 用于不在事务中的清理场景（如替换内容后删除旧对象）。登记失败时直接删除，
+/// 删除也失败的对象留给孤儿对象回收处理
+pub async fn delete_or_enqueue(
+    db: &Database,
+    storage: &SharedStorage,
+    object_key: &str,
+    media_id: Option<&str>,
+) -> bool {
+    delete_or_enqueue_from(db, storage, storage.name(), object_key, media_id).await
+}
+
+/// 与 [`delete_or_enqueue`] 相同，对象位于记录中的 `storage_backend`
+pub async fn delete_or_enqueue_from(
+    db: &Database,
+    storage: &SharedStorage,
+    storage_backend: &str,
+    object_key: &str,
+    media_id: Option<&str>,
+) -> bool {
+    match enqueue_deletion(&db.pool, object_key, storage_backend, media_id).await {
+        Ok(id) => attempt_queued_deletion(db, storage, &id)
+            .await
+            .unwrap_or_else(|e| {
+                crate::log_with_storage!(error, "处理删除队列记录失败 {}: {}", object_key, e);
+                false
+            }),
+        Err(e) => {
+            crate::log_with_storage!(error, "登记存储对象删除失败 {}: {}", object_key, e);
+            match storage::for_backend(storage, storage_backend) {
+                Ok(backend) => backend.delete(object_key).await.is_ok(),
+                Err(_) => false,
+            }
+        }
+    }
+}
+
+/// 处理已到重试时间的记录，返回成功删除的数量
+pub async fn process_due_deletions(
+    db: &Database,
+    storage: &SharedStorage,
+    batch_size: i64,
+) -> Result<usize, sqlx::Error> {
+    let now = Utc::now();
+    let claimed = sqlx::query_as::<_, StorageDeletion>(
+        r#"
+        UPDATE storage_deletions SET next_attempt_at = $1
+        WHERE id IN (
+            SELECT id FROM storage_deletions
+            WHERE status = 'pending' AND next_attempt_at <= $2
+            ORDER BY next_attempt_at
+            LIMIT $3
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING *
+        "#,
+    )
+    .bind(now + Duration::seconds(CLAIM_LEASE_SECS))
+    .bind(now)
+    .bind(batch_size)
+    .fetch_all(&db.pool)
+    .await?;
+
+    let mut deleted = 0;
+    for deletion in &claimed {
+        if attempt_deletion(db, storage, deletion).await? {
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+/// 删除队列的统计信息
+pub async fn deletion_stats(db: &Database) -> Result<DeletionStats, sqlx::Error> {
+    let (pending, failed, due, oldest_pending_at): (i64, i64, i64, Option<DateTime<Utc>>) =
+        sqlx::query_as(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE status = 'pending'),
+                COUNT(*) FILTER (WHERE status = 'failed'),
+                COUNT(*) FILTER (WHERE status = 'pending' AND next_attempt_at <= NOW()),
+                MIN(created_at) FILTER (WHERE status = 'pending')
+            FROM storage_deletions
+            "#,
+        )
+        .fetch_one(&db.pool)
+        .await?;
+
+    Ok(DeletionStats {
+        pending,
+        failed,
+        due,
+        oldest_pending_at,
+    })
+}
+
+/// 列出删除队列中的记录
+pub async fn list_deletions(
+    db: &Database,
+    status: Option<&str>,
+    limit: i64,
+) -> Result<Vec<StorageDeletion>, sqlx::Error> {
+    sqlx::query_as::<_, StorageDeletion>(
+        r#"
+        SELECT * FROM storage_deletions
+        WHERE ($1::TEXT IS NULL OR status = $1)
+        ORDER BY created_at
+        LIMIT $2
+        "#,
+    )
+    .bind(status)
+    .bind(limit)
+    .fetch_all(&db.pool)
+    .await
+}
+
+/// 将失败的记录重新入队，`ids` 为空时重新入队所有失败记录
+pub async fn requeue_failed_deletions(
+    db: &Database,
+    ids: Option<&[String]>,
+) -> Result<u64, sqlx::Error> {
+    let now = Utc::now();
+    let result = sqlx::query(
+        r#"
+        UPDATE storage_deletions
+        SET status = 'pending', attempts = 0, next_attempt_at = $1, updated_at = $1
+        WHERE status = 'failed' AND ($2::TEXT[] IS NULL OR id = ANY($2))
+        "#,
+    )
+    .bind(now)
+    .bind(ids)
+    .execute(&db.pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,265 @@
+//! 孤儿对象回收
+//!
+//! 列出当前存储后端中 `media/`、`blobs/` 和 `derivatives/` 前缀下的对象，与 `storage_backend` 为该后端的
+//! `media_files.cos_key` 和 `media_derivatives.object_key` 比对，找出没有任何媒体记录引用的对象（例如上传后未调用 `create_media`，或删除媒体时删除对象失败）。
+//! 超过宽限期的孤儿对象可以只生成报告、移动到隔离区或直接删除
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::database::Database;
+use crate::storage::{SharedStorage, StorageError};
+
+/// 扫描的对象前缀：用户上传的对象、共享对象和派生文件
+const SCANNED_PREFIXES: [&str; 3] = [
+    "media/",
+    crate::dedupe::BLOB_PREFIX,
+    crate::thumbnails::DERIVATIVE_PREFIX,
+];
+
+/// 隔离区前缀，隔离的对象保存在 `quarantine/{原对象键}`
+pub const QUARANTINE_PREFIX: &str = "quarantine/";
+
+/// 默认宽限期，避免误删刚上传、尚未登记的对象
+pub const DEFAULT_GRACE_HOURS: i64 = 24;
+
+/// 每次查询数据库的对象键数量
+const LOOKUP_BATCH_SIZE: usize = 500;
+
+/// 对孤儿对象执行的操作
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanAction {
+    /// 只生成报告（演练模式）
+    #[default]
+    Report,
+    /// 移动到隔离区
+    Quarantine,
+    /// 直接删除
+    Delete,
+}
+
+impl OrphanAction {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "report" | "dry_run" | "dry-run" => Some(OrphanAction::Report),
+            "quarantine" => Some(OrphanAction::Quarantine),
+            "delete" => Some(OrphanAction::Delete),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GcOptions {
+    pub action: OrphanAction,
+    pub grace_period: Duration,
+}
+
+impl Default for GcOptions {
+    fn default() -> Self {
+        GcOptions {
+            action: OrphanAction::Report,
+            grace_period: Duration::hours(DEFAULT_GRACE_HOURS),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct OrphanObject {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<String>,
+}
+
+/// 一次回收的结果
+#[derive(Serialize, Debug, Clone)]
+pub struct GcReport {
+    pub action: OrphanAction,
+    /// 扫描的存储后端
+    pub storage_backend: &'static str,
+    pub dry_run: bool,
+    pub grace_hours: i64,
+    /// 扫描的对象数
+    pub scanned: usize,
+    /// 被媒体记录或上传会话引用的对象数
+    pub referenced: usize,
+    /// 未被引用但仍在宽限期内的对象数
+    pub within_grace: usize,
+    pub orphans: Vec<OrphanObject>,
+    pub orphan_bytes: u64,
+    /// 成功隔离或删除的对象数
+    pub processed: usize,
+    /// 处理失败的对象键
+    pub failed: Vec<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum GcError {
+    Storage(StorageError),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for GcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GcError::Storage(e) => write!(f, "列举存储对象失败: {}", e),
+            GcError::Database(e) => write!(f, "查询媒体记录失败: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GcError {}
+
+impl From<StorageError> for GcError {
+    fn from(e: StorageError) -> Self {
+        GcError::Storage(e)
+    }
+}
+
+impl From<sqlx::Error> for GcError {
+    fn from(e: sqlx::Error) -> Self {
+        GcError::Database(e)
+    }
+}
+
+/// 查询给定对象键中仍被引用的部分
+///
+/// 只有 `storage_backend` 为扫描的后端的记录才算引用，切换后端后其他后端中同名的对象不影响回收。
+/// 上传会话始终写入当前后端
+async fn referenced_keys(
+    db: &Database,
+    storage_backend: &str,
+    keys: &[String],
+) -> Result<HashSet<String>, sqlx::Error> {
+    let mut referenced = HashSet::new();
+    for batch in keys.chunks(LOOKUP_BATCH_SIZE) {
+        let rows: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT cos_key FROM media_files WHERE cos_key = ANY($1) AND storage_backend = $2
+            UNION
+            SELECT object_key FROM media_derivatives
+            WHERE object_key = ANY($1) AND storage_backend = $2
+            UNION
+            SELECT cos_key FROM upload_sessions
+            WHERE cos_key = ANY($1) AND status IN ('uploading', 'finalizing')
+            "#,
+        )
+        .bind(batch)
+        .bind(storage_backend)
+        .fetch_all(&db.pool)
+        .await?;
+        referenced.extend(rows);
+    }
+    Ok(referenced)
+}
+
+/// 将对象复制到隔离区后删除原对象
+async fn quarantine_object(storage: &SharedStorage, key: &str) -> Result<(), StorageError> {
+    let meta = storage.head(key).await?;
+    let stream = storage.get_stream(key, None).await?;
+    storage
+        .put_stream(
+            &format!("{}{}", QUARANTINE_PREFIX, key),
+            stream,
+            meta.content_type.as_deref(),
+        )
+        .await?;
+    storage.delete(key).await
+}
+
+/// 扫描并处理孤儿对象
+pub async fn collect_orphans(
+    db: &Database,
+    storage: &SharedStorage,
+    options: GcOptions,
+) -> Result<GcReport, GcError> {
+    let started_at = Utc::now();
+    let cutoff = started_at - options.grace_period;
+
+    let mut objects = Vec::new();
+    for prefix in SCANNED_PREFIXES {
+        objects.extend(storage.list(prefix).await?);
+    }
+    let keys: Vec<String> = objects.iter().map(|object| object.key.clone()).collect();
+    let referenced = referenced_keys(db, storage.name(), &keys).await?;
+
+    let mut within_grace = 0;
+    let mut orphans = Vec::new();
+    for object in &objects {
+        if referenced.contains(&object.key) {
+            continue;
+        }
+
+        // 无法确定修改时间的对象按仍在宽限期内处理
+        let is_old = object
+            .last_modified
+            .as_deref()
+            .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+            .is_some_and(|modified| modified < cutoff);
+        if is_old {
+            orphans.push(OrphanObject {
+                key: object.key.clone(),
+                size: object.size,
+                last_modified: object.last_modified.clone(),
+            });
+        } else {
+            within_grace += 1;
+        }
+    }
+
+    let mut processed = 0;
+    let mut failed = Vec::new();
+    if options.action != OrphanAction::Report && !orphans.is_empty() {
+        // 处理前再次确认，避免与刚刚完成的 create_media 竞争
+        let orphan_keys: Vec<String> = orphans.iter().map(|o| o.key.clone()).collect();
+        let now_referenced = referenced_keys(db, storage.name(), &orphan_keys).await?;
+        orphans.retain(|orphan| !now_referenced.contains(&orphan.key));
+
+        for orphan in &orphans {
+            let result = match options.action {
+                OrphanAction::Quarantine => quarantine_object(storage, &orphan.key).await,
+                _ => storage.delete(&orphan.key).await,
+            };
+            match result {
+                Ok(()) => processed += 1,
+                Err(e) => {
+                    crate::log_with_storage!(warn, "处理孤儿对象失败 {}: {}", orphan.key, e);
+                    failed.push(orphan.key.clone());
+                }
+            }
+        }
+    }
+
+    let report = GcReport {
+        action: options.action,
+        storage_backend: storage.name(),
+        dry_run: options.action == OrphanAction::Report,
+        grace_hours: options.grace_period.num_hours(),
+        scanned: objects.len(),
+        referenced: referenced.len(),
+        within_grace,
+        orphan_bytes: orphans.iter().map(|o| o.size).sum(),
+        orphans,
+        processed,
+        failed,
+        started_at,
+        finished_at: Utc::now(),
+    };
+
+    crate::log_with_storage!(
+        info,
+        "孤儿对象扫描完成: 扫描 {} 个, 孤儿 {} 个 ({} 字节), 已处理 {} 个, 失败 {} 个",
+        report.scanned,
+        report.orphans.len(),
+        report.orphan_bytes,
+        report.processed,
+        report.failed.len()
+    );
+
+    Ok(report)
+}
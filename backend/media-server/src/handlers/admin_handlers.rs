@@ -0,0 +1,278 @@
+use axum::{
+    Json,
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::credentials::{AuthUser, UserRepository};
+use crate::database::Database;
+use crate::deletion_queue::{
+    DeletionStats, StorageDeletion, deletion_stats, list_deletions, requeue_failed_deletions,
+};
+use crate::gc::{GcOptions, GcReport, OrphanAction, collect_orphans};
+use crate::jobs::{JobStats, MediaJob, job_stats, list_jobs, requeue_dead_jobs};
+use crate::quota::{Quota, Usage, UserUsageSummary, all_users_usage, set_user_quota, user_usage};
+use crate::storage::SharedStorage;
+
+type AdminError = (StatusCode, String);
+
+/// 确认当前用户是管理员
+pub async fn require_admin(db: &Database, auth_user: &AuthUser) -> Result<(), AdminError> {
+    let is_admin = UserRepository::is_admin(&db.pool, &auth_user.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Database error checking admin role: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "数据库错误".to_string())
+        })?;
+
+    if is_admin {
+        Ok(())
+    } else {
+        Err((StatusCode::FORBIDDEN, "需要管理员权限".to_string()))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OrphanGcParams {
+    /// `report`（默认，只生成报告）、`quarantine` 或 `delete`
+    pub action: Option<OrphanAction>,
+    /// 宽限期（小时），默认24小时
+    pub grace_hours: Option<i64>,
+}
+
+/// 扫描并回收孤儿对象（需要管理员权限）
+///
+/// 默认只返回报告，不修改存储
+pub async fn run_orphan_gc(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(params): Query<OrphanGcParams>,
+) -> Result<Json<GcReport>, AdminError> {
+    require_admin(&db, &auth_user).await?;
+
+    let mut options = GcOptions {
+        action: params.action.unwrap_or_default(),
+        ..GcOptions::default()
+    };
+    if let Some(hours) = params.grace_hours {
+        if hours < 0 {
+            return Err((StatusCode::BAD_REQUEST, "宽限期不能为负数".to_string()));
+        }
+        options.grace_period = chrono::Duration::hours(hours);
+    }
+
+    crate::log_with_storage!(
+        info,
+        "管理员 {} 发起孤儿对象回收: {:?}",
+        auth_user.username,
+        options.action
+    );
+
+    collect_orphans(&db, &storage, options)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            crate::log_with_storage!(error, "孤儿对象回收失败: {}", e);
+            (StatusCode::BAD_GATEWAY, e.to_string())
+        })
+}
+
+fn database_error(e: sqlx::Error) -> AdminError {
+    eprintln!("Database error in admin handler: {}", e);
+    (StatusCode::INTERNAL_SERVER_ERROR, "数据库错误".to_string())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeletionListParams {
+    /// `pending` 或 `failed`，默认全部
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeletionQueueResponse {
+    #[serde(flatten)]
+    pub stats: DeletionStats,
+    pub items: Vec<StorageDeletion>,
+}
+
+/// 查看存储对象删除队列（需要管理员权限）
+pub async fn get_storage_deletions(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(params): Query<DeletionListParams>,
+) -> Result<Json<DeletionQueueResponse>, AdminError> {
+    require_admin(&db, &auth_user).await?;
+
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    let stats = deletion_stats(&db).await.map_err(database_error)?;
+    let items = list_deletions(&db, params.status.as_deref(), limit)
+        .await
+        .map_err(database_error)?;
+
+    Ok(Json(DeletionQueueResponse { stats, items }))
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct RequeueDeletionsRequest {
+    /// 要重新入队的记录ID，为空时重新入队所有失败记录
+    pub ids: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RequeueDeletionsResponse {
+    pub requeued: u64,
+}
+
+/// 将失败的删除记录重新入队（需要管理员权限）
+pub async fn requeue_storage_deletions(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    payload: Option<Json<RequeueDeletionsRequest>>,
+) -> Result<Json<RequeueDeletionsResponse>, AdminError> {
+    require_admin(&db, &auth_user).await?;
+
+    let request = payload.map(|Json(request)| request).unwrap_or_default();
+    let requeued = requeue_failed_deletions(&db, request.ids.as_deref())
+        .await
+        .map_err(database_error)?;
+
+    crate::log_with_storage!(
+        info,
+        "管理员 {} 重新入队 {} 条删除记录",
+        auth_user.username,
+        requeued
+    );
+
+    Ok(Json(RequeueDeletionsResponse { requeued }))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct JobListParams {
+    /// `pending`、`running`、`succeeded` 或 `dead`，默认全部
+    pub status: Option<String>,
+    pub job_type: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct JobQueueResponse {
+    #[serde(flatten)]
+    pub stats: JobStats,
+    pub items: Vec<MediaJob>,
+}
+
+/// 查看媒体处理任务队列（需要管理员权限）
+pub async fn get_jobs(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(params): Query<JobListParams>,
+) -> Result<Json<JobQueueResponse>, AdminError> {
+    require_admin(&db, &auth_user).await?;
+
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    let stats = job_stats(&db).await.map_err(database_error)?;
+    let items = list_jobs(
+        &db,
+        params.status.as_deref(),
+        params.job_type.as_deref(),
+        limit,
+    )
+    .await
+    .map_err(database_error)?;
+
+    Ok(Json(JobQueueResponse { stats, items }))
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct RequeueJobsRequest {
+    /// 要重新入队的任务ID，为空时重新入队所有死信任务
+    pub ids: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RequeueJobsResponse {
+    pub requeued: u64,
+}
+
+/// 将死信任务重新入队（需要管理员权限）
+pub async fn requeue_jobs(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    payload: Option<Json<RequeueJobsRequest>>,
+) -> Result<Json<RequeueJobsResponse>, AdminError> {
+    require_admin(&db, &auth_user).await?;
+
+    let request = payload.map(|Json(request)| request).unwrap_or_default();
+    let requeued = requeue_dead_jobs(&db, request.ids.as_deref())
+        .await
+        .map_err(database_error)?;
+
+    crate::log_with_storage!(
+        info,
+        "管理员 {} 重新入队 {} 个死信任务",
+        auth_user.username,
+        requeued
+    );
+
+    Ok(Json(RequeueJobsResponse { requeued }))
+}
+
+/// 查看所有用户的存储用量（需要管理员权限），用于计费
+pub async fn get_all_usage(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<Json<Vec<UserUsageSummary>>, AdminError> {
+    require_admin(&db, &auth_user).await?;
+    all_users_usage(&db).await.map(Json).map_err(database_error)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SetQuotaRequest {
+    /// 最大存储字节数，为空时使用默认配额
+    pub quota_bytes: Option<i64>,
+    /// 最大媒体数量，为空时使用默认配额
+    pub quota_items: Option<i64>,
+}
+
+/// 设置用户的存储配额（需要管理员权限），返回该用户的用量
+pub async fn set_quota(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(user_id): Path<String>,
+    Json(request): Json<SetQuotaRequest>,
+) -> Result<Json<Usage>, AdminError> {
+    require_admin(&db, &auth_user).await?;
+
+    if request.quota_bytes.is_some_and(|bytes| bytes < 0)
+        || request.quota_items.is_some_and(|items| items < 0)
+    {
+        return Err((StatusCode::BAD_REQUEST, "配额不能为负数".to_string()));
+    }
+
+    let quota = Quota {
+        max_bytes: request.quota_bytes,
+        max_items: request.quota_items,
+    };
+    if !set_user_quota(&db, &user_id, quota)
+        .await
+        .map_err(database_error)?
+    {
+        return Err((StatusCode::NOT_FOUND, "用户不存在".to_string()));
+    }
+
+    crate::log_with_storage!(
+        info,
+        "管理员 {} 设置用户 {} 的配额: {:?}",
+        auth_user.username,
+        user_id,
+        quota
+    );
+
+    user_usage(&db, &user_id)
+        .await
+        .map(Json)
+        .map_err(database_error)
+}
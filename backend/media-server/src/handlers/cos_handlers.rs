@@ -1,15 +1,29 @@
-use axum::{extract::Query, response::Json};
-use cos_rust_sdk::sts::{GetCredentialsRequest, Policy, StsClient};
-use cos_rust_sdk::{Config, CosClient, ObjectClient};
+use axum::{
+    extract::{Extension, Query, State},
+    http::StatusCode,
+    response::Json,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::time::Duration;
 use tracing::instrument;
 
+use crate::credentials::{AuthUser, UserRepository};
+use crate::database::Database;
+use crate::handlers::resumable_handlers::MAX_CHUNK_SIZE;
+use crate::quota::{QuotaError, check_quota};
+use crate::storage::{PresignMethod, SharedStorage, StorageError, UploadCredentialsRequest};
+use crate::upload_policy::{PolicyViolation, policy_for_user};
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct StsRequest {
     pub duration_seconds: Option<u32>,
+    /// 要上传的对象键（`validate_file_upload` 返回的 `suggested_key`），提供时凭证仅对该对象有效
+    pub key: Option<String>,
+    /// 自定义策略，仅管理员可用
     pub policy: Option<String>,
+    /// 要上传的文件大小（必填），用于检查上传策略的大小上限和存储配额，凭证只能上传不超过该大小的对象
+    pub file_size: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -32,170 +46,398 @@ pub struct StsErrorResponse {
     pub message: String,
 }
 
+fn sts_error(error: &str, message: impl Into<String>) -> Json<StsErrorResponse> {
+    Json(StsErrorResponse {
+        error: error.to_string(),
+        message: message.into(),
+    })
+}
+
 /// 获取STS临时凭证
 ///
 /// 此端点用于获取腾讯云COS的临时访问凭证，包括临时SecretId、SecretKey和SessionToken
 /// 这些凭证可以用于前端直接上传文件到COS，避免在后端中转文件
-#[instrument]
+///
+/// 凭证只允许上传，范围限定在当前用户的 `media/{user_id}/` 前缀下；
+/// 请求中带有 `key` 时进一步限定为该对象。必须声明 `file_size`，凭证只允许单个请求上传
+/// 不超过该大小的对象；超出上传策略的大小上限或存储配额时不签发凭证
+#[instrument(skip(db))]
 pub async fn get_sts_credentials(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
     Query(params): Query<StsRequest>,
 ) -> Result<Json<StsResponse>, Json<StsErrorResponse>> {
     crate::log_with_storage!(info, "开始获取STS临时凭证");
 
-    // 设置默认的持续时间（秒），最大7200秒（2小时）
-    let duration_seconds = params.duration_seconds.unwrap_or(3600).min(7200);
+    // 自定义策略可以授予任意权限，只允许管理员使用
+    if params.policy.is_some() {
+        let is_admin = UserRepository::is_admin(&db.pool, &auth_user.user_id)
+            .await
+            .map_err(|e| {
+                crate::log_with_storage!(error, "查询用户角色失败: {}", e);
+                sts_error("DatabaseError", "查询用户角色失败")
+            })?;
+        if !is_admin {
+            crate::log_with_storage!(
+                warn,
+                "非管理员用户尝试使用自定义Policy: {}",
+                auth_user.user_id
+            );
+            return Err(sts_error("PolicyForbidden", "仅管理员可以使用自定义Policy"));
+        }
+    }
 
-    // 从环境变量获取存储桶名称
-    let bucket = std::env::var("COS_BUCKET").map_err(|_| {
-        crate::log_with_storage!(error, "未找到环境变量 COS_BUCKET");
-        Json(StsErrorResponse {
-            error: "ConfigError".to_string(),
-            message: "未配置腾讯云存储桶名称".to_string(),
-        })
-    })?;
-
-    // Policy 使用示例：
-    // 1. Policy::allow_put_object(&bucket, Some("uploads/"))     - 仅允许上传到 uploads/ 前缀
-    // 2. Policy::allow_get_object(&bucket, Some("public/"))      - 仅允许下载 public/ 前缀的文件
-    // 3. Policy::allow_delete_object(&bucket, Some("temp/"))     - 仅允许删除 temp/ 前缀的文件
-    // 4. Policy::allow_read_write(&bucket, Some("media/"))       - 允许读写 media/ 前缀的文件
-    // 5. Policy::allow_read_write(&bucket, None)                 - 允许读写整个存储桶
-
-    let policy_obj = if let Some(custom_policy) = params.policy.as_deref() {
-        // 如果提供了自定义策略，尝试解析 JSON
-        serde_json::from_str::<Policy>(custom_policy).map_err(|e| {
-            crate::log_with_storage!(error, "自定义Policy解析失败: {}", e);
-            Json(StsErrorResponse {
-                error: "PolicyError".to_string(),
-                message: format!("自定义Policy解析失败: {}", e),
-            })
-        })?
-    } else {
-        // 使用预定义的读写策略，限制在 media/ 前缀下
-        // 根据需要可以改为其他策略方法：
-        // Policy::allow_put_object(&bucket, Some("uploads/"))  // 仅上传
-        // Policy::allow_get_object(&bucket, Some("downloads/")) // 仅下载
-        // Policy::allow_delete_object(&bucket, Some("temp/"))   // 仅删除
-        Policy::allow_read_write(&bucket, Some("media/"))
+    // 凭证只能上传不超过声明大小的对象
+    let Some(file_size) = params.file_size else {
+        return Err(sts_error(
+            "MissingFileSize",
+            "请提供要上传的文件大小 file_size",
+        ));
     };
+    let policy = policy_for_user(&db, &auth_user.user_id)
+        .await
+        .map_err(|e| {
+            crate::log_with_storage!(error, "查询用户角色失败: {}", e);
+            sts_error("DatabaseError", "查询用户角色失败")
+        })?;
+    if file_size > policy.max_file_size {
+        return Err(sts_error(
+            "FileTooLarge",
+            PolicyViolation::TooLarge {
+                max_size: policy.max_file_size,
+            }
+            .to_string(),
+        ));
+    }
 
-    // 从环境变量获取配置
-    let secret_id = std::env::var("COS_SECRET_ID").map_err(|_| {
-        crate::log_with_storage!(error, "未找到环境变量 COS_SECRET_ID");
-        Json(StsErrorResponse {
-            error: "ConfigError".to_string(),
-            message: "未配置腾讯云SecretId".to_string(),
-        })
-    })?;
-
-    let secret_key = std::env::var("COS_SECRET_KEY").map_err(|_| {
-        crate::log_with_storage!(error, "未找到环境变量 COS_SECRET_KEY");
-        Json(StsErrorResponse {
-            error: "ConfigError".to_string(),
-            message: "未配置腾讯云SecretKey".to_string(),
-        })
-    })?;
-
-    let region = std::env::var("COS_REGION").unwrap_or_else(|_| "ap-beijing".to_string());
-
-    println!("secret_id: {}", secret_id);
-    println!("secret_key: {}", secret_key);
-    println!("region: {}", region);
-
-    // 创建STS客户端
-    let sts_client = StsClient::new(secret_id, secret_key, region);
-
-    // 构建请求参数
-    crate::log_with_storage!(info, "使用Policy: {:?}", policy_obj);
-
-    let sts_request = GetCredentialsRequest {
-        name: Some("media-hub-temp-credentials".to_string()),
-        policy: policy_obj,
-        duration_seconds: Some(duration_seconds),
+    check_quota(&db, &auth_user.user_id, file_size as i64, 1)
+        .await
+        .map_err(|e| match e {
+            QuotaError::Exceeded(message) => sts_error("QuotaExceeded", message),
+            QuotaError::Database(e) => {
+                crate::log_with_storage!(error, "查询存储用量失败: {}", e);
+                sts_error("DatabaseError", "查询存储用量失败")
+            }
+        })?;
+
+    let prefix = match params.key {
+        Some(key) if is_user_object_key(&auth_user.user_id, &key) => key,
+        Some(_) => {
+            return Err(sts_error(
+                "InvalidKey",
+                format!(
+                    "对象键必须位于 {} 前缀下",
+                    user_upload_prefix(&auth_user.user_id)
+                ),
+            ));
+        }
+        None => user_upload_prefix(&auth_user.user_id),
     };
 
+    // 设置默认的持续时间（秒），最大7200秒（2小时）
+    let duration_seconds = params.duration_seconds.unwrap_or(3600).min(7200);
+
     crate::log_with_storage!(
         info,
         "正在请求STS临时凭证，持续时间: {}秒",
         duration_seconds
     );
 
-    // 调用STS API获取临时凭证
-    match sts_client.get_credentials(sts_request).await {
-        Ok(response) => {
-            crate::log_with_storage!(info, "成功获取STS临时凭证");
+    let request = UploadCredentialsRequest {
+        prefix,
+        custom_policy: params.policy,
+        duration_seconds,
+        max_object_size: file_size,
+    };
 
-            // 解析响应
-            let credentials = StsCredentials {
-                session_token: response.token,
-                tmp_secret_id: response.tmp_secret_id,
-                tmp_secret_key: response.tmp_secret_key,
-            };
+    match storage.issue_upload_credentials(request).await {
+        Ok(credentials) => {
+            crate::log_with_storage!(info, "成功获取STS临时凭证");
 
-            let expiration_time = response
-                .expired_time
-                .map(|t| {
-                    chrono::DateTime::from_timestamp(t as i64, 0)
-                        .unwrap_or_else(chrono::Utc::now)
-                        .to_rfc3339()
-                })
-                .unwrap_or_else(|| (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339());
-
-            let sts_response = StsResponse {
-                credentials,
-                expiration: expiration_time,
+            Ok(Json(StsResponse {
+                credentials: StsCredentials {
+                    session_token: credentials.session_token,
+                    tmp_secret_id: credentials.tmp_secret_id,
+                    tmp_secret_key: credentials.tmp_secret_key,
+                },
+                expiration: credentials.expiration,
                 request_id: "sts-request".to_string(),
-            };
-
-            Ok(Json(sts_response))
+            }))
         }
         Err(e) => {
             crate::log_with_storage!(error, "获取STS临时凭证失败: {}", e);
-            Err(Json(StsErrorResponse {
-                error: "StsError".to_string(),
-                message: format!("获取临时凭证失败: {}", e),
-            }))
+            let error = match e {
+                StorageError::Config(_) => "ConfigError",
+                StorageError::InvalidRequest(_) => "PolicyError",
+                StorageError::Unsupported(_) => "UnsupportedError",
+                _ => "StsError",
+            };
+            Err(sts_error(error, e.to_string()))
         }
     }
 }
 
 /// 获取COS上传配置信息
 ///
-/// 返回COS存储桶的基本配置信息，用于前端上传文件
-#[instrument]
-pub async fn get_cos_config() -> Json<HashMap<String, String>> {
+/// 返回存储桶的基本配置信息，用于前端上传文件
+/// `backend` 为当前存储后端，`upload_protocol` 告知前端使用STS凭证还是预签名URL上传，
+/// 类型和大小限制来自当前用户适用的上传策略
+#[instrument(skip(db))]
+pub async fn get_cos_config(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<Json<HashMap<String, String>>, StatusCode> {
     crate::log_with_storage!(info, "获取COS配置信息");
 
+    let policy = policy_for_user(&db, &auth_user.user_id)
+        .await
+        .map_err(|e| {
+            crate::log_with_storage!(error, "查询用户角色失败: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
     let mut config = HashMap::new();
 
-    // 从环境变量获取COS配置
-    if let Ok(bucket) = std::env::var("COS_BUCKET") {
-        config.insert("bucket".to_string(), bucket);
-    }
+    config.insert("backend".to_string(), storage.name().to_string());
+    config.insert(
+        "upload_protocol".to_string(),
+        storage.upload_protocol().to_string(),
+    );
 
-    if let Ok(region) = std::env::var("COS_REGION") {
-        config.insert("region".to_string(), region);
-    } else {
-        config.insert("region".to_string(), "ap-beijing".to_string());
+    let bucket = storage.bucket();
+    if !bucket.is_empty() {
+        config.insert("bucket".to_string(), bucket);
     }
+    config.insert("region".to_string(), storage.region());
 
     if let Ok(domain) = std::env::var("COS_DOMAIN") {
         config.insert("domain".to_string(), domain);
     }
 
-    // 设置默认的上传路径前缀
-    config.insert("upload_prefix".to_string(), "media/".to_string());
+    // 当前用户的上传路径前缀
+    config.insert(
+        "upload_prefix".to_string(),
+        user_upload_prefix(&auth_user.user_id),
+    );
+
+    // 允许的文件类型，格式可直接用于 `<input accept>`
+    config.insert("allowed_types".to_string(), policy.accept());
+    if !policy.allowed_extensions.is_empty() {
+        config.insert(
+            "allowed_extensions".to_string(),
+            policy.allowed_extensions.join(","),
+        );
+    }
+    if !policy.blocked_extensions.is_empty() {
+        config.insert(
+            "blocked_extensions".to_string(),
+            policy.blocked_extensions.join(","),
+        );
+    }
 
-    // 设置允许的文件类型
     config.insert(
-        "allowed_types".to_string(),
-        "image/*,video/*,audio/*".to_string(),
+        "max_file_size".to_string(),
+        policy.max_file_size.to_string(),
     );
 
-    // 设置最大文件大小（100MB）
-    config.insert("max_file_size".to_string(), "104857600".to_string());
+    // 超过上限的文件需要使用断点续传
+    config.insert(
+        "resumable_max_file_size".to_string(),
+        policy.max_resumable_file_size.to_string(),
+    );
+    config.insert(
+        "resumable_chunk_size".to_string(),
+        MAX_CHUNK_SIZE.to_string(),
+    );
 
-    Json(config)
+    Ok(Json(config))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PresignRequest {
+    pub key: String,
+    /// `put`（上传，默认）或 `get`（下载）
+    pub method: Option<String>,
+    pub expires_in: Option<u64>,
+    /// 要上传的文件大小，上传时必填。预签名URL只能上传恰好为该大小的对象
+    pub file_size: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PresignResponse {
+    pub url: String,
+    pub method: String,
+    pub key: String,
+    pub object_url: String,
+    pub expiration: String,
+}
+
+/// 获取预签名URL
+///
+/// 对不支持STS临时凭证的存储后端（S3兼容存储、本地存储），前端通过此URL直接上传或下载文件
+///
+/// 上传只允许当前用户前缀下尚未被媒体记录或上传会话引用的对象键，声明的大小须符合上传策略和存储配额；
+/// 下载还允许当前用户媒体记录引用的对象
+#[instrument(skip(db))]
+pub async fn get_presigned_url(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<PresignRequest>,
+) -> Result<Json<PresignResponse>, (StatusCode, Json<StsErrorResponse>)> {
+    let method = match request.method.as_deref().unwrap_or("put") {
+        "put" | "PUT" => PresignMethod::Put,
+        "get" | "GET" => PresignMethod::Get,
+        other => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(StsErrorResponse {
+                    error: "InvalidMethod".to_string(),
+                    message: format!("不支持的方法: {}", other),
+                }),
+            ));
+        }
+    };
+
+    let allowed = is_user_object_key(&auth_user.user_id, &request.key)
+        || (method == PresignMethod::Get
+            && sqlx::query_scalar::<_, bool>(
+                "SELECT EXISTS(SELECT 1 FROM media_files WHERE cos_key = $1 AND user_id = $2 AND status IN ('active', 'processing'))",
+            )
+            .bind(&request.key)
+            .bind(&auth_user.user_id)
+            .fetch_one(&db.pool)
+            .await
+            .map_err(|e| {
+                crate::log_with_storage!(error, "查询媒体记录失败: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    sts_error("DatabaseError", "查询媒体记录失败"),
+                )
+            })?);
+
+    if !allowed {
+        return Err((
+            StatusCode::FORBIDDEN,
+            sts_error(
+                "InvalidKey",
+                format!(
+                    "对象键必须位于 {} 前缀下",
+                    user_upload_prefix(&auth_user.user_id)
+                ),
+            ),
+        ));
+    }
+
+    let database_error = |e: sqlx::Error| {
+        crate::log_with_storage!(error, "查询媒体记录失败: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            sts_error("DatabaseError", "查询媒体记录失败"),
+        )
+    };
+
+    // 上传地址只能写入尚未登记的对象，且大小随签名一起校验
+    let upload_size = if method == PresignMethod::Put {
+        let Some(file_size) = request.file_size else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                sts_error("MissingFileSize", "请提供要上传的文件大小 file_size"),
+            ));
+        };
+
+        let policy = policy_for_user(&db, &auth_user.user_id)
+            .await
+            .map_err(database_error)?;
+        if file_size > policy.max_file_size {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                sts_error(
+                    "FileTooLarge",
+                    PolicyViolation::TooLarge {
+                        max_size: policy.max_file_size,
+                    }
+                    .to_string(),
+                ),
+            ));
+        }
+
+        // 已登记的对象通过了内容和配额检查，不能再被覆盖
+        let referenced: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS(SELECT 1 FROM media_files WHERE cos_key = $1 AND storage_backend = $2)
+                OR EXISTS(SELECT 1 FROM upload_sessions WHERE cos_key = $1)
+            "#,
+        )
+        .bind(&request.key)
+        .bind(storage.name())
+        .fetch_one(&db.pool)
+        .await
+        .map_err(database_error)?;
+        if referenced {
+            return Err((
+                StatusCode::CONFLICT,
+                sts_error("KeyInUse", "对象键已被使用，请使用新的对象键"),
+            ));
+        }
+
+        // 存储配额不足时不再签发上传地址
+        if let Err(e) = check_quota(&db, &auth_user.user_id, file_size as i64, 1).await {
+            return Err(match e {
+                QuotaError::Exceeded(message) => {
+                    (StatusCode::FORBIDDEN, sts_error("QuotaExceeded", message))
+                }
+                QuotaError::Database(e) => {
+                    crate::log_with_storage!(error, "查询存储用量失败: {}", e);
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        sts_error("DatabaseError", "查询存储用量失败"),
+                    )
+                }
+            });
+        }
+        Some(file_size)
+    } else {
+        None
+    };
+
+    // 默认1小时，最长2小时
+    let expires_in = request.expires_in.unwrap_or(3600).clamp(60, 7200);
+
+    let expires = Duration::from_secs(expires_in);
+    let presigned = match upload_size {
+        Some(file_size) => {
+            storage
+                .presign_upload(&request.key, file_size, expires)
+                .await
+        }
+        None => storage.presign(&request.key, method, expires).await,
+    };
+    match presigned {
+        Ok(url) => {
+            crate::log_with_storage!(info, "签发预签名URL: {} {}", method.as_str(), request.key);
+            Ok(Json(PresignResponse {
+                url,
+                method: method.as_str().to_string(),
+                object_url: storage.object_url(&request.key),
+                key: request.key,
+                expiration: (chrono::Utc::now() + chrono::Duration::seconds(expires_in as i64))
+                    .to_rfc3339(),
+            }))
+        }
+        Err(e) => {
+            crate::log_with_storage!(error, "签发预签名URL失败: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(StsErrorResponse {
+                    error: "PresignError".to_string(),
+                    message: e.to_string(),
+                }),
+            ))
+        }
+    }
 }
 
 /// 验证上传的文件信息
@@ -215,55 +457,49 @@ pub struct FileValidationResponse {
 
 /// 验证文件上传请求
 ///
-/// 在获取STS凭证之前，验证文件是否符合上传要求
-#[instrument]
+/// 在获取STS凭证之前，验证文件是否符合上传要求以及用户的存储配额是否足够
+#[instrument(skip(db))]
 pub async fn validate_file_upload(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
     Json(request): Json<FileValidationRequest>,
 ) -> Json<FileValidationResponse> {
     crate::log_with_storage!(info, "验证文件上传请求: {}", request.filename);
 
-    // 检查文件大小（最大100MB）
-    const MAX_FILE_SIZE: u64 = 100 * 1024 * 1024;
-    if request.file_size > MAX_FILE_SIZE {
+    let policy = match policy_for_user(&db, &auth_user.user_id).await {
+        Ok(policy) => policy,
+        Err(e) => {
+            crate::log_with_storage!(error, "查询用户角色失败: {}", e);
+            return Json(FileValidationResponse {
+                valid: false,
+                message: "查询上传策略失败".to_string(),
+                suggested_key: None,
+            });
+        }
+    };
+
+    if let Err(violation) = policy.check(
+        Some(&request.filename),
+        Some(request.file_size),
+        &request.content_type,
+    ) {
         return Json(FileValidationResponse {
             valid: false,
-            message: format!(
-                "文件大小超过限制，最大允许{}MB",
-                MAX_FILE_SIZE / 1024 / 1024
-            ),
+            message: violation.to_string(),
             suggested_key: None,
         });
     }
 
-    // 检查文件类型
-    let allowed_types = ["image/", "video/", "audio/"];
-    let is_allowed_type = allowed_types
-        .iter()
-        .any(|&t| request.content_type.starts_with(t));
-
-    if !is_allowed_type {
+    if let Err(e) = check_quota(&db, &auth_user.user_id, request.file_size as i64, 1).await {
+        crate::log_with_storage!(warn, "文件验证未通过 {}: {}", request.filename, e);
         return Json(FileValidationResponse {
             valid: false,
-            message: "不支持的文件类型，仅支持图片、视频和音频文件".to_string(),
+            message: e.to_string(),
             suggested_key: None,
         });
     }
 
-    // 生成建议的文件键名（包含时间戳和UUID避免冲突）
-    let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
-    let uuid = uuid::Uuid::new_v4().to_string()[..8].to_string();
-    let extension = std::path::Path::new(&request.filename)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("bin");
-
-    let suggested_key = format!(
-        "media/{}_{}_{}.{}",
-        timestamp,
-        uuid,
-        sanitize_filename(&request.filename),
-        extension
-    );
+    let suggested_key = generate_object_key(&auth_user.user_id, &request.filename);
 
     crate::log_with_storage!(
         info,
@@ -279,6 +515,35 @@ pub async fn validate_file_upload(
     })
 }
 
+/// 用户上传对象的键前缀
+pub fn user_upload_prefix(user_id: &str) -> String {
+    format!("media/{}/", user_id)
+}
+
+/// 检查对象键是否位于用户自己的上传前缀下
+pub fn is_user_object_key(user_id: &str, key: &str) -> bool {
+    key.starts_with(&user_upload_prefix(user_id)) && !key.split('/').any(|s| s == "..")
+}
+
+/// 生成对象键名（位于用户前缀下，包含时间戳和UUID避免冲突）
+pub fn generate_object_key(user_id: &str, filename: &str) -> String {
+    let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
+    let uuid = uuid::Uuid::new_v4().to_string()[..8].to_string();
+    let extension = std::path::Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("bin");
+
+    format!(
+        "{}{}_{}_{}.{}",
+        user_upload_prefix(user_id),
+        timestamp,
+        uuid,
+        sanitize_filename(filename),
+        extension
+    )
+}
+
 /// 清理文件名，移除特殊字符
 fn sanitize_filename(filename: &str) -> String {
     // 移除文件扩展名
@@ -295,46 +560,3 @@ fn sanitize_filename(filename: &str) -> String {
         .take(50) // 限制长度
         .collect()
 }
-
-/// 从腾讯云COS删除文件
-pub async fn delete_cos_file(
-    cos_key: &str,
-    bucket: &str,
-    region: &str,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // 从环境变量获取COS配置
-    let secret_id = std::env::var("COS_SECRET_ID").map_err(|_| "未找到环境变量 COS_SECRET_ID")?;
-
-    let secret_key =
-        std::env::var("COS_SECRET_KEY").map_err(|_| "未找到环境变量 COS_SECRET_KEY")?;
-
-    // 创建COS配置
-    let config = Config::new(
-        &secret_id,
-        &secret_key,
-        &region.to_string(),
-        &bucket.to_string(),
-    )
-    .with_timeout(Duration::from_secs(30));
-
-    // 创建COS客户端
-    let cos_client = CosClient::new(config)?;
-    let object_client = ObjectClient::new(cos_client);
-
-    // 删除对象
-    match object_client.delete_object(cos_key).await {
-        Ok(_) => {
-            crate::log_with_storage!(info, "成功从COS删除文件: {}", cos_key);
-            Ok(())
-        }
-        Err(e) => {
-            // 如果是404错误（文件不存在），也视为成功
-            if e.to_string().contains("404") || e.to_string().contains("NoSuchKey") {
-                crate::log_with_storage!(info, "COS文件不存在，视为删除成功: {}", cos_key);
-                Ok(())
-            } else {
-                Err(format!("COS删除失败: {}", e).into())
-            }
-        }
-    }
-}
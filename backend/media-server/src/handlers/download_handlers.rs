@@ -0,0 +1,537 @@
+use axum::{
+    Json,
+    body::Body,
+    extract::{Extension, Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Redirect, Response},
+};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::credentials::AuthUser;
+use crate::database::Database;
+use crate::handlers::media_handlers::{MediaItem, find_readable_media};
+use crate::previews::{SPRITE_KIND, SPRITE_TRACK_KIND, find_sprite};
+use crate::storage::{self, ByteRange, SharedStorage, StorageError, content_disposition};
+use crate::thumbnails::{MediaDerivative, find_thumbnail};
+use crate::transcode::{HLS_MEDIA_KIND, find_hls_object, parse_hls_file};
+
+/// 下载地址的有效期
+const DOWNLOAD_URL_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// 媒体内容的交付方式（环境变量 `MEDIA_DELIVERY_MODE`）
+///
+/// - `redirect`: 重定向到存储后端的签名URL
+/// - `proxy`: 由服务器读取对象并转发，支持 Range 请求，适用于客户端无法直接访问存储的部署
+///
+/// 未配置时本地存储使用 `proxy`，其他后端使用 `redirect`
+static DELIVERY_MODE: Lazy<Option<String>> = Lazy::new(|| {
+    std::env::var("MEDIA_DELIVERY_MODE")
+        .ok()
+        .filter(|mode| !mode.is_empty())
+});
+
+fn use_proxy(storage: &SharedStorage) -> bool {
+    match DELIVERY_MODE.as_deref() {
+        Some(mode) => mode == "proxy",
+        None => storage.name() == "local",
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeliveryParams {
+    /// 为 `false` 时返回JSON格式的签名URL，默认直接重定向（代理模式下忽略）
+    pub redirect: Option<bool>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SignedUrlResponse {
+    pub url: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+async fn readable_media(
+    db: &Database,
+    media_id: &str,
+    user_id: &str,
+) -> Result<MediaItem, StatusCode> {
+    find_readable_media(db, media_id, user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Database error getting media for delivery: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// 要交付的存储对象：媒体原文件或其派生文件
+struct DeliveredObject<'a> {
+    /// 所属媒体的ID，用于日志
+    media_id: &'a str,
+    /// 对象所在的存储后端，切换后端后旧对象仍从原后端读取
+    storage_backend: &'a str,
+    key: &'a str,
+    content_type: &'a str,
+    filename: &'a str,
+}
+
+impl<'a> From<&'a MediaItem> for DeliveredObject<'a> {
+    fn from(media: &'a MediaItem) -> Self {
+        DeliveredObject {
+            media_id: &media.id,
+            storage_backend: &media.storage_backend,
+            key: &media.cos_key,
+            content_type: &media.content_type,
+            filename: &media.original_filename,
+        }
+    }
+}
+
+/// 取得对象所在的存储后端
+fn object_storage(
+    storage: &SharedStorage,
+    object: &DeliveredObject<'_>,
+) -> Result<SharedStorage, StatusCode> {
+    storage::for_backend(storage, object.storage_backend).map_err(|e| {
+        crate::log_with_storage!(
+            error,
+            "无法访问媒体对象的存储后端 {}: {}",
+            object.media_id,
+            e
+        );
+        StatusCode::BAD_GATEWAY
+    })
+}
+
+/// 按交付方式返回对象内容：签发短期有效的下载地址并重定向（或以JSON返回），或由服务器转发
+async fn deliver(
+    storage: &SharedStorage,
+    object: DeliveredObject<'_>,
+    disposition: &str,
+    params: &DeliveryParams,
+    headers: &HeaderMap,
+) -> Result<Response, StatusCode> {
+    let storage = &object_storage(storage, &object)?;
+    let disposition = content_disposition(disposition, object.filename);
+    if use_proxy(storage) {
+        return proxy_object(storage, &object, &disposition, headers).await;
+    }
+
+    let url = storage
+        .presign_download(object.key, DOWNLOAD_URL_TTL, Some(&disposition))
+        .await
+        .map_err(|e| {
+            crate::log_with_storage!(error, "签发下载地址失败 {}: {}", object.media_id, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    if params.redirect == Some(false) {
+        let expires_at = Utc::now()
+            + chrono::Duration::from_std(DOWNLOAD_URL_TTL).unwrap_or(chrono::Duration::minutes(5));
+        return Ok(Json(SignedUrlResponse { url, expires_at }).into_response());
+    }
+
+    // 签名地址很快过期，重定向本身不能被缓存
+    let mut response = Redirect::temporary(&url).into_response();
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    Ok(response)
+}
+
+/// 下载媒体文件
+///
+/// 所有者或被分享的用户可以访问，重定向到存储后端的短期签名URL（以附件形式下载），
+/// 代理模式下由服务器直接返回文件内容
+pub async fn download_media(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+    Query(params): Query<DeliveryParams>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let media = readable_media(&db, &media_id, &auth_user.user_id).await?;
+    deliver(&storage, (&media).into(), "attachment", &params, &headers).await
+}
+
+/// 在线播放/预览媒体文件
+///
+/// 与下载相同，但以内联方式返回内容，可直接用于 `<img>`、`<video>` 等标签
+pub async fn stream_media(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+    Query(params): Query<DeliveryParams>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let media = readable_media(&db, &media_id, &auth_user.user_id).await?;
+    deliver(&storage, (&media).into(), "inline", &params, &headers).await
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ThumbnailParams {
+    /// 需要的尺寸（最长边像素），默认 480，返回不小于该尺寸的最小缩略图
+    pub size: Option<u32>,
+    pub redirect: Option<bool>,
+}
+
+/// 默认的缩略图尺寸
+const DEFAULT_THUMBNAIL_SIZE: u32 = 480;
+
+/// 获取图片的缩略图
+///
+/// 访问权限与原文件相同。缩略图尚未生成或无法生成时返回 404，客户端应回退到原文件
+pub async fn get_media_thumbnail(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+    Query(params): Query<ThumbnailParams>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let media = readable_media(&db, &media_id, &auth_user.user_id).await?;
+    let size = params.size.unwrap_or(DEFAULT_THUMBNAIL_SIZE);
+
+    let thumbnail = find_thumbnail(&db, &media.id, size)
+        .await
+        .map_err(|e| {
+            eprintln!("Database error getting thumbnail: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let extension = thumbnail.object_key.rsplit('.').next().unwrap_or("jpg");
+    let filename = format!("thumbnail_{}.{}", thumbnail.size, extension);
+    let object = DeliveredObject {
+        media_id: &media.id,
+        storage_backend: &thumbnail.storage_backend,
+        key: &thumbnail.object_key,
+        content_type: &thumbnail.content_type,
+        filename: &filename,
+    };
+    let params = DeliveryParams {
+        redirect: params.redirect,
+    };
+    deliver(&storage, object, "inline", &params, &headers).await
+}
+
+/// 查找视频的拖动预览图或其轨道，不存在时返回 404
+async fn readable_sprite(
+    db: &Database,
+    media_id: &str,
+    user_id: &str,
+    kind: &str,
+) -> Result<(MediaItem, MediaDerivative), StatusCode> {
+    let media = readable_media(db, media_id, user_id).await?;
+    let sprite = find_sprite(db, &media.id, kind)
+        .await
+        .map_err(|e| {
+            eprintln!("Database error getting sprite: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok((media, sprite))
+}
+
+/// 获取视频拖动预览的 WebVTT 缩略图轨道
+///
+/// 轨道中的图片地址是相对路径（`sprite.jpg`），因此始终由服务器返回
+pub async fn get_media_sprite_track(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let (media, track) =
+        readable_sprite(&db, &media_id, &auth_user.user_id, SPRITE_TRACK_KIND).await?;
+    let object = DeliveredObject {
+        media_id: &media.id,
+        storage_backend: &track.storage_backend,
+        key: &track.object_key,
+        content_type: &track.content_type,
+        filename: "sprite.vtt",
+    };
+    let disposition = content_disposition("inline", object.filename);
+    proxy_object(&storage, &object, &disposition, &headers).await
+}
+
+/// 获取视频拖动预览的雪碧图，访问权限与原文件相同
+pub async fn get_media_sprite_image(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+    Query(params): Query<DeliveryParams>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let (media, sprite) = readable_sprite(&db, &media_id, &auth_user.user_id, SPRITE_KIND).await?;
+    let object = DeliveredObject {
+        media_id: &media.id,
+        storage_backend: &sprite.storage_backend,
+        key: &sprite.object_key,
+        content_type: &sprite.content_type,
+        filename: "sprite.jpg",
+    };
+    deliver(&storage, object, "inline", &params, &headers).await
+}
+
+/// 获取转码后的 HLS 播放列表或媒体文件（`master.m3u8`、`{短边}p.m3u8`、`{短边}p.ts`）
+///
+/// 访问权限与原文件相同。播放列表中的地址是相对路径，始终由服务器返回，使后续请求同样经过授权；
+/// 媒体文件按交付方式重定向或转发。转码尚未完成（`transcode_status` 不是 `ready`）时返回 404，
+/// 客户端应回退到原文件
+pub async fn get_media_hls(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path((media_id, file)): Path<(String, String)>,
+    Query(params): Query<DeliveryParams>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let (kind, size) = parse_hls_file(&file).ok_or(StatusCode::NOT_FOUND)?;
+    let media = readable_media(&db, &media_id, &auth_user.user_id).await?;
+    // 内容被替换后旧的转码结果不再对应当前内容，重新转码完成前不提供
+    if media.transcode_status.as_deref() != Some("ready") {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let derivative = find_hls_object(&db, &media.id, kind, size)
+        .await
+        .map_err(|e| {
+            eprintln!("Database error getting HLS object: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let object = DeliveredObject {
+        media_id: &media.id,
+        storage_backend: &derivative.storage_backend,
+        key: &derivative.object_key,
+        content_type: &derivative.content_type,
+        filename: &file,
+    };
+    if kind == HLS_MEDIA_KIND {
+        return deliver(&storage, object, "inline", &params, &headers).await;
+    }
+    let disposition = content_disposition("inline", &file);
+    proxy_object(&storage, &object, &disposition, &headers).await
+}
+
+/// Range 请求头的解析结果
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RangeRequest {
+    /// 没有（或忽略）Range 请求，返回完整内容
+    Full,
+    Partial(ByteRange),
+    Unsatisfiable,
+}
+
+/// 解析 `Range: bytes=...` 请求头
+///
+/// 只支持单个范围，多个范围时返回完整内容
+pub(crate) fn parse_range(value: &str, size: u64) -> RangeRequest {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let range = match (start.trim(), end.trim()) {
+        // bytes=-N: 最后N个字节
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return RangeRequest::Unsatisfiable,
+            Ok(n) if size > 0 => ByteRange {
+                start: size.saturating_sub(n),
+                end: size - 1,
+            },
+            Ok(_) => return RangeRequest::Unsatisfiable,
+            Err(_) => return RangeRequest::Full,
+        },
+        (start, end) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = if end.is_empty() {
+                size.saturating_sub(1)
+            } else {
+                match end.parse::<u64>() {
+                    Ok(end) => end.min(size.saturating_sub(1)),
+                    Err(_) => return RangeRequest::Full,
+                }
+            };
+            if start >= size || start > end {
+                return RangeRequest::Unsatisfiable;
+            }
+            ByteRange { start, end }
+        }
+    };
+
+    RangeRequest::Partial(range)
+}
+
+pub(crate) fn etag_matches(header_value: &str, etag: &str) -> bool {
+    let normalize = |value: &str| value.trim().trim_start_matches("W/").to_string();
+    header_value
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || normalize(candidate) == normalize(etag))
+}
+
+/// 由服务器读取对象并返回，支持 `Range`、`If-Range` 和 `If-None-Match`
+async fn proxy_object(
+    storage: &SharedStorage,
+    object: &DeliveredObject<'_>,
+    disposition: &str,
+    request_headers: &HeaderMap,
+) -> Result<Response, StatusCode> {
+    let storage_status = |e: StorageError| match e {
+        StorageError::NotFound(_) => StatusCode::NOT_FOUND,
+        e => {
+            crate::log_with_storage!(error, "读取媒体对象失败 {}: {}", object.media_id, e);
+            StatusCode::BAD_GATEWAY
+        }
+    };
+
+    let storage = object_storage(storage, object)?;
+    let meta = storage.head(object.key).await.map_err(storage_status)?;
+    let size = meta.size;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("private, no-cache"),
+    );
+    if let Ok(value) = HeaderValue::from_str(object.content_type) {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(disposition) {
+        headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+    let etag = meta.etag.as_deref();
+    if let Some(value) = etag.and_then(|etag| HeaderValue::from_str(etag).ok()) {
+        headers.insert(header::ETAG, value);
+    }
+
+    let header_str = |name: header::HeaderName| {
+        request_headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+
+    if let (Some(if_none_match), Some(etag)) = (header_str(header::IF_NONE_MATCH), etag)
+        && etag_matches(if_none_match, etag)
+    {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    // If-Range 与当前ETag不一致时忽略 Range，返回完整内容
+    let range_allowed = match header_str(header::IF_RANGE) {
+        Some(if_range) => etag.is_some_and(|etag| etag_matches(if_range, etag)),
+        None => true,
+    };
+    let range = match header_str(header::RANGE) {
+        Some(value) if range_allowed => parse_range(value, size),
+        _ => RangeRequest::Full,
+    };
+
+    let (status, range) = match range {
+        RangeRequest::Full => (StatusCode::OK, None),
+        RangeRequest::Partial(range) => {
+            if let Ok(value) =
+                HeaderValue::from_str(&format!("bytes {}-{}/{}", range.start, range.end, size))
+            {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+            (StatusCode::PARTIAL_CONTENT, Some(range))
+        }
+        RangeRequest::Unsatisfiable => {
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", size)) {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+        }
+    };
+
+    let length = range.map(|range| range.len()).unwrap_or(size);
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+
+    let stream = storage
+        .get_stream(object.key, range)
+        .await
+        .map_err(storage_status)?;
+
+    Ok((status, headers, Body::from_stream(stream)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partial(start: u64, end: u64) -> RangeRequest {
+        RangeRequest::Partial(ByteRange { start, end })
+    }
+
+    #[test]
+    fn parses_closed_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), partial(0, 99));
+        assert_eq!(parse_range(" bytes= 10 - 19 ", 1000), partial(10, 19));
+        // 结束位置超出对象末尾时截断
+        assert_eq!(parse_range("bytes=900-2000", 1000), partial(900, 999));
+    }
+
+    #[test]
+    fn parses_open_ended_range() {
+        assert_eq!(parse_range("bytes=100-", 1000), partial(100, 999));
+        assert_eq!(parse_range("bytes=999-", 1000), partial(999, 999));
+    }
+
+    #[test]
+    fn parses_suffix_range() {
+        assert_eq!(parse_range("bytes=-100", 1000), partial(900, 999));
+        // 后缀长度超过对象大小时返回整个对象
+        assert_eq!(parse_range("bytes=-5000", 1000), partial(0, 999));
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-10", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn ignores_multiple_ranges() {
+        assert_eq!(parse_range("bytes=0-9,20-29", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=-10, 0-5", 1000), RangeRequest::Full);
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_range() {
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            parse_range("bytes=1000-1999", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(
+            parse_range("bytes=50-10", 1000),
+            RangeRequest::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_range() {
+        assert_eq!(parse_range("items=0-9", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=abc-9", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=0-abc", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=-abc", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=100", 1000), RangeRequest::Full);
+    }
+}
@@ -0,0 +1,46 @@
+use axum::{
+    Json,
+    extract::{Extension, Path, State},
+    http::StatusCode,
+};
+use serde::Serialize;
+
+use crate::credentials::AuthUser;
+use crate::database::Database;
+use crate::handlers::media_handlers::find_readable_media;
+use crate::jobs::{MediaJob, list_media_jobs};
+
+#[derive(Serialize, Debug)]
+pub struct MediaJobsResponse {
+    pub media_id: String,
+    /// 媒体状态，有未完成的任务时为 `processing`
+    pub status: String,
+    pub jobs: Vec<MediaJob>,
+}
+
+fn internal_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Database error in media jobs: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// 查看媒体的处理任务（元数据提取、缩略图等）及其状态
+pub async fn get_media_jobs(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+) -> Result<Json<MediaJobsResponse>, StatusCode> {
+    let media = find_readable_media(&db, &media_id, &auth_user.user_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let jobs = list_media_jobs(&db, &media.id)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(MediaJobsResponse {
+        media_id: media.id,
+        status: media.status,
+        jobs,
+    }))
+}
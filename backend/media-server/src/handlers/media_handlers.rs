@@ -1,6 +1,16 @@
 use crate::credentials::AuthUser;
 use crate::database::Database;
-use crate::handlers::cos_handlers;
+use crate::dedupe::{
+    DedupeError, Registration, find_duplicate, hash_object, is_valid_hash, register_media,
+};
+use crate::handlers::cos_handlers::is_user_object_key;
+use crate::handlers::upload_handlers::{media_type_from_content_type, verify_content};
+use crate::jobs::schedule_media_processing;
+use crate::metadata::strip_server_keys;
+use crate::previews::{MediaPreviews, media_previews};
+use crate::quota::{QuotaError, check_quota};
+use crate::storage::{ObjectMeta, SharedStorage, StorageError};
+use crate::upload_policy::policy_for_user;
 use axum::{
     Json as AxumJson,
     extract::{Extension, Path, Query, State},
@@ -9,6 +19,8 @@ use axum::{
 };
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::PgExecutor;
+use sqlx::postgres::PgQueryResult;
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
@@ -25,13 +37,33 @@ pub struct MediaItem {
     pub cos_url: String,
     pub cos_bucket: String,
     pub cos_region: String,
+    pub storage_backend: String,
     pub media_type: String,
     pub status: String,
     pub metadata: Option<serde_json::Value>,
+    /// 文件内容的 SHA-256 摘要（十六进制）
+    pub content_hash: Option<String>,
+    /// 视频转码状态：`pending`、`running`、`ready` 或 `failed`，不需要转码时为空
+    pub transcode_status: Option<String>,
+    /// 转码进度（0 到 1）
+    pub transcode_progress: Option<f32>,
+    pub transcode_error: Option<String>,
+    /// 图片的 BlurHash，缩略图加载前用于显示模糊占位图
+    pub blurhash: Option<String>,
+    /// 图片的主色调（`#rrggbb`）
+    pub dominant_color: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// 媒体详情：媒体记录及其缩略图、拖动预览和转码播放地址
+#[derive(Serialize, Debug)]
+pub struct MediaDetail {
+    #[serde(flatten)]
+    pub media: MediaItem,
+    pub previews: MediaPreviews,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct CreateMediaRequest {
     pub title: String,
@@ -41,11 +73,16 @@ pub struct CreateMediaRequest {
     pub file_size: i64,
     pub content_type: String,
     pub cos_key: String,
-    pub cos_url: String,
-    pub cos_bucket: String,
-    pub cos_region: String,
+    // 以下字段以存储后端的配置为准，客户端声明的值仅用于核对
+    pub cos_url: Option<String>,
+    pub cos_bucket: Option<String>,
+    pub cos_region: Option<String>,
     pub media_type: String,
     pub metadata: Option<serde_json::Value>,
+    /// 上传完成时存储后端返回的ETag，提供时会与对象实际的ETag比对
+    pub etag: Option<String>,
+    /// 客户端计算的 SHA-256 摘要，提供时由服务器校验，并用于识别重复上传
+    pub content_hash: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -68,6 +105,12 @@ pub struct MediaQueryParams {
     pub per_page: Option<i32>,
     pub media_type: Option<String>,
     pub q: Option<String>,
+    /// 排序方式：`created_at`（默认）或 `taken_at`（拍摄时间，没有拍摄时间的排在最后）
+    pub sort: Option<String>,
+    /// 按相机品牌或型号筛选
+    pub camera: Option<String>,
+    /// 只返回带有（或不带）GPS位置的媒体
+    pub has_location: Option<bool>,
 }
 
 /// 获取用户的媒体项目
@@ -77,11 +120,12 @@ pub async fn get_media(
     Query(params): Query<MediaQueryParams>,
 ) -> Result<Json<MediaListResponse>, StatusCode> {
     let page = params.page.unwrap_or(1).max(1);
-    let per_page = params.per_page.unwrap_or(20).min(100).max(1);
+    let per_page = params.per_page.unwrap_or(20).clamp(1, 100);
     let offset = (page - 1) * per_page;
 
     let mut query =
-        "SELECT * FROM media_files WHERE user_id = $1 AND status = 'active'".to_string();
+        "SELECT * FROM media_files WHERE user_id = $1 AND status IN ('active', 'processing')"
+            .to_string();
     let mut query_params: Vec<String> = vec![auth_user.user_id.clone()];
     let mut param_count = 1;
 
@@ -103,12 +147,37 @@ pub async fn get_media(
         query_params.push(search_param);
     }
 
-    query.push_str(" ORDER BY created_at DESC");
+    // 添加相机筛选
+    if let Some(camera) = &params.camera {
+        param_count += 1;
+        query.push_str(&format!(
+            " AND (metadata->'image'->>'make' ILIKE ${} OR metadata->'image'->>'model' ILIKE ${})",
+            param_count, param_count
+        ));
+        query_params.push(format!("%{}%", camera));
+    }
+
+    // 添加位置筛选
+    match params.has_location {
+        Some(true) => query.push_str(" AND COALESCE(metadata->'image' ? 'gps', false)"),
+        Some(false) => query.push_str(" AND NOT COALESCE(metadata->'image' ? 'gps', false)"),
+        None => {}
+    }
 
     // 获取总数 - 构建相同的查询条件
-    let count_query = query
-        .replace("SELECT *", "SELECT COUNT(*)")
-        .replace(" ORDER BY created_at DESC", "");
+    let count_query = query.replace("SELECT *", "SELECT COUNT(*)");
+
+    let order_by = match params.sort.as_deref() {
+        None | Some("created_at") => " ORDER BY created_at DESC",
+        Some("taken_at") => {
+            " ORDER BY metadata->'image'->>'taken_at' DESC NULLS LAST, created_at DESC"
+        }
+        Some(other) => {
+            eprintln!("Unsupported media sort: {}", other);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+    query.push_str(order_by);
 
     // 为总数查询绑定所有参数
     let mut count_query_builder = sqlx::query_scalar(&count_query);
@@ -152,6 +221,7 @@ pub async fn get_media(
 /// 创建新的媒体项目
 pub async fn create_media(
     State(db): State<Database>,
+    State(storage): State<SharedStorage>,
     Extension(auth_user): Extension<AuthUser>,
     AxumJson(payload): AxumJson<CreateMediaRequest>,
 ) -> Result<Json<MediaItem>, StatusCode> {
@@ -161,6 +231,119 @@ pub async fn create_media(
     );
     println!("📋 媒体数据: {:?}", payload);
 
+    let object = verify_uploaded_object(&db, &storage, &auth_user.user_id, &payload).await?;
+    let content_hash = verify_content_hash(&storage, &payload).await?;
+
+    // 重复上传会返回已有媒体，不占用新的配额
+    let is_duplicate = match content_hash.as_deref() {
+        Some(hash) => find_duplicate(&db, &auth_user.user_id, hash)
+            .await
+            .map_err(|e| {
+                eprintln!("❌ 数据库错误 - 查询重复媒体失败: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .is_some(),
+        None => false,
+    };
+    if !is_duplicate {
+        check_quota(&db, &auth_user.user_id, object.size as i64, 1)
+            .await
+            .map_err(|e| match e {
+                QuotaError::Exceeded(message) => {
+                    eprintln!("❌ 超出存储配额 - 用户: {}, {}", auth_user.user_id, message);
+                    StatusCode::FORBIDDEN
+                }
+                QuotaError::Database(e) => {
+                    eprintln!("❌ 数据库错误 - 查询存储用量失败: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            })?;
+    }
+
+    // 以存储后端的实际信息为准
+    let declared_content_type = object
+        .content_type
+        .clone()
+        .unwrap_or_else(|| payload.content_type.clone());
+    if declared_content_type != payload.content_type {
+        println!(
+            "⚠️ 内容类型与对象不一致，使用对象的类型: {} -> {}",
+            payload.content_type, declared_content_type
+        );
+    }
+    let policy = policy_for_user(&db, &auth_user.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("❌ 数据库错误 - 查询用户角色失败: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if let Err(violation) = policy.check(
+        Some(&payload.original_filename),
+        Some(object.size),
+        &declared_content_type,
+    ) {
+        eprintln!("❌ 对象不符合上传策略 {}: {}", payload.cos_key, violation);
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    // 声明的类型不可信，按文件头识别出的真实格式修正
+    let content_check = verify_content(
+        &storage,
+        policy,
+        &payload.cos_key,
+        object.size,
+        &declared_content_type,
+    )
+    .await
+    .map_err(|(status, message)| {
+        eprintln!("❌ 文件内容检查未通过 {}: {}", payload.cos_key, message);
+        if status == StatusCode::INTERNAL_SERVER_ERROR {
+            status
+        } else {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+    })?;
+    let content_type = content_check.content_type().to_string();
+    let media_type = media_type_from_content_type(&content_type);
+    if payload.media_type != media_type {
+        println!(
+            "⚠️ 媒体类型与文件内容不一致，使用: {} -> {}",
+            payload.media_type, media_type
+        );
+    }
+
+    // 服务器维护的字段（图片信息、内容检查等）不接受客户端提交的值
+    let mut metadata = payload.metadata.unwrap_or_else(|| serde_json::json!({}));
+    strip_server_keys(&mut metadata);
+    if let Some(map) = metadata.as_object_mut() {
+        if let Some(etag) = object.etag.as_ref() {
+            map.insert("etag".to_string(), serde_json::json!(etag));
+        }
+        map.insert(
+            "content_check".to_string(),
+            serde_json::json!(content_check),
+        );
+    }
+
+    // 对象地址始终由存储后端生成，忽略客户端声明的值
+    let cos_url = storage.object_url(&payload.cos_key);
+    let (cos_bucket, cos_region) = (storage.bucket(), storage.region());
+    if payload.cos_url.as_ref().is_some_and(|url| *url != cos_url)
+        || payload
+            .cos_bucket
+            .as_ref()
+            .is_some_and(|b| *b != cos_bucket)
+        || payload
+            .cos_region
+            .as_ref()
+            .is_some_and(|r| *r != cos_region)
+    {
+        println!(
+            "⚠️ 客户端声明的对象地址与存储后端不一致，已使用: {}",
+            cos_url
+        );
+    }
+
     let media_id = Uuid::new_v4().to_string();
     let now = Utc::now();
 
@@ -171,65 +354,211 @@ pub async fn create_media(
         description: payload.description,
         filename: payload.filename,
         original_filename: payload.original_filename,
-        file_size: payload.file_size,
-        content_type: payload.content_type,
+        file_size: object.size as i64,
+        media_type: media_type.to_string(),
+        content_type,
+        cos_url,
         cos_key: payload.cos_key,
-        cos_url: payload.cos_url,
-        cos_bucket: payload.cos_bucket,
-        cos_region: payload.cos_region,
-        media_type: payload.media_type,
+        cos_bucket,
+        cos_region,
+        storage_backend: storage.name().to_string(),
         status: "active".to_string(),
-        metadata: payload.metadata,
+        metadata: Some(metadata),
+        content_hash,
+        transcode_status: None,
+        transcode_progress: None,
+        transcode_error: None,
+        blurhash: None,
+        dominant_color: None,
         created_at: now,
         updated_at: now,
     };
 
     println!("💾 准备插入数据库 - 媒体ID: {}", media_id);
 
+    match register_media(&db, &storage, media_item).await {
+        Ok(Registration::Created(mut media)) => {
+            println!("✅ 媒体记录创建成功 - ID: {}", media.id);
+            schedule_media_processing(&db, &mut media).await;
+            Ok(Json(media))
+        }
+        Ok(Registration::Duplicate(media)) => {
+            println!("♻️ 内容与已有媒体相同，返回已有媒体 - ID: {}", media.id);
+            Ok(Json(media))
+        }
+        Err(e) if e.is_object_key_conflict() => {
+            eprintln!("❌ 对象已被其他媒体记录引用: {}", media_id);
+            Err(StatusCode::CONFLICT)
+        }
+        Err(DedupeError::QuotaExceeded(message)) => {
+            eprintln!("❌ 超出存储配额 - 用户: {}, {}", auth_user.user_id, message);
+            Err(StatusCode::FORBIDDEN)
+        }
+        Err(e) => {
+            eprintln!("❌ 数据库错误 - 创建媒体失败: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// 校验客户端提供的内容摘要：读取对象计算摘要并与声明的值比对
+///
+/// 未提供摘要时不计算，媒体不参与去重
+async fn verify_content_hash(
+    storage: &SharedStorage,
+    payload: &CreateMediaRequest,
+) -> Result<Option<String>, StatusCode> {
+    let Some(claimed) = payload.content_hash.as_deref() else {
+        return Ok(None);
+    };
+    if !is_valid_hash(claimed) {
+        eprintln!("❌ 无效的内容摘要: {}", claimed);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let actual = hash_object(storage, &payload.cos_key).await.map_err(|e| {
+        eprintln!("❌ 计算对象摘要失败 {}: {}", payload.cos_key, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+    if !actual.eq_ignore_ascii_case(claimed) {
+        eprintln!(
+            "❌ 内容摘要不一致 {}: 声明 {}, 实际 {}",
+            payload.cos_key, claimed, actual
+        );
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    Ok(Some(actual))
+}
+
+/// 按内容摘要查找用户已有的媒体，客户端可以在上传前调用以跳过重复文件
+pub async fn get_media_by_hash(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(content_hash): Path<String>,
+) -> Result<Json<MediaItem>, StatusCode> {
+    if !is_valid_hash(&content_hash) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match find_duplicate(&db, &auth_user.user_id, &content_hash.to_ascii_lowercase()).await {
+        Ok(Some(media)) => Ok(Json(media)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            eprintln!("Database error getting media by hash: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// 去掉ETag的弱校验前缀和引号，便于比较
+fn normalize_etag(etag: &str) -> &str {
+    etag.trim().trim_start_matches("W/").trim_matches('"')
+}
+
+/// 通过 HEAD 请求确认客户端声明的对象确实存在且与声明一致
+///
+/// 大小和ETag不一致时拒绝；对象已被其他媒体记录引用时返回 409。这里的检查只用于尽早拒绝，
+/// 并发登记同一对象由唯一索引保证，插入时违反约束同样返回 409
+async fn verify_uploaded_object(
+    db: &Database,
+    storage: &SharedStorage,
+    user_id: &str,
+    payload: &CreateMediaRequest,
+) -> Result<ObjectMeta, StatusCode> {
+    // 只能登记自己上传前缀下的对象
+    if !is_user_object_key(user_id, &payload.cos_key) {
+        eprintln!("❌ 非法的对象键: {}", payload.cos_key);
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let registered: bool =
+        sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM media_files WHERE cos_key = $1)")
+            .bind(&payload.cos_key)
+            .fetch_one(&db.pool)
+            .await
+            .map_err(|e| {
+                eprintln!("❌ 数据库错误 - 检查对象键失败: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    if registered {
+        eprintln!("❌ 对象已被其他媒体记录引用: {}", payload.cos_key);
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let object = storage.head(&payload.cos_key).await.map_err(|e| match e {
+        StorageError::NotFound(_) => {
+            eprintln!("❌ 对象不存在: {}", payload.cos_key);
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        StorageError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+        e => {
+            eprintln!("❌ 查询对象信息失败 {}: {}", payload.cos_key, e);
+            StatusCode::BAD_GATEWAY
+        }
+    })?;
+
+    if payload.file_size < 0 || object.size != payload.file_size as u64 {
+        eprintln!(
+            "❌ 文件大小不一致 {}: 声明 {} 字节, 实际 {} 字节",
+            payload.cos_key, payload.file_size, object.size
+        );
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    if let (Some(claimed), Some(actual)) = (payload.etag.as_deref(), object.etag.as_deref())
+        && normalize_etag(claimed) != normalize_etag(actual)
+    {
+        eprintln!(
+            "❌ ETag不一致 {}: 声明 {}, 实际 {}",
+            payload.cos_key, claimed, actual
+        );
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    Ok(object)
+}
+
+/// 插入一条媒体记录
+pub async fn insert_media_item<'e, E>(
+    executor: E,
+    media_item: &MediaItem,
+) -> Result<PgQueryResult, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
     let query = r#"
         INSERT INTO media_files (
             id, user_id, title, description, filename, original_filename,
             file_size, content_type, cos_key, cos_url, cos_bucket, cos_region,
-            media_type, status, metadata, created_at, updated_at
+            storage_backend, media_type, status, metadata, content_hash, created_at, updated_at
         ) VALUES (
-            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19
         )
     "#;
 
-    match sqlx::query(query)
+    sqlx::query(query)
         .bind(&media_item.id)
         .bind(&media_item.user_id)
         .bind(&media_item.title)
         .bind(&media_item.description)
         .bind(&media_item.filename)
         .bind(&media_item.original_filename)
-        .bind(&media_item.file_size)
+        .bind(media_item.file_size)
         .bind(&media_item.content_type)
         .bind(&media_item.cos_key)
         .bind(&media_item.cos_url)
         .bind(&media_item.cos_bucket)
         .bind(&media_item.cos_region)
+        .bind(&media_item.storage_backend)
         .bind(&media_item.media_type)
         .bind(&media_item.status)
         .bind(&media_item.metadata)
-        .bind(&media_item.created_at)
-        .bind(&media_item.updated_at)
-        .execute(&db.pool)
+        .bind(&media_item.content_hash)
+        .bind(media_item.created_at)
+        .bind(media_item.updated_at)
+        .execute(executor)
         .await
-    {
-        Ok(result) => {
-            println!(
-                "✅ 媒体记录创建成功 - ID: {}, 影响行数: {}",
-                media_id,
-                result.rows_affected()
-            );
-            Ok(Json(media_item))
-        }
-        Err(e) => {
-            eprintln!("❌ 数据库错误 - 创建媒体失败: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
 }
 
 /// 搜索媒体项目
@@ -247,22 +576,41 @@ pub async fn get_media_by_id(
     State(db): State<Database>,
     Extension(auth_user): Extension<AuthUser>,
     Path(media_id): Path<String>,
-) -> Result<Json<MediaItem>, StatusCode> {
-    let query = "SELECT * FROM media_files WHERE id = $1 AND user_id = $2 AND status = 'active'";
+) -> Result<Json<MediaDetail>, StatusCode> {
+    let internal_error = |e: sqlx::Error| {
+        eprintln!("Database error getting media by id: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
 
-    match sqlx::query_as::<_, MediaItem>(query)
-        .bind(&media_id)
-        .bind(&auth_user.user_id)
-        .fetch_one(&db.pool)
+    let media = find_readable_media(&db, &media_id, &auth_user.user_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let previews = media_previews(&db, &media).await.map_err(internal_error)?;
+
+    Ok(Json(MediaDetail { media, previews }))
+}
+
+/// 查找用户可以读取的媒体：自己的媒体或被分享给该用户的媒体
+pub async fn find_readable_media(
+    db: &Database,
+    media_id: &str,
+    user_id: &str,
+) -> Result<Option<MediaItem>, sqlx::Error> {
+    let query = r#"
+        SELECT * FROM media_files m
+        WHERE m.id = $1 AND m.status IN ('active', 'processing')
+        AND (
+            m.user_id = $2
+            OR EXISTS (SELECT 1 FROM media_shares s WHERE s.media_id = m.id AND s.user_id = $2)
+        )
+    "#;
+
+    sqlx::query_as::<_, MediaItem>(query)
+        .bind(media_id)
+        .bind(user_id)
+        .fetch_optional(&db.pool)
         .await
-    {
-        Ok(media) => Ok(Json(media)),
-        Err(sqlx::Error::RowNotFound) => Err(StatusCode::NOT_FOUND),
-        Err(e) => {
-            eprintln!("Database error getting media by id: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
 }
 
 /// 更新媒体项目
@@ -279,14 +627,14 @@ pub async fn update_media(
         SET title = COALESCE($1, title),
             description = COALESCE($2, description),
             updated_at = $3
-        WHERE id = $4 AND user_id = $5 AND status = 'active'
+        WHERE id = $4 AND user_id = $5 AND status IN ('active', 'processing')
         RETURNING *
     "#;
 
     match sqlx::query_as::<_, MediaItem>(query)
         .bind(&payload.title)
         .bind(&payload.description)
-        .bind(&now)
+        .bind(now)
         .bind(&media_id)
         .bind(&auth_user.user_id)
         .fetch_one(&db.pool)
@@ -302,49 +650,23 @@ pub async fn update_media(
 }
 
 /// 删除媒体项目
+///
+/// 媒体移入回收站，可以在保留期内恢复，超过保留期后由后台任务彻底删除
 pub async fn delete_media(
     State(db): State<Database>,
     Extension(auth_user): Extension<AuthUser>,
     Path(media_id): Path<String>,
 ) -> Result<StatusCode, StatusCode> {
-    // 首先获取媒体项目信息，用于删除COS文件
-    let get_query =
-        "SELECT * FROM media_files WHERE id = $1 AND user_id = $2 AND status = 'active'";
-
-    let media_item = match sqlx::query_as::<_, MediaItem>(get_query)
-        .bind(&media_id)
-        .bind(&auth_user.user_id)
-        .fetch_one(&db.pool)
-        .await
-    {
-        Ok(item) => item,
-        Err(sqlx::Error::RowNotFound) => return Err(StatusCode::NOT_FOUND),
-        Err(e) => {
-            eprintln!("Database error getting media for deletion: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
-
-    // 从腾讯云COS删除文件
-    if let Err(e) = cos_handlers::delete_cos_file(
-        &media_item.cos_key,
-        &media_item.cos_bucket,
-        &media_item.cos_region,
-    )
-    .await
-    {
-        eprintln!("Failed to delete file from COS: {}", e);
-        // 注意：即使COS删除失败，我们仍然继续删除数据库记录
-        // 这样可以避免数据库中留下无效的记录
-        crate::log_with_storage!(warn, "COS文件删除失败，但继续删除数据库记录: {}", e);
-    } else {
-        crate::log_with_storage!(info, "成功从COS删除文件: {}", media_item.cos_key);
-    }
+    let now = Utc::now();
 
-    // 从数据库硬删除记录
-    let delete_query = "DELETE FROM media_files WHERE id = $1 AND user_id = $2";
+    let query = r#"
+        UPDATE media_files
+        SET status = 'deleted', deleted_at = $1, updated_at = $1
+        WHERE id = $2 AND user_id = $3 AND status IN ('active', 'processing')
+    "#;
 
-    match sqlx::query(delete_query)
+    match sqlx::query(query)
+        .bind(now)
         .bind(&media_id)
         .bind(&auth_user.user_id)
         .execute(&db.pool)
@@ -352,7 +674,7 @@ pub async fn delete_media(
     {
         Ok(result) => {
             if result.rows_affected() > 0 {
-                crate::log_with_storage!(info, "成功删除媒体项目: {}", media_id);
+                crate::log_with_storage!(info, "媒体项目已移入回收站: {}", media_id);
                 Ok(StatusCode::NO_CONTENT)
             } else {
                 Err(StatusCode::NOT_FOUND)
@@ -364,66 +686,3 @@ pub async fn delete_media(
         }
     }
 }
-
-#[derive(Deserialize, Debug)]
-pub struct UploadMediaRequest {
-    pub filename: String,
-    pub original_filename: String,
-    pub file_size: i64,
-    pub content_type: String,
-    pub cos_key: String,
-    pub cos_url: String,
-    pub cos_bucket: String,
-    pub cos_region: String,
-    pub media_type: String,
-}
-
-/// 上传媒体文件后更新记录
-pub async fn upload_media_file(
-    State(db): State<Database>,
-    Extension(auth_user): Extension<AuthUser>,
-    Path(media_id): Path<String>,
-    AxumJson(payload): AxumJson<UploadMediaRequest>,
-) -> Result<Json<MediaItem>, StatusCode> {
-    let now = Utc::now();
-
-    let query = r#"
-        UPDATE media_files 
-        SET filename = $1,
-            original_filename = $2,
-            file_size = $3,
-            content_type = $4,
-            cos_key = $5,
-            cos_url = $6,
-            cos_bucket = $7,
-            cos_region = $8,
-            media_type = $9,
-            updated_at = $10
-        WHERE id = $11 AND user_id = $12 AND status = 'active'
-        RETURNING *
-    "#;
-
-    match sqlx::query_as::<_, MediaItem>(query)
-        .bind(&payload.filename)
-        .bind(&payload.original_filename)
-        .bind(&payload.file_size)
-        .bind(&payload.content_type)
-        .bind(&payload.cos_key)
-        .bind(&payload.cos_url)
-        .bind(&payload.cos_bucket)
-        .bind(&payload.cos_region)
-        .bind(&payload.media_type)
-        .bind(&now)
-        .bind(&media_id)
-        .bind(&auth_user.user_id)
-        .fetch_one(&db.pool)
-        .await
-    {
-        Ok(media) => Ok(Json(media)),
-        Err(sqlx::Error::RowNotFound) => Err(StatusCode::NOT_FOUND),
-        Err(e) => {
-            eprintln!("Database error uploading media file: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
-}
@@ -1,18 +1,51 @@
 //! 处理函数模块
 //!
 //! 本模块将原来的单一 handlers.rs 文件按功能拆分为多个子模块：
+//! - admin_handlers: 管理员维护接口（孤儿对象回收、删除队列、任务队列、用量与配额等）
 //! - auth_handlers: 用户认证相关处理函数
 //! - media_handlers: 媒体项目相关处理函数  
 //! - system_handlers: 系统相关处理函数（健康检查、日志、监控等）
 //! - cos_handlers: 腾讯云COS相关处理函数（STS临时凭证、文件上传等）
+//! - storage_handlers: 本地存储后端的签名URL读写
+//! - upload_handlers: 经由服务器中转的流式上传
+//! - resumable_handlers: 断点续传（tus风格的分块上传）
+//! - download_handlers: 媒体下载、在线播放（短期签名URL）、缩略图、视频拖动预览与 HLS 转码播放
+//! - job_handlers: 媒体处理任务状态
+//! - share_handlers: 媒体分享
+//! - similarity_handlers: 相似媒体与可能重复的媒体（感知哈希）
+//! - trash_handlers: 回收站（恢复与彻底删除）
+//! - usage_handlers: 存储用量查询
+//! - waveform_handlers: 音频波形数据
 
 // 重新导出所有处理函数，保持向后兼容性
+pub mod admin_handlers;
 pub mod auth_handlers;
 pub mod cos_handlers;
+pub mod download_handlers;
+pub mod job_handlers;
 pub mod media_handlers;
+pub mod resumable_handlers;
+pub mod share_handlers;
+pub mod similarity_handlers;
+pub mod storage_handlers;
 pub mod system_handlers;
+pub mod trash_handlers;
+pub mod upload_handlers;
+pub mod usage_handlers;
+pub mod waveform_handlers;
 
+pub use admin_handlers::*;
 pub use auth_handlers::*;
 pub use cos_handlers::*;
+pub use download_handlers::*;
+pub use job_handlers::*;
 pub use media_handlers::*;
+pub use resumable_handlers::*;
+pub use share_handlers::*;
+pub use similarity_handlers::*;
+pub use storage_handlers::*;
 pub use system_handlers::*;
+pub use trash_handlers::*;
+pub use upload_handlers::*;
+pub use usage_handlers::*;
+pub use waveform_handlers::*;
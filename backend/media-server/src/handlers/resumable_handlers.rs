@@ -0,0 +1,757 @@
+use axum::{
+    Json,
+    extract::{Extension, Path, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, Utc};
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+
+use crate::credentials::AuthUser;
+use crate::database::Database;
+use crate::dedupe::{ContentHasher, acquire_blob, discard_unused, find_duplicate, prepare_blob};
+use crate::deletion_queue::delete_or_enqueue;
+use crate::handlers::cos_handlers::generate_object_key;
+use crate::handlers::media_handlers::{MediaItem, insert_media_item};
+use crate::handlers::upload_handlers::{
+    ReceivedUpload, build_media_item, limit_stream, policy_error, quota_error, user_policy,
+    verify_content,
+};
+use crate::jobs::schedule_media_processing;
+use crate::quota::{QuotaError, check_quota, check_quota_locked};
+use crate::storage::{ByteStream, SharedStorage};
+
+/// 单个分块的最大大小（32MB）
+pub const MAX_CHUNK_SIZE: u64 = 32 * 1024 * 1024;
+
+/// 上传会话在最后一次活动后的保留时间
+const UPLOAD_SESSION_TTL_HOURS: i64 = 24;
+
+/// 合并超过该时间（分钟）仍未结束的会话视为已中断（如合并期间服务重启），允许重新合并
+const FINALIZE_TIMEOUT_MINUTES: i64 = 60;
+
+/// 已结束的会话记录保留天数
+const FINISHED_SESSION_RETENTION_DAYS: i64 = 7;
+
+/// 兼容的 tus 协议版本
+const TUS_VERSION: &str = "1.0.0";
+
+type UploadError = (StatusCode, String);
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct UploadSession {
+    pub id: String,
+    pub user_id: String,
+    pub media_id: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub original_filename: String,
+    pub content_type: String,
+    pub cos_key: String,
+    pub total_size: i64,
+    pub upload_offset: i64,
+    pub status: String,
+    /// 最近一次合并失败的原因
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct UploadPart {
+    part_offset: i64,
+    part_size: i64,
+    part_key: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateUploadSessionRequest {
+    pub filename: String,
+    pub content_type: String,
+    pub file_size: u64,
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct UploadChunkResponse {
+    pub upload_offset: i64,
+    pub total_size: i64,
+    pub status: String,
+    pub completed: bool,
+    /// 上传完成后创建的媒体记录
+    pub media: Option<MediaItem>,
+}
+
+fn internal_error(e: sqlx::Error) -> UploadError {
+    eprintln!("Database error in resumable upload: {}", e);
+    (StatusCode::INTERNAL_SERVER_ERROR, "数据库错误".to_string())
+}
+
+/// tus 协议的进度响应头
+fn progress_headers(session: &UploadSession) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("Tus-Resumable", HeaderValue::from_static(TUS_VERSION));
+    headers.insert("Upload-Offset", HeaderValue::from(session.upload_offset));
+    headers.insert("Upload-Length", HeaderValue::from(session.total_size));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    if let Ok(value) = HeaderValue::from_str(&session.expires_at.to_rfc2822()) {
+        headers.insert("Upload-Expires", value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&session.status) {
+        headers.insert("Upload-Status", value);
+    }
+    if session.status == "completed"
+        && let Ok(value) = HeaderValue::from_str(&session.media_id)
+    {
+        headers.insert("Upload-Media-Id", value);
+    }
+    headers
+}
+
+async fn find_session(
+    db: &Database,
+    session_id: &str,
+    user_id: &str,
+) -> Result<UploadSession, UploadError> {
+    sqlx::query_as::<_, UploadSession>(
+        "SELECT * FROM upload_sessions WHERE id = $1 AND user_id = $2",
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .fetch_optional(&db.pool)
+    .await
+    .map_err(internal_error)?
+    .ok_or((StatusCode::NOT_FOUND, "上传会话不存在".to_string()))
+}
+
+/// 合并是否已中断
+fn is_stale_finalizing(session: &UploadSession) -> bool {
+    session.status == "finalizing"
+        && session.updated_at < Utc::now() - Duration::minutes(FINALIZE_TIMEOUT_MINUTES)
+}
+
+/// 检查会话是否仍可继续上传，合并已中断的会话可以重新合并
+fn ensure_uploading(session: &UploadSession) -> Result<(), UploadError> {
+    if is_stale_finalizing(session) {
+        return Ok(());
+    }
+    match session.status.as_str() {
+        "uploading" if session.expires_at > Utc::now() => Ok(()),
+        "uploading" | "expired" | "aborted" => {
+            Err((StatusCode::GONE, "上传会话已过期或已取消".to_string()))
+        }
+        "completed" => Err((StatusCode::CONFLICT, "上传已完成".to_string())),
+        _ => Err((StatusCode::CONFLICT, "上传正在处理中".to_string())),
+    }
+}
+
+/// 删除会话的全部临时分块（对象和记录）
+async fn remove_parts(db: &Database, storage: &SharedStorage, session_id: &str) {
+    let parts = match sqlx::query_as::<_, UploadPart>(
+        "SELECT part_offset, part_size, part_key FROM upload_session_parts WHERE session_id = $1",
+    )
+    .bind(session_id)
+    .fetch_all(&db.pool)
+    .await
+    {
+        Ok(parts) => parts,
+        Err(e) => {
+            crate::log_with_storage!(warn, "读取上传分块失败 {}: {}", session_id, e);
+            return;
+        }
+    };
+
+    for part in &parts {
+        if let Err(e) = storage.delete(&part.part_key).await {
+            crate::log_with_storage!(warn, "删除上传分块失败 {}: {}", part.part_key, e);
+            // 保留记录，下次清理时重试
+            return;
+        }
+    }
+
+    if let Err(e) = sqlx::query("DELETE FROM upload_session_parts WHERE session_id = $1")
+        .bind(session_id)
+        .execute(&db.pool)
+        .await
+    {
+        crate::log_with_storage!(warn, "删除上传分块记录失败 {}: {}", session_id, e);
+    }
+}
+
+/// 创建断点续传会话
+///
+/// 返回的 `Location` 头为后续 `PATCH`/`HEAD` 请求的地址
+pub async fn create_upload_session(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(request): Json<CreateUploadSessionRequest>,
+) -> Result<Response, UploadError> {
+    if request.file_size == 0 {
+        return Err((StatusCode::BAD_REQUEST, "文件大小不能为0".to_string()));
+    }
+
+    user_policy(&db, &auth_user.user_id)
+        .await?
+        .check_resumable(
+            Some(&request.filename),
+            Some(request.file_size),
+            &request.content_type,
+        )
+        .map_err(policy_error)?;
+
+    check_quota(&db, &auth_user.user_id, request.file_size as i64, 1)
+        .await
+        .map_err(quota_error)?;
+
+    let now = Utc::now();
+    let query = r#"
+        INSERT INTO upload_sessions (
+            id, user_id, media_id, title, description, original_filename,
+            content_type, cos_key, total_size, upload_offset, status,
+            created_at, updated_at, expires_at
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, 0, 'uploading', $10, $10, $11)
+        RETURNING *
+    "#;
+
+    let session = sqlx::query_as::<_, UploadSession>(query)
+        .bind(Uuid::new_v4().to_string())
+        .bind(&auth_user.user_id)
+        .bind(Uuid::new_v4().to_string())
+        .bind(&request.title)
+        .bind(&request.description)
+        .bind(&request.filename)
+        .bind(&request.content_type)
+        .bind(generate_object_key(&auth_user.user_id, &request.filename))
+        .bind(request.file_size as i64)
+        .bind(now)
+        .bind(now + Duration::hours(UPLOAD_SESSION_TTL_HOURS))
+        .fetch_one(&db.pool)
+        .await
+        .map_err(internal_error)?;
+
+    crate::log_with_storage!(
+        info,
+        "创建上传会话: {} ({} 字节)",
+        session.id,
+        session.total_size
+    );
+
+    let mut headers = progress_headers(&session);
+    if let Ok(location) = HeaderValue::from_str(&format!("/api/uploads/{}", session.id)) {
+        headers.insert(header::LOCATION, location);
+    }
+
+    Ok((StatusCode::CREATED, headers, Json(session)).into_response())
+}
+
+/// 获取上传会话状态
+pub async fn get_upload_session(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(session_id): Path<String>,
+) -> Result<Response, UploadError> {
+    let session = find_session(&db, &session_id, &auth_user.user_id).await?;
+    Ok((progress_headers(&session), Json(session)).into_response())
+}
+
+/// 查询上传进度（tus `HEAD`）
+///
+/// `Upload-Status` 为会话状态，合并完成后为 `completed`，`Upload-Media-Id` 为创建的媒体ID；
+/// 合并失败时恢复为 `uploading`，失败原因见会话的 `last_error`
+pub async fn head_upload_session(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(session_id): Path<String>,
+) -> Result<Response, UploadError> {
+    let session = find_session(&db, &session_id, &auth_user.user_id).await?;
+    Ok((StatusCode::OK, progress_headers(&session)).into_response())
+}
+
+/// 上传一个分块（tus `PATCH`）
+///
+/// `Upload-Offset` 请求头必须等于服务器记录的当前偏移量，否则返回 409，
+/// 客户端应通过 `HEAD` 获取偏移量后从该位置继续上传。最后一个分块写入后在后台合并文件并创建媒体记录，
+/// 返回 202，客户端通过 `HEAD` 轮询合并结果
+pub async fn upload_chunk(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(session_id): Path<String>,
+    request: Request,
+) -> Result<Response, UploadError> {
+    let offset = request
+        .headers()
+        .get("Upload-Offset")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<i64>().ok())
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "缺少或无效的 Upload-Offset 请求头".to_string(),
+        ))?;
+    let declared_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+
+    let session = find_session(&db, &session_id, &auth_user.user_id).await?;
+    ensure_uploading(&session)?;
+
+    if offset != session.upload_offset {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("偏移量不匹配，当前偏移量为 {}", session.upload_offset),
+        ));
+    }
+
+    // 所有数据已接收但上次合并失败或中断时，直接重试合并
+    if session.upload_offset == session.total_size {
+        return finalize_upload(&db, &storage, session).await;
+    }
+
+    let max_size = ((session.total_size - offset) as u64).min(MAX_CHUNK_SIZE);
+    if declared_length.is_some_and(|len| len > max_size) {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("分块过大，本次最多允许 {} 字节", max_size),
+        ));
+    }
+
+    let part_key = format!(
+        "uploads/{}/{:020}_{}",
+        session.id,
+        offset,
+        &Uuid::new_v4().to_string()[..8]
+    );
+    let received = Arc::new(AtomicU64::new(0));
+    let stream = limit_stream(
+        request.into_body().into_data_stream(),
+        received.clone(),
+        max_size,
+    );
+    let result = storage
+        .put_stream(&part_key, stream, Some("application/octet-stream"))
+        .await;
+    let size = received.load(Ordering::SeqCst);
+
+    if let Err(e) = result {
+        delete_or_enqueue(&db, &storage, &part_key, None).await;
+        if size > max_size {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("分块过大，本次最多允许 {} 字节", max_size),
+            ));
+        }
+        crate::log_with_storage!(error, "写入上传分块失败 {}: {}", part_key, e);
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("分块写入失败: {}", e),
+        ));
+    }
+
+    if size == 0 {
+        delete_or_enqueue(&db, &storage, &part_key, None).await;
+        return Ok(chunk_response(&session, None));
+    }
+
+    // 仅当偏移量未被其他请求推进时才记录分块
+    let recorded = async {
+        let mut tx = db.pool.begin().await?;
+        let now = Utc::now();
+
+        let updated = sqlx::query_as::<_, UploadSession>(
+            r#"
+            UPDATE upload_sessions
+            SET upload_offset = upload_offset + $1, updated_at = $2, expires_at = $3
+            WHERE id = $4 AND status = 'uploading' AND upload_offset = $5
+            RETURNING *
+            "#,
+        )
+        .bind(size as i64)
+        .bind(now)
+        .bind(now + Duration::hours(UPLOAD_SESSION_TTL_HOURS))
+        .bind(&session.id)
+        .bind(offset)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(updated) = updated else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            "INSERT INTO upload_session_parts (session_id, part_offset, part_size, part_key, created_at) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&session.id)
+        .bind(offset)
+        .bind(size as i64)
+        .bind(&part_key)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(Some(updated))
+    }
+    .await;
+
+    let updated = match recorded {
+        Ok(Some(updated)) => updated,
+        Ok(None) => {
+            delete_or_enqueue(&db, &storage, &part_key, None).await;
+            return Err((StatusCode::CONFLICT, "偏移量已变化，请重新查询".to_string()));
+        }
+        Err(e) => {
+            delete_or_enqueue(&db, &storage, &part_key, None).await;
+            return Err(internal_error(e));
+        }
+    };
+
+    if updated.upload_offset == updated.total_size {
+        finalize_upload(&db, &storage, updated).await
+    } else {
+        Ok(chunk_response(&updated, None))
+    }
+}
+
+fn chunk_response(session: &UploadSession, media: Option<MediaItem>) -> Response {
+    let completed = media.is_some();
+    (
+        progress_headers(session),
+        Json(UploadChunkResponse {
+            upload_offset: session.upload_offset,
+            total_size: session.total_size,
+            status: session.status.clone(),
+            completed,
+            media,
+        }),
+    )
+        .into_response()
+}
+
+/// 标记会话为合并中，并在后台合并分块
+///
+/// 合并大文件耗时较长，放在请求之外执行，客户端断开连接不会中断合并
+async fn finalize_upload(
+    db: &Database,
+    storage: &SharedStorage,
+    session: UploadSession,
+) -> Result<Response, UploadError> {
+    // 标记为合并中，避免并发请求重复合并；同时推迟过期时间，避免合并期间分块被清理
+    let now = Utc::now();
+    let claimed = sqlx::query_as::<_, UploadSession>(
+        r#"
+        UPDATE upload_sessions
+        SET status = 'finalizing', last_error = NULL, updated_at = $1,
+            expires_at = GREATEST(expires_at, $2)
+        WHERE id = $3 AND upload_offset = total_size
+          AND (status = 'uploading' OR (status = 'finalizing' AND updated_at < $4))
+        RETURNING *
+        "#,
+    )
+    .bind(now)
+    .bind(now + Duration::hours(UPLOAD_SESSION_TTL_HOURS))
+    .bind(&session.id)
+    .bind(now - Duration::minutes(FINALIZE_TIMEOUT_MINUTES))
+    .fetch_optional(&db.pool)
+    .await
+    .map_err(internal_error)?
+    .ok_or((StatusCode::CONFLICT, "上传正在处理中".to_string()))?;
+
+    tokio::spawn(run_finalize(db.clone(), storage.clone(), claimed.clone()));
+    Ok((StatusCode::ACCEPTED, chunk_response(&claimed, None)).into_response())
+}
+
+/// 合并分块并创建媒体记录，失败时恢复为上传中并记录原因
+async fn run_finalize(db: Database, storage: SharedStorage, session: UploadSession) {
+    match assemble_upload(&db, &storage, &session).await {
+        Ok(media) => {
+            remove_parts(&db, &storage, &session.id).await;
+            crate::log_with_storage!(
+                info,
+                "断点续传完成: {} ({} 字节) -> {}",
+                media.id,
+                media.file_size,
+                media.cos_key
+            );
+        }
+        Err((_, message)) => {
+            crate::log_with_storage!(warn, "合并上传失败 {}: {}", session.id, message);
+            // 恢复为上传中，客户端可以再次发送 PATCH 重试合并
+            if let Err(e) = sqlx::query(
+                "UPDATE upload_sessions SET status = 'uploading', last_error = $1, updated_at = $2 WHERE id = $3 AND status = 'finalizing'",
+            )
+            .bind(&message)
+            .bind(Utc::now())
+            .bind(&session.id)
+            .execute(&db.pool)
+            .await
+            {
+                crate::log_with_storage!(warn, "恢复上传会话状态失败 {}: {}", session.id, e);
+            }
+        }
+    }
+}
+
+async fn assemble_upload(
+    db: &Database,
+    storage: &SharedStorage,
+    session: &UploadSession,
+) -> Result<MediaItem, UploadError> {
+    let parts = sqlx::query_as::<_, UploadPart>(
+        "SELECT part_offset, part_size, part_key FROM upload_session_parts WHERE session_id = $1 ORDER BY part_offset",
+    )
+    .bind(&session.id)
+    .fetch_all(&db.pool)
+    .await
+    .map_err(internal_error)?;
+
+    // 分块必须首尾相接并覆盖整个文件
+    let mut expected_offset = 0;
+    for part in &parts {
+        if part.part_offset != expected_offset {
+            crate::log_with_storage!(
+                error,
+                "上传分块不连续: {} @ {}",
+                session.id,
+                expected_offset
+            );
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "上传分块不完整".to_string(),
+            ));
+        }
+        expected_offset += part.part_size;
+    }
+    if expected_offset != session.total_size {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "上传分块不完整".to_string(),
+        ));
+    }
+
+    // 会话创建后用量可能已经变化，合并前再次检查配额
+    check_quota(db, &session.user_id, session.total_size, 1)
+        .await
+        .map_err(quota_error)?;
+
+    // 根据第一个分块的文件头检查内容类型
+    let policy = user_policy(db, &session.user_id).await?;
+    let content_check = match parts.first() {
+        Some(first) => Some(
+            verify_content(
+                storage,
+                policy,
+                &first.part_key,
+                first.part_size as u64,
+                &session.content_type,
+            )
+            .await?,
+        ),
+        None => None,
+    };
+    let content_type = content_check
+        .as_ref()
+        .map(|check| check.content_type().to_string())
+        .unwrap_or_else(|| session.content_type.clone());
+
+    // 逐个分块流式读取，内存中不保留完整的分块
+    let stream: ByteStream = Box::pin(
+        futures::stream::iter(parts)
+            .then(move |part| async move {
+                storage
+                    .get_stream(&part.part_key, None)
+                    .await
+                    .map_err(|e| std::io::Error::other(e.to_string()))
+            })
+            .try_flatten(),
+    );
+
+    let hasher = ContentHasher::new();
+    let meta = storage
+        .put_stream(&session.cos_key, hasher.wrap(stream), Some(&content_type))
+        .await
+        .map_err(|e| {
+            crate::log_with_storage!(error, "合并上传分块失败 {}: {}", session.id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("合并文件失败: {}", e),
+            )
+        })?;
+    let content_hash = hasher.finish();
+
+    // 用户已上传过相同内容时直接返回已有媒体
+    let duplicate = find_duplicate(db, &session.user_id, &content_hash)
+        .await
+        .map_err(internal_error)?;
+    if let Some(existing) = duplicate {
+        sqlx::query(
+            "UPDATE upload_sessions SET status = 'completed', media_id = $1, updated_at = $2 WHERE id = $3",
+        )
+        .bind(&existing.id)
+        .bind(Utc::now())
+        .bind(&session.id)
+        .execute(&db.pool)
+        .await
+        .map_err(internal_error)?;
+        delete_or_enqueue(db, storage, &session.cos_key, None).await;
+        return Ok(existing);
+    }
+
+    let mut media_item = build_media_item(
+        storage,
+        session.media_id.clone(),
+        &session.user_id,
+        ReceivedUpload {
+            key: session.cos_key.clone(),
+            original_filename: session.original_filename.clone(),
+            content_type,
+            size: session.total_size as u64,
+            etag: meta.etag,
+            content_hash: Some(content_hash.clone()),
+            content_check,
+            title: session.title.clone(),
+            description: session.description.clone(),
+        },
+        "resumable",
+    );
+
+    let prepared = match prepare_blob(
+        db,
+        storage,
+        &session.user_id,
+        &session.cos_key,
+        &content_hash,
+        session.total_size,
+    )
+    .await
+    {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            crate::log_with_storage!(error, "准备共享对象失败 {}: {}", session.id, e);
+            delete_or_enqueue(db, storage, &session.cos_key, None).await;
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "合并文件失败".to_string(),
+            ));
+        }
+    };
+
+    let committed = async {
+        let mut tx = db.pool.begin().await?;
+        check_quota_locked(&mut tx, &session.user_id, session.total_size, 1).await?;
+        let key = acquire_blob(&mut tx, storage, &session.user_id, &prepared).await?;
+        media_item.cos_url = storage.object_url(&key);
+        media_item.cos_key = key;
+        insert_media_item(&mut *tx, &media_item).await?;
+        sqlx::query(
+            "UPDATE upload_sessions SET status = 'completed', updated_at = $1 WHERE id = $2",
+        )
+        .bind(Utc::now())
+        .bind(&session.id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok::<_, QuotaError>(())
+    }
+    .await;
+
+    if let Err(e) = committed {
+        discard_unused(db, storage, &prepared, "").await;
+        return Err(match e {
+            QuotaError::Database(e) => internal_error(e),
+            e => quota_error(e),
+        });
+    }
+
+    discard_unused(db, storage, &prepared, &media_item.cos_key).await;
+    schedule_media_processing(db, &mut media_item).await;
+    Ok(media_item)
+}
+
+/// 取消上传并删除已接收的分块
+pub async fn abort_upload_session(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(session_id): Path<String>,
+) -> Result<Response, UploadError> {
+    let aborted = sqlx::query(
+        "UPDATE upload_sessions SET status = 'aborted', updated_at = $1 WHERE id = $2 AND user_id = $3 AND status = 'uploading'",
+    )
+    .bind(Utc::now())
+    .bind(&session_id)
+    .bind(&auth_user.user_id)
+    .execute(&db.pool)
+    .await
+    .map_err(internal_error)?;
+
+    if aborted.rows_affected() == 0 {
+        let session = find_session(&db, &session_id, &auth_user.user_id).await?;
+        return Err((
+            StatusCode::CONFLICT,
+            format!("当前状态无法取消上传: {}", session.status),
+        ));
+    }
+
+    remove_parts(&db, &storage, &session_id).await;
+    crate::log_with_storage!(info, "上传会话已取消: {}", session_id);
+
+    let mut headers = HeaderMap::new();
+    headers.insert("Tus-Resumable", HeaderValue::from_static(TUS_VERSION));
+    Ok((StatusCode::NO_CONTENT, headers).into_response())
+}
+
+/// 清理过期的上传会话
+///
+/// 超过有效期仍未完成的会话标记为 `expired` 并删除其分块；
+/// 已结束的会话记录保留一段时间后删除。返回本次过期的会话数
+pub async fn cleanup_expired_upload_sessions(
+    db: &Database,
+    storage: &SharedStorage,
+) -> Result<u64, sqlx::Error> {
+    let expired: Vec<String> = sqlx::query_scalar(
+        r#"
+        UPDATE upload_sessions SET status = 'expired', updated_at = $1
+        WHERE status IN ('uploading', 'finalizing') AND expires_at < $1
+        RETURNING id
+        "#,
+    )
+    .bind(Utc::now())
+    .fetch_all(&db.pool)
+    .await?;
+
+    // 之前删除失败的分块也在这里重试
+    let pending: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT DISTINCT s.id FROM upload_sessions s
+        JOIN upload_session_parts p ON p.session_id = s.id
+        WHERE s.status IN ('expired', 'aborted', 'completed')
+        "#,
+    )
+    .fetch_all(&db.pool)
+    .await?;
+
+    for session_id in &pending {
+        remove_parts(db, storage, session_id).await;
+    }
+
+    sqlx::query(
+        r#"
+        DELETE FROM upload_sessions
+        WHERE status IN ('expired', 'aborted', 'completed') AND updated_at < $1
+        AND NOT EXISTS (SELECT 1 FROM upload_session_parts p WHERE p.session_id = upload_sessions.id)
+        "#,
+    )
+    .bind(Utc::now() - Duration::days(FINISHED_SESSION_RETENTION_DAYS))
+    .execute(&db.pool)
+    .await?;
+
+    Ok(expired.len() as u64)
+}
@@ -0,0 +1,130 @@
+use axum::{
+    Json,
+    extract::{Extension, Path, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::credentials::{AuthUser, UserRepository};
+use crate::database::Database;
+
+#[derive(Serialize, Debug, sqlx::FromRow)]
+pub struct MediaShare {
+    pub media_id: String,
+    pub user_id: String,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateShareRequest {
+    pub username: String,
+}
+
+fn internal_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Database error in media shares: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// 确认媒体属于当前用户，只有所有者可以管理分享
+async fn ensure_owner(db: &Database, media_id: &str, user_id: &str) -> Result<(), StatusCode> {
+    let owned: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM media_files WHERE id = $1 AND user_id = $2 AND status IN ('active', 'processing'))",
+    )
+    .bind(media_id)
+    .bind(user_id)
+    .fetch_one(&db.pool)
+    .await
+    .map_err(internal_error)?;
+
+    if owned {
+        Ok(())
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+async fn list_shares(db: &Database, media_id: &str) -> Result<Vec<MediaShare>, StatusCode> {
+    let query = r#"
+        SELECT s.media_id, s.user_id, u.username, s.created_at
+        FROM media_shares s
+        JOIN users u ON u.id = s.user_id
+        WHERE s.media_id = $1
+        ORDER BY s.created_at
+    "#;
+
+    sqlx::query_as::<_, MediaShare>(query)
+        .bind(media_id)
+        .fetch_all(&db.pool)
+        .await
+        .map_err(internal_error)
+}
+
+/// 获取媒体的分享列表
+pub async fn get_media_shares(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+) -> Result<Json<Vec<MediaShare>>, StatusCode> {
+    ensure_owner(&db, &media_id, &auth_user.user_id).await?;
+    Ok(Json(list_shares(&db, &media_id).await?))
+}
+
+/// 将媒体分享给指定用户
+pub async fn share_media(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+    Json(request): Json<CreateShareRequest>,
+) -> Result<(StatusCode, Json<Vec<MediaShare>>), StatusCode> {
+    ensure_owner(&db, &media_id, &auth_user.user_id).await?;
+
+    let target = UserRepository::find_by_username(&db.pool, &request.username)
+        .await
+        .map_err(internal_error)?
+        .filter(|user| user.is_active)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if target.id == auth_user.user_id {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    sqlx::query(
+        "INSERT INTO media_shares (media_id, user_id, created_at) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+    )
+    .bind(&media_id)
+    .bind(&target.id)
+    .bind(Utc::now())
+    .execute(&db.pool)
+    .await
+    .map_err(internal_error)?;
+
+    crate::log_with_storage!(info, "媒体 {} 已分享给用户 {}", media_id, target.username);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(list_shares(&db, &media_id).await?),
+    ))
+}
+
+/// 取消对指定用户的分享
+pub async fn unshare_media(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path((media_id, user_id)): Path<(String, String)>,
+) -> Result<StatusCode, StatusCode> {
+    ensure_owner(&db, &media_id, &auth_user.user_id).await?;
+
+    let result = sqlx::query("DELETE FROM media_shares WHERE media_id = $1 AND user_id = $2")
+        .bind(&media_id)
+        .bind(&user_id)
+        .execute(&db.pool)
+        .await
+        .map_err(internal_error)?;
+
+    if result.rows_affected() > 0 {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
@@ -0,0 +1,109 @@
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::credentials::AuthUser;
+use crate::database::Database;
+use crate::handlers::media_handlers::find_readable_media;
+use crate::similarity::{
+    self, DEFAULT_MAX_DISTANCE, DUPLICATE_MAX_DISTANCE, DuplicatePair, MAX_DISTANCE_LIMIT,
+    SimilarMedia,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct SimilarityParams {
+    /// 最大汉明距离，越小越严格：相似媒体为 0 到 32，默认 10；可能重复的媒体报告为 0 到 3，默认 3
+    pub max_distance: Option<i32>,
+    pub limit: Option<i64>,
+}
+
+impl SimilarityParams {
+    fn max_distance(&self) -> i32 {
+        self.max_distance
+            .unwrap_or(DEFAULT_MAX_DISTANCE)
+            .clamp(0, MAX_DISTANCE_LIMIT)
+    }
+
+    fn duplicate_max_distance(&self) -> i32 {
+        self.max_distance
+            .unwrap_or(DUPLICATE_MAX_DISTANCE)
+            .clamp(0, DUPLICATE_MAX_DISTANCE)
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(50).clamp(1, 200)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct SimilarMediaResponse {
+    pub items: Vec<SimilarMedia>,
+    pub max_distance: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DuplicateReportResponse {
+    pub pairs: Vec<DuplicatePair>,
+    pub max_distance: i32,
+}
+
+/// 在当前用户的媒体中查找与指定媒体画面相似的图片或视频，按距离从近到远排序
+///
+/// 指定的媒体需要可读（自己的或分享给自己的）。感知哈希尚未计算时返回空列表
+pub async fn get_similar_media(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+    Query(params): Query<SimilarityParams>,
+) -> Result<Json<SimilarMediaResponse>, StatusCode> {
+    let internal_error = |e: sqlx::Error| {
+        eprintln!("Database error finding similar media: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let media = find_readable_media(&db, &media_id, &auth_user.user_id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let max_distance = params.max_distance();
+    let items = similarity::find_similar(
+        &db,
+        &media,
+        &auth_user.user_id,
+        max_distance,
+        params.limit(),
+    )
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(SimilarMediaResponse {
+        items,
+        max_distance,
+    }))
+}
+
+/// 当前用户媒体中可能重复的媒体对（连拍、重新编码或缩放后的副本），按距离从近到远排序
+///
+/// 需要比较用户的所有媒体，距离上限较小以便按哈希分段筛选候选
+pub async fn get_possible_duplicates(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(params): Query<SimilarityParams>,
+) -> Result<Json<DuplicateReportResponse>, StatusCode> {
+    let max_distance = params.duplicate_max_distance();
+    let pairs = similarity::find_duplicates(&db, &auth_user.user_id, max_distance, params.limit())
+        .await
+        .map_err(|e| {
+            eprintln!("Database error finding possible duplicates: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(DuplicateReportResponse {
+        pairs,
+        max_distance,
+    }))
+}
@@ -0,0 +1,198 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::handlers::download_handlers::{RangeRequest, etag_matches, parse_range};
+use crate::storage::{SharedStorage, StorageError, local};
+
+#[derive(Deserialize, Debug)]
+pub struct SignedUrlParams {
+    pub expires: i64,
+    /// 上传地址允许的对象大小
+    pub size: Option<u64>,
+    pub signature: String,
+}
+
+/// 校验签名，仅在使用本地存储后端时开放
+fn authorize(
+    storage: &SharedStorage,
+    method: &str,
+    key: &str,
+    params: &SignedUrlParams,
+) -> Result<(), StatusCode> {
+    if storage.name() != "local" {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    if !local::verify_signature(method, key, params.expires, params.size, &params.signature) {
+        crate::log_with_storage!(warn, "本地存储签名校验失败: {} {}", method, key);
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(())
+}
+
+fn storage_error_status(e: &StorageError) -> StatusCode {
+    match e {
+        StorageError::NotFound(_) => StatusCode::NOT_FOUND,
+        StorageError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// 通过签名URL下载本地存储中的对象，对象内容以流的方式返回
+///
+/// 与媒体流式代理一样支持单个范围的 `Range` 请求和 `If-Range`
+pub async fn download_local_object(
+    State(storage): State<SharedStorage>,
+    Path(key): Path<String>,
+    Query(params): Query<SignedUrlParams>,
+    request_headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    authorize(&storage, "GET", &key, &params)?;
+
+    let read_error = |e: StorageError| {
+        eprintln!("Failed to read local object {}: {}", key, e);
+        storage_error_status(&e)
+    };
+    let meta = storage.head(&key).await.map_err(read_error)?;
+    let size = meta.size;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    let content_type = meta
+        .content_type
+        .as_deref()
+        .unwrap_or("application/octet-stream");
+    if let Ok(value) = HeaderValue::from_str(content_type) {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+    let etag = meta.etag.as_deref();
+    if let Some(value) = etag.and_then(|etag| HeaderValue::from_str(etag).ok()) {
+        headers.insert(header::ETAG, value);
+    }
+
+    let header_str = |name: header::HeaderName| {
+        request_headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    // If-Range 与当前ETag不一致时忽略 Range，返回完整内容
+    let range_allowed = match header_str(header::IF_RANGE) {
+        Some(if_range) => etag.is_some_and(|etag| etag_matches(if_range, etag)),
+        None => true,
+    };
+    let range = match header_str(header::RANGE) {
+        Some(value) if range_allowed => parse_range(value, size),
+        _ => RangeRequest::Full,
+    };
+
+    let (status, range) = match range {
+        RangeRequest::Full => (StatusCode::OK, None),
+        RangeRequest::Partial(range) => {
+            if let Ok(value) =
+                HeaderValue::from_str(&format!("bytes {}-{}/{}", range.start, range.end, size))
+            {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+            (StatusCode::PARTIAL_CONTENT, Some(range))
+        }
+        RangeRequest::Unsatisfiable => {
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", size)) {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+        }
+    };
+
+    let length = range.map(|range| range.len()).unwrap_or(size);
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+
+    let stream = storage.get_stream(&key, range).await.map_err(read_error)?;
+    Ok((status, headers, Body::from_stream(stream)).into_response())
+}
+
+/// 通过签名URL上传对象到本地存储
+///
+/// 请求体以流的方式写入，大小必须与签名中的 `size` 一致，不一致时中断写入，不会留下对象
+pub async fn upload_local_object(
+    State(storage): State<SharedStorage>,
+    Path(key): Path<String>,
+    Query(params): Query<SignedUrlParams>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, StatusCode> {
+    authorize(&storage, "PUT", &key, &params)?;
+
+    let Some(size) = params.size else {
+        return Err(StatusCode::FORBIDDEN);
+    };
+    let declared_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    match declared_length {
+        Some(length) if length > size => return Err(StatusCode::PAYLOAD_TOO_LARGE),
+        Some(length) if length < size => return Err(StatusCode::BAD_REQUEST),
+        _ => {}
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+
+    let received = Arc::new(AtomicU64::new(0));
+    let counted = received.clone();
+    let limited = body.into_data_stream().map(move |chunk| {
+        let chunk = chunk.map_err(std::io::Error::other)?;
+        let total = counted.fetch_add(chunk.len() as u64, Ordering::SeqCst) + chunk.len() as u64;
+        if total > size {
+            Err(std::io::Error::other("文件大小超过签名的大小"))
+        } else {
+            Ok(chunk)
+        }
+    });
+    // 请求体结束时检查是否写满，不足时同样中断写入
+    let total = received.clone();
+    let complete = futures::stream::once(async move {
+        (total.load(Ordering::SeqCst) != size)
+            .then(|| Err(std::io::Error::other("文件大小小于签名的大小")))
+    })
+    .filter_map(futures::future::ready);
+
+    let meta = match storage
+        .put_stream(&key, Box::pin(limited.chain(complete)), content_type)
+        .await
+    {
+        Ok(meta) => meta,
+        Err(e) => {
+            eprintln!("Failed to write local object {}: {}", key, e);
+            let received = received.load(Ordering::SeqCst);
+            return Err(if received > size {
+                StatusCode::PAYLOAD_TOO_LARGE
+            } else if received < size {
+                StatusCode::BAD_REQUEST
+            } else {
+                storage_error_status(&e)
+            });
+        }
+    };
+
+    crate::log_with_storage!(info, "对象已写入本地存储: {} ({} 字节)", key, meta.size);
+
+    let mut response_headers = HeaderMap::new();
+    if let Some(etag) = meta.etag.as_deref()
+        && let Ok(value) = HeaderValue::from_str(etag)
+    {
+        response_headers.insert(header::ETAG, value);
+    }
+
+    Ok((StatusCode::OK, response_headers).into_response())
+}
@@ -0,0 +1,238 @@
+use axum::{
+    Json,
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::credentials::AuthUser;
+use crate::database::Database;
+use crate::dedupe::release_blob;
+use crate::handlers::media_handlers::MediaItem;
+use crate::jobs::{cancel_media_jobs, enqueue_deletion_job, restored_status, wake_workers};
+use crate::thumbnails::release_derivatives;
+
+/// 默认的回收站保留天数
+const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
+
+/// 每批清理的媒体数量
+const PURGE_BATCH_SIZE: i64 = 200;
+
+/// 回收站保留天数（环境变量 `TRASH_RETENTION_DAYS`）
+static TRASH_RETENTION_DAYS: Lazy<i64> = Lazy::new(|| {
+    std::env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse::<i64>().ok())
+        .filter(|days| *days >= 0)
+        .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS)
+});
+
+/// 回收站中的媒体保留多久后被彻底删除
+pub fn trash_retention() -> Duration {
+    Duration::days(*TRASH_RETENTION_DAYS)
+}
+
+#[derive(Serialize, Debug, sqlx::FromRow)]
+pub struct TrashItem {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub media: MediaItem,
+    pub deleted_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TrashListResponse {
+    pub items: Vec<TrashItem>,
+    pub total: i64,
+    pub page: i32,
+    pub per_page: i32,
+    pub retention_days: i64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TrashQueryParams {
+    pub page: Option<i32>,
+    pub per_page: Option<i32>,
+}
+
+fn internal_error(e: sqlx::Error) -> StatusCode {
+    eprintln!("Database error in trash: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// 获取回收站中的媒体
+pub async fn get_trash(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(params): Query<TrashQueryParams>,
+) -> Result<Json<TrashListResponse>, StatusCode> {
+    let page = params.page.unwrap_or(1).max(1);
+    let per_page = params.per_page.unwrap_or(20).clamp(1, 100);
+    let offset = (page - 1) * per_page;
+
+    let total: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM media_files WHERE user_id = $1 AND status = 'deleted'",
+    )
+    .bind(&auth_user.user_id)
+    .fetch_one(&db.pool)
+    .await
+    .map_err(internal_error)?;
+
+    let items = sqlx::query_as::<_, TrashItem>(
+        r#"
+        SELECT * FROM media_files
+        WHERE user_id = $1 AND status = 'deleted'
+        ORDER BY deleted_at DESC
+        LIMIT $2 OFFSET $3
+        "#,
+    )
+    .bind(&auth_user.user_id)
+    .bind(per_page as i64)
+    .bind(offset as i64)
+    .fetch_all(&db.pool)
+    .await
+    .map_err(internal_error)?;
+
+    Ok(Json(TrashListResponse {
+        items,
+        total,
+        page,
+        per_page,
+        retention_days: trash_retention().num_days(),
+    }))
+}
+
+/// 从回收站恢复媒体
+pub async fn restore_media(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+) -> Result<Json<MediaItem>, StatusCode> {
+    // 回收站中的媒体仍有未完成的处理任务时恢复为 processing
+    let status = restored_status(&db.pool, &media_id)
+        .await
+        .map_err(internal_error)?;
+
+    let query = r#"
+        UPDATE media_files
+        SET status = $1, deleted_at = NULL, updated_at = $2
+        WHERE id = $3 AND user_id = $4 AND status = 'deleted'
+        RETURNING *
+    "#;
+
+    let media = sqlx::query_as::<_, MediaItem>(query)
+        .bind(status)
+        .bind(Utc::now())
+        .bind(&media_id)
+        .bind(&auth_user.user_id)
+        .fetch_optional(&db.pool)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    crate::log_with_storage!(info, "媒体已从回收站恢复: {}", media_id);
+    Ok(Json(media))
+}
+
+/// 彻底删除回收站中的一个媒体：删除数据库记录，并在同一事务中登记存储对象的删除任务
+///
+/// 媒体不在回收站中时返回 `false`
+async fn purge_media(
+    db: &Database,
+    media_id: &str,
+    user_id: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = db.pool.begin().await?;
+
+    // 缩略图等派生文件随媒体一起删除
+    release_derivatives(&mut tx, media_id).await?;
+
+    let purged: Option<(String, String)> = sqlx::query_as(
+        r#"
+        DELETE FROM media_files
+        WHERE id = $1 AND status = 'deleted' AND ($2::TEXT IS NULL OR user_id = $2)
+        RETURNING cos_key, storage_backend
+        "#,
+    )
+    .bind(media_id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some((cos_key, storage_backend)) = purged else {
+        return Ok(false);
+    };
+
+    cancel_media_jobs(&mut *tx, media_id).await?;
+
+    // 其他媒体仍在引用同一对象时只减少引用数
+    if let Some(deletion_id) =
+        release_blob(&mut tx, &cos_key, &storage_backend, Some(media_id)).await?
+    {
+        enqueue_deletion_job(&mut *tx, &deletion_id).await?;
+    }
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// 从回收站彻底删除媒体，删除后无法恢复
+pub async fn purge_trashed_media(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    if !purge_media(&db, &media_id, Some(&auth_user.user_id))
+        .await
+        .map_err(internal_error)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    crate::log_with_storage!(info, "媒体已彻底删除: {}", media_id);
+
+    // 存储中的文件由任务队列删除，失败时由删除队列稍后重试
+    wake_workers();
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 彻底删除超过保留期的回收站媒体，返回删除的数量
+///
+/// 存储对象的删除由任务队列处理
+pub async fn purge_expired_trash(db: &Database, retention: Duration) -> Result<u64, sqlx::Error> {
+    let cutoff = Utc::now() - retention;
+    let mut purged = 0;
+
+    loop {
+        let expired: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT id FROM media_files
+            WHERE status = 'deleted' AND deleted_at < $1
+            ORDER BY deleted_at
+            LIMIT $2
+            "#,
+        )
+        .bind(cutoff)
+        .bind(PURGE_BATCH_SIZE)
+        .fetch_all(&db.pool)
+        .await?;
+
+        for media_id in &expired {
+            if purge_media(db, media_id, None).await? {
+                purged += 1;
+            }
+        }
+
+        if (expired.len() as i64) < PURGE_BATCH_SIZE {
+            break;
+        }
+    }
+
+    if purged > 0 {
+        wake_workers();
+    }
+    Ok(purged)
+}
@@ -0,0 +1,667 @@
+use axum::{
+    Json,
+    extract::{Extension, FromRequest, Multipart, Path, Query, Request, State},
+    http::{StatusCode, header},
+};
+use bytes::Bytes;
+use chrono::Utc;
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+
+use crate::credentials::AuthUser;
+use crate::database::Database;
+use crate::dedupe::{
+    ContentHasher, DedupeError, Registration, acquire_blob, discard_unused, find_duplicate,
+    prepare_blob, register_media, release_blob,
+};
+use crate::deletion_queue::delete_or_enqueue;
+use crate::handlers::cos_handlers::generate_object_key;
+use crate::handlers::media_handlers::MediaItem;
+use crate::jobs::{enqueue_deletion_job, schedule_media_processing};
+use crate::quota::{QuotaError, check_quota, check_quota_locked};
+use crate::sniff::{ContentCheck, check_content, read_header};
+use crate::storage::{ByteStream, SharedStorage};
+use crate::upload_policy::{PolicyViolation, UploadPolicy, policy_for_user};
+
+type UploadError = (StatusCode, String);
+
+#[derive(Deserialize, Debug)]
+pub struct StreamUploadParams {
+    /// 原始文件名，原始请求体上传时必填；multipart 上传时取文件字段的文件名
+    pub filename: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+/// 已写入存储后端的上传文件
+pub(crate) struct ReceivedUpload {
+    pub key: String,
+    pub original_filename: String,
+    pub content_type: String,
+    pub size: u64,
+    pub etag: Option<String>,
+    /// 写入过程中计算的 SHA-256 摘要
+    pub content_hash: Option<String>,
+    /// 文件头与声明类型的比对结果
+    pub content_check: Option<ContentCheck>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+/// 根据 MIME 类型推断媒体类型
+pub fn media_type_from_content_type(content_type: &str) -> &'static str {
+    if content_type.starts_with("image/") {
+        "image"
+    } else if content_type.starts_with("video/") {
+        "video"
+    } else if content_type.starts_with("audio/") {
+        "audio"
+    } else {
+        "document"
+    }
+}
+
+/// 为已写入存储的文件构建媒体记录
+///
+/// 未提供标题时使用不带扩展名的原始文件名
+pub(crate) fn build_media_item(
+    storage: &SharedStorage,
+    id: String,
+    user_id: &str,
+    upload: ReceivedUpload,
+    upload_method: &str,
+) -> MediaItem {
+    let now = Utc::now();
+    MediaItem {
+        id,
+        user_id: user_id.to_string(),
+        title: upload.title.unwrap_or_else(|| {
+            std::path::Path::new(&upload.original_filename)
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or(&upload.original_filename)
+                .to_string()
+        }),
+        description: upload.description,
+        filename: upload
+            .key
+            .rsplit('/')
+            .next()
+            .unwrap_or(&upload.key)
+            .to_string(),
+        original_filename: upload.original_filename,
+        file_size: upload.size as i64,
+        media_type: media_type_from_content_type(&upload.content_type).to_string(),
+        content_type: upload.content_type,
+        cos_url: storage.object_url(&upload.key),
+        cos_bucket: storage.bucket(),
+        cos_region: storage.region(),
+        storage_backend: storage.name().to_string(),
+        cos_key: upload.key,
+        status: "active".to_string(),
+        metadata: Some(serde_json::json!({
+            "upload_method": upload_method,
+            "etag": upload.etag,
+            "content_check": upload.content_check,
+        })),
+        content_hash: upload.content_hash,
+        transcode_status: None,
+        transcode_progress: None,
+        transcode_error: None,
+        blurhash: None,
+        dominant_color: None,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+/// 为字节流加上大小限制，超出 `max_size` 时中断写入
+pub(crate) fn limit_stream<'a, S, E>(
+    stream: S,
+    received: Arc<AtomicU64>,
+    max_size: u64,
+) -> ByteStream<'a>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'a,
+    E: std::fmt::Display,
+{
+    Box::pin(stream.map(move |chunk| {
+        let chunk = chunk.map_err(|e| std::io::Error::other(e.to_string()))?;
+        let total = received.fetch_add(chunk.len() as u64, Ordering::SeqCst) + chunk.len() as u64;
+        if total > max_size {
+            Err(std::io::Error::other("文件大小超过限制"))
+        } else {
+            Ok(chunk)
+        }
+    }))
+}
+
+/// 将配额检查失败转换为响应
+pub(crate) fn quota_error(e: QuotaError) -> UploadError {
+    match e {
+        QuotaError::Exceeded(message) => (StatusCode::FORBIDDEN, message),
+        QuotaError::Database(e) => {
+            eprintln!("Database error checking quota: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "数据库错误".to_string())
+        }
+    }
+}
+
+/// 将上传策略检查失败转换为响应
+pub(crate) fn policy_error(violation: PolicyViolation) -> UploadError {
+    let status = match violation {
+        PolicyViolation::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        _ => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+    };
+    (status, violation.to_string())
+}
+
+/// 查询用户适用的上传策略
+pub(crate) async fn user_policy(
+    db: &Database,
+    user_id: &str,
+) -> Result<&'static UploadPolicy, UploadError> {
+    policy_for_user(db, user_id).await.map_err(|e| {
+        eprintln!("Database error loading upload policy: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "数据库错误".to_string())
+    })
+}
+
+/// 已写入存储后端的字节流
+struct StoredStream {
+    size: u64,
+    etag: Option<String>,
+    content_hash: String,
+}
+
+/// 将字节流写入存储后端并计算摘要，检查写入结果
+async fn store_stream(
+    storage: &SharedStorage,
+    key: &str,
+    stream: ByteStream<'_>,
+    content_type: &str,
+    received: &AtomicU64,
+    max_size: u64,
+) -> Result<StoredStream, UploadError> {
+    let hasher = ContentHasher::new();
+    let result = storage
+        .put_stream(key, hasher.wrap(stream), Some(content_type))
+        .await;
+    let size = received.load(Ordering::SeqCst);
+
+    match result {
+        Ok(meta) if size == 0 => {
+            let _ = storage.delete(&meta.key).await;
+            Err((StatusCode::BAD_REQUEST, "上传内容为空".to_string()))
+        }
+        Ok(meta) => Ok(StoredStream {
+            size,
+            etag: meta.etag,
+            content_hash: hasher.finish(),
+        }),
+        Err(_) if size > max_size => Err(policy_error(PolicyViolation::TooLarge { max_size })),
+        Err(e) => {
+            crate::log_with_storage!(error, "流式上传写入存储失败: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("文件写入失败: {}", e),
+            ))
+        }
+    }
+}
+
+/// 检查已写入存储的文件内容，按识别出的真实格式修正内容类型
+///
+/// 修正后的类型仍需符合上传策略；声明的类型与内容不符且无法识别时拒绝
+pub(crate) async fn verify_content(
+    storage: &SharedStorage,
+    policy: &UploadPolicy,
+    key: &str,
+    size: u64,
+    declared: &str,
+) -> Result<ContentCheck, UploadError> {
+    let header = read_header(storage, key, size).await.map_err(|e| {
+        crate::log_with_storage!(error, "读取上传文件失败 {}: {}", key, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("读取上传文件失败: {}", e),
+        )
+    })?;
+
+    let check = check_content(&header, declared)
+        .map_err(|e| (StatusCode::UNSUPPORTED_MEDIA_TYPE, e.to_string()))?;
+    if check.is_spoofed() {
+        crate::log_with_storage!(
+            warn,
+            "文件内容与声明的类型不符 {}: {} -> {}",
+            key,
+            declared,
+            check.content_type()
+        );
+    }
+    policy
+        .check(None, None, check.content_type())
+        .map_err(policy_error)?;
+
+    Ok(check)
+}
+
+/// 接收请求体并写入存储后端，按文件内容修正内容类型
+///
+/// 内容检查未通过时删除已写入的对象
+async fn receive_upload(
+    storage: &SharedStorage,
+    policy: &UploadPolicy,
+    user_id: &str,
+    params: StreamUploadParams,
+    request: Request,
+) -> Result<ReceivedUpload, UploadError> {
+    let mut upload = receive_body(storage, policy, user_id, params, request).await?;
+
+    match verify_content(
+        storage,
+        policy,
+        &upload.key,
+        upload.size,
+        &upload.content_type,
+    )
+    .await
+    {
+        Ok(check) => {
+            upload.content_type = check.content_type().to_string();
+            upload.content_check = Some(check);
+            Ok(upload)
+        }
+        Err(e) => {
+            let _ = storage.delete(&upload.key).await;
+            Err(e)
+        }
+    }
+}
+
+/// 接收请求体并写入存储后端
+///
+/// 支持两种格式：
+/// - `multipart/form-data`：文件放在 `file` 字段，可在文件之前附带 `title`、`description` 字段
+/// - 原始请求体：`Content-Type` 为文件的 MIME 类型，文件名通过 `filename` 查询参数传递
+///
+/// 文件类型、扩展名和大小按用户的上传策略检查
+async fn receive_body(
+    storage: &SharedStorage,
+    policy: &UploadPolicy,
+    user_id: &str,
+    params: StreamUploadParams,
+    request: Request,
+) -> Result<ReceivedUpload, UploadError> {
+    let request_content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    // 声明了长度的请求可以在读取前直接拒绝
+    let declared_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+
+    let received = Arc::new(AtomicU64::new(0));
+
+    if request_content_type.starts_with("multipart/form-data") {
+        let mut multipart = Multipart::from_request(request, &())
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("multipart解析失败: {}", e)))?;
+
+        let mut title = params.title;
+        let mut description = params.description;
+
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("multipart解析失败: {}", e)))?
+        {
+            match field.name() {
+                Some("title") => {
+                    title =
+                        Some(field.text().await.map_err(|e| {
+                            (StatusCode::BAD_REQUEST, format!("读取title失败: {}", e))
+                        })?)
+                }
+                Some("description") => {
+                    description = Some(field.text().await.map_err(|e| {
+                        (
+                            StatusCode::BAD_REQUEST,
+                            format!("读取description失败: {}", e),
+                        )
+                    })?)
+                }
+                Some("file") => {
+                    let original_filename = field
+                        .file_name()
+                        .map(|s| s.to_string())
+                        .or(params.filename)
+                        .ok_or((StatusCode::BAD_REQUEST, "缺少文件名".to_string()))?;
+                    let content_type = field
+                        .content_type()
+                        .unwrap_or("application/octet-stream")
+                        .to_string();
+
+                    policy
+                        .check(Some(&original_filename), None, &content_type)
+                        .map_err(policy_error)?;
+
+                    let key = generate_object_key(user_id, &original_filename);
+                    let max_size = policy.max_file_size;
+                    let stream = limit_stream(field, received.clone(), max_size);
+                    let stored =
+                        store_stream(storage, &key, stream, &content_type, &received, max_size)
+                            .await?;
+
+                    return Ok(ReceivedUpload {
+                        key,
+                        original_filename,
+                        content_type,
+                        size: stored.size,
+                        etag: stored.etag,
+                        content_hash: Some(stored.content_hash),
+                        content_check: None,
+                        title,
+                        description,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        Err((StatusCode::BAD_REQUEST, "缺少 file 字段".to_string()))
+    } else {
+        let original_filename = params.filename.ok_or((
+            StatusCode::BAD_REQUEST,
+            "缺少 filename 查询参数".to_string(),
+        ))?;
+
+        policy
+            .check(
+                Some(&original_filename),
+                declared_length,
+                &request_content_type,
+            )
+            .map_err(policy_error)?;
+
+        let key = generate_object_key(user_id, &original_filename);
+        let max_size = policy.max_file_size;
+        let stream = limit_stream(
+            request.into_body().into_data_stream(),
+            received.clone(),
+            max_size,
+        );
+        let stored = store_stream(
+            storage,
+            &key,
+            stream,
+            &request_content_type,
+            &received,
+            max_size,
+        )
+        .await?;
+
+        Ok(ReceivedUpload {
+            key,
+            original_filename,
+            content_type: request_content_type,
+            size: stored.size,
+            etag: stored.etag,
+            content_hash: Some(stored.content_hash),
+            content_check: None,
+            title: params.title,
+            description: params.description,
+        })
+    }
+}
+
+/// 流式上传文件并创建媒体记录
+///
+/// 请求体直接写入当前配置的存储后端，适用于无法持有云存储凭证的客户端（命令行工具、导入任务等）
+pub async fn stream_upload_media(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(params): Query<StreamUploadParams>,
+    request: Request,
+) -> Result<(StatusCode, Json<MediaItem>), UploadError> {
+    // 声明了长度时在读取请求体之前检查配额
+    let declared_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<i64>().ok());
+    check_quota(&db, &auth_user.user_id, declared_length.unwrap_or(1), 1)
+        .await
+        .map_err(quota_error)?;
+
+    let policy = user_policy(&db, &auth_user.user_id).await?;
+    let upload = receive_upload(&storage, policy, &auth_user.user_id, params, request).await?;
+
+    // 重复上传不占用新的配额，其余按实际大小再次检查
+    let is_duplicate = match upload.content_hash.as_deref() {
+        Some(hash) => find_duplicate(&db, &auth_user.user_id, hash)
+            .await
+            .map_err(|e| quota_error(e.into()))?
+            .is_some(),
+        None => false,
+    };
+    if !is_duplicate
+        && let Err(e) = check_quota(&db, &auth_user.user_id, upload.size as i64, 1).await
+    {
+        delete_or_enqueue(&db, &storage, &upload.key, None).await;
+        return Err(quota_error(e));
+    }
+
+    let media_item = build_media_item(
+        &storage,
+        Uuid::new_v4().to_string(),
+        &auth_user.user_id,
+        upload,
+        "server_stream",
+    );
+
+    let uploaded_key = media_item.cos_key.clone();
+    let mut media_item = match register_media(&db, &storage, media_item).await {
+        Ok(Registration::Created(media)) => media,
+        // 用户已上传过相同内容，返回已有媒体
+        Ok(Registration::Duplicate(media)) => return Ok((StatusCode::OK, Json(media))),
+        Err(e) => {
+            // 记录创建失败时清理已上传的对象，避免产生孤儿文件
+            if !delete_or_enqueue(&db, &storage, &uploaded_key, None).await {
+                crate::log_with_storage!(warn, "清理未登记的上传对象失败: {}", uploaded_key);
+            }
+            if let DedupeError::QuotaExceeded(message) = e {
+                return Err(quota_error(QuotaError::Exceeded(message)));
+            }
+            eprintln!("Database error creating streamed media: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "媒体记录创建失败".to_string(),
+            ));
+        }
+    };
+
+    schedule_media_processing(&db, &mut media_item).await;
+
+    crate::log_with_storage!(
+        info,
+        "流式上传完成: {} ({} 字节) -> {}",
+        media_item.id,
+        media_item.file_size,
+        media_item.cos_key
+    );
+
+    Ok((StatusCode::CREATED, Json(media_item)))
+}
+
+/// 流式上传文件并替换已有媒体项目的内容
+///
+/// 新内容写入新的对象键，数据库更新成功后才删除旧对象
+pub async fn stream_replace_media_content(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+    Query(params): Query<StreamUploadParams>,
+    request: Request,
+) -> Result<Json<MediaItem>, UploadError> {
+    let internal_error = |e: sqlx::Error| {
+        eprintln!("Database error replacing media content: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "数据库错误".to_string())
+    };
+
+    // 先确认媒体存在，避免无效的上传
+    let current_size: Option<i64> = sqlx::query_scalar(
+        "SELECT file_size FROM media_files WHERE id = $1 AND user_id = $2 AND status IN ('active', 'processing')",
+    )
+    .bind(&media_id)
+    .bind(&auth_user.user_id)
+    .fetch_optional(&db.pool)
+    .await
+    .map_err(internal_error)?;
+    let Some(current_size) = current_size else {
+        return Err((StatusCode::NOT_FOUND, "媒体不存在".to_string()));
+    };
+
+    let policy = user_policy(&db, &auth_user.user_id).await?;
+    let upload = receive_upload(&storage, policy, &auth_user.user_id, params, request).await?;
+
+    // 替换内容只按大小的增量占用配额
+    if let Err(e) = check_quota(
+        &db,
+        &auth_user.user_id,
+        upload.size as i64 - current_size,
+        0,
+    )
+    .await
+    {
+        delete_or_enqueue(&db, &storage, &upload.key, None).await;
+        return Err(quota_error(e));
+    }
+    let content_hash = upload.content_hash.clone().unwrap_or_default();
+
+    let prepared = match prepare_blob(
+        &db,
+        &storage,
+        &auth_user.user_id,
+        &upload.key,
+        &content_hash,
+        upload.size as i64,
+    )
+    .await
+    {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            crate::log_with_storage!(error, "准备共享对象失败: {}", e);
+            delete_or_enqueue(&db, &storage, &upload.key, None).await;
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "文件写入失败".to_string(),
+            ));
+        }
+    };
+
+    let result = async {
+        let mut tx = db.pool.begin().await?;
+
+        let old_object: Option<(String, String, i64)> = sqlx::query_as(
+            "SELECT cos_key, storage_backend, file_size FROM media_files WHERE id = $1 AND user_id = $2 AND status IN ('active', 'processing') FOR UPDATE",
+        )
+        .bind(&media_id)
+        .bind(&auth_user.user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some((old_key, old_backend, old_size)) = old_object else {
+            return Ok(None);
+        };
+        check_quota_locked(&mut tx, &auth_user.user_id, upload.size as i64 - old_size, 0).await?;
+
+        // 内容与已有对象相同时共享该对象
+        let key = acquire_blob(&mut tx, &storage, &auth_user.user_id, &prepared).await?;
+
+        let query = r#"
+            UPDATE media_files
+            SET filename = $1,
+                original_filename = $2,
+                file_size = $3,
+                content_type = $4,
+                cos_key = $5,
+                cos_url = $6,
+                cos_bucket = $7,
+                cos_region = $8,
+                storage_backend = $9,
+                media_type = $10,
+                metadata = COALESCE(metadata, '{}'::jsonb) || $11,
+                content_hash = $12,
+                updated_at = $13
+            WHERE id = $14 AND user_id = $15
+            RETURNING *
+        "#;
+
+        let media = sqlx::query_as::<_, MediaItem>(query)
+            .bind(upload.key.rsplit('/').next().unwrap_or(&upload.key))
+            .bind(&upload.original_filename)
+            .bind(upload.size as i64)
+            .bind(&upload.content_type)
+            .bind(&key)
+            .bind(storage.object_url(&key))
+            .bind(storage.bucket())
+            .bind(storage.region())
+            .bind(storage.name())
+            .bind(media_type_from_content_type(&upload.content_type))
+            .bind(serde_json::json!({
+                "upload_method": "server_stream",
+                "etag": upload.etag,
+                "content_check": upload.content_check,
+            }))
+            .bind(&content_hash)
+            .bind(Utc::now())
+            .bind(&media_id)
+            .bind(&auth_user.user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        // 释放旧对象的引用，没有其他媒体引用时在同一事务中登记到删除队列，由任务队列删除
+        if let Some(deletion_id) =
+            release_blob(&mut tx, &old_key, &old_backend, Some(&media_id)).await?
+        {
+            enqueue_deletion_job(&mut *tx, &deletion_id).await?;
+        }
+
+        tx.commit().await?;
+        Ok::<_, QuotaError>(Some(media))
+    }
+    .await;
+
+    match result {
+        Ok(Some(mut media)) => {
+            discard_unused(&db, &storage, &prepared, &media.cos_key).await;
+            // 内容已变化，登记任务重新提取元数据并重新生成缩略图
+            schedule_media_processing(&db, &mut media).await;
+            crate::log_with_storage!(info, "媒体内容已替换: {} -> {}", media.id, media.cos_key);
+            Ok(Json(media))
+        }
+        Ok(None) => {
+            discard_unused(&db, &storage, &prepared, "").await;
+            Err((StatusCode::NOT_FOUND, "媒体不存在".to_string()))
+        }
+        Err(e) => {
+            discard_unused(&db, &storage, &prepared, "").await;
+            match e {
+                QuotaError::Database(e) => Err(internal_error(e)),
+                e => Err(quota_error(e)),
+            }
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use axum::{
+    Json,
+    extract::{Extension, State},
+    http::StatusCode,
+};
+
+use crate::credentials::AuthUser;
+use crate::database::Database;
+use crate::quota::{Usage, user_usage};
+
+/// 获取当前用户的存储用量和配额
+///
+/// 用量包括回收站中尚未彻底删除的媒体，并按媒体类型分类
+pub async fn get_my_usage(
+    State(db): State<Database>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<Json<Usage>, StatusCode> {
+    user_usage(&db, &auth_user.user_id)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            eprintln!("Database error getting usage: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
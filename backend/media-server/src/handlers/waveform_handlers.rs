@@ -0,0 +1,75 @@
+use axum::{
+    Json,
+    extract::{Extension, Path, Query, State},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::credentials::AuthUser;
+use crate::database::Database;
+use crate::handlers::media_handlers::find_readable_media;
+use crate::storage::SharedStorage;
+use crate::waveform;
+
+#[derive(Deserialize, Debug)]
+pub struct WaveformParams {
+    /// `json`（默认）或 `dat`（audiowaveform 二进制格式）
+    pub format: Option<String>,
+    /// 最多返回的像素数，超出时合并相邻像素，用于列表中的小尺寸预览
+    pub pixels: Option<usize>,
+}
+
+/// 获取音频的波形数据（audiowaveform 格式）
+///
+/// 访问权限与原文件相同。波形尚未生成或无法生成时返回 404
+pub async fn get_media_waveform(
+    State(db): State<Database>,
+    State(storage): State<SharedStorage>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(media_id): Path<String>,
+    Query(params): Query<WaveformParams>,
+) -> Result<Response, StatusCode> {
+    let binary = match params.format.as_deref() {
+        None | Some("json") => false,
+        Some("dat") => true,
+        Some(_) => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let media = find_readable_media(&db, &media_id, &auth_user.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Database error getting media for waveform: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut waveform = waveform::load(&db, &storage, &media.id)
+        .await
+        .map_err(|e| {
+            crate::log_with_storage!(error, "读取波形失败 {}: {}", media.id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(pixels) = params.pixels.filter(|pixels| *pixels > 0)
+        && waveform.pixels() > pixels
+    {
+        waveform = waveform.downsample(waveform.pixels().div_ceil(pixels));
+    }
+
+    let mut response = if binary {
+        (
+            [(header::CONTENT_TYPE, "application/octet-stream")],
+            waveform.to_binary(),
+        )
+            .into_response()
+    } else {
+        Json(waveform.to_json()).into_response()
+    };
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("private, no-cache"),
+    );
+    Ok(response)
+}
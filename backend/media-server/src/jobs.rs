@@ -0,0 +1,863 @@
+//! 媒体处理任务队列
+//!
+//! 元数据提取、缩略图生成、音频波形、视频转码、摘要计算和存储对象删除等耗时操作不在请求中执行，
+//! 而是登记到 `media_jobs`，由后台工作线程池处理：
+//! - 按优先级和执行时间领取任务，`FOR UPDATE SKIP LOCKED` 保证多个工作线程（或多个实例）不会重复领取
+//! - 领取时设置可见性超时，执行期间定期续期；工作线程异常退出后任务在超时后被重新领取
+//! - 失败的任务按指数退避重试，超过最大次数或遇到无法重试的错误后进入死信状态 `dead`，
+//!   由管理员排查后重新入队
+//! - 视频转码占用大量 CPU，同时执行的转码任务数受 `TRANSCODE_CONCURRENCY` 限制
+//! - 同一媒体的同类任务最多一个待执行、一个执行中，执行期间失去租约的工作线程立即停止
+//!
+//! 媒体有未完成的处理任务时 `status` 为 `processing`，所有任务结束后恢复为 `active`
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::{Value, json};
+use sqlx::PgExecutor;
+use std::fmt;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::database::Database;
+use crate::dedupe::hash_object;
+use crate::deletion_queue::attempt_queued_deletion;
+use crate::handlers::media_handlers::MediaItem;
+use crate::metadata;
+use crate::storage::{self, SharedStorage, StorageError};
+use crate::thumbnails::{self, ThumbnailError};
+use crate::transcode::{self, TranscodeError};
+use crate::waveform::{self, WaveformError};
+
+/// 首次重试的等待时间（秒），之后每次翻倍
+const BASE_BACKOFF_SECS: i64 = 10;
+
+/// 重试等待时间上限（秒）
+const MAX_BACKOFF_SECS: i64 = 3600;
+
+/// 可见性超时：领取后未续期的任务在超时后可被重新领取
+const VISIBILITY_TIMEOUT_SECS: i64 = 120;
+
+/// 执行中任务的续期间隔
+const HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 没有可执行任务时的轮询间隔
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// 领取任务时持有的事务级咨询锁
+const CLAIM_LOCK_KEY: i64 = 0x6d65_6469_615f_6a6f;
+
+/// 登记新任务后唤醒空闲的工作线程
+static WAKE: Notify = Notify::const_new();
+
+/// 任务类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobType {
+    /// 提取图片和音视频元数据
+    ExtractMetadata,
+    /// 生成图片缩略图，视频的封面和拖动预览图
+    GenerateThumbnails,
+    /// 为没有摘要的媒体计算内容摘要
+    ComputeHash,
+    /// 删除存储对象，载荷为删除队列记录的ID
+    DeleteObject,
+    /// 将视频转码为 HLS 多码率版本
+    TranscodeVideo,
+    /// 解码音频并生成波形
+    GenerateWaveform,
+}
+
+impl JobType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobType::ExtractMetadata => "extract_metadata",
+            JobType::GenerateThumbnails => "generate_thumbnails",
+            JobType::ComputeHash => "compute_hash",
+            JobType::DeleteObject => "delete_object",
+            JobType::TranscodeVideo => "transcode_video",
+            JobType::GenerateWaveform => "generate_waveform",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "extract_metadata" => Some(JobType::ExtractMetadata),
+            "generate_thumbnails" => Some(JobType::GenerateThumbnails),
+            "compute_hash" => Some(JobType::ComputeHash),
+            "delete_object" => Some(JobType::DeleteObject),
+            "transcode_video" => Some(JobType::TranscodeVideo),
+            "generate_waveform" => Some(JobType::GenerateWaveform),
+            _ => None,
+        }
+    }
+
+    /// 默认优先级，数值越大越先执行：元数据决定列表中的排序和筛选，最先处理
+    fn priority(self) -> i32 {
+        match self {
+            JobType::ExtractMetadata => 30,
+            JobType::GenerateThumbnails | JobType::GenerateWaveform => 20,
+            JobType::DeleteObject => 10,
+            JobType::TranscodeVideo => 5,
+            JobType::ComputeHash => 0,
+        }
+    }
+
+    fn max_attempts(self) -> i32 {
+        match self {
+            // 删除失败时由删除队列继续重试
+            JobType::DeleteObject => 3,
+            // 转码耗时很长，失败通常是源文件本身的问题
+            JobType::TranscodeVideo => 3,
+            _ => 5,
+        }
+    }
+
+    /// 单次执行的时间上限
+    fn timeout(self) -> std::time::Duration {
+        let secs = match self {
+            JobType::ExtractMetadata | JobType::DeleteObject => 5 * 60,
+            // 视频需要下载原文件并多次抽取画面，音频需要解码整个文件
+            JobType::GenerateThumbnails | JobType::GenerateWaveform => 30 * 60,
+            // 需要读取整个文件
+            JobType::ComputeHash => 60 * 60,
+            JobType::TranscodeVideo => 4 * 60 * 60,
+        };
+        std::time::Duration::from_secs(secs)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct MediaJob {
+    pub id: String,
+    pub media_id: Option<String>,
+    pub job_type: String,
+    pub payload: Value,
+    pub priority: i32,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub last_error: Option<String>,
+    pub run_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub locked_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct JobStats {
+    pub pending: i64,
+    pub running: i64,
+    pub dead: i64,
+    /// 已到执行时间、等待工作线程领取的数量
+    pub due: i64,
+    pub oldest_pending_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub enum JobError {
+    Storage(StorageError),
+    Database(sqlx::Error),
+    Thumbnail(ThumbnailError),
+    Transcode(TranscodeError),
+    Waveform(WaveformError),
+    /// 任务本身无效（类型未知、缺少参数等）
+    Invalid(String),
+    /// 执行超时或异常退出
+    Aborted(String),
+}
+
+impl JobError {
+    /// 是否值得重试：对象不存在、图片或音频无法解码、转码程序不可用和无效任务重试也不会成功
+    fn is_retryable(&self) -> bool {
+        match self {
+            JobError::Transcode(e) | JobError::Thumbnail(ThumbnailError::Transcode(e)) => {
+                return e.is_retryable();
+            }
+            JobError::Waveform(e) => return e.is_retryable(),
+            _ => {}
+        }
+        !matches!(
+            self,
+            JobError::Storage(StorageError::NotFound(_))
+                | JobError::Thumbnail(ThumbnailError::Storage(StorageError::NotFound(_)))
+                | JobError::Thumbnail(ThumbnailError::Image(_))
+                | JobError::Invalid(_)
+        )
+    }
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Storage(e) => write!(f, "读取存储对象失败: {}", e),
+            JobError::Database(e) => write!(f, "数据库错误: {}", e),
+            JobError::Thumbnail(e) => write!(f, "{}", e),
+            JobError::Transcode(e) => write!(f, "{}", e),
+            JobError::Waveform(e) => write!(f, "{}", e),
+            JobError::Invalid(e) => write!(f, "无效的任务: {}", e),
+            JobError::Aborted(e) => write!(f, "任务中断: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+impl From<StorageError> for JobError {
+    fn from(e: StorageError) -> Self {
+        JobError::Storage(e)
+    }
+}
+
+impl From<sqlx::Error> for JobError {
+    fn from(e: sqlx::Error) -> Self {
+        JobError::Database(e)
+    }
+}
+
+impl From<ThumbnailError> for JobError {
+    fn from(e: ThumbnailError) -> Self {
+        JobError::Thumbnail(e)
+    }
+}
+
+impl From<TranscodeError> for JobError {
+    fn from(e: TranscodeError) -> Self {
+        JobError::Transcode(e)
+    }
+}
+
+impl From<WaveformError> for JobError {
+    fn from(e: WaveformError) -> Self {
+        JobError::Waveform(e)
+    }
+}
+
+/// 登记任务，可以在事务中调用；提交后调用 [`wake_workers`] 让空闲的工作线程立即处理
+///
+/// 同一媒体已有同类型的待执行任务时不重复登记（任务执行时读取媒体的最新状态），返回 `None`；
+/// 由唯一索引 `uq_media_jobs_pending` 保证，并发登记时也不会重复
+pub async fn enqueue<'e, E>(
+    executor: E,
+    job_type: JobType,
+    media_id: Option<&str>,
+    payload: Value,
+) -> Result<Option<String>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+
+    let inserted: Option<String> = sqlx::query_scalar(
+        r#"
+        INSERT INTO media_jobs (
+            id, media_id, job_type, payload, priority, status, attempts, max_attempts,
+            run_at, created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, 'pending', 0, $6, $7, $7, $7)
+        ON CONFLICT (media_id, job_type) WHERE status = 'pending' DO NOTHING
+        RETURNING id
+        "#,
+    )
+    .bind(&id)
+    .bind(media_id)
+    .bind(job_type.as_str())
+    .bind(&payload)
+    .bind(job_type.priority())
+    .bind(job_type.max_attempts())
+    .bind(now)
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(inserted)
+}
+
+/// 唤醒空闲的工作线程
+pub fn wake_workers() {
+    WAKE.notify_waiters();
+}
+
+/// 登记存储对象删除任务，可以在登记删除队列记录的事务中调用
+pub async fn enqueue_deletion_job<'e, E>(executor: E, deletion_id: &str) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    enqueue(
+        executor,
+        JobType::DeleteObject,
+        None,
+        json!({ "deletion_id": deletion_id }),
+    )
+    .await
+    .map(|_| ())
+}
+
+/// 为新上传或内容被替换的媒体登记处理任务，并将媒体标记为 `processing`
+///
+/// 音频还会登记波形任务，启用转码时视频还会登记转码任务；曾经生成过波形或转码过的媒体
+/// 内容被替换为其他类型时，由对应的任务清理旧的结果。同时更新 `media` 中的 `status` 和转码状态
+pub async fn enqueue_media_processing(
+    db: &Database,
+    media: &mut MediaItem,
+) -> Result<(), sqlx::Error> {
+    let mut job_types = vec![JobType::ExtractMetadata, JobType::GenerateThumbnails];
+    if media.content_hash.is_none() {
+        job_types.push(JobType::ComputeHash);
+    }
+    let is_video = media.media_type == "video";
+    let transcode = transcode::enabled() && (is_video || media.transcode_status.is_some());
+    if transcode {
+        job_types.push(JobType::TranscodeVideo);
+    }
+
+    let mut tx = db.pool.begin().await?;
+    if media.media_type == "audio" || waveform::exists(&mut *tx, &media.id).await? {
+        job_types.push(JobType::GenerateWaveform);
+    }
+    for job_type in job_types {
+        enqueue(&mut *tx, job_type, Some(&media.id), json!({})).await?;
+    }
+    let status: Option<String> = sqlx::query_scalar(
+        r#"
+        UPDATE media_files SET status = 'processing'
+        WHERE id = $1 AND status IN ('active', 'processing')
+        RETURNING status
+        "#,
+    )
+    .bind(&media.id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if transcode && is_video {
+        sqlx::query(
+            r#"
+            UPDATE media_files
+            SET transcode_status = 'pending', transcode_progress = 0, transcode_error = NULL
+            WHERE id = $1
+            "#,
+        )
+        .bind(&media.id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    if let Some(status) = status {
+        media.status = status;
+    }
+    if transcode && is_video {
+        media.transcode_status = Some("pending".to_string());
+        media.transcode_progress = Some(0.0);
+        media.transcode_error = None;
+    }
+    wake_workers();
+    Ok(())
+}
+
+/// 登记处理任务，失败只记录日志，不影响上传结果
+pub async fn schedule_media_processing(db: &Database, media: &mut MediaItem) {
+    if let Err(e) = enqueue_media_processing(db, media).await {
+        crate::log_with_storage!(error, "登记媒体处理任务失败 {}: {}", media.id, e);
+    }
+}
+
+/// 在彻底删除媒体的事务中取消其尚未执行的任务
+pub async fn cancel_media_jobs<'e, E>(executor: E, media_id: &str) -> Result<u64, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let result = sqlx::query("DELETE FROM media_jobs WHERE media_id = $1 AND status = 'pending'")
+        .bind(media_id)
+        .execute(executor)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// 媒体的所有任务都已结束时将 `processing` 恢复为 `active`
+async fn settle_media(db: &Database, media_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE media_files SET status = 'active'
+        WHERE id = $1 AND status = 'processing'
+        AND NOT EXISTS (
+            SELECT 1 FROM media_jobs WHERE media_id = $1 AND status IN ('pending', 'running')
+        )
+        "#,
+    )
+    .bind(media_id)
+    .execute(&db.pool)
+    .await?;
+    Ok(())
+}
+
+/// 从回收站恢复的媒体应处于的状态：仍有未完成的任务时为 `processing`
+pub async fn restored_status<'e, E>(
+    executor: E,
+    media_id: &str,
+) -> Result<&'static str, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let unfinished: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM media_jobs WHERE media_id = $1 AND status IN ('pending', 'running'))",
+    )
+    .bind(media_id)
+    .fetch_one(executor)
+    .await?;
+    Ok(if unfinished { "processing" } else { "active" })
+}
+
+fn backoff(attempts: i32) -> Duration {
+    let secs = BASE_BACKOFF_SECS
+        .saturating_mul(1i64 << attempts.clamp(0, 20))
+        .min(MAX_BACKOFF_SECS);
+    Duration::seconds(secs)
+}
+
+/// 领取一个可执行的任务：到达执行时间的待执行任务，或可见性超时的执行中任务
+///
+/// 正在执行的转码任务达到上限时不领取转码任务，同一媒体的同类任务正在执行时不领取其待执行任务。
+/// 这两项检查在事务级咨询锁 [`CLAIM_LOCK_KEY`] 下进行，多个工作线程（或多个实例）依次领取
+async fn claim(db: &Database, worker_id: &str) -> Result<Option<MediaJob>, sqlx::Error> {
+    let now = Utc::now();
+    let mut tx = db.pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(CLAIM_LOCK_KEY)
+        .execute(&mut *tx)
+        .await?;
+
+    let job = sqlx::query_as::<_, MediaJob>(
+        r#"
+        UPDATE media_jobs
+        SET status = 'running', attempts = attempts + 1, locked_until = $1, locked_by = $2, updated_at = $3
+        WHERE id = (
+            SELECT id FROM media_jobs j
+            WHERE ((j.status = 'pending' AND j.run_at <= $3)
+               OR (j.status = 'running' AND j.locked_until < $3))
+              AND (j.job_type <> $4 OR (
+                  SELECT COUNT(*) FROM media_jobs
+                  WHERE job_type = $4 AND status = 'running' AND locked_until >= $3
+              ) < $5)
+              AND (j.status = 'running' OR j.media_id IS NULL OR NOT EXISTS (
+                  SELECT 1 FROM media_jobs r
+                  WHERE r.media_id = j.media_id AND r.job_type = j.job_type AND r.status = 'running'
+              ))
+            ORDER BY j.priority DESC, j.run_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING *
+        "#,
+    )
+    .bind(now + Duration::seconds(VISIBILITY_TIMEOUT_SECS))
+    .bind(worker_id)
+    .bind(now)
+    .bind(JobType::TranscodeVideo.as_str())
+    .bind(transcode::concurrency())
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(job)
+}
+
+/// 延长执行中任务的可见性超时，任务已被其他工作线程领取时返回 `false`
+async fn extend_lease(db: &Database, job: &MediaJob, worker_id: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE media_jobs SET locked_until = $1 WHERE id = $2 AND locked_by = $3 AND status = 'running'",
+    )
+    .bind(Utc::now() + Duration::seconds(VISIBILITY_TIMEOUT_SECS))
+    .bind(&job.id)
+    .bind(worker_id)
+    .execute(&db.pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// 记录任务的执行结果：成功、按退避重试或进入死信状态
+async fn finish(
+    db: &Database,
+    job: &MediaJob,
+    worker_id: &str,
+    result: Result<(), JobError>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let (status, error, run_at) = match &result {
+        Ok(()) => ("succeeded", None, job.run_at),
+        Err(e) if e.is_retryable() && job.attempts < job.max_attempts => {
+            ("pending", Some(e.to_string()), now + backoff(job.attempts))
+        }
+        Err(e) => ("dead", Some(e.to_string()), job.run_at),
+    };
+
+    if let Some(error) = &error {
+        crate::log_with_storage!(
+            warn,
+            "任务执行失败 {} {} (第 {} 次，状态 {}): {}",
+            job.job_type,
+            job.id,
+            job.attempts,
+            status,
+            error
+        );
+    }
+
+    // 执行期间已登记了新的同类任务（例如内容被替换），由新任务重新处理，本任务不再重试
+    if status == "pending"
+        && let Some(media_id) = &job.media_id
+    {
+        let superseded = sqlx::query(
+            r#"
+            DELETE FROM media_jobs
+            WHERE id = $1 AND locked_by = $2 AND status = 'running'
+            AND EXISTS (
+                SELECT 1 FROM media_jobs
+                WHERE media_id = $3 AND job_type = $4 AND status = 'pending'
+            )
+            "#,
+        )
+        .bind(&job.id)
+        .bind(worker_id)
+        .bind(media_id)
+        .bind(&job.job_type)
+        .execute(&db.pool)
+        .await?;
+        if superseded.rows_affected() > 0 {
+            crate::log_with_storage!(info, "已有新的同类任务，不再重试: {}", job.id);
+            return Ok(());
+        }
+    }
+
+    let updated = sqlx::query(
+        r#"
+        UPDATE media_jobs
+        SET status = $1, last_error = COALESCE($2, last_error), run_at = $3,
+            locked_until = NULL, locked_by = NULL, updated_at = $4,
+            finished_at = CASE WHEN $1 = 'pending' THEN NULL ELSE $4 END
+        WHERE id = $5 AND locked_by = $6 AND status = 'running'
+        "#,
+    )
+    .bind(status)
+    .bind(&error)
+    .bind(run_at)
+    .bind(now)
+    .bind(&job.id)
+    .bind(worker_id)
+    .execute(&db.pool)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        crate::log_with_storage!(warn, "任务已超时并被重新领取，忽略本次结果: {}", job.id);
+        return Ok(());
+    }
+
+    if job.job_type == JobType::TranscodeVideo.as_str()
+        && let (Some(media_id), Some(error)) = (&job.media_id, &error)
+    {
+        transcode::record_failure(db, media_id, error, status == "pending").await?;
+    }
+
+    if let Some(media_id) = &job.media_id {
+        settle_media(db, media_id).await?;
+    }
+    Ok(())
+}
+
+/// 读取任务对应的媒体，媒体已被彻底删除时返回 `None`
+async fn load_media(db: &Database, job: &MediaJob) -> Result<Option<MediaItem>, JobError> {
+    let media_id = job
+        .media_id
+        .as_deref()
+        .ok_or_else(|| JobError::Invalid("缺少媒体ID".to_string()))?;
+    let media = sqlx::query_as::<_, MediaItem>("SELECT * FROM media_files WHERE id = $1")
+        .bind(media_id)
+        .fetch_optional(&db.pool)
+        .await?;
+    if media.is_none() {
+        crate::log_with_storage!(info, "媒体已删除，跳过任务 {}: {}", job.job_type, media_id);
+    }
+    Ok(media)
+}
+
+/// 执行任务
+async fn execute(db: &Database, storage: &SharedStorage, job: &MediaJob) -> Result<(), JobError> {
+    let job_type = JobType::parse(&job.job_type)
+        .ok_or_else(|| JobError::Invalid(format!("未知的任务类型 {}", job.job_type)))?;
+
+    match job_type {
+        JobType::ExtractMetadata => {
+            let Some(media) = load_media(db, job).await? else {
+                return Ok(());
+            };
+            let extracted = metadata::extract(storage, &media).await?;
+            metadata::save(db, &media, &extracted).await?;
+        }
+        JobType::GenerateThumbnails => {
+            let Some(media) = load_media(db, job).await? else {
+                return Ok(());
+            };
+            let derivatives = thumbnails::generate(db, storage, &media).await?;
+            if !derivatives.is_empty() {
+                crate::log_with_storage!(
+                    info,
+                    "已生成 {} 个缩略图: {}",
+                    derivatives.len(),
+                    media.id
+                );
+            }
+        }
+        JobType::ComputeHash => {
+            let Some(media) = load_media(db, job).await? else {
+                return Ok(());
+            };
+            if media.content_hash.is_none() {
+                let source = storage::for_backend(storage, &media.storage_backend)?;
+                let hash = hash_object(&source, &media.cos_key).await?;
+                // 内容在计算期间被替换时不写入
+                sqlx::query(
+                    "UPDATE media_files SET content_hash = $1 WHERE id = $2 AND cos_key = $3 AND content_hash IS NULL",
+                )
+                .bind(&hash)
+                .bind(&media.id)
+                .bind(&media.cos_key)
+                .execute(&db.pool)
+                .await?;
+            }
+        }
+        JobType::TranscodeVideo => {
+            let Some(media) = load_media(db, job).await? else {
+                return Ok(());
+            };
+            transcode::transcode(db, storage, &media).await?;
+        }
+        JobType::GenerateWaveform => {
+            let Some(media) = load_media(db, job).await? else {
+                return Ok(());
+            };
+            if let Some(waveform) = waveform::generate(db, storage, &media).await? {
+                crate::log_with_storage!(
+                    info,
+                    "已生成波形: {} ({} 像素)",
+                    media.id,
+                    waveform.pixels()
+                );
+            }
+        }
+        JobType::DeleteObject => {
+            let deletion_id = job
+                .payload
+                .get("deletion_id")
+                .and_then(Value::as_str)
+                .ok_or_else(|| JobError::Invalid("缺少 deletion_id".to_string()))?;
+            // 删除失败时删除队列已安排重试，任务本身视为完成
+            attempt_queued_deletion(db, storage, deletion_id).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 在独立的任务中执行，期间定期续期；超时、异常退出或失去租约视为失败
+async fn run(
+    db: &Database,
+    storage: &SharedStorage,
+    job: &MediaJob,
+    worker_id: &str,
+) -> Result<(), JobError> {
+    let timeout = JobType::parse(&job.job_type)
+        .map(JobType::timeout)
+        .unwrap_or(HEARTBEAT_INTERVAL);
+
+    let mut handle = {
+        let (db, storage, job) = (db.clone(), storage.clone(), job.clone());
+        tokio::spawn(async move { execute(&db, &storage, &job).await })
+    };
+    let deadline = tokio::time::sleep(timeout);
+    tokio::pin!(deadline);
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    heartbeat.tick().await;
+
+    loop {
+        tokio::select! {
+            joined = &mut handle => {
+                return joined.unwrap_or_else(|e| Err(JobError::Aborted(e.to_string())));
+            }
+            _ = &mut deadline => {
+                handle.abort();
+                return Err(JobError::Aborted(format!("执行超过 {} 秒", timeout.as_secs())));
+            }
+            _ = heartbeat.tick() => {
+                match extend_lease(db, job, worker_id).await {
+                    Ok(true) => {}
+                    // 续期前已超时并被其他工作线程领取，停止执行以免重复处理
+                    Ok(false) => {
+                        handle.abort();
+                        return Err(JobError::Aborted("任务已被其他工作线程领取".to_string()));
+                    }
+                    Err(e) => crate::log_with_storage!(warn, "任务续期失败 {}: {}", job.id, e),
+                }
+            }
+        }
+    }
+}
+
+/// 领取并执行一个任务，没有可执行的任务时返回 `false`
+pub async fn process_next(
+    db: &Database,
+    storage: &SharedStorage,
+    worker_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let Some(job) = claim(db, worker_id).await? else {
+        return Ok(false);
+    };
+
+    // 可见性超时后被重新领取的任务可能已用完重试次数
+    let result = if job.attempts > job.max_attempts {
+        Err(JobError::Aborted("多次执行超时".to_string()))
+    } else {
+        run(db, storage, &job, worker_id).await
+    };
+    finish(db, &job, worker_id, result).await?;
+    Ok(true)
+}
+
+/// 工作线程：循环领取任务，空闲时等待新任务登记或轮询间隔到期
+pub async fn run_worker(db: Database, storage: SharedStorage, worker_id: String) {
+    loop {
+        match process_next(&db, &storage, &worker_id).await {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(e) => crate::log_with_storage!(error, "处理任务队列失败 {}: {}", worker_id, e),
+        }
+
+        tokio::select! {
+            _ = WAKE.notified() => {}
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+        }
+    }
+}
+
+/// 列出媒体的任务，最新的在前
+pub async fn list_media_jobs(db: &Database, media_id: &str) -> Result<Vec<MediaJob>, sqlx::Error> {
+    sqlx::query_as::<_, MediaJob>(
+        "SELECT * FROM media_jobs WHERE media_id = $1 ORDER BY created_at DESC, priority DESC",
+    )
+    .bind(media_id)
+    .fetch_all(&db.pool)
+    .await
+}
+
+/// 任务队列的统计信息
+pub async fn job_stats(db: &Database) -> Result<JobStats, sqlx::Error> {
+    let (pending, running, dead, due, oldest_pending_at): (
+        i64,
+        i64,
+        i64,
+        i64,
+        Option<DateTime<Utc>>,
+    ) = sqlx::query_as(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE status = 'pending'),
+            COUNT(*) FILTER (WHERE status = 'running'),
+            COUNT(*) FILTER (WHERE status = 'dead'),
+            COUNT(*) FILTER (WHERE status = 'pending' AND run_at <= NOW()),
+            MIN(created_at) FILTER (WHERE status = 'pending')
+        FROM media_jobs
+        "#,
+    )
+    .fetch_one(&db.pool)
+    .await?;
+
+    Ok(JobStats {
+        pending,
+        running,
+        dead,
+        due,
+        oldest_pending_at,
+    })
+}
+
+/// 列出任务队列中的记录
+pub async fn list_jobs(
+    db: &Database,
+    status: Option<&str>,
+    job_type: Option<&str>,
+    limit: i64,
+) -> Result<Vec<MediaJob>, sqlx::Error> {
+    sqlx::query_as::<_, MediaJob>(
+        r#"
+        SELECT * FROM media_jobs
+        WHERE ($1::TEXT IS NULL OR status = $1) AND ($2::TEXT IS NULL OR job_type = $2)
+        ORDER BY created_at DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(status)
+    .bind(job_type)
+    .bind(limit)
+    .fetch_all(&db.pool)
+    .await
+}
+
+/// 将死信任务重新入队，`ids` 为空时重新入队所有死信任务；对应的媒体重新标记为 `processing`
+///
+/// 已有同类待执行任务的死信任务直接删除，不计入返回的数量
+pub async fn requeue_dead_jobs(db: &Database, ids: Option<&[String]>) -> Result<u64, sqlx::Error> {
+    let now = Utc::now();
+    let mut tx = db.pool.begin().await?;
+
+    // 同一媒体的同类任务只能有一个待执行：已有待执行任务时不再重新入队，多个死信任务只保留最新的一个
+    sqlx::query(
+        r#"
+        DELETE FROM media_jobs d
+        WHERE d.status = 'dead' AND d.media_id IS NOT NULL AND ($1::TEXT[] IS NULL OR d.id = ANY($1))
+        AND EXISTS (
+            SELECT 1 FROM media_jobs o
+            WHERE o.media_id = d.media_id AND o.job_type = d.job_type
+            AND (o.status = 'pending' OR (
+                o.status = 'dead' AND ($1::TEXT[] IS NULL OR o.id = ANY($1))
+                AND (o.created_at, o.id) > (d.created_at, d.id)
+            ))
+        )
+        "#,
+    )
+    .bind(ids)
+    .execute(&mut *tx)
+    .await?;
+
+    let media_ids: Vec<Option<String>> = sqlx::query_scalar(
+        r#"
+        UPDATE media_jobs
+        SET status = 'pending', attempts = 0, run_at = $1, updated_at = $1, finished_at = NULL
+        WHERE status = 'dead' AND ($2::TEXT[] IS NULL OR id = ANY($2))
+        RETURNING media_id
+        "#,
+    )
+    .bind(now)
+    .bind(ids)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let affected: Vec<String> = media_ids.iter().flatten().cloned().collect();
+    sqlx::query(
+        "UPDATE media_files SET status = 'processing' WHERE id = ANY($1) AND status = 'active'",
+    )
+    .bind(&affected)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    wake_workers();
+    Ok(media_ids.len() as u64)
+}
+
+/// 删除已成功超过保留期的任务记录，死信任务保留到管理员处理
+pub async fn purge_finished_jobs(db: &Database, retention: Duration) -> Result<u64, sqlx::Error> {
+    let result =
+        sqlx::query("DELETE FROM media_jobs WHERE status = 'succeeded' AND finished_at < $1")
+            .bind(Utc::now() - retention)
+            .execute(&db.pool)
+            .await?;
+    Ok(result.rows_affected())
+}
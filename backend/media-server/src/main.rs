@@ -1,13 +1,30 @@
 // 添加模块声明
 mod credentials;
 mod database;
+mod dedupe;
+mod deletion_queue;
+mod gc;
 mod handlers;
+mod jobs;
 mod logging;
+mod metadata;
+mod previews;
+mod quota;
 mod routes;
+mod similarity;
+mod sniff;
+mod state;
+mod storage;
+mod tasks;
+mod thumbnails;
+mod transcode;
+mod upload_policy;
+mod waveform;
 
 use database::Database;
 use logging::init_logging;
 use routes::{create_routes, print_endpoints};
+use state::AppState;
 
 /// Media Hub 服务器入口点
 #[tokio::main]
@@ -21,8 +38,20 @@ async fn main() {
     // 初始化数据库
     let database = Database::new().await.expect("数据库初始化失败");
 
+    // 初始化存储后端
+    let storage = storage::from_env().expect("存储后端初始化失败");
+    crate::log_with_storage!(info, "使用存储后端: {}", storage.name());
+
+    // 加载上传策略
+    upload_policy::init().expect("上传策略加载失败");
+
+    let state = AppState { database, storage };
+
+    // 启动后台任务
+    tasks::spawn_background_tasks(&state);
+
     // 创建应用路由
-    let app = create_routes().with_state(database);
+    let app = create_routes().with_state(state);
 
     // 绑定服务器地址
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
@@ -0,0 +1,802 @@
+//! 音频文件解析：MP3、FLAC、WAV 和 OGG
+//!
+//! - MP3: ID3v2 标签之后的第一个帧头给出采样率和声道；时长优先使用 Xing/VBRI 头中的帧数，
+//!   否则按固定码率估算。标签来自 ID3v2，缺失时回退到文件末尾的 ID3v1
+//! - FLAC: `STREAMINFO` 和 `VORBIS_COMMENT` 元数据块
+//! - WAV: `fmt `、`data` 和 `LIST/INFO` 块
+//! - OGG: Vorbis/Opus 的标识头和注释头，时长来自最后一页的 granule position
+
+use super::probe::{AudioStream, MediaProbe, ObjectReader, Tags};
+use crate::storage::StorageError;
+
+/// 标签读取的字节数上限，封面图片等大块数据之后的标签会被忽略
+const MAX_TAG_LEN: u64 = 1024 * 1024;
+
+/// 查找 MP3 第一帧时读取的字节数
+const FRAME_SEARCH_LEN: u64 = 64 * 1024;
+
+/// 元数据块或 RIFF 块数量上限
+const MAX_CHUNKS: usize = 256;
+
+fn le_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn le_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// ID3v2 使用的 syncsafe 整数（每字节 7 位）
+fn syncsafe(data: &[u8]) -> u32 {
+    data.iter()
+        .take(4)
+        .fold(0, |value, byte| (value << 7) | (*byte & 0x7F) as u32)
+}
+
+fn latin1(data: &[u8]) -> String {
+    data.iter().map(|byte| *byte as char).collect()
+}
+
+fn utf16(data: &[u8], big_endian: bool) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| {
+            if big_endian {
+                u16::from_be_bytes([pair[0], pair[1]])
+            } else {
+                u16::from_le_bytes([pair[0], pair[1]])
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Vorbis 注释（FLAC 和 OGG 共用）：厂商字符串，然后是 `KEY=value` 列表
+fn parse_vorbis_comment(data: &[u8], tags: &mut Tags) {
+    let Some(vendor_len) = le_u32(data, 0) else {
+        return;
+    };
+    let mut offset = 4 + vendor_len as usize;
+    let Some(count) = le_u32(data, offset) else {
+        return;
+    };
+    offset += 4;
+
+    for _ in 0..count {
+        let Some(len) = le_u32(data, offset) else {
+            return;
+        };
+        offset += 4;
+        let Some(comment) = data.get(offset..offset + len as usize) else {
+            return;
+        };
+        offset += len as usize;
+
+        let comment = String::from_utf8_lossy(comment);
+        if let Some((name, value)) = comment.split_once('=') {
+            tags.set(name, value);
+        }
+    }
+}
+
+// MP3
+
+/// ID3v2 文本帧的内容，多个值时取第一个
+fn id3_text(frame: &[u8]) -> String {
+    let Some((encoding, text)) = frame.split_first() else {
+        return String::new();
+    };
+    let value = match encoding {
+        0 => latin1(text),
+        1 => match text {
+            [0xFE, 0xFF, rest @ ..] => utf16(rest, true),
+            [0xFF, 0xFE, rest @ ..] => utf16(rest, false),
+            _ => utf16(text, false),
+        },
+        2 => utf16(text, true),
+        _ => String::from_utf8_lossy(text).to_string(),
+    };
+    value.split('\0').next().unwrap_or_default().to_string()
+}
+
+/// 解析 ID3v2 标签中的标题、艺术家和专辑
+fn parse_id3v2(tag: &[u8], tags: &mut Tags) {
+    let Some(&version) = tag.get(3) else {
+        return;
+    };
+    let flags = tag.get(5).copied().unwrap_or(0);
+    let mut offset = 10;
+
+    // 跳过扩展头
+    if flags & 0x40 != 0 && version >= 3 {
+        let Some(size) = tag.get(10..14) else {
+            return;
+        };
+        offset += match version {
+            3 => be_u32(size, 0).unwrap_or(0) as usize + 4,
+            _ => syncsafe(size) as usize,
+        };
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    while let Some(header) = tag.get(offset..offset + header_len) {
+        // 遇到填充区结束
+        if header[0] == 0 {
+            break;
+        }
+        let id = &header[..id_len];
+        let size = match version {
+            2 => u32::from_be_bytes([0, header[3], header[4], header[5]]),
+            3 => be_u32(header, 4).unwrap_or(0),
+            _ => syncsafe(&header[4..8]),
+        } as usize;
+        offset += header_len;
+        let Some(frame) = tag.get(offset..offset + size) else {
+            break;
+        };
+        offset += size;
+
+        let name = match id {
+            b"TIT2" | b"TT2" => "title",
+            b"TPE1" | b"TP1" => "artist",
+            b"TALB" | b"TAL" => "album",
+            _ => continue,
+        };
+        tags.set(name, &id3_text(frame));
+    }
+}
+
+/// 文件末尾 128 字节的 ID3v1 标签
+fn parse_id3v1(tail: &[u8], tags: &mut Tags) {
+    if tail.len() != 128 || !tail.starts_with(b"TAG") {
+        return;
+    }
+    tags.set("title", &latin1(&tail[3..33]));
+    tags.set("artist", &latin1(&tail[33..63]));
+    tags.set("album", &latin1(&tail[63..93]));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MpegVersion {
+    V1,
+    V2,
+    V25,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameHeader {
+    version: MpegVersion,
+    layer: u8,
+    /// 码率（比特/秒）
+    bitrate: u32,
+    sample_rate: u32,
+    channels: u16,
+    padding: u32,
+}
+
+const BITRATES_V1: [[u32; 14]; 3] = [
+    [
+        32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+    ],
+    [
+        32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+    ],
+    [
+        32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+];
+
+const BITRATES_V2: [[u32; 14]; 2] = [
+    [
+        32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+    ],
+    [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+impl FrameHeader {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let [0xFF, b1, b2, b3, ..] = *data else {
+            return None;
+        };
+        if b1 & 0xE0 != 0xE0 {
+            return None;
+        }
+
+        let version = match (b1 >> 3) & 0x03 {
+            0 => MpegVersion::V25,
+            2 => MpegVersion::V2,
+            3 => MpegVersion::V1,
+            _ => return None,
+        };
+        let layer = match (b1 >> 1) & 0x03 {
+            1 => 3,
+            2 => 2,
+            3 => 1,
+            _ => return None,
+        };
+
+        // 0 为自由码率，15 无效
+        let bitrate_index = (b2 >> 4) as usize;
+        if bitrate_index == 0 || bitrate_index == 15 {
+            return None;
+        }
+        let kbps = match version {
+            MpegVersion::V1 => BITRATES_V1[layer as usize - 1][bitrate_index - 1],
+            _ => BITRATES_V2[if layer == 1 { 0 } else { 1 }][bitrate_index - 1],
+        };
+
+        let base_rate = match (b2 >> 2) & 0x03 {
+            0 => 44100,
+            1 => 48000,
+            2 => 32000,
+            _ => return None,
+        };
+        let sample_rate = match version {
+            MpegVersion::V1 => base_rate,
+            MpegVersion::V2 => base_rate / 2,
+            MpegVersion::V25 => base_rate / 4,
+        };
+
+        Some(Self {
+            version,
+            layer,
+            bitrate: kbps * 1000,
+            sample_rate,
+            channels: if b3 >> 6 == 3 { 1 } else { 2 },
+            padding: ((b2 >> 1) & 0x01) as u32,
+        })
+    }
+
+    fn samples_per_frame(&self) -> u32 {
+        match (self.layer, self.version) {
+            (1, _) => 384,
+            (3, MpegVersion::V2 | MpegVersion::V25) => 576,
+            _ => 1152,
+        }
+    }
+
+    fn frame_len(&self) -> usize {
+        if self.layer == 1 {
+            ((12 * self.bitrate / self.sample_rate + self.padding) * 4) as usize
+        } else {
+            (self.samples_per_frame() / 8 * self.bitrate / self.sample_rate + self.padding) as usize
+        }
+    }
+
+    /// Xing/Info 头相对于帧开头的偏移（帧头 + side information）
+    fn xing_offset(&self) -> usize {
+        match (self.version, self.channels) {
+            (MpegVersion::V1, 1) => 4 + 17,
+            (MpegVersion::V1, _) => 4 + 32,
+            (_, 1) => 4 + 9,
+            _ => 4 + 17,
+        }
+    }
+
+    /// VBR 头中记录的总帧数
+    fn vbr_frames(&self, frame: &[u8]) -> Option<u32> {
+        let xing = self.xing_offset();
+        if let Some(tag) = frame.get(xing..xing + 4)
+            && (tag == b"Xing" || tag == b"Info")
+        {
+            let flags = be_u32(frame, xing + 4)?;
+            return (flags & 0x01 != 0)
+                .then(|| be_u32(frame, xing + 8))
+                .flatten();
+        }
+        // VBRI 头固定位于帧头之后 32 字节
+        if frame.get(36..40) == Some(b"VBRI") {
+            return be_u32(frame, 36 + 14);
+        }
+        None
+    }
+}
+
+/// 查找第一个有效的帧头，要求紧随其后的也是帧头（数据不足时除外），避免误判
+fn find_frame(data: &[u8]) -> Option<(usize, FrameHeader)> {
+    (0..data.len().saturating_sub(4)).find_map(|offset| {
+        let header = FrameHeader::parse(&data[offset..])?;
+        let next = offset + header.frame_len();
+        if next + 4 <= data.len() {
+            let next_header = FrameHeader::parse(&data[next..])?;
+            if next_header.version != header.version || next_header.layer != header.layer {
+                return None;
+            }
+        }
+        Some((offset, header))
+    })
+}
+
+pub async fn probe_mp3(reader: &ObjectReader<'_>) -> Result<Option<MediaProbe>, StorageError> {
+    let mut probe = MediaProbe::new("mp3");
+
+    let head = reader.read_at(0, 10).await?;
+    let mut audio_start = 0;
+    if head.starts_with(b"ID3") && head.len() == 10 {
+        let footer = if head[5] & 0x10 != 0 { 10 } else { 0 };
+        let tag_len = 10 + syncsafe(&head[6..10]) as u64 + footer;
+        let tag = reader.read_at(0, tag_len.min(MAX_TAG_LEN)).await?;
+        parse_id3v2(&tag, &mut probe.tags);
+        audio_start = tag_len;
+    }
+
+    let data = reader.read_at(audio_start, FRAME_SEARCH_LEN).await?;
+    let Some((frame_offset, header)) = find_frame(&data) else {
+        return Ok(None);
+    };
+
+    // 末尾的 ID3v1 标签不属于音频数据
+    let mut audio_end = reader.size;
+    if reader.size >= audio_start + 128 {
+        let tail = reader.read_at(reader.size - 128, 128).await?;
+        if tail.starts_with(b"TAG") {
+            audio_end -= 128;
+        }
+        parse_id3v1(&tail, &mut probe.tags);
+    }
+    let audio_bytes = audio_end.saturating_sub(audio_start + frame_offset as u64);
+
+    probe.duration = match header.vbr_frames(&data[frame_offset..]) {
+        Some(frames) if frames > 0 => {
+            let duration =
+                frames as f64 * header.samples_per_frame() as f64 / header.sample_rate as f64;
+            probe.bitrate = Some((audio_bytes as f64 * 8.0 / duration).round() as u64);
+            Some(duration)
+        }
+        _ => {
+            probe.bitrate = Some(header.bitrate as u64);
+            Some(audio_bytes as f64 * 8.0 / header.bitrate as f64)
+        }
+    };
+    probe.audio = Some(AudioStream {
+        codec: format!("mp{}", header.layer),
+        sample_rate: Some(header.sample_rate),
+        channels: Some(header.channels),
+        bits_per_sample: None,
+    });
+
+    Ok(Some(probe))
+}
+
+// FLAC
+
+pub async fn probe_flac(reader: &ObjectReader<'_>) -> Result<Option<MediaProbe>, StorageError> {
+    let mut probe = MediaProbe::new("flac");
+    let mut offset = 4;
+
+    for _ in 0..MAX_CHUNKS {
+        let header = reader.read_at(offset, 4).await?;
+        let [flags, a, b, c] = header[..] else {
+            break;
+        };
+        let len = u32::from_be_bytes([0, a, b, c]) as u64;
+        let block_start = offset + 4;
+
+        match flags & 0x7F {
+            // STREAMINFO
+            0 => {
+                let info = reader.read_at(block_start, len).await?;
+                if info.len() < 18 {
+                    return Ok(None);
+                }
+                let sample_rate =
+                    ((info[10] as u32) << 12) | ((info[11] as u32) << 4) | (info[12] as u32 >> 4);
+                let channels = ((info[12] >> 1) & 0x07) as u16 + 1;
+                let bits = ((((info[12] & 0x01) << 4) | (info[13] >> 4)) + 1) as u16;
+                let total_samples =
+                    (((info[13] & 0x0F) as u64) << 32) | be_u32(&info, 14).unwrap_or(0) as u64;
+
+                if sample_rate > 0 && total_samples > 0 {
+                    probe.duration = Some(total_samples as f64 / sample_rate as f64);
+                }
+                probe.audio = Some(AudioStream {
+                    codec: "flac".to_string(),
+                    sample_rate: (sample_rate > 0).then_some(sample_rate),
+                    channels: Some(channels),
+                    bits_per_sample: Some(bits),
+                });
+            }
+            // VORBIS_COMMENT
+            4 => {
+                let comment = reader.read_at(block_start, len.min(MAX_TAG_LEN)).await?;
+                parse_vorbis_comment(&comment, &mut probe.tags);
+            }
+            _ => {}
+        }
+
+        offset = block_start + len;
+        if flags & 0x80 != 0 || offset >= reader.size {
+            break;
+        }
+    }
+
+    Ok(probe.audio.is_some().then_some(probe))
+}
+
+// WAV
+
+fn wav_codec(format: u16, bits: u16) -> String {
+    match format {
+        1 => format!("pcm_s{}le", bits).replace("pcm_s8le", "pcm_u8"),
+        3 => format!("pcm_f{}le", bits),
+        6 => "pcm_alaw".to_string(),
+        7 => "pcm_mulaw".to_string(),
+        0x02 | 0x11 => "adpcm".to_string(),
+        0x55 => "mp3".to_string(),
+        other => format!("0x{:04x}", other),
+    }
+}
+
+pub async fn probe_wav(reader: &ObjectReader<'_>) -> Result<Option<MediaProbe>, StorageError> {
+    let mut probe = MediaProbe::new("wav");
+    let mut byte_rate = 0u32;
+    let mut data_len = None;
+    let mut offset = 12;
+
+    for _ in 0..MAX_CHUNKS {
+        let header = reader.read_at(offset, 8).await?;
+        if header.len() < 8 {
+            break;
+        }
+        let id = &header[..4];
+        let len = le_u32(&header, 4).unwrap_or(0) as u64;
+        let chunk_start = offset + 8;
+
+        match id {
+            b"fmt " => {
+                let fmt = reader.read_at(chunk_start, len.min(64)).await?;
+                let Some(mut format) = le_u16(&fmt, 0) else {
+                    return Ok(None);
+                };
+                let bits = le_u16(&fmt, 14).unwrap_or(0);
+                // WAVE_FORMAT_EXTENSIBLE 的实际格式在子格式 GUID 的开头
+                if format == 0xFFFE
+                    && let Some(sub_format) = le_u16(&fmt, 24)
+                {
+                    format = sub_format;
+                }
+                byte_rate = le_u32(&fmt, 8).unwrap_or(0);
+                probe.audio = Some(AudioStream {
+                    codec: wav_codec(format, bits),
+                    sample_rate: le_u32(&fmt, 4),
+                    channels: le_u16(&fmt, 2),
+                    bits_per_sample: (bits > 0).then_some(bits),
+                });
+            }
+            b"data" => {
+                // 流式写入的文件可能没有回填长度
+                let available = reader.size - chunk_start;
+                data_len = Some(if len == 0 || len == u32::MAX as u64 {
+                    available
+                } else {
+                    len.min(available)
+                });
+            }
+            b"LIST" => {
+                let list = reader.read_at(chunk_start, len.min(MAX_TAG_LEN)).await?;
+                if list.starts_with(b"INFO") {
+                    let mut position = 4;
+                    while let (Some(sub_id), Some(sub_len)) = (
+                        list.get(position..position + 4),
+                        le_u32(&list, position + 4),
+                    ) {
+                        let start = position + 8;
+                        let Some(text) = list.get(start..start + sub_len as usize) else {
+                            break;
+                        };
+                        let name = match sub_id {
+                            b"INAM" => "title",
+                            b"IART" => "artist",
+                            b"IPRD" => "album",
+                            _ => "",
+                        };
+                        probe.tags.set(name, &String::from_utf8_lossy(text));
+                        position = start + sub_len as usize + (sub_len as usize & 1);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        // 块按偶数字节对齐
+        offset = chunk_start + len + (len & 1);
+        if offset >= reader.size {
+            break;
+        }
+    }
+
+    if probe.audio.is_none() {
+        return Ok(None);
+    }
+    if byte_rate > 0 {
+        probe.bitrate = Some(byte_rate as u64 * 8);
+        if let Some(data_len) = data_len {
+            probe.duration = Some(data_len as f64 / byte_rate as f64);
+        }
+    }
+    Ok(Some(probe))
+}
+
+// OGG
+
+/// 开头读取的字节数，标识头和注释头通常在前几页
+const OGG_HEAD_LEN: u64 = 256 * 1024;
+
+/// 查找最后一页时从末尾读取的字节数（单页最大约 64KB）
+const OGG_TAIL_LEN: u64 = 65_536 + 27 + 255;
+
+/// 页头：`OggS`、版本、类型、granule position、序列号、页码、校验和、分段数和分段表
+fn ogg_page(data: &[u8]) -> Option<(u64, &[u8], &[u8])> {
+    if !data.starts_with(b"OggS") || *data.get(4)? != 0 {
+        return None;
+    }
+    let granule = u64::from_le_bytes(data.get(6..14)?.try_into().ok()?);
+    let segments = *data.get(26)? as usize;
+    let lacing = data.get(27..27 + segments)?;
+    let body_len: usize = lacing.iter().map(|len| *len as usize).sum();
+    let body_start = 27 + segments;
+    Some((
+        granule,
+        lacing,
+        data.get(body_start..body_start + body_len)?,
+    ))
+}
+
+/// 从开头的页中拼出前 `count` 个数据包
+fn ogg_packets(data: &[u8], count: usize) -> Vec<Vec<u8>> {
+    let mut packets = Vec::new();
+    let mut current = Vec::new();
+    let mut offset = 0;
+
+    while packets.len() < count {
+        let Some((_, lacing, body)) = data.get(offset..).and_then(ogg_page) else {
+            break;
+        };
+        offset += 27 + lacing.len() + body.len();
+
+        let mut position = 0;
+        for len in lacing {
+            let len = *len as usize;
+            current.extend_from_slice(&body[position..position + len]);
+            position += len;
+            // 长度小于 255 的分段结束一个数据包
+            if len < 255 {
+                packets.push(std::mem::take(&mut current));
+                if packets.len() == count {
+                    break;
+                }
+            }
+        }
+    }
+    packets
+}
+
+pub async fn probe_ogg(reader: &ObjectReader<'_>) -> Result<Option<MediaProbe>, StorageError> {
+    let mut probe = MediaProbe::new("ogg");
+
+    let head = reader.read_at(0, OGG_HEAD_LEN).await?;
+    let packets = ogg_packets(&head, 2);
+    let Some(identification) = packets.first() else {
+        return Ok(None);
+    };
+
+    // granule position 的时间单位和 Opus 开头需要丢弃的样本数
+    let (granule_rate, pre_skip) = if identification.starts_with(b"\x01vorbis") {
+        let sample_rate = le_u32(identification, 12).unwrap_or(0);
+        probe.audio = Some(AudioStream {
+            codec: "vorbis".to_string(),
+            sample_rate: Some(sample_rate),
+            channels: identification.get(11).map(|channels| *channels as u16),
+            bits_per_sample: None,
+        });
+        if let Some(comment) = packets.get(1).and_then(|p| p.strip_prefix(b"\x03vorbis")) {
+            parse_vorbis_comment(comment, &mut probe.tags);
+        }
+        (sample_rate, 0)
+    } else if identification.starts_with(b"OpusHead") {
+        // Opus 始终以 48kHz 解码，头中的采样率只是原始输入的采样率
+        probe.audio = Some(AudioStream {
+            codec: "opus".to_string(),
+            sample_rate: Some(48000),
+            channels: identification.get(9).map(|channels| *channels as u16),
+            bits_per_sample: None,
+        });
+        if let Some(comment) = packets.get(1).and_then(|p| p.strip_prefix(b"OpusTags")) {
+            parse_vorbis_comment(comment, &mut probe.tags);
+        }
+        (48000, le_u16(identification, 10).unwrap_or(0) as u64)
+    } else {
+        return Ok(None);
+    };
+
+    // 最后一页的 granule position 是总样本数
+    let tail_start = reader.size.saturating_sub(OGG_TAIL_LEN);
+    let tail = reader.read_at(tail_start, OGG_TAIL_LEN).await?;
+    let last_granule = (0..tail.len().saturating_sub(4))
+        .rev()
+        .filter(|offset| tail[*offset..].starts_with(b"OggS"))
+        .filter_map(|offset| ogg_page(&tail[offset..]))
+        .map(|(granule, _, _)| granule)
+        .find(|granule| *granule != u64::MAX);
+    if let Some(granule) = last_granule
+        && granule_rate > 0
+    {
+        probe.duration = Some(granule.saturating_sub(pre_skip) as f64 / granule_rate as f64);
+    }
+
+    Ok(Some(probe))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::probe::probe_bytes;
+
+    fn vorbis_comment(comments: &[&str]) -> Vec<u8> {
+        let mut data = 3u32.to_le_bytes().to_vec();
+        data.extend_from_slice(b"lib");
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment.as_bytes());
+        }
+        data
+    }
+
+    /// MPEG-1 Layer III、128kbps、44.1kHz 联合立体声的帧，帧长 417 字节
+    fn mp3_frame() -> Vec<u8> {
+        let mut frame = vec![0xFF, 0xFB, 0x90, 0x64];
+        frame.resize(417, 0);
+        frame
+    }
+
+    fn flac(info_len: u32, info: &[u8]) -> Vec<u8> {
+        let mut data = b"fLaC".to_vec();
+        data.push(0x80);
+        data.extend_from_slice(&info_len.to_be_bytes()[1..]);
+        data.extend_from_slice(info);
+        data
+    }
+
+    /// 8kHz 单声道 8 位 PCM，`data` 块声明长度 `declared_len`，实际内容 `data_len` 字节
+    fn wav(declared_len: u32, data_len: usize) -> Vec<u8> {
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&1u16.to_le_bytes());
+        fmt.extend_from_slice(&1u16.to_le_bytes());
+        fmt.extend_from_slice(&8000u32.to_le_bytes());
+        fmt.extend_from_slice(&8000u32.to_le_bytes());
+        fmt.extend_from_slice(&1u16.to_le_bytes());
+        fmt.extend_from_slice(&8u16.to_le_bytes());
+
+        let mut data = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        data.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+        data.extend_from_slice(&fmt);
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&declared_len.to_le_bytes());
+        data.resize(data.len() + data_len, 0x80);
+        data
+    }
+
+    #[test]
+    fn parses_vorbis_comments() {
+        let mut tags = Tags::default();
+        parse_vorbis_comment(
+            &vorbis_comment(&["TITLE=Song", "artist=Band", "ENCODER=x"]),
+            &mut tags,
+        );
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(tags.artist.as_deref(), Some("Band"));
+        assert_eq!(tags.album, None);
+    }
+
+    #[test]
+    fn stops_at_oversized_vorbis_comment() {
+        let mut data = vorbis_comment(&["TITLE=Song"]);
+        // 注释数量改为 2，第二条的长度超出数据
+        data[7..11].copy_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(b"ALBUM=x");
+        let mut tags = Tags::default();
+        parse_vorbis_comment(&data, &mut tags);
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(tags.album, None);
+
+        let mut tags = Tags::default();
+        parse_vorbis_comment(&u32::MAX.to_le_bytes(), &mut tags);
+        assert!(tags.is_empty());
+    }
+
+    #[test]
+    fn stops_at_truncated_id3_frame() {
+        let mut tag = b"ID3\x03\0\0\0\0\0\0".to_vec();
+        tag.extend_from_slice(b"TIT2");
+        tag.extend_from_slice(&5u32.to_be_bytes());
+        // 帧标志 2 字节，内容为编码字节和文本
+        tag.extend_from_slice(b"\0\0\0Song");
+        tag.extend_from_slice(b"TALB");
+        tag.extend_from_slice(&1000u32.to_be_bytes());
+        tag.extend_from_slice(b"\0\0\0Album");
+
+        let mut tags = Tags::default();
+        parse_id3v2(&tag, &mut tags);
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(tags.album, None);
+    }
+
+    #[test]
+    fn finds_consecutive_frames() {
+        let data = [vec![0; 3], mp3_frame(), mp3_frame()].concat();
+        let (offset, header) = find_frame(&data).unwrap();
+        assert_eq!(offset, 3);
+        assert_eq!(header.frame_len(), 417);
+        assert_eq!((header.sample_rate, header.channels), (44100, 2));
+
+        // 帧头之后不是另一个帧头
+        let mut broken = [mp3_frame(), vec![0; 417]].concat();
+        broken[417] = 0xFF;
+        assert!(find_frame(&broken).is_none());
+        assert!(find_frame(&[0xFF; 64]).is_none());
+    }
+
+    #[tokio::test]
+    async fn probes_constant_bitrate_mp3() {
+        let data = mp3_frame().repeat(10);
+        let probe = probe_bytes(data).await.unwrap();
+        assert_eq!(probe.container, "mp3");
+        assert_eq!(probe.bitrate, Some(128_000));
+        assert_eq!(probe.duration, Some(0.261));
+        assert_eq!(probe.audio.unwrap().codec, "mp3");
+    }
+
+    #[tokio::test]
+    async fn probes_flac_stream_info() {
+        let mut info = [0u8; 34];
+        let packed: u64 = (44100 << 44) | (1 << 41) | (15 << 36) | 88200;
+        info[10..18].copy_from_slice(&packed.to_be_bytes());
+        let probe = probe_bytes(flac(34, &info)).await.unwrap();
+        assert_eq!(probe.duration, Some(2.0));
+        let audio = probe.audio.unwrap();
+        assert_eq!(audio.sample_rate, Some(44100));
+        assert_eq!(audio.channels, Some(2));
+        assert_eq!(audio.bits_per_sample, Some(16));
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_flac_stream_info() {
+        assert!(probe_bytes(flac(34, &[0; 10])).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn clamps_wav_data_to_file_size() {
+        for declared_len in [0, 100_000, u32::MAX] {
+            let probe = probe_bytes(wav(declared_len, 4000)).await.unwrap();
+            assert_eq!(probe.duration, Some(0.5));
+            assert_eq!(probe.audio.unwrap().codec, "pcm_u8");
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_ogg_page() {
+        let mut page = b"OggS\0\x04".to_vec();
+        page.extend_from_slice(&480u64.to_le_bytes());
+        page.resize(26, 0);
+        page.extend_from_slice(&[1, 3]);
+        page.extend_from_slice(b"abc");
+        let (granule, lacing, body) = ogg_page(&page).unwrap();
+        assert_eq!((granule, lacing, body), (480, &[3u8][..], &b"abc"[..]));
+
+        page.pop();
+        assert!(ogg_page(&page).is_none());
+        assert!(ogg_page(&page[..20]).is_none());
+    }
+}
@@ -0,0 +1,175 @@
+//! 图片元数据：尺寸、方向、相机信息、拍摄时间和GPS位置
+//!
+//! 尺寸从图片头部解析，其余字段来自 EXIF（支持 JPEG、PNG、WebP、HEIF 和 TIFF）
+
+use exif::{Exif, In, Tag, Value};
+use serde::Serialize;
+use std::io::Cursor;
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct GpsLocation {
+    /// 纬度，南纬为负
+    pub latitude: f64,
+    /// 经度，西经为负
+    pub longitude: f64,
+    /// 海拔（米），海平面以下为负
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ImageMetadata {
+    /// 存储的像素尺寸，未应用方向旋转
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u64>,
+    /// EXIF 方向（1-8），1 表示无需旋转
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub make: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 相机记录的本地拍摄时间（`YYYY-MM-DDTHH:MM:SS`）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taken_at: Option<String>,
+    /// 拍摄时间的时区偏移（如 `+08:00`），相机未记录时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taken_at_offset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gps: Option<GpsLocation>,
+}
+
+fn ascii_field(exif: &Exif, tag: Tag) -> Option<String> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    let Value::Ascii(values) = &field.value else {
+        return None;
+    };
+    let value = String::from_utf8_lossy(values.first()?)
+        .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+        .to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+fn rational_values(exif: &Exif, tag: Tag) -> Option<Vec<f64>> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    match &field.value {
+        Value::Rational(values) if !values.is_empty() => {
+            Some(values.iter().map(|value| value.to_f64()).collect())
+        }
+        _ => None,
+    }
+}
+
+/// 将度、分、秒转换为带符号的十进制度数，`negative_ref` 为南纬或西经的标记
+fn coordinate(exif: &Exif, tag: Tag, ref_tag: Tag, negative_ref: &str) -> Option<f64> {
+    let values = rational_values(exif, tag)?;
+    let degrees = values.first().copied().unwrap_or(0.0)
+        + values.get(1).copied().unwrap_or(0.0) / 60.0
+        + values.get(2).copied().unwrap_or(0.0) / 3600.0;
+    if !degrees.is_finite() {
+        return None;
+    }
+
+    let reference = ascii_field(exif, ref_tag).unwrap_or_default();
+    Some(if reference.eq_ignore_ascii_case(negative_ref) {
+        -degrees
+    } else {
+        degrees
+    })
+}
+
+fn gps_location(exif: &Exif) -> Option<GpsLocation> {
+    let latitude = coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S")?;
+    let longitude = coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W")?;
+    // 部分设备在没有定位时写入 0,0
+    if !(-90.0..=90.0).contains(&latitude)
+        || !(-180.0..=180.0).contains(&longitude)
+        || (latitude == 0.0 && longitude == 0.0)
+    {
+        return None;
+    }
+
+    let altitude = rational_values(exif, Tag::GPSAltitude)
+        .and_then(|values| values.first().copied())
+        .filter(|altitude| altitude.is_finite())
+        .map(|altitude| {
+            // GPSAltitudeRef 为 1 表示海平面以下
+            let below_sea_level = exif
+                .get_field(Tag::GPSAltitudeRef, In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+                == Some(1);
+            if below_sea_level { -altitude } else { altitude }
+        });
+
+    Some(GpsLocation {
+        latitude,
+        longitude,
+        altitude,
+    })
+}
+
+/// 拍摄时间及时区偏移，优先使用 `DateTimeOriginal`
+fn capture_time(exif: &Exif) -> Option<(String, Option<String>)> {
+    let (time_tag, offset_tag) = [
+        (Tag::DateTimeOriginal, Tag::OffsetTimeOriginal),
+        (Tag::DateTimeDigitized, Tag::OffsetTimeDigitized),
+        (Tag::DateTime, Tag::OffsetTime),
+    ]
+    .into_iter()
+    .find(|(tag, _)| exif.get_field(*tag, In::PRIMARY).is_some())?;
+
+    let raw = ascii_field(exif, time_tag)?;
+    let mut time = exif::DateTime::from_ascii(raw.as_bytes()).ok()?;
+    if !(1..=12).contains(&time.month) || !(1..=31).contains(&time.day) || time.hour > 23 {
+        return None;
+    }
+
+    if let Some(offset) = ascii_field(exif, offset_tag) {
+        // 时区偏移格式错误时忽略，只保留本地时间
+        let _ = time.parse_offset(offset.as_bytes());
+    }
+    let offset = time.offset.map(|minutes| {
+        let sign = if minutes < 0 { '-' } else { '+' };
+        let minutes = minutes.unsigned_abs();
+        format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+    });
+
+    Some((
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            time.year, time.month, time.day, time.hour, time.minute, time.second
+        ),
+        offset,
+    ))
+}
+
+/// 从图片开头的字节解析元数据，无法识别的字段为空
+pub fn parse(data: &[u8]) -> ImageMetadata {
+    let mut metadata = ImageMetadata::default();
+
+    if let Ok(size) = imagesize::blob_size(data) {
+        metadata.width = Some(size.width as u64);
+        metadata.height = Some(size.height as u64);
+    }
+
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut Cursor::new(data)) else {
+        return metadata;
+    };
+
+    metadata.orientation = exif
+        .get_field(Tag::Orientation, In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        .filter(|orientation| (1..=8).contains(orientation))
+        .map(|orientation| orientation as u16);
+    metadata.make = ascii_field(&exif, Tag::Make);
+    metadata.model = ascii_field(&exif, Tag::Model);
+    if let Some((taken_at, offset)) = capture_time(&exif) {
+        metadata.taken_at = Some(taken_at);
+        metadata.taken_at_offset = offset;
+    }
+    metadata.gps = gps_location(&exif);
+
+    metadata
+}
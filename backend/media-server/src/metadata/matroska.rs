@@ -0,0 +1,489 @@
+//! WebM/Matroska（EBML）解析
+//!
+//! 读取文件开头，依次解析 `Segment` 中位于第一个 `Cluster` 之前的元素：
+//! - `Info`: 时长和标题
+//! - `Tracks`: 编码、分辨率、帧率、采样率和声道
+//! - `Tags`: 标题、艺术家和专辑
+//!
+//! 不在开头的元素（通常是写在文件末尾的 `Tags`）根据 `SeekHead` 中的位置单独读取
+
+use super::probe::{AudioStream, MediaProbe, ObjectReader, VideoStream};
+use crate::storage::StorageError;
+
+const EBML_HEADER: u64 = 0x1A45DFA3;
+const SEGMENT: u64 = 0x18538067;
+const SEEK_HEAD: u64 = 0x114D9B74;
+const SEEK: u64 = 0x4DBB;
+const SEEK_ID: u64 = 0x53AB;
+const SEEK_POSITION: u64 = 0x53AC;
+const INFO: u64 = 0x1549A966;
+const TIMECODE_SCALE: u64 = 0x2AD7B1;
+const DURATION: u64 = 0x4489;
+const TITLE: u64 = 0x7BA9;
+const TRACKS: u64 = 0x1654AE6B;
+const TRACK_ENTRY: u64 = 0xAE;
+const TRACK_TYPE: u64 = 0x83;
+const CODEC_ID: u64 = 0x86;
+const DEFAULT_DURATION: u64 = 0x23E383;
+const VIDEO: u64 = 0xE0;
+const PIXEL_WIDTH: u64 = 0xB0;
+const PIXEL_HEIGHT: u64 = 0xBA;
+const AUDIO: u64 = 0xE1;
+const SAMPLING_FREQUENCY: u64 = 0xB5;
+const CHANNELS: u64 = 0x9F;
+const BIT_DEPTH: u64 = 0x6264;
+const TAGS: u64 = 0x1254C367;
+const TAG: u64 = 0x7373;
+const SIMPLE_TAG: u64 = 0x67C8;
+const TAG_NAME: u64 = 0x45A3;
+const TAG_STRING: u64 = 0x4487;
+const CLUSTER: u64 = 0x1F43B675;
+
+/// 开头读取的字节数
+const HEAD_LEN: u64 = 1024 * 1024;
+
+/// 通过 `SeekHead` 单独读取的元素大小上限
+const MAX_ELEMENT_SIZE: u64 = 8 * 1024 * 1024;
+
+/// 读取 EBML 变长整数，返回值和占用的字节数。`keep_marker` 为 true 时保留长度标记位（用于元素 ID）
+fn read_vint(data: &[u8], keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 || data.len() < len {
+        return None;
+    }
+
+    let mut value = if keep_marker {
+        first as u64
+    } else {
+        (first & 0xFFu8.checked_shr(len as u32).unwrap_or(0)) as u64
+    };
+    for byte in &data[1..len] {
+        value = (value << 8) | *byte as u64;
+    }
+    Some((value, len))
+}
+
+/// 元素头部：ID、内容大小（未知时为 `None`）和头部长度
+fn element_header(data: &[u8]) -> Option<(u64, Option<u64>, usize)> {
+    let (id, id_len) = read_vint(data, true)?;
+    let (size, size_len) = read_vint(data.get(id_len..)?, false)?;
+    // 所有数据位为 1 表示大小未知
+    let unknown = size == (1u64 << (7 * size_len)) - 1;
+    Some((id, (!unknown).then_some(size), id_len + size_len))
+}
+
+/// 依次返回 `data` 中完整的子元素（ID 和内容）
+fn elements(data: &[u8]) -> impl Iterator<Item = (u64, &[u8])> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        let (id, size, header_len) = element_header(rest)?;
+        let end = header_len as u64 + size?;
+        if end > rest.len() as u64 {
+            rest = &[];
+            return None;
+        }
+
+        let body = &rest[header_len..end as usize];
+        rest = &rest[end as usize..];
+        Some((id, body))
+    })
+}
+
+fn child(data: &[u8], id: u64) -> Option<&[u8]> {
+    elements(data)
+        .find(|(child_id, _)| *child_id == id)
+        .map(|(_, body)| body)
+}
+
+fn uint(data: &[u8]) -> Option<u64> {
+    (data.len() <= 8).then(|| {
+        data.iter()
+            .fold(0u64, |value, byte| (value << 8) | *byte as u64)
+    })
+}
+
+fn float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes(data.try_into().ok()?) as f64),
+        8 => Some(f64::from_be_bytes(data.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+fn string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+fn codec_name(codec_id: &str) -> String {
+    let name = match codec_id {
+        "V_MPEG4/ISO/AVC" => "h264",
+        "V_MPEGH/ISO/HEVC" => "hevc",
+        "V_AV1" => "av1",
+        "V_VP8" => "vp8",
+        "V_VP9" => "vp9",
+        "V_THEORA" => "theora",
+        "V_MJPEG" => "mjpeg",
+        "V_PRORES" => "prores",
+        "A_OPUS" => "opus",
+        "A_VORBIS" => "vorbis",
+        "A_FLAC" => "flac",
+        "A_MPEG/L3" => "mp3",
+        "A_MPEG/L2" => "mp2",
+        "A_AC3" => "ac3",
+        "A_EAC3" => "eac3",
+        "A_DTS" => "dts",
+        "A_ALAC" => "alac",
+        id if id.starts_with("V_MPEG4/ISO/") => "mpeg4",
+        id if id.starts_with("A_AAC") => "aac",
+        id if id.starts_with("A_PCM/") => "pcm",
+        id => return id.to_ascii_lowercase(),
+    };
+    name.to_string()
+}
+
+/// 解析过程中收集的信息
+#[derive(Default)]
+struct Segment {
+    timecode_scale: Option<u64>,
+    duration: Option<f64>,
+    /// 由 `SeekHead` 记录的元素位置（相对于 `Segment` 内容开头）
+    seeks: Vec<(u64, u64)>,
+    parsed: Vec<u64>,
+}
+
+impl Segment {
+    fn handle(&mut self, id: u64, body: &[u8], probe: &mut MediaProbe) {
+        match id {
+            SEEK_HEAD => {
+                for (_, seek) in elements(body).filter(|(id, _)| *id == SEEK) {
+                    let target = child(seek, SEEK_ID).and_then(uint);
+                    let position = child(seek, SEEK_POSITION).and_then(uint);
+                    if let (Some(target), Some(position)) = (target, position) {
+                        self.seeks.push((target, position));
+                    }
+                }
+            }
+            INFO => self.handle_info(body, probe),
+            TRACKS => handle_tracks(body, probe),
+            TAGS => handle_tags(body, probe),
+            _ => return,
+        }
+        self.parsed.push(id);
+    }
+
+    fn handle_info(&mut self, body: &[u8], probe: &mut MediaProbe) {
+        for (id, value) in elements(body) {
+            match id {
+                TIMECODE_SCALE => self.timecode_scale = uint(value),
+                DURATION => self.duration = float(value),
+                TITLE => probe.tags.set("title", &string(value)),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn handle_tracks(body: &[u8], probe: &mut MediaProbe) {
+    for (_, entry) in elements(body).filter(|(id, _)| *id == TRACK_ENTRY) {
+        let codec = child(entry, CODEC_ID)
+            .map(|codec_id| codec_name(&string(codec_id)))
+            .unwrap_or_default();
+
+        match child(entry, TRACK_TYPE).and_then(uint) {
+            Some(1) if probe.video.is_none() => {
+                let settings = child(entry, VIDEO).unwrap_or_default();
+                // DefaultDuration 为每帧的纳秒数
+                let frame_rate = child(entry, DEFAULT_DURATION)
+                    .and_then(uint)
+                    .filter(|nanos| *nanos > 0)
+                    .map(|nanos| (1e9 / nanos as f64 * 1000.0).round() / 1000.0);
+                probe.video = Some(VideoStream {
+                    codec,
+                    width: child(settings, PIXEL_WIDTH)
+                        .and_then(uint)
+                        .map(|w| w as u32),
+                    height: child(settings, PIXEL_HEIGHT)
+                        .and_then(uint)
+                        .map(|h| h as u32),
+                    frame_rate,
+                });
+            }
+            Some(2) if probe.audio.is_none() => {
+                let settings = child(entry, AUDIO).unwrap_or_default();
+                probe.audio = Some(AudioStream {
+                    codec,
+                    // 未记录时默认为 8000 Hz 单声道
+                    sample_rate: Some(
+                        child(settings, SAMPLING_FREQUENCY)
+                            .and_then(float)
+                            .map_or(8000, |rate| rate as u32),
+                    ),
+                    channels: Some(
+                        child(settings, CHANNELS)
+                            .and_then(uint)
+                            .map_or(1, |channels| channels as u16),
+                    ),
+                    bits_per_sample: child(settings, BIT_DEPTH)
+                        .and_then(uint)
+                        .map(|bits| bits as u16),
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+fn handle_tags(body: &[u8], probe: &mut MediaProbe) {
+    for (_, tag) in elements(body).filter(|(id, _)| *id == TAG) {
+        for (_, simple) in elements(tag).filter(|(id, _)| *id == SIMPLE_TAG) {
+            if let (Some(name), Some(value)) = (child(simple, TAG_NAME), child(simple, TAG_STRING))
+            {
+                probe.tags.set(&string(name), &string(value));
+            }
+        }
+    }
+}
+
+pub async fn probe(
+    reader: &ObjectReader<'_>,
+    container: &'static str,
+) -> Result<Option<MediaProbe>, StorageError> {
+    let head = reader.read_at(0, HEAD_LEN).await?;
+
+    let Some((EBML_HEADER, Some(size), header_len)) = element_header(&head) else {
+        return Ok(None);
+    };
+    let Some(segment_offset) = usize::try_from(size)
+        .ok()
+        .and_then(|size| size.checked_add(header_len))
+    else {
+        return Ok(None);
+    };
+    let Some((SEGMENT, _, header_len)) = head.get(segment_offset..).and_then(element_header) else {
+        return Ok(None);
+    };
+    let segment_start = (segment_offset + header_len) as u64;
+
+    let mut probe = MediaProbe::new(container);
+    let mut segment = Segment::default();
+
+    // 解析开头已读取的顶层元素，直到第一个 Cluster
+    let mut offset = segment_start as usize;
+    while let Some((id, size, header_len)) = head.get(offset..).and_then(element_header) {
+        let Some(size) = size else {
+            break;
+        };
+        if id == CLUSTER {
+            break;
+        }
+        let end = offset as u64 + header_len as u64 + size;
+        if end > head.len() as u64 {
+            // 元素超出已读取的范围，记录位置稍后单独读取
+            segment.seeks.push((id, offset as u64 - segment_start));
+            break;
+        }
+        segment.handle(id, &head[offset + header_len..end as usize], &mut probe);
+        offset = end as usize;
+    }
+
+    // 根据 SeekHead 读取尚未解析的元素
+    for target in [INFO, TRACKS, TAGS] {
+        if segment.parsed.contains(&target) {
+            continue;
+        }
+        let Some(&(_, position)) = segment.seeks.iter().find(|(id, _)| *id == target) else {
+            continue;
+        };
+        // 位置来自文件内容，异常的值可能使偏移量溢出
+        let Some(element_offset) = segment_start.checked_add(position) else {
+            break;
+        };
+        let header = reader.read_at(element_offset, 16).await?;
+        let Some((id, Some(size), header_len)) = element_header(&header) else {
+            continue;
+        };
+        if id != target || size > MAX_ELEMENT_SIZE {
+            continue;
+        }
+        let body = reader
+            .read_at(element_offset + header_len as u64, size)
+            .await?;
+        segment.handle(id, &body, &mut probe);
+    }
+
+    if let Some(duration) = segment.duration {
+        let scale = segment.timecode_scale.unwrap_or(1_000_000);
+        probe.duration = Some(duration * scale as f64 / 1e9);
+    }
+
+    Ok(Some(probe))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::probe::probe_bytes;
+
+    const DOC_TYPE: u64 = 0x4282;
+
+    fn element_with_size(id: u64, size: &[u8], body: &[u8]) -> Vec<u8> {
+        let id_bytes = id.to_be_bytes();
+        let skip = id_bytes.iter().take_while(|byte| **byte == 0).count();
+        [&id_bytes[skip..], size, body].concat()
+    }
+
+    fn element(id: u64, body: &[u8]) -> Vec<u8> {
+        let size = if body.len() < 0x7F {
+            vec![0x80 | body.len() as u8]
+        } else {
+            let mut size = (body.len() as u64).to_be_bytes();
+            size[0] = 0x01;
+            size.to_vec()
+        };
+        element_with_size(id, &size, body)
+    }
+
+    fn uint_element(id: u64, value: u64) -> Vec<u8> {
+        element(id, &value.to_be_bytes())
+    }
+
+    fn info_body(duration_ms: f64) -> Vec<u8> {
+        [
+            uint_element(TIMECODE_SCALE, 1_000_000),
+            element(DURATION, &duration_ms.to_be_bytes()),
+        ]
+        .concat()
+    }
+
+    fn info(duration_ms: f64) -> Vec<u8> {
+        element(INFO, &info_body(duration_ms))
+    }
+
+    fn seek_head(target: u64, position: u64) -> Vec<u8> {
+        let seek = [
+            uint_element(SEEK_ID, target),
+            uint_element(SEEK_POSITION, position),
+        ]
+        .concat();
+        element(SEEK_HEAD, &element(SEEK, &seek))
+    }
+
+    fn webm(segment: &[u8]) -> Vec<u8> {
+        [
+            element(EBML_HEADER, &element(DOC_TYPE, b"webm")),
+            element(SEGMENT, segment),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn reads_vints() {
+        assert_eq!(read_vint(&[0x81], false), Some((1, 1)));
+        assert_eq!(read_vint(&[0x40, 0x02], false), Some((2, 2)));
+        assert_eq!(
+            read_vint(&[0x1A, 0x45, 0xDF, 0xA3], true),
+            Some((EBML_HEADER, 4))
+        );
+        // 长度标记位不在第一个字节中
+        assert_eq!(read_vint(&[0x00, 0x81], false), None);
+        // 数据不足
+        assert_eq!(read_vint(&[0x40], false), None);
+    }
+
+    #[test]
+    fn detects_unknown_size() {
+        assert_eq!(element_header(&[0xEC, 0xFF]), Some((0xEC, None, 2)));
+        assert_eq!(element_header(&[0xEC, 0x82]), Some((0xEC, Some(2), 2)));
+    }
+
+    #[test]
+    fn stops_at_truncated_element() {
+        let mut data = [uint_element(TRACK_TYPE, 1), element(CODEC_ID, b"V_VP9")].concat();
+        data.truncate(data.len() - 1);
+        assert_eq!(elements(&data).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn probes_info_and_tracks() {
+        let video = element(
+            VIDEO,
+            &[
+                uint_element(PIXEL_WIDTH, 1920),
+                uint_element(PIXEL_HEIGHT, 1080),
+            ]
+            .concat(),
+        );
+        let entry = element(
+            TRACK_ENTRY,
+            &[
+                uint_element(TRACK_TYPE, 1),
+                element(CODEC_ID, b"V_VP9"),
+                video,
+            ]
+            .concat(),
+        );
+        let data = webm(&[info(2500.0), element(TRACKS, &entry)].concat());
+
+        let probe = probe_bytes(data).await.unwrap();
+        assert_eq!(probe.container, "webm");
+        assert_eq!(probe.duration, Some(2.5));
+        let video = probe.video.unwrap();
+        assert_eq!(video.codec, "vp9");
+        assert_eq!((video.width, video.height), (Some(1920), Some(1080)));
+    }
+
+    #[tokio::test]
+    async fn reads_element_after_cluster_through_seek_head() {
+        let head = [seek_head(INFO, 0), element(CLUSTER, &[0; 8])].concat();
+        let position = head.len() as u64;
+        let data = webm(
+            &[
+                seek_head(INFO, position),
+                element(CLUSTER, &[0; 8]),
+                info(1000.0),
+            ]
+            .concat(),
+        );
+        let probe = probe_bytes(data).await.unwrap();
+        assert_eq!(probe.duration, Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn stops_on_seek_position_overflow() {
+        let data = webm(&[seek_head(INFO, u64::MAX), element(CLUSTER, &[0; 8])].concat());
+        let probe = probe_bytes(data).await.unwrap();
+        assert_eq!(probe.duration, None);
+    }
+
+    #[tokio::test]
+    async fn ignores_oversized_sought_element() {
+        let mut size = (MAX_ELEMENT_SIZE + 1).to_be_bytes();
+        size[0] = 0x01;
+        let oversized = element_with_size(INFO, &size, &info_body(1000.0));
+        let head = [seek_head(INFO, 0), element(CLUSTER, &[0; 8])].concat();
+        let data = webm(
+            &[
+                seek_head(INFO, head.len() as u64),
+                element(CLUSTER, &[0; 8]),
+                oversized,
+            ]
+            .concat(),
+        );
+        let probe = probe_bytes(data).await.unwrap();
+        assert_eq!(probe.duration, None);
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_ebml_header() {
+        let mut data = element_with_size(
+            EBML_HEADER,
+            &[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE],
+            &[],
+        );
+        data.extend_from_slice(&[0; 32]);
+        assert!(probe_bytes(data).await.is_none());
+    }
+}
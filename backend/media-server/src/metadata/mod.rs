@@ -0,0 +1,162 @@
+//! 服务器端媒体元数据提取
+//!
+//! 从存储中的对象解析元数据，写入 `media_files.metadata` 中由服务器维护的字段：
+//! - image: 图片的尺寸、方向、相机、拍摄时间和GPS位置
+//! - probe: 音视频的容器格式、时长、码率、编码、分辨率、帧率、采样率、声道和内嵌标签
+//!
+//! 这些字段只能由服务器写入，客户端提交的同名字段会被丢弃，保证排序和筛选所用的数据可信。
+//! 图片的 BlurHash 和主色调写入 `media_files` 的 `blurhash`、`dominant_color` 列，见 [`placeholder`]；
+//! 图片的感知哈希登记到 `media_perceptual_hashes`，见 [`crate::similarity`]
+
+mod audio;
+pub mod image;
+mod matroska;
+mod mp4;
+mod placeholder;
+pub mod probe;
+
+use serde_json::{Value, json};
+
+use crate::database::Database;
+use crate::handlers::media_handlers::MediaItem;
+use crate::similarity;
+use crate::storage::{self, SharedStorage, StorageError, read_prefix};
+use crate::thumbnails;
+use placeholder::Placeholder;
+
+/// 由元数据提取写入的字段，重新提取时先移除旧值
+const EXTRACTED_KEYS: [&str; 2] = ["image", "probe"];
+
+/// 只能由服务器写入的字段
+const SERVER_KEYS: [&str; 4] = ["image", "probe", "content_check", "etag"];
+
+/// 超过完整读取上限的图片只读取开头部分，EXIF 和尺寸信息通常位于文件开头
+const IMAGE_HEADER_LEN: u64 = 1024 * 1024;
+
+/// 完整读取的图片大小上限，完整读取的图片同时解码计算占位信息和感知哈希
+const IMAGE_FULL_READ_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// 提取结果
+#[derive(Debug)]
+pub struct Extracted {
+    /// 要合并到 `metadata` 的字段
+    pub fields: Value,
+    /// 图片的占位信息，不是图片或无法解码时为空
+    pub placeholder: Option<Placeholder>,
+    /// 图片的感知哈希，不是图片或无法解码时为空
+    pub perceptual_hash: Option<i64>,
+}
+
+/// 解码完整的图片，计算占位信息和感知哈希；格式无法解码（如 HEIC）时都为空
+async fn analyze_image(data: Vec<u8>) -> (Option<Placeholder>, Option<i64>) {
+    let result = tokio::task::spawn_blocking(move || match thumbnails::decode(&data) {
+        Ok(image) => {
+            let sample = image.thumbnail(placeholder::SAMPLE_SIZE, placeholder::SAMPLE_SIZE);
+            (
+                placeholder::compute(&sample),
+                Some(similarity::dhash(&sample)),
+            )
+        }
+        Err(e) => {
+            crate::log_with_storage!(info, "无法解码图片，跳过计算占位图和感知哈希: {}", e);
+            (None, None)
+        }
+    })
+    .await;
+    result.unwrap_or_else(|e| {
+        crate::log_with_storage!(error, "解码图片的任务异常退出: {}", e);
+        (None, None)
+    })
+}
+
+/// 移除客户端提交的元数据中由服务器维护的字段
+pub fn strip_server_keys(metadata: &mut Value) {
+    if let Some(map) = metadata.as_object_mut() {
+        for key in SERVER_KEYS {
+            map.remove(key);
+        }
+    }
+}
+
+/// 解析存储对象的元数据
+///
+/// 不支持的媒体类型返回空对象
+pub async fn extract(
+    storage: &SharedStorage,
+    media: &MediaItem,
+) -> Result<Extracted, StorageError> {
+    let size = media.file_size.max(0) as u64;
+    let storage = &storage::for_backend(storage, &media.storage_backend)?;
+
+    match media.media_type.as_str() {
+        "image" => {
+            let full = size <= IMAGE_FULL_READ_LIMIT;
+            let len = if full { size } else { IMAGE_HEADER_LEN };
+            let data = read_prefix(storage, &media.cos_key, size, len).await?;
+            let image = image::parse(&data);
+            let (placeholder, perceptual_hash) = if full {
+                analyze_image(data).await
+            } else {
+                (None, None)
+            };
+            Ok(Extracted {
+                fields: json!({ "image": image }),
+                placeholder,
+                perceptual_hash,
+            })
+        }
+        "video" | "audio" => {
+            let fields = match probe::probe(storage, &media.cos_key, size).await? {
+                Some(probe) => json!({ "probe": probe }),
+                None => json!({}),
+            };
+            Ok(Extracted {
+                fields,
+                placeholder: None,
+                perceptual_hash: None,
+            })
+        }
+        _ => Ok(Extracted {
+            fields: json!({}),
+            placeholder: None,
+            perceptual_hash: None,
+        }),
+    }
+}
+
+/// 将提取的字段、占位信息和图片的感知哈希写入数据库，替换之前提取的值
+///
+/// 视频的感知哈希由生成拖动预览的任务登记，这里不修改；其他类型的媒体（如内容被替换为音频）
+/// 移除已有的感知哈希。媒体内容在提取期间被替换（对象键已变化）时不写入，由新内容的任务重新提取
+pub async fn save(
+    db: &Database,
+    media: &MediaItem,
+    extracted: &Extracted,
+) -> Result<(), sqlx::Error> {
+    let placeholder = extracted.placeholder.as_ref();
+    let mut tx = db.pool.begin().await?;
+    let updated = sqlx::query(
+        r#"
+        UPDATE media_files
+        SET metadata = (COALESCE(metadata, '{}'::jsonb) - $1::TEXT[]) || $2,
+            blurhash = $3, dominant_color = $4
+        WHERE id = $5 AND cos_key = $6
+        "#,
+    )
+    .bind(EXTRACTED_KEYS.as_slice())
+    .bind(&extracted.fields)
+    .bind(placeholder.map(|p| &p.blurhash))
+    .bind(placeholder.and_then(|p| p.dominant_color.as_ref()))
+    .bind(&media.id)
+    .bind(&media.cos_key)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if updated > 0 && media.media_type != "video" {
+        let hashes: Vec<i64> = extracted.perceptual_hash.into_iter().collect();
+        similarity::replace(&mut tx, &media.id, &hashes).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
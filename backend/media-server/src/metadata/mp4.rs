@@ -0,0 +1,468 @@
+//! MP4/MOV/M4A（ISO BMFF）解析
+//!
+//! 沿顶层 box 头部查找 `moov`（可能位于文件开头或 `mdat` 之后），读取后解析：
+//! - `mvhd`: 总时长
+//! - `trak`: 轨道类型、编码（`stsd`）、分辨率、采样率、声道，视频帧率由 `stts` 的帧数和时长计算
+//! - `udta`: iTunes 风格的 `meta/ilst` 标签和 QuickTime 的 `©nam` 等文本标签
+
+use super::probe::{AudioStream, MediaProbe, ObjectReader, VideoStream};
+use crate::storage::StorageError;
+
+/// 允许读取的 `moov` 大小上限，超过时放弃解析
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+
+/// 顶层 box 数量上限，防止异常文件导致过多读取
+const MAX_TOP_LEVEL_BOXES: usize = 1024;
+
+fn be_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn be_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// 依次返回 `data` 中的子 box（类型和内容），遇到格式错误时停止
+fn boxes(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        let size = be_u32(rest, 0)? as u64;
+        let kind: [u8; 4] = rest.get(4..8)?.try_into().ok()?;
+        let (size, header_len) = match size {
+            0 => (rest.len() as u64, 8),
+            1 => (be_u64(rest, 8)?, 16),
+            size => (size, 8),
+        };
+        if size < header_len as u64 || size > rest.len() as u64 {
+            rest = &[];
+            return None;
+        }
+
+        let payload = &rest[header_len..size as usize];
+        rest = &rest[size as usize..];
+        Some((kind, payload))
+    })
+}
+
+/// 按路径查找第一个嵌套的 box
+fn find<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    let (first, rest) = path.split_first()?;
+    let (_, payload) = boxes(data).find(|(kind, _)| kind == *first)?;
+    if rest.is_empty() {
+        Some(payload)
+    } else {
+        find(payload, rest)
+    }
+}
+
+/// `mvhd`/`mdhd` 中的时间刻度和以刻度计的时长
+fn timescale_duration(payload: &[u8]) -> Option<(u32, u64)> {
+    let (timescale, duration) = match payload.first()? {
+        1 => (be_u32(payload, 20)?, be_u64(payload, 24)?),
+        _ => (be_u32(payload, 12)?, be_u32(payload, 16)? as u64),
+    };
+    // 全 1 表示时长未知
+    let unknown = duration == u64::MAX || duration == u32::MAX as u64;
+    (timescale > 0 && duration > 0 && !unknown).then_some((timescale, duration))
+}
+
+fn video_codec(fourcc: &[u8; 4]) -> String {
+    match fourcc {
+        b"avc1" | b"avc3" => "h264",
+        b"hvc1" | b"hev1" => "hevc",
+        b"av01" => "av1",
+        b"vp08" => "vp8",
+        b"vp09" => "vp9",
+        b"mp4v" => "mpeg4",
+        b"jpeg" | b"mjpa" | b"mjpb" => "mjpeg",
+        b"apch" | b"apcn" | b"apcs" | b"apco" | b"ap4h" | b"ap4x" => "prores",
+        _ => return String::from_utf8_lossy(fourcc).trim().to_string(),
+    }
+    .to_string()
+}
+
+/// 读取 MPEG-4 描述符的变长长度
+fn descriptor_len(data: &[u8], offset: &mut usize) -> Option<usize> {
+    let mut len = 0usize;
+    for _ in 0..4 {
+        let byte = *data.get(*offset)?;
+        *offset += 1;
+        len = (len << 7) | (byte & 0x7F) as usize;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Some(len)
+}
+
+/// `esds` 中解码器配置的 objectTypeIndication，区分 `mp4a` 中的 AAC 和 MP3 等
+fn esds_object_type(esds: &[u8]) -> Option<u8> {
+    // 跳过 version 和 flags
+    let mut offset = 4;
+    if *esds.get(offset)? != 0x03 {
+        return None;
+    }
+    offset += 1;
+    descriptor_len(esds, &mut offset)?;
+    // ES_ID
+    offset += 2;
+    let flags = *esds.get(offset)?;
+    offset += 1;
+    if flags & 0x80 != 0 {
+        offset += 2;
+    }
+    if flags & 0x40 != 0 {
+        offset += 1 + *esds.get(offset)? as usize;
+    }
+    if flags & 0x20 != 0 {
+        offset += 2;
+    }
+
+    if *esds.get(offset)? != 0x04 {
+        return None;
+    }
+    offset += 1;
+    descriptor_len(esds, &mut offset)?;
+    esds.get(offset).copied()
+}
+
+fn audio_codec(fourcc: &[u8; 4], children: &[u8]) -> String {
+    match fourcc {
+        b"mp4a" => match find(children, &[b"esds"]).and_then(esds_object_type) {
+            Some(0x69 | 0x6B) => "mp3",
+            Some(0xA5) => "ac3",
+            Some(0xA6) => "eac3",
+            Some(0xAD) => "opus",
+            _ => "aac",
+        },
+        b"ac-3" => "ac3",
+        b"ec-3" => "eac3",
+        b"alac" => "alac",
+        b"Opus" => "opus",
+        b"fLaC" => "flac",
+        b".mp3" => "mp3",
+        b"lpcm" | b"sowt" | b"twos" | b"in24" | b"in32" | b"fl32" | b"fl64" | b"raw " => "pcm",
+        b"ulaw" => "pcm_mulaw",
+        b"alaw" => "pcm_alaw",
+        _ => return String::from_utf8_lossy(fourcc).trim().to_string(),
+    }
+    .to_string()
+}
+
+/// `stsd` 的第一个样本描述
+fn sample_entry(mdia: &[u8]) -> Option<([u8; 4], &[u8])> {
+    let stsd = find(mdia, &[b"minf", b"stbl", b"stsd"])?;
+    // 跳过 version、flags 和 entry_count
+    boxes(stsd.get(8..)?).next()
+}
+
+fn parse_video(mdia: &[u8]) -> VideoStream {
+    let mut video = VideoStream::default();
+    if let Some((fourcc, entry)) = sample_entry(mdia) {
+        video.codec = video_codec(&fourcc);
+        video.width = be_u16(entry, 24).filter(|w| *w > 0).map(u32::from);
+        video.height = be_u16(entry, 26).filter(|h| *h > 0).map(u32::from);
+    }
+
+    // 平均帧率 = 帧数 / 轨道时长
+    let frames = find(mdia, &[b"minf", b"stbl", b"stts"]).map(|stts| {
+        let count = be_u32(stts, 4).unwrap_or(0) as usize;
+        (0..count)
+            .map_while(|i| be_u32(stts, 8 + i * 8))
+            .map(u64::from)
+            .sum::<u64>()
+    });
+    if let Some(frames) = frames.filter(|frames| *frames > 0)
+        && let Some((timescale, duration)) = find(mdia, &[b"mdhd"]).and_then(timescale_duration)
+    {
+        let frame_rate = frames as f64 * timescale as f64 / duration as f64;
+        video.frame_rate = Some((frame_rate * 1000.0).round() / 1000.0);
+    }
+
+    video
+}
+
+fn parse_audio(mdia: &[u8]) -> AudioStream {
+    let mut audio = AudioStream::default();
+    let Some((fourcc, entry)) = sample_entry(mdia) else {
+        return audio;
+    };
+
+    // QuickTime 声音描述版本 2 的采样率和声道数位于扩展字段
+    let version = be_u16(entry, 8).unwrap_or(0);
+    let children_offset = match version {
+        1 => 44,
+        2 => 64,
+        _ => 28,
+    };
+    audio.codec = audio_codec(&fourcc, entry.get(children_offset..).unwrap_or_default());
+
+    if version == 2 {
+        audio.sample_rate = be_u64(entry, 32)
+            .map(f64::from_bits)
+            .filter(|rate| rate.is_finite() && *rate > 0.0)
+            .map(|rate| rate as u32);
+        audio.channels = be_u32(entry, 40).map(|channels| channels as u16);
+        audio.bits_per_sample = be_u32(entry, 48).map(|bits| bits as u16);
+    } else {
+        audio.channels = be_u16(entry, 16);
+        audio.bits_per_sample = be_u16(entry, 18);
+        audio.sample_rate = be_u32(entry, 24).map(|rate| rate >> 16);
+    }
+    audio.channels = audio.channels.filter(|channels| *channels > 0);
+    audio.sample_rate = audio.sample_rate.filter(|rate| *rate > 0);
+    // 压缩格式的样本位数没有意义
+    if audio.codec != "pcm" && audio.codec != "alac" && audio.codec != "flac" {
+        audio.bits_per_sample = None;
+    }
+
+    audio
+}
+
+/// 标签 box 对应的标签名
+fn tag_name(kind: &[u8; 4]) -> Option<&'static str> {
+    match kind {
+        b"\xA9nam" => Some("title"),
+        b"\xA9ART" | b"aART" => Some("artist"),
+        b"\xA9alb" => Some("album"),
+        _ => None,
+    }
+}
+
+fn parse_tags(udta: &[u8], probe: &mut MediaProbe) {
+    if let Some(meta) = find(udta, &[b"meta"]) {
+        // MP4 的 meta 是 full box，QuickTime 的不是
+        let children = if meta.get(4..8) == Some(b"hdlr") {
+            meta
+        } else {
+            meta.get(4..).unwrap_or_default()
+        };
+        if let Some(ilst) = find(children, &[b"ilst"]) {
+            for (kind, item) in boxes(ilst) {
+                let Some(name) = tag_name(&kind) else {
+                    continue;
+                };
+                // data box: 类型（1 为 UTF-8）、语言，然后是值
+                if let Some(data) = find(item, &[b"data"])
+                    && be_u32(data, 0).map(|kind| kind & 0xFFFFFF) == Some(1)
+                {
+                    probe
+                        .tags
+                        .set(name, &String::from_utf8_lossy(&data[8.min(data.len())..]));
+                }
+            }
+        }
+    }
+
+    // QuickTime 文本标签：长度、语言，然后是文本
+    for (kind, payload) in boxes(udta) {
+        if let Some(name) = tag_name(&kind)
+            && let Some(len) = be_u16(payload, 0)
+            && let Some(text) = payload.get(4..4 + len as usize)
+        {
+            probe.tags.set(name, &String::from_utf8_lossy(text));
+        }
+    }
+}
+
+fn parse_moov(moov: &[u8], container: &'static str) -> MediaProbe {
+    let mut probe = MediaProbe::new(container);
+
+    if let Some((timescale, duration)) = find(moov, &[b"mvhd"]).and_then(timescale_duration) {
+        probe.duration = Some(duration as f64 / timescale as f64);
+    }
+
+    for (_, trak) in boxes(moov).filter(|(kind, _)| kind == b"trak") {
+        let Some(mdia) = find(trak, &[b"mdia"]) else {
+            continue;
+        };
+        // hdlr: version、flags、pre_defined，然后是轨道类型
+        match find(mdia, &[b"hdlr"]).and_then(|hdlr| hdlr.get(8..12)) {
+            Some(b"vide") if probe.video.is_none() => probe.video = Some(parse_video(mdia)),
+            Some(b"soun") if probe.audio.is_none() => probe.audio = Some(parse_audio(mdia)),
+            _ => {}
+        }
+    }
+
+    if let Some(udta) = find(moov, &[b"udta"]) {
+        parse_tags(udta, &mut probe);
+    }
+
+    probe
+}
+
+/// 根据 `ftyp` 的主品牌确定容器名称
+fn container_name(major_brand: Option<&[u8]>) -> &'static str {
+    match major_brand {
+        Some(b"qt  ") | None => "mov",
+        Some(b"M4A " | b"M4B " | b"M4P ") => "m4a",
+        Some(_) => "mp4",
+    }
+}
+
+pub async fn probe(reader: &ObjectReader<'_>) -> Result<Option<MediaProbe>, StorageError> {
+    let mut offset = 0;
+    let mut major_brand = None;
+
+    for _ in 0..MAX_TOP_LEVEL_BOXES {
+        if reader.size.saturating_sub(offset) < 8 {
+            break;
+        }
+        let header = reader.read_at(offset, 16).await?;
+        let (Some(size), Some(kind)) = (be_u32(&header, 0), header.get(4..8)) else {
+            break;
+        };
+        let (size, header_len) = match size {
+            0 => (reader.size - offset, 8),
+            1 => match be_u64(&header, 8) {
+                Some(size) => (size, 16),
+                None => break,
+            },
+            size => (size as u64, 8),
+        };
+        if size < header_len {
+            break;
+        }
+
+        if kind == b"ftyp" {
+            major_brand = header.get(8..12).map(<[u8]>::to_vec);
+        }
+        if kind == b"moov" {
+            if size > MAX_MOOV_SIZE {
+                return Ok(None);
+            }
+            let moov = reader
+                .read_at(offset + header_len, size - header_len)
+                .await?;
+            let container = container_name(major_brand.as_deref());
+            return Ok(Some(parse_moov(&moov, container)));
+        }
+
+        // 异常的 64 位 box 大小可能使偏移量溢出
+        let Some(next) = offset.checked_add(size) else {
+            break;
+        };
+        offset = next;
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::probe::probe_bytes;
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn ftyp() -> Vec<u8> {
+        mp4_box(b"ftyp", b"isom\0\0\0\0isommp41")
+    }
+
+    /// 版本 0 的 `mvhd`：时间刻度 1000，时长 `millis`
+    fn mvhd(millis: u32) -> Vec<u8> {
+        let mut payload = vec![0; 12];
+        payload.extend_from_slice(&1000u32.to_be_bytes());
+        payload.extend_from_slice(&millis.to_be_bytes());
+        payload.resize(100, 0);
+        mp4_box(b"mvhd", &payload)
+    }
+
+    #[test]
+    fn iterates_child_boxes() {
+        let data = [mp4_box(b"free", b"abc"), mp4_box(b"skip", b"")].concat();
+        let kinds: Vec<[u8; 4]> = boxes(&data).map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [*b"free", *b"skip"]);
+    }
+
+    #[test]
+    fn stops_at_truncated_box() {
+        let mut data = mp4_box(b"free", b"abc");
+        let mut truncated = mp4_box(b"skip", &[0; 16]);
+        truncated.truncate(12);
+        data.extend_from_slice(&truncated);
+        assert_eq!(boxes(&data).count(), 1);
+    }
+
+    #[test]
+    fn stops_at_invalid_box_size() {
+        // 大小小于头部长度
+        assert_eq!(boxes(b"\0\0\0\x04free").count(), 0);
+        // 64 位大小超出剩余数据
+        let mut large = vec![0, 0, 0, 1];
+        large.extend_from_slice(b"free");
+        large.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(boxes(&large).count(), 0);
+    }
+
+    #[test]
+    fn size_zero_extends_to_end() {
+        let data = [b"\0\0\0\0free".as_slice(), b"rest"].concat();
+        let (kind, payload) = boxes(&data).next().unwrap();
+        assert_eq!(&kind, b"free");
+        assert_eq!(payload, b"rest");
+    }
+
+    #[test]
+    fn finds_nested_box() {
+        let moov = mp4_box(b"moov", &mp4_box(b"udta", &mp4_box(b"meta", b"x")));
+        assert_eq!(find(&moov, &[b"moov", b"udta", b"meta"]), Some(&b"x"[..]));
+        assert_eq!(find(&moov, &[b"moov", b"trak"]), None);
+    }
+
+    #[tokio::test]
+    async fn probes_duration_from_mvhd() {
+        let data = [
+            ftyp(),
+            mp4_box(b"mdat", &[0; 32]),
+            mp4_box(b"moov", &mvhd(2500)),
+        ]
+        .concat();
+        let probe = probe_bytes(data).await.unwrap();
+        assert_eq!(probe.container, "mp4");
+        assert_eq!(probe.duration, Some(2.5));
+    }
+
+    #[tokio::test]
+    async fn ignores_truncated_moov() {
+        let mut data = [ftyp(), mp4_box(b"moov", &mvhd(2500))].concat();
+        data.truncate(data.len() - 60);
+        let probe = probe_bytes(data).await.unwrap();
+        assert_eq!(probe.duration, None);
+    }
+
+    #[tokio::test]
+    async fn stops_on_offset_overflow() {
+        // 64 位大小使下一个 box 的偏移量溢出
+        let mut data = ftyp();
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&(u64::MAX - 8).to_be_bytes());
+        data.extend_from_slice(&[0; 64]);
+        assert!(probe_bytes(data).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_moov() {
+        let mut data = ftyp();
+        data.extend_from_slice(&((MAX_MOOV_SIZE + 16) as u32).to_be_bytes());
+        data.extend_from_slice(b"moov");
+        data.extend_from_slice(&[0; 64]);
+        assert!(probe_bytes(data).await.is_none());
+    }
+}
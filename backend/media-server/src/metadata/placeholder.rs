@@ -0,0 +1,85 @@
+//! 图片占位信息：BlurHash 和主色调
+//!
+//! 在缩小到不超过 64 像素的图片上计算，列表在缩略图加载前用它们显示模糊预览或纯色背景
+
+use image::DynamicImage;
+
+/// 计算前将图片缩小到的最长边像素
+pub const SAMPLE_SIZE: u32 = 64;
+
+/// BlurHash 在长边和短边方向的分量数
+const LONG_SIDE_COMPONENTS: u32 = 4;
+const SHORT_SIDE_COMPONENTS: u32 = 3;
+
+/// 计算主色调时每个颜色通道保留的位数
+const COLOR_BUCKET_BITS: u32 = 4;
+
+/// 透明度低于该值的像素不参与主色调计算
+const MIN_ALPHA: u8 = 128;
+
+#[derive(Debug, Clone)]
+pub struct Placeholder {
+    pub blurhash: String,
+    /// `#rrggbb`，图片完全透明时为空
+    pub dominant_color: Option<String>,
+}
+
+/// 像素最多的颜色区间内所有像素的平均颜色
+fn dominant_color(image: &DynamicImage) -> Option<String> {
+    let shift = 8 - COLOR_BUCKET_BITS;
+    // 每个区间的像素数和各通道之和
+    let mut buckets = vec![(0u32, [0u32; 3]); 1 << (COLOR_BUCKET_BITS * 3)];
+    for pixel in image.to_rgba8().pixels() {
+        let [r, g, b, a] = pixel.0;
+        if a < MIN_ALPHA {
+            continue;
+        }
+        let index = ((r as usize >> shift) << (COLOR_BUCKET_BITS * 2))
+            | ((g as usize >> shift) << COLOR_BUCKET_BITS)
+            | (b as usize >> shift);
+        let bucket = &mut buckets[index];
+        bucket.0 += 1;
+        bucket.1[0] += r as u32;
+        bucket.1[1] += g as u32;
+        bucket.1[2] += b as u32;
+    }
+
+    let (count, sums) = buckets
+        .into_iter()
+        .max_by_key(|(count, _)| *count)
+        .filter(|(count, _)| *count > 0)?;
+    Some(format!(
+        "#{:02x}{:02x}{:02x}",
+        sums[0] / count,
+        sums[1] / count,
+        sums[2] / count
+    ))
+}
+
+/// 由缩小后的图片（见 [`SAMPLE_SIZE`]）计算占位信息（CPU 密集，应在阻塞线程中调用）
+pub fn compute(image: &DynamicImage) -> Option<Placeholder> {
+    let (components_x, components_y) = if image.width() >= image.height() {
+        (LONG_SIDE_COMPONENTS, SHORT_SIDE_COMPONENTS)
+    } else {
+        (SHORT_SIDE_COMPONENTS, LONG_SIDE_COMPONENTS)
+    };
+    let rgba = image.to_rgba8();
+    let blurhash = match blurhash::encode(
+        components_x,
+        components_y,
+        rgba.width(),
+        rgba.height(),
+        rgba.as_raw(),
+    ) {
+        Ok(blurhash) => blurhash,
+        Err(e) => {
+            crate::log_with_storage!(warn, "计算 BlurHash 失败: {}", e);
+            return None;
+        }
+    };
+
+    Some(Placeholder {
+        blurhash,
+        dominant_color: dominant_color(image),
+    })
+}
@@ -0,0 +1,160 @@
+//! 音视频容器解析：时长、码率、编码、分辨率、帧率、采样率、声道和内嵌标签
+//!
+//! 只解析容器结构，不解码音视频数据。根据文件头识别格式，通过
+//! [`StorageBackend::get_stream`](crate::storage::StorageBackend::get_stream) 按需范围读取对象的
+//! 部分内容。内置的存储后端都支持范围读取，不会下载整个文件；使用默认实现的后端每次读取都会
+//! 下载完整对象：
+//! - MP4/MOV/M4A: `moov` box，见 [`super::mp4`]
+//! - WebM/Matroska: `Info`、`Tracks` 和 `Tags` 元素，见 [`super::matroska`]
+//! - MP3、FLAC、WAV、OGG: 见 [`super::audio`]
+
+use serde::Serialize;
+
+use super::{audio, matroska, mp4};
+use crate::sniff::{self, Format, SNIFF_LEN};
+use crate::storage::{SharedStorage, StorageError, read_range};
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct VideoStream {
+    pub codec: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// 平均帧率（帧/秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_rate: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct AudioStream {
+    pub codec: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bits_per_sample: Option<u16>,
+}
+
+/// 内嵌的标签，同名标签只保留第一个
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Tags {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+}
+
+impl Tags {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.artist.is_none() && self.album.is_none()
+    }
+
+    /// 按标签名（不区分大小写）写入，不认识的标签和空值忽略
+    pub fn set(&mut self, name: &str, value: &str) {
+        let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        if value.is_empty() {
+            return;
+        }
+
+        let slot = match name.to_ascii_uppercase().as_str() {
+            "TITLE" => &mut self.title,
+            "ARTIST" => &mut self.artist,
+            "ALBUM" => &mut self.album,
+            _ => return,
+        };
+        if slot.is_none() {
+            *slot = Some(value.to_string());
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MediaProbe {
+    /// 容器格式，如 `mp4`、`mov`、`webm`、`matroska`、`mp3`、`flac`、`wav`、`ogg`
+    pub container: &'static str,
+    /// 时长（秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    /// 总码率（比特/秒），容器未记录时按文件大小和时长估算
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub video: Option<VideoStream>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioStream>,
+    #[serde(skip_serializing_if = "Tags::is_empty")]
+    pub tags: Tags,
+}
+
+impl MediaProbe {
+    pub fn new(container: &'static str) -> Self {
+        Self {
+            container,
+            duration: None,
+            bitrate: None,
+            video: None,
+            audio: None,
+            tags: Tags::default(),
+        }
+    }
+}
+
+/// 按偏移量范围读取存储对象
+pub struct ObjectReader<'a> {
+    storage: &'a SharedStorage,
+    key: &'a str,
+    pub size: u64,
+}
+
+impl ObjectReader<'_> {
+    /// 读取从 `offset` 开始最多 `len` 字节，超出对象末尾的部分被截断
+    pub async fn read_at(&self, offset: u64, len: u64) -> Result<Vec<u8>, StorageError> {
+        read_range(self.storage, self.key, self.size, offset, len).await
+    }
+}
+
+/// 解析音视频容器，无法识别的格式返回 `None`
+pub async fn probe(
+    storage: &SharedStorage,
+    key: &str,
+    size: u64,
+) -> Result<Option<MediaProbe>, StorageError> {
+    let reader = ObjectReader { storage, key, size };
+    let header = reader.read_at(0, SNIFF_LEN).await?;
+
+    let probe = match sniff::detect(&header) {
+        Some(Format::Mp4 | Format::QuickTime | Format::M4a) => mp4::probe(&reader).await?,
+        Some(Format::WebM) => matroska::probe(&reader, "webm").await?,
+        Some(Format::Matroska) => matroska::probe(&reader, "matroska").await?,
+        Some(Format::Mp3) => audio::probe_mp3(&reader).await?,
+        Some(Format::Flac) => audio::probe_flac(&reader).await?,
+        Some(Format::Wav) => audio::probe_wav(&reader).await?,
+        Some(Format::Ogg) => audio::probe_ogg(&reader).await?,
+        _ => None,
+    };
+
+    Ok(probe.map(|mut probe| {
+        probe.duration = probe
+            .duration
+            .filter(|duration| duration.is_finite() && *duration > 0.0)
+            .map(|duration| (duration * 1000.0).round() / 1000.0);
+        if probe.bitrate.is_none()
+            && let Some(duration) = probe.duration
+        {
+            probe.bitrate = Some((size as f64 * 8.0 / duration).round() as u64);
+        }
+        probe
+    }))
+}
+
+/// 解析内存中的文件内容
+#[cfg(test)]
+pub(super) async fn probe_bytes(data: Vec<u8>) -> Option<MediaProbe> {
+    let size = data.len() as u64;
+    let storage = crate::storage::memory::MemoryStorage::with_object("media", data);
+    probe(&storage, "media", size).await.unwrap()
+}
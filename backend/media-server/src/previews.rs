@@ -0,0 +1,417 @@
+//! 视频封面与拖动预览图
+//!
+//! 使用转码程序（`TRANSCODER_PATH`）从视频中抽取画面：
+//! - 封面：视频开头附近的一帧，按图片缩略图的各个尺寸登记为 `thumbnail`，与图片共用缩略图接口
+//! - 拖动预览：按固定间隔抽取最多 100 帧，缩小后拼成一张雪碧图（`sprite`），
+//!   并生成 WebVTT 缩略图轨道（`sprite_vtt`），鼠标悬停或拖动进度条时按时间显示对应的画面
+//!
+//! 未配置转码程序时不生成，客户端回退到通用图标
+
+use chrono::Utc;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::{self, FilterType};
+use image::{ImageError, RgbImage};
+use serde::Serialize;
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::database::Database;
+use crate::deletion_queue::{delete_or_enqueue, delete_or_enqueue_from};
+use crate::handlers::media_handlers::MediaItem;
+use crate::metadata::probe;
+use crate::similarity;
+use crate::storage::{self, SharedStorage, download_to_file};
+use crate::thumbnails::{self, DERIVATIVE_PREFIX, MediaDerivative, ThumbnailError};
+use crate::transcode::{self, HLS_MASTER_KIND, TranscodeError};
+use crate::waveform::WAVEFORM_KIND;
+
+/// 派生文件类型
+pub const SPRITE_KIND: &str = "sprite";
+pub const SPRITE_TRACK_KIND: &str = "sprite_vtt";
+const SPRITE_KINDS: [&str; 2] = [SPRITE_KIND, SPRITE_TRACK_KIND];
+
+/// 封面取视频时长的 10% 处，但不晚于第 10 秒，避开开头的黑场
+const POSTER_POSITION: f64 = 0.1;
+const POSTER_MAX_OFFSET: f64 = 10.0;
+
+/// 预览图中每一帧的宽度（像素），高度按比例缩放
+const SPRITE_TILE_WIDTH: u32 = 160;
+
+/// 雪碧图每行的帧数
+const SPRITE_COLUMNS: u32 = 10;
+
+/// 预览帧数上限和最小间隔（秒）
+const SPRITE_MAX_FRAMES: usize = 100;
+const SPRITE_MIN_INTERVAL: f64 = 2.0;
+
+const SPRITE_JPEG_QUALITY: u8 = 70;
+
+/// 媒体详情中的预览地址，尚未生成的为 `None`
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct MediaPreviews {
+    /// 缩略图（视频为封面），可以通过 `size` 参数选择尺寸
+    pub thumbnail_url: Option<String>,
+    /// 拖动预览的 WebVTT 缩略图轨道
+    pub sprite_url: Option<String>,
+    /// 转码后的 HLS 主播放列表
+    pub hls_url: Option<String>,
+    /// 音频波形（audiowaveform 格式）
+    pub waveform_url: Option<String>,
+}
+
+/// 抽取一帧保存为 JPEG，`input` 为 [`transcode::input_args`] 返回的输入参数，
+/// `width` 为缩放后的宽度；指定位置没有画面时返回 `false`
+async fn extract_frame(
+    input: &[String],
+    output: &Path,
+    offset: f64,
+    width: Option<u32>,
+) -> Result<bool, TranscodeError> {
+    let mut args: Vec<String> = [
+        "-hide_banner",
+        "-nostdin",
+        "-y",
+        "-loglevel",
+        "error",
+        "-ss",
+        &format!("{:.3}", offset),
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect();
+    args.extend_from_slice(input);
+    args.extend(
+        ["-frames:v", "1", "-q:v", "3"]
+            .into_iter()
+            .map(str::to_string),
+    );
+    if let Some(width) = width {
+        args.push("-vf".to_string());
+        args.push(format!("scale={}:-2", width));
+    }
+    args.push(output.to_string_lossy().into_owned());
+
+    transcode::run(&args).await?;
+    Ok(tokio::fs::metadata(output)
+        .await
+        .is_ok_and(|meta| meta.len() > 0))
+}
+
+/// 拼接后的雪碧图
+struct Sprite {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    /// 每一帧的感知哈希
+    frame_hashes: Vec<i64>,
+}
+
+/// 按抽取顺序将帧拼成雪碧图，尺寸不一致的帧缩放到第一帧的尺寸（CPU 密集，应在阻塞线程中调用）
+fn compose(frames: Vec<Vec<u8>>) -> Result<Sprite, ImageError> {
+    let images = frames
+        .iter()
+        .map(|data| image::load_from_memory(data))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (tile_width, tile_height) = (images[0].width(), images[0].height());
+    let columns = SPRITE_COLUMNS.min(images.len() as u32);
+    let rows = (images.len() as u32).div_ceil(columns);
+
+    let mut sheet = RgbImage::new(tile_width * columns, tile_height * rows);
+    for (index, frame) in images.iter().enumerate() {
+        let tile = if frame.width() == tile_width && frame.height() == tile_height {
+            frame.to_rgb8()
+        } else {
+            frame
+                .resize_exact(tile_width, tile_height, FilterType::Triangle)
+                .to_rgb8()
+        };
+        let index = index as u32;
+        let x = (index % columns) * tile_width;
+        let y = (index / columns) * tile_height;
+        imageops::replace(&mut sheet, &tile, x as i64, y as i64);
+    }
+
+    let mut data = Vec::new();
+    JpegEncoder::new_with_quality(&mut data, SPRITE_JPEG_QUALITY).encode_image(&sheet)?;
+    Ok(Sprite {
+        data,
+        width: sheet.width(),
+        height: sheet.height(),
+        tile_width,
+        tile_height,
+        frame_hashes: images.iter().map(similarity::dhash).collect(),
+    })
+}
+
+fn format_timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// 生成 WebVTT 缩略图轨道，每个时间段指向雪碧图中的一帧（相对地址 `sprite.jpg`）
+fn sprite_track(sprite: &Sprite, offsets: &[f64], duration: f64) -> String {
+    let columns = sprite.width / sprite.tile_width.max(1);
+    let mut track = String::from("WEBVTT\n");
+    for (index, start) in offsets.iter().enumerate() {
+        let end = offsets.get(index + 1).copied().unwrap_or(duration);
+        let index = index as u32;
+        track.push_str(&format!(
+            "\n{} --> {}\nsprite.jpg#xywh={},{},{},{}\n",
+            format_timestamp(*start),
+            format_timestamp(end.max(*start)),
+            (index % columns) * sprite.tile_width,
+            (index / columns) * sprite.tile_height,
+            sprite.tile_width,
+            sprite.tile_height
+        ));
+    }
+    track
+}
+
+/// 按间隔抽取预览帧并拼成雪碧图，返回雪碧图、WebVTT 轨道和关键帧的感知哈希；
+/// 一帧也没有抽取到时返回 `None`
+async fn render_sprite(
+    input: &[String],
+    dir: &Path,
+    duration: f64,
+) -> Result<Option<(Sprite, String, Vec<i64>)>, ThumbnailError> {
+    let interval = (duration / SPRITE_MAX_FRAMES as f64).max(SPRITE_MIN_INTERVAL);
+    let count = ((duration / interval).ceil() as usize).clamp(1, SPRITE_MAX_FRAMES);
+
+    let mut offsets = Vec::with_capacity(count);
+    let mut frames = Vec::with_capacity(count);
+    for index in 0..count {
+        let offset = index as f64 * interval;
+        let path = dir.join(format!("frame_{:03}.jpg", index));
+        if extract_frame(input, &path, offset, Some(SPRITE_TILE_WIDTH)).await? {
+            frames.push(tokio::fs::read(&path).await.map_err(TranscodeError::from)?);
+            offsets.push(offset);
+        }
+    }
+    if frames.is_empty() {
+        return Ok(None);
+    }
+
+    let sprite = match tokio::task::spawn_blocking(move || compose(frames)).await {
+        Ok(result) => result?,
+        Err(e) => return Err(ThumbnailError::Task(e.to_string())),
+    };
+    let track = sprite_track(&sprite, &offsets, duration);
+    let keyframes = similarity::keyframe_hashes(&offsets, &sprite.frame_hashes, duration);
+    Ok(Some((sprite, track, keyframes)))
+}
+
+/// 上传雪碧图和轨道并替换已有的记录；任何一步失败时清理本次上传的对象
+async fn store_sprite(
+    db: &Database,
+    storage: &SharedStorage,
+    media_id: &str,
+    sprite: Sprite,
+    track: String,
+) -> Result<(), ThumbnailError> {
+    let generation = Uuid::new_v4().simple().to_string();
+    let objects = [
+        (SPRITE_KIND, "jpg", "image/jpeg", sprite.data),
+        (SPRITE_TRACK_KIND, "vtt", "text/vtt", track.into_bytes()),
+    ];
+
+    let mut uploaded = Vec::with_capacity(objects.len());
+    let mut result = Ok(());
+    for (kind, extension, content_type, data) in objects {
+        let key = format!(
+            "{}{}/{}_sprite.{}",
+            DERIVATIVE_PREFIX, media_id, generation, extension
+        );
+        let file_size = data.len() as i64;
+        if let Err(e) = storage.put(&key, data, Some(content_type)).await {
+            result = Err(ThumbnailError::from(e));
+            break;
+        }
+        uploaded.push((kind, content_type, key, file_size));
+    }
+
+    let result = match result {
+        Ok(()) => async {
+            let mut tx = db.pool.begin().await?;
+            let previous: Vec<(String, String)> = sqlx::query_as(
+                "DELETE FROM media_derivatives WHERE media_id = $1 AND kind = ANY($2) RETURNING object_key, storage_backend",
+            )
+            .bind(media_id)
+            .bind(&SPRITE_KINDS[..])
+            .fetch_all(&mut *tx)
+            .await?;
+            for (kind, content_type, key, file_size) in &uploaded {
+                sqlx::query(
+                    r#"
+                    INSERT INTO media_derivatives (
+                        id, media_id, kind, size, width, height, content_type,
+                        file_size, object_key, storage_backend, created_at
+                    ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                    "#,
+                )
+                .bind(Uuid::new_v4().to_string())
+                .bind(media_id)
+                .bind(kind)
+                .bind(SPRITE_TILE_WIDTH as i32)
+                .bind(sprite.width as i32)
+                .bind(sprite.height as i32)
+                .bind(content_type)
+                .bind(file_size)
+                .bind(key)
+                .bind(storage.name())
+                .bind(Utc::now())
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            Ok::<_, sqlx::Error>(previous)
+        }
+        .await
+        .map_err(ThumbnailError::from),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(previous) => {
+            // 删除被替换的旧预览图对象
+            for (key, backend) in previous {
+                delete_or_enqueue_from(db, storage, &backend, &key, Some(media_id)).await;
+            }
+            Ok(())
+        }
+        Err(e) => {
+            for (_, _, key, _) in &uploaded {
+                delete_or_enqueue(db, storage, key, Some(media_id)).await;
+            }
+            Err(e)
+        }
+    }
+}
+
+/// 为视频生成封面缩略图和拖动预览图，替换已有的记录，返回登记的缩略图
+///
+/// 同时登记关键帧的感知哈希。未配置转码程序时移除已有的缩略图、预览图和感知哈希（可能属于
+/// 被替换前的内容）；时长未知时只生成封面
+pub async fn generate(
+    db: &Database,
+    storage: &SharedStorage,
+    media: &MediaItem,
+) -> Result<Vec<MediaDerivative>, ThumbnailError> {
+    if !transcode::enabled() {
+        thumbnails::remove(db, storage, &media.id).await?;
+        remove(db, storage, &media.id).await?;
+        similarity::store(db, media, &[]).await?;
+        return Ok(Vec::new());
+    }
+
+    let source = storage::for_backend(storage, &media.storage_backend)?;
+    let size = media.file_size.max(0) as u64;
+    let duration = probe::probe(&source, &media.cos_key, size)
+        .await?
+        .and_then(|probe| probe.duration);
+
+    let dir = tempfile::Builder::new()
+        .prefix("previews-")
+        .tempdir()
+        .map_err(TranscodeError::from)?;
+    let input = dir.path().join("source");
+    download_to_file(&source, &media.cos_key, &input).await?;
+    let input = transcode::input_args(&input).await?;
+
+    // 视频很短时指定位置可能没有画面，回退到第一帧
+    let poster = dir.path().join("poster.jpg");
+    let offset = duration
+        .map(|duration| (duration * POSTER_POSITION).min(POSTER_MAX_OFFSET))
+        .unwrap_or(0.0);
+    let extracted = extract_frame(&input, &poster, offset, None).await?
+        || (offset > 0.0 && extract_frame(&input, &poster, 0.0, None).await?);
+    if !extracted {
+        return Err(TranscodeError::Failed("视频中没有可抽取的画面".to_string()).into());
+    }
+    let data = tokio::fs::read(&poster)
+        .await
+        .map_err(TranscodeError::from)?;
+    let derivatives = thumbnails::replace(db, storage, &media.id, data).await?;
+
+    let keyframes = match duration {
+        Some(duration) => match render_sprite(&input, dir.path(), duration).await? {
+            Some((sprite, track, keyframes)) => {
+                store_sprite(db, storage, &media.id, sprite, track).await?;
+                keyframes
+            }
+            None => Vec::new(),
+        },
+        None => {
+            remove(db, storage, &media.id).await?;
+            Vec::new()
+        }
+    };
+    similarity::store(db, media, &keyframes).await?;
+
+    Ok(derivatives)
+}
+
+/// 移除媒体的拖动预览图，对象通过删除队列删除
+pub async fn remove(
+    db: &Database,
+    storage: &SharedStorage,
+    media_id: &str,
+) -> Result<(), sqlx::Error> {
+    let removed: Vec<(String, String)> = sqlx::query_as(
+        "DELETE FROM media_derivatives WHERE media_id = $1 AND kind = ANY($2) RETURNING object_key, storage_backend",
+    )
+    .bind(media_id)
+    .bind(&SPRITE_KINDS[..])
+    .fetch_all(&db.pool)
+    .await?;
+
+    for (key, backend) in removed {
+        delete_or_enqueue_from(db, storage, &backend, &key, Some(media_id)).await;
+    }
+    Ok(())
+}
+
+/// 查找媒体的拖动预览图或其 WebVTT 轨道
+pub async fn find_sprite(
+    db: &Database,
+    media_id: &str,
+    kind: &str,
+) -> Result<Option<MediaDerivative>, sqlx::Error> {
+    sqlx::query_as::<_, MediaDerivative>(
+        "SELECT * FROM media_derivatives WHERE media_id = $1 AND kind = $2",
+    )
+    .bind(media_id)
+    .bind(kind)
+    .fetch_optional(&db.pool)
+    .await
+}
+
+/// 查询媒体已生成的预览，返回对应的接口地址
+pub async fn media_previews(
+    db: &Database,
+    media: &MediaItem,
+) -> Result<MediaPreviews, sqlx::Error> {
+    let kinds: Vec<String> =
+        sqlx::query_scalar("SELECT DISTINCT kind FROM media_derivatives WHERE media_id = $1")
+            .bind(&media.id)
+            .fetch_all(&db.pool)
+            .await?;
+    let has = |kind: &str| kinds.iter().any(|k| k == kind);
+    let base = format!("/api/media/{}", media.id);
+
+    Ok(MediaPreviews {
+        thumbnail_url: has(thumbnails::THUMBNAIL_KIND).then(|| format!("{}/thumbnail", base)),
+        sprite_url: has(SPRITE_TRACK_KIND).then(|| format!("{}/sprite.vtt", base)),
+        hls_url: (has(HLS_MASTER_KIND) && media.transcode_status.as_deref() == Some("ready"))
+            .then(|| format!("{}/hls/master.m3u8", base)),
+        waveform_url: has(WAVEFORM_KIND).then(|| format!("{}/waveform", base)),
+    })
+}
@@ -0,0 +1,283 @@
+//! 用户存储配额与用量统计
+//!
+//! 用量按 `media_files.file_size` 统计，回收站中的媒体在彻底删除前仍然占用配额。
+//! 默认配额由环境变量配置，`users.quota_bytes` / `users.quota_items` 可以为单个用户覆盖：
+//! - `USER_QUOTA_BYTES`: 每个用户的最大存储字节数
+//! - `USER_QUOTA_ITEMS`: 每个用户的最大媒体数量
+//!
+//! 未配置时不限制。上传前的检查只用于尽早拒绝，登记媒体时在插入记录的事务中锁定用户记录后再次检查
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sqlx::{PgConnection, PgExecutor};
+use std::fmt;
+
+use crate::database::Database;
+
+fn env_limit(name: &str) -> Option<i64> {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse::<i64>().ok())
+        .filter(|value| *value >= 0)
+}
+
+static DEFAULT_QUOTA: Lazy<Quota> = Lazy::new(|| Quota {
+    max_bytes: env_limit("USER_QUOTA_BYTES"),
+    max_items: env_limit("USER_QUOTA_ITEMS"),
+});
+
+/// 用户的配额，`None` 表示不限制
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct Quota {
+    pub max_bytes: Option<i64>,
+    pub max_items: Option<i64>,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct MediaTypeUsage {
+    pub media_type: String,
+    pub bytes: i64,
+    pub items: i64,
+}
+
+/// 用户的存储用量
+#[derive(Serialize, Debug, Clone)]
+pub struct Usage {
+    /// 计入配额的总用量（包括回收站）
+    pub bytes: i64,
+    pub items: i64,
+    /// 其中回收站占用的部分
+    pub trash_bytes: i64,
+    pub trash_items: i64,
+    pub quota: Quota,
+    pub remaining_bytes: Option<i64>,
+    pub remaining_items: Option<i64>,
+    pub by_media_type: Vec<MediaTypeUsage>,
+}
+
+#[derive(Debug)]
+pub enum QuotaError {
+    /// 超出配额，附带说明
+    Exceeded(String),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaError::Exceeded(message) => write!(f, "{}", message),
+            QuotaError::Database(e) => write!(f, "查询存储用量失败: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for QuotaError {}
+
+impl From<sqlx::Error> for QuotaError {
+    fn from(e: sqlx::Error) -> Self {
+        QuotaError::Database(e)
+    }
+}
+
+impl Quota {
+    /// 用户单独设置的配额，未设置的项使用默认配额
+    fn with_overrides(overrides: Option<(Option<i64>, Option<i64>)>) -> Self {
+        let (bytes, items) = overrides.unwrap_or_default();
+        Quota {
+            max_bytes: bytes.or(DEFAULT_QUOTA.max_bytes),
+            max_items: items.or(DEFAULT_QUOTA.max_items),
+        }
+    }
+
+    fn is_unlimited(&self) -> bool {
+        self.max_bytes.is_none() && self.max_items.is_none()
+    }
+
+    /// 已用 `used_bytes` 字节、`used_items` 个媒体时能否再增加 `bytes` 字节、`items` 个媒体
+    fn ensure_room(
+        &self,
+        (used_bytes, used_items): (i64, i64),
+        bytes: i64,
+        items: i64,
+    ) -> Result<(), QuotaError> {
+        if let Some(max_bytes) = self.max_bytes
+            && used_bytes + bytes > max_bytes
+        {
+            return Err(QuotaError::Exceeded(format!(
+                "存储空间不足：已使用 {} 字节，配额 {} 字节",
+                used_bytes, max_bytes
+            )));
+        }
+        if let Some(max_items) = self.max_items
+            && used_items + items > max_items
+        {
+            return Err(QuotaError::Exceeded(format!(
+                "媒体数量超出配额：已有 {} 个，配额 {} 个",
+                used_items, max_items
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// 查询用户的配额，未单独设置时使用默认配额
+pub async fn user_quota(db: &Database, user_id: &str) -> Result<Quota, sqlx::Error> {
+    let overrides: Option<(Option<i64>, Option<i64>)> =
+        sqlx::query_as("SELECT quota_bytes, quota_items FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(&db.pool)
+            .await?;
+
+    Ok(Quota::with_overrides(overrides))
+}
+
+/// 查询用户计入配额的总字节数和媒体数量
+async fn usage_totals<'e, E>(executor: E, user_id: &str) -> Result<(i64, i64), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as(
+        r#"
+        SELECT COALESCE(SUM(file_size), 0)::BIGINT, COUNT(*)
+        FROM media_files
+        WHERE user_id = $1 AND status IN ('active', 'processing', 'deleted')
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(executor)
+    .await
+}
+
+/// 检查用户再增加 `bytes` 字节、`items` 个媒体后是否超出配额
+///
+/// 只用于在接收上传之前尽早拒绝，登记媒体时还需要在插入记录的事务中调用 [`check_quota_locked`]
+pub async fn check_quota(
+    db: &Database,
+    user_id: &str,
+    bytes: i64,
+    items: i64,
+) -> Result<(), QuotaError> {
+    let quota = user_quota(db, user_id).await?;
+    if quota.is_unlimited() {
+        return Ok(());
+    }
+
+    let used = usage_totals(&db.pool, user_id).await?;
+    quota.ensure_room(used, bytes, items)
+}
+
+/// 在插入媒体记录的事务中再次检查配额
+///
+/// 锁定用户记录后再统计用量，同一用户并发登记的媒体依次检查，前一个事务提交后才能继续，
+/// 因此不会都通过检查后共同超出配额
+pub async fn check_quota_locked(
+    conn: &mut PgConnection,
+    user_id: &str,
+    bytes: i64,
+    items: i64,
+) -> Result<(), QuotaError> {
+    let overrides: Option<(Option<i64>, Option<i64>)> =
+        sqlx::query_as("SELECT quota_bytes, quota_items FROM users WHERE id = $1 FOR UPDATE")
+            .bind(user_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+    let quota = Quota::with_overrides(overrides);
+    if quota.is_unlimited() {
+        return Ok(());
+    }
+
+    let used = usage_totals(&mut *conn, user_id).await?;
+    quota.ensure_room(used, bytes, items)
+}
+
+/// 统计用户的存储用量，按媒体类型分类
+pub async fn user_usage(db: &Database, user_id: &str) -> Result<Usage, sqlx::Error> {
+    let quota = user_quota(db, user_id).await?;
+    let (bytes, items) = usage_totals(&db.pool, user_id).await?;
+
+    let (trash_bytes, trash_items): (i64, i64) = sqlx::query_as(
+        r#"
+        SELECT COALESCE(SUM(file_size), 0)::BIGINT, COUNT(*)
+        FROM media_files
+        WHERE user_id = $1 AND status = 'deleted'
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(&db.pool)
+    .await?;
+
+    let by_media_type = sqlx::query_as::<_, MediaTypeUsage>(
+        r#"
+        SELECT media_type, COALESCE(SUM(file_size), 0)::BIGINT AS bytes, COUNT(*) AS items
+        FROM media_files
+        WHERE user_id = $1 AND status IN ('active', 'processing', 'deleted')
+        GROUP BY media_type
+        ORDER BY bytes DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(&db.pool)
+    .await?;
+
+    Ok(Usage {
+        bytes,
+        items,
+        trash_bytes,
+        trash_items,
+        quota,
+        remaining_bytes: quota.max_bytes.map(|max| (max - bytes).max(0)),
+        remaining_items: quota.max_items.map(|max| (max - items).max(0)),
+        by_media_type,
+    })
+}
+
+/// 单个用户的用量汇总，用于管理员查看和计费
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct UserUsageSummary {
+    pub user_id: String,
+    pub username: String,
+    pub bytes: i64,
+    pub items: i64,
+    pub quota_bytes: Option<i64>,
+    pub quota_items: Option<i64>,
+}
+
+/// 所有用户的用量汇总，按占用空间从大到小排列
+pub async fn all_users_usage(db: &Database) -> Result<Vec<UserUsageSummary>, sqlx::Error> {
+    let mut summaries = sqlx::query_as::<_, UserUsageSummary>(
+        r#"
+        SELECT u.id AS user_id, u.username,
+               COALESCE(SUM(m.file_size), 0)::BIGINT AS bytes,
+               COUNT(m.id) AS items,
+               u.quota_bytes, u.quota_items
+        FROM users u
+        LEFT JOIN media_files m ON m.user_id = u.id AND m.status IN ('active', 'processing', 'deleted')
+        GROUP BY u.id, u.username, u.quota_bytes, u.quota_items
+        ORDER BY bytes DESC, u.username
+        "#,
+    )
+    .fetch_all(&db.pool)
+    .await?;
+
+    for summary in &mut summaries {
+        summary.quota_bytes = summary.quota_bytes.or(DEFAULT_QUOTA.max_bytes);
+        summary.quota_items = summary.quota_items.or(DEFAULT_QUOTA.max_items);
+    }
+    Ok(summaries)
+}
+
+/// 设置用户的配额，`None` 表示恢复默认配额，返回用户是否存在
+pub async fn set_user_quota(
+    db: &Database,
+    user_id: &str,
+    quota: Quota,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE users SET quota_bytes = $1, quota_items = $2 WHERE id = $3")
+        .bind(quota.max_bytes)
+        .bind(quota.max_items)
+        .bind(user_id)
+        .execute(&db.pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -1,36 +1,95 @@
 use axum::{
-    Router, middleware,
+    Router,
+    extract::DefaultBodyLimit,
+    middleware,
     routing::{delete, get, post, put},
 };
 
-use crate::database::Database;
+use crate::state::AppState;
 
 use crate::credentials::auth_middleware;
 use crate::handlers::*;
 
 /// 创建应用程序的所有路由
-pub fn create_routes() -> Router<Database> {
+pub fn create_routes() -> Router<AppState> {
     // 公开路由 (不需要认证)
     let public_routes = Router::new()
         .route("/api/health", get(health))
         .route("/api/auth/register", post(register))
-        .route("/api/auth/login", post(login));
+        .route("/api/auth/login", post(login))
+        // 本地存储签名URL，通过签名而不是登录态鉴权；上传在写入过程中自行限制大小
+        .route(
+            "/api/storage/{*key}",
+            get(download_local_object)
+                .put(upload_local_object)
+                .layer(DefaultBodyLimit::disable()),
+        );
 
     // 需要认证的路由
     let protected_routes = Router::new()
         .route("/api/auth/me", get(me))
         .route("/api/auth/logout", post(logout))
+        .route("/api/me/usage", get(get_my_usage))
         .route("/api/media", get(get_media))
         .route("/api/media", post(create_media))
         .route("/api/media/search", get(search_media))
+        .route("/api/media/by-hash/{hash}", get(get_media_by_hash))
+        .route("/api/media/duplicates", get(get_possible_duplicates))
         .route("/api/media/{id}", get(get_media_by_id))
         .route("/api/media/{id}", put(update_media))
         .route("/api/media/{id}", delete(delete_media))
-        .route("/api/media/{id}/upload", put(upload_media_file))
+        .route("/api/media/{id}/download", get(download_media))
+        .route("/api/media/{id}/stream", get(stream_media))
+        .route("/api/media/{id}/thumbnail", get(get_media_thumbnail))
+        .route("/api/media/{id}/sprite.vtt", get(get_media_sprite_track))
+        .route("/api/media/{id}/sprite.jpg", get(get_media_sprite_image))
+        .route("/api/media/{id}/hls/{file}", get(get_media_hls))
+        .route("/api/media/{id}/waveform", get(get_media_waveform))
+        .route("/api/media/{id}/jobs", get(get_media_jobs))
+        .route("/api/media/{id}/similar", get(get_similar_media))
+        .route(
+            "/api/media/{id}/shares",
+            get(get_media_shares).post(share_media),
+        )
+        .route("/api/media/{id}/shares/{user_id}", delete(unshare_media))
+        // 回收站
+        .route("/api/media/trash", get(get_trash))
+        .route("/api/media/trash/{id}", delete(purge_trashed_media))
+        .route("/api/media/{id}/restore", post(restore_media))
+        // 流式上传在写入过程中自行限制大小
+        .route(
+            "/api/media/upload",
+            post(stream_upload_media).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/media/{id}/content",
+            put(stream_replace_media_content).layer(DefaultBodyLimit::disable()),
+        )
+        // 断点续传
+        .route("/api/uploads", post(create_upload_session))
+        .route(
+            "/api/uploads/{id}",
+            get(get_upload_session)
+                .head(head_upload_session)
+                .patch(upload_chunk)
+                .delete(abort_upload_session)
+                .layer(DefaultBodyLimit::disable()),
+        )
+        .route("/api/admin/gc/orphans", post(run_orphan_gc))
+        .route("/api/admin/storage/deletions", get(get_storage_deletions))
+        .route(
+            "/api/admin/storage/deletions/requeue",
+            post(requeue_storage_deletions),
+        )
+        .route("/api/admin/jobs", get(get_jobs))
+        .route("/api/admin/jobs/requeue", post(requeue_jobs))
+        .route("/api/admin/usage", get(get_all_usage))
+        .route("/api/admin/users/{id}/quota", put(set_quota))
         .route("/api/logs", get(query_logs))
         .route("/api/metrics", get(metrics))
         .route("/api/cos/sts", get(get_sts_credentials))
         .route("/api/cos/config", get(get_cos_config))
+        .route("/api/cos/presign", post(get_presigned_url))
         .route("/api/cos/validate", post(validate_file_upload))
         .layer(middleware::from_fn(auth_middleware));
 
@@ -54,18 +113,51 @@ pub fn print_endpoints() {
     println!("  GET  /api/health          - 健康检查");
     println!("  POST /api/auth/register   - 用户注册");
     println!("  POST /api/auth/login      - 用户登录");
+    println!("  GET  /api/storage/*key    - 通过签名URL下载本地存储对象");
+    println!("  PUT  /api/storage/*key    - 通过签名URL上传本地存储对象");
     println!("  GET  /api/auth/me         - 获取当前用户信息 (需要认证)");
     println!("  POST /api/auth/logout     - 用户登出 (需要认证)");
+    println!("  GET  /api/me/usage        - 获取存储用量和配额 (需要认证)");
     println!("  GET  /api/media           - 获取用户媒体列表 (需要认证)");
     println!("  POST /api/media           - 创建新媒体 (需要认证)");
     println!("  GET  /api/media/search    - 搜索媒体 (需要认证)");
     println!("  GET  /api/media/:id       - 获取单个媒体 (需要认证)");
     println!("  PUT  /api/media/:id       - 更新媒体信息 (需要认证)");
-    println!("  DELETE /api/media/:id     - 删除媒体 (需要认证)");
-    println!("  PUT  /api/media/:id/upload - 上传媒体文件 (需要认证)");
+    println!("  DELETE /api/media/:id     - 删除媒体，移入回收站 (需要认证)");
+    println!("  GET  /api/media/by-hash/:hash - 按内容摘要查找已有媒体 (需要认证)");
+    println!("  GET  /api/media/duplicates?max_distance= - 可能重复的媒体报告 (需要认证)");
+    println!("  GET  /api/media/:id/download - 下载媒体文件 (需要认证)");
+    println!("  GET  /api/media/:id/stream - 在线播放媒体文件 (需要认证)");
+    println!("  GET  /api/media/:id/thumbnail?size= - 获取图片缩略图或视频封面 (需要认证)");
+    println!("  GET  /api/media/:id/sprite.vtt - 视频拖动预览的缩略图轨道 (需要认证)");
+    println!("  GET  /api/media/:id/waveform?format=&pixels= - 获取音频波形 (需要认证)");
+    println!("  GET  /api/media/:id/hls/master.m3u8 - 转码后的 HLS 自适应码率播放 (需要认证)");
+    println!("  GET  /api/media/:id/jobs  - 查看媒体处理任务状态 (需要认证)");
+    println!("  GET  /api/media/:id/similar?max_distance= - 查找画面相似的媒体 (需要认证)");
+    println!("  GET  /api/media/:id/shares - 获取媒体分享列表 (需要认证)");
+    println!("  POST /api/media/:id/shares - 分享媒体给其他用户 (需要认证)");
+    println!("  DELETE /api/media/:id/shares/:user_id - 取消分享 (需要认证)");
+    println!("  GET  /api/media/trash     - 获取回收站中的媒体 (需要认证)");
+    println!("  POST /api/media/:id/restore - 从回收站恢复媒体 (需要认证)");
+    println!("  DELETE /api/media/trash/:id - 从回收站彻底删除媒体 (需要认证)");
+    println!("  POST /api/media/upload    - 流式上传文件并创建媒体 (需要认证)");
+    println!("  PUT  /api/media/:id/content - 流式上传替换媒体内容 (需要认证)");
+    println!("  POST /api/uploads         - 创建断点续传会话 (需要认证)");
+    println!("  GET  /api/uploads/:id     - 获取上传会话状态 (需要认证)");
+    println!("  HEAD /api/uploads/:id     - 查询上传进度 (需要认证)");
+    println!("  PATCH /api/uploads/:id    - 上传分块 (需要认证)");
+    println!("  DELETE /api/uploads/:id   - 取消上传 (需要认证)");
+    println!("  POST /api/admin/gc/orphans - 扫描并回收孤儿对象 (需要管理员)");
+    println!("  GET  /api/admin/storage/deletions - 查看存储对象删除队列 (需要管理员)");
+    println!("  POST /api/admin/storage/deletions/requeue - 重新入队失败的删除 (需要管理员)");
+    println!("  GET  /api/admin/jobs      - 查看媒体处理任务队列 (需要管理员)");
+    println!("  POST /api/admin/jobs/requeue - 重新入队死信任务 (需要管理员)");
+    println!("  GET  /api/admin/usage     - 查看所有用户的存储用量 (需要管理员)");
+    println!("  PUT  /api/admin/users/:id/quota - 设置用户存储配额 (需要管理员)");
     println!("  GET  /api/logs            - 查询日志记录 (需要认证)");
     println!("  GET  /api/metrics         - 获取监控指标 (需要认证)");
     println!("  GET  /api/cos/sts         - 获取COS STS临时凭证 (需要认证)");
     println!("  GET  /api/cos/config      - 获取COS配置信息 (需要认证)");
+    println!("  POST /api/cos/presign     - 获取预签名上传/下载URL (需要认证)");
     println!("  POST /api/cos/validate    - 验证文件上传参数 (需要认证)");
 }
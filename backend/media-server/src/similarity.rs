@@ -0,0 +1,314 @@
+//! 感知哈希与相似媒体
+//!
+//! 内容摘要只能找出完全相同的文件，连拍、重新编码或缩放后的副本需要按画面比较。
+//! 为图片和视频关键帧计算 64 位差异哈希（dHash），登记到 `media_perceptual_hashes`：
+//! - 图片：元数据提取时由解码后（已按 EXIF 方向旋转）的图片计算一个哈希
+//! - 视频：生成拖动预览时，从预览帧中取时长 10%、30%、50%、70%、90% 附近的帧各计算一个哈希
+//!
+//! 两个媒体的距离为对应帧哈希的汉明距离中的最大值，即每一帧都足够接近才视为相似。
+//! 只比较同一用户、同一媒体类型的媒体
+//!
+//! 可能重复的媒体报告需要比较用户的所有媒体。哈希按 16 位分为四段并建有索引，
+//! 距离不超过 [`DUPLICATE_MAX_DISTANCE`] 的两个哈希至少有一段完全相同，
+//! 因此只需比较第一帧至少有一段相同的媒体
+
+use image::DynamicImage;
+use image::imageops::FilterType;
+use serde::Serialize;
+use sqlx::PgConnection;
+
+use crate::database::Database;
+use crate::handlers::media_handlers::MediaItem;
+
+/// 视频关键帧在时长中的位置
+pub const KEYFRAME_POSITIONS: [f64; 5] = [0.1, 0.3, 0.5, 0.7, 0.9];
+
+/// 默认和允许的最大汉明距离（64 位中不同的位数）
+pub const DEFAULT_MAX_DISTANCE: i32 = 10;
+pub const MAX_DISTANCE_LIMIT: i32 = 32;
+
+/// 可能重复的媒体报告允许的最大汉明距离：哈希分为四段，距离小于段数时至少有一段完全相同
+pub const DUPLICATE_MAX_DISTANCE: i32 = 3;
+
+/// 差异哈希：缩小为 9x8 的灰度图，每行比较相邻像素的亮度（CPU 密集，应在阻塞线程中调用）
+pub fn dhash(image: &DynamicImage) -> i64 {
+    let gray = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash: u64 = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if gray.get_pixel(x, y).0[0] > gray.get_pixel(x + 1, y).0[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash as i64
+}
+
+/// 从按时间排序的预览帧中选出关键帧的哈希，没有预览帧时返回空列表
+pub fn keyframe_hashes(offsets: &[f64], hashes: &[i64], duration: f64) -> Vec<i64> {
+    if offsets.is_empty() || offsets.len() != hashes.len() {
+        return Vec::new();
+    }
+    KEYFRAME_POSITIONS
+        .iter()
+        .map(|position| {
+            let target = duration * position;
+            let index = offsets
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| (*a - target).abs().total_cmp(&(*b - target).abs()))
+                .map(|(index, _)| index)
+                .unwrap_or(0);
+            hashes[index]
+        })
+        .collect()
+}
+
+/// 替换媒体的感知哈希，`hashes` 为空时只移除已有的哈希
+pub async fn replace(
+    conn: &mut PgConnection,
+    media_id: &str,
+    hashes: &[i64],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM media_perceptual_hashes WHERE media_id = $1")
+        .bind(media_id)
+        .execute(&mut *conn)
+        .await?;
+    if hashes.is_empty() {
+        return Ok(());
+    }
+
+    let frames: Vec<i32> = (0..hashes.len() as i32).collect();
+    sqlx::query(
+        r#"
+        INSERT INTO media_perceptual_hashes (media_id, frame, hash)
+        SELECT $1, frame, hash FROM UNNEST($2::INTEGER[], $3::BIGINT[]) AS t(frame, hash)
+        "#,
+    )
+    .bind(media_id)
+    .bind(&frames)
+    .bind(hashes)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// 不在事务中时替换媒体的感知哈希，见 [`replace`]
+///
+/// 媒体内容在计算期间被替换（对象键已变化）时不写入，由新内容的任务重新计算
+pub async fn store(db: &Database, media: &MediaItem, hashes: &[i64]) -> Result<(), sqlx::Error> {
+    let mut tx = db.pool.begin().await?;
+    let current: Option<String> =
+        sqlx::query_scalar("SELECT id FROM media_files WHERE id = $1 AND cos_key = $2 FOR SHARE")
+            .bind(&media.id)
+            .bind(&media.cos_key)
+            .fetch_optional(&mut *tx)
+            .await?;
+    if current.is_some() {
+        replace(&mut tx, &media.id, hashes).await?;
+    }
+    tx.commit().await
+}
+
+/// 相似的媒体及其距离
+#[derive(Serialize, Debug, sqlx::FromRow)]
+pub struct SimilarMedia {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub media: MediaItem,
+    pub distance: i32,
+}
+
+/// 可能重复的一对媒体
+#[derive(Serialize, Debug)]
+pub struct DuplicatePair {
+    pub distance: i32,
+    pub first: MediaItem,
+    pub second: MediaItem,
+}
+
+/// 在用户的媒体中查找与指定媒体相似的媒体，按距离从近到远排序
+///
+/// 指定的媒体没有感知哈希时返回空列表
+pub async fn find_similar(
+    db: &Database,
+    media: &MediaItem,
+    user_id: &str,
+    max_distance: i32,
+    limit: i64,
+) -> Result<Vec<SimilarMedia>, sqlx::Error> {
+    sqlx::query_as::<_, SimilarMedia>(
+        r#"
+        WITH source AS (
+            SELECT frame, hash FROM media_perceptual_hashes WHERE media_id = $1
+        ),
+        candidates AS (
+            SELECT h.media_id, MAX(bit_count((h.hash # s.hash)::BIT(64)))::INTEGER AS distance
+            FROM media_perceptual_hashes h
+            JOIN source s ON s.frame = h.frame
+            JOIN media_files m ON m.id = h.media_id
+            WHERE m.user_id = $2 AND m.status IN ('active', 'processing')
+              AND m.media_type = $3 AND m.id <> $1
+              AND bit_count((h.hash # s.hash)::BIT(64)) <= $4
+            GROUP BY h.media_id
+            HAVING COUNT(*) = (SELECT COUNT(*) FROM source)
+        )
+        SELECT m.*, c.distance
+        FROM candidates c
+        JOIN media_files m ON m.id = c.media_id
+        ORDER BY c.distance, m.created_at DESC
+        LIMIT $5
+        "#,
+    )
+    .bind(&media.id)
+    .bind(user_id)
+    .bind(&media.media_type)
+    .bind(max_distance)
+    .bind(limit)
+    .fetch_all(&db.pool)
+    .await
+}
+
+/// 列出用户媒体中可能重复的媒体对，按距离从近到远排序
+///
+/// `max_distance` 不超过 [`DUPLICATE_MAX_DISTANCE`]，先按第一帧哈希的段值找出候选的媒体对，再比较所有帧
+pub async fn find_duplicates(
+    db: &Database,
+    user_id: &str,
+    max_distance: i32,
+    limit: i64,
+) -> Result<Vec<DuplicatePair>, sqlx::Error> {
+    let pairs: Vec<(String, String, i32)> = sqlx::query_as(
+        r#"
+        WITH hashes AS (
+            SELECT h.media_id, h.frame, h.hash, h.band0, h.band1, h.band2, h.band3,
+                   m.media_type, m.created_at
+            FROM media_perceptual_hashes h
+            JOIN media_files m ON m.id = h.media_id
+            WHERE m.user_id = $1 AND m.status IN ('active', 'processing')
+        ),
+        firsts AS (
+            SELECT * FROM hashes WHERE frame = 0
+        ),
+        candidates AS (
+            SELECT a.media_id AS first_id, b.media_id AS second_id
+            FROM firsts a JOIN firsts b ON b.band0 = a.band0
+                AND b.media_type = a.media_type AND b.media_id > a.media_id
+            UNION
+            SELECT a.media_id, b.media_id
+            FROM firsts a JOIN firsts b ON b.band1 = a.band1
+                AND b.media_type = a.media_type AND b.media_id > a.media_id
+            UNION
+            SELECT a.media_id, b.media_id
+            FROM firsts a JOIN firsts b ON b.band2 = a.band2
+                AND b.media_type = a.media_type AND b.media_id > a.media_id
+            UNION
+            SELECT a.media_id, b.media_id
+            FROM firsts a JOIN firsts b ON b.band3 = a.band3
+                AND b.media_type = a.media_type AND b.media_id > a.media_id
+        ),
+        frame_counts AS (
+            SELECT media_id, COUNT(*) AS frames FROM hashes GROUP BY media_id
+        ),
+        pairs AS (
+            SELECT c.first_id, c.second_id,
+                   MAX(bit_count((a.hash # b.hash)::BIT(64)))::INTEGER AS distance,
+                   COUNT(*) AS frames,
+                   GREATEST(MAX(a.created_at), MAX(b.created_at)) AS created_at
+            FROM candidates c
+            JOIN hashes a ON a.media_id = c.first_id
+            JOIN hashes b ON b.media_id = c.second_id AND b.frame = a.frame
+            GROUP BY c.first_id, c.second_id
+        )
+        SELECT p.first_id, p.second_id, p.distance
+        FROM pairs p
+        JOIN frame_counts fa ON fa.media_id = p.first_id AND fa.frames = p.frames
+        JOIN frame_counts fb ON fb.media_id = p.second_id AND fb.frames = p.frames
+        WHERE p.distance <= $2
+        ORDER BY p.distance, p.created_at DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(max_distance.min(DUPLICATE_MAX_DISTANCE))
+    .bind(limit)
+    .fetch_all(&db.pool)
+    .await?;
+
+    let ids: Vec<&str> = pairs
+        .iter()
+        .flat_map(|(first, second, _)| [first.as_str(), second.as_str()])
+        .collect();
+    let media: std::collections::HashMap<String, MediaItem> =
+        sqlx::query_as::<_, MediaItem>("SELECT * FROM media_files WHERE id = ANY($1)")
+            .bind(&ids)
+            .fetch_all(&db.pool)
+            .await?
+            .into_iter()
+            .map(|media| (media.id.clone(), media))
+            .collect();
+
+    Ok(pairs
+        .into_iter()
+        .filter_map(|(first, second, distance)| {
+            Some(DuplicatePair {
+                distance,
+                first: media.get(&first)?.clone(),
+                second: media.get(&second)?.clone(),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    /// 亮度沿水平方向变化的灰度图
+    fn gradient(width: u32, height: u32, increasing: bool) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, _| {
+            let value = (x * 255 / (width - 1)) as u8;
+            Luma([if increasing { value } else { 255 - value }])
+        }))
+    }
+
+    #[test]
+    fn hashes_brightness_direction() {
+        assert_eq!(dhash(&gradient(90, 80, true)), 0);
+        assert_eq!(dhash(&gradient(90, 80, false)), -1);
+        assert_eq!(dhash(&DynamicImage::new_luma8(16, 16)), 0);
+    }
+
+    #[test]
+    fn hash_is_stable_across_sizes() {
+        // 40x40 的亮度块，缩小为 9x8 时每个块对应一个像素
+        let image = DynamicImage::ImageLuma8(GrayImage::from_fn(360, 320, |x, y| {
+            Luma([((x / 40 * 97 + y / 40 * 31) % 256) as u8])
+        }));
+        let original = dhash(&image);
+        assert_ne!(original, 0);
+        let resized = dhash(&image.resize_exact(180, 160, FilterType::Triangle));
+        assert!((original ^ resized).count_ones() <= 4);
+    }
+
+    #[test]
+    fn picks_nearest_frames_for_keyframes() {
+        let offsets: Vec<f64> = (0..10).map(f64::from).collect();
+        let hashes: Vec<i64> = (0..10).collect();
+        assert_eq!(keyframe_hashes(&offsets, &hashes, 10.0), [1, 3, 5, 7, 9]);
+
+        // 预览帧少于关键帧时重复使用最近的帧
+        assert_eq!(
+            keyframe_hashes(&[0.0, 5.0], &[10, 20], 10.0),
+            [10, 20, 20, 20, 20]
+        );
+    }
+
+    #[test]
+    fn requires_matching_offsets_and_hashes() {
+        assert!(keyframe_hashes(&[], &[], 10.0).is_empty());
+        assert!(keyframe_hashes(&[0.0, 1.0], &[1], 10.0).is_empty());
+    }
+}
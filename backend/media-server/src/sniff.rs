@@ -0,0 +1,427 @@
+//! 文件内容嗅探
+//!
+//! 上传完成后读取对象开头的字节，根据文件头（magic bytes）识别真实格式，
+//! 与客户端声明的 `content_type` 比对：
+//! - 格式一致：`verified`
+//! - 同一媒体类型下的子类型不一致（如声明 PNG 实为 JPEG）：改用识别出的类型，`corrected`
+//! - 媒体类型不一致（如声明图片实为 PDF）：改用识别出的类型并标记为 `spoofed`，
+//!   改正后的类型仍需符合上传策略
+//! - 声明为图片、视频或音频但文件头不匹配任何格式：拒绝。这些文件会交给图片解码器和
+//!   转码程序处理，也会在浏览器中内联显示，不能接受无法识别的内容（如带脚本的 SVG、
+//!   伪装成视频的播放列表）
+//! - 声明为其他类型（文档等）且无法识别：`unverified`
+//!
+//! 检查结果写入媒体的 `metadata.content_check`
+
+use serde::Serialize;
+use std::fmt;
+
+use crate::handlers::upload_handlers::media_type_from_content_type;
+use crate::storage::{SharedStorage, StorageError, read_prefix};
+
+/// 识别格式需要读取的字节数
+pub const SNIFF_LEN: u64 = 4096;
+
+/// 可以识别的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Jpeg,
+    Png,
+    Gif,
+    WebP,
+    Heic,
+    Avif,
+    Mp4,
+    QuickTime,
+    M4a,
+    WebM,
+    Matroska,
+    Mp3,
+    Flac,
+    Wav,
+    Ogg,
+    Pdf,
+}
+
+impl Format {
+    /// 标准的 MIME 类型
+    pub fn mime(self) -> &'static str {
+        match self {
+            Format::Jpeg => "image/jpeg",
+            Format::Png => "image/png",
+            Format::Gif => "image/gif",
+            Format::WebP => "image/webp",
+            Format::Heic => "image/heic",
+            Format::Avif => "image/avif",
+            Format::Mp4 => "video/mp4",
+            Format::QuickTime => "video/quicktime",
+            Format::M4a => "audio/mp4",
+            Format::WebM => "video/webm",
+            Format::Matroska => "video/x-matroska",
+            Format::Mp3 => "audio/mpeg",
+            Format::Flac => "audio/flac",
+            Format::Wav => "audio/wav",
+            Format::Ogg => "audio/ogg",
+            Format::Pdf => "application/pdf",
+        }
+    }
+
+    /// 与该格式一致的声明类型（包括常见的别名）
+    fn aliases(self) -> &'static [&'static str] {
+        match self {
+            Format::Jpeg => &["image/jpeg", "image/jpg", "image/pjpeg"],
+            Format::Png => &["image/png", "image/apng"],
+            Format::Gif => &["image/gif"],
+            Format::WebP => &["image/webp"],
+            Format::Heic => &["image/heic", "image/heif", "image/heic-sequence"],
+            Format::Avif => &["image/avif"],
+            // 通用的 MP4 品牌无法区分音频和视频
+            Format::Mp4 => &["video/mp4", "audio/mp4", "application/mp4", "audio/x-m4a"],
+            Format::QuickTime => &["video/quicktime", "video/mp4"],
+            Format::M4a => &["audio/mp4", "audio/x-m4a", "audio/m4a", "audio/aac"],
+            // WebM 是 Matroska 的子集
+            Format::WebM => &["video/webm", "audio/webm", "video/x-matroska"],
+            Format::Matroska => &["video/x-matroska", "video/mkv", "audio/x-matroska"],
+            Format::Mp3 => &["audio/mpeg", "audio/mp3", "audio/mpeg3", "audio/x-mpeg"],
+            Format::Flac => &["audio/flac", "audio/x-flac"],
+            Format::Wav => &["audio/wav", "audio/x-wav", "audio/wave", "audio/vnd.wave"],
+            Format::Ogg => &[
+                "audio/ogg",
+                "audio/opus",
+                "audio/vorbis",
+                "video/ogg",
+                "application/ogg",
+            ],
+            Format::Pdf => &["application/pdf"],
+        }
+    }
+
+    /// 转码程序（ffmpeg）读取该格式使用的解复用器，图片和文档为 `None`
+    pub fn demuxer(self) -> Option<&'static str> {
+        match self {
+            Format::Mp4 | Format::QuickTime | Format::M4a => Some("mov"),
+            Format::WebM | Format::Matroska => Some("matroska"),
+            Format::Mp3 => Some("mp3"),
+            Format::Flac => Some("flac"),
+            Format::Wav => Some("wav"),
+            Format::Ogg => Some("ogg"),
+            _ => None,
+        }
+    }
+
+    /// 对应的媒体类型
+    pub fn media_type(self) -> &'static str {
+        media_type_from_content_type(self.mime())
+    }
+
+    fn matches(self, content_type: &str) -> bool {
+        self.aliases().contains(&content_type)
+    }
+}
+
+const ALL_FORMATS: [Format; 16] = [
+    Format::Jpeg,
+    Format::Png,
+    Format::Gif,
+    Format::WebP,
+    Format::Heic,
+    Format::Avif,
+    Format::Mp4,
+    Format::QuickTime,
+    Format::M4a,
+    Format::WebM,
+    Format::Matroska,
+    Format::Mp3,
+    Format::Flac,
+    Format::Wav,
+    Format::Ogg,
+    Format::Pdf,
+];
+
+/// 读取 Matroska 头部的 DocType（`webm` 或 `matroska`）
+fn ebml_doc_type(header: &[u8]) -> Option<&[u8]> {
+    let position = header.windows(2).position(|w| w == [0x42, 0x82])?;
+    let size_byte = *header.get(position + 2)?;
+    // DocType 很短，只处理单字节长度
+    if size_byte & 0x80 == 0 {
+        return None;
+    }
+    let len = (size_byte & 0x7f) as usize;
+    header.get(position + 3..position + 3 + len)
+}
+
+/// ISO BMFF（MP4/MOV/HEIF）的 `ftyp` 品牌
+fn iso_format(header: &[u8]) -> Option<Format> {
+    let box_type = header.get(4..8)?;
+    if box_type != b"ftyp" {
+        // 早期的 QuickTime 文件没有 ftyp，直接以其他顶层 box 开头
+        return matches!(box_type, b"moov" | b"mdat" | b"wide" | b"free" | b"skip")
+            .then_some(Format::QuickTime);
+    }
+
+    let major_brand = header.get(8..12)?;
+    Some(match major_brand {
+        b"qt  " => Format::QuickTime,
+        b"M4A " | b"M4B " | b"M4P " => Format::M4a,
+        b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" | b"mif1" | b"msf1" => {
+            Format::Heic
+        }
+        b"avif" | b"avis" => Format::Avif,
+        _ => Format::Mp4,
+    })
+}
+
+/// 根据文件头识别格式
+pub fn detect(header: &[u8]) -> Option<Format> {
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(Format::Jpeg);
+    }
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(Format::Png);
+    }
+    if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        return Some(Format::Gif);
+    }
+    if header.starts_with(b"RIFF") {
+        return match header.get(8..12)? {
+            b"WEBP" => Some(Format::WebP),
+            b"WAVE" => Some(Format::Wav),
+            _ => None,
+        };
+    }
+    if header.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        return match ebml_doc_type(header) {
+            Some(b"webm") => Some(Format::WebM),
+            _ => Some(Format::Matroska),
+        };
+    }
+    if header.starts_with(b"fLaC") {
+        return Some(Format::Flac);
+    }
+    if header.starts_with(b"OggS") {
+        return Some(Format::Ogg);
+    }
+    if header.starts_with(b"%PDF-") {
+        return Some(Format::Pdf);
+    }
+    if header.starts_with(b"ID3") {
+        return Some(Format::Mp3);
+    }
+    // MPEG 音频帧同步字，层号为 0 的是 AAC ADTS
+    if let [0xFF, second, ..] = header
+        && second & 0xE0 == 0xE0
+        && second & 0x06 != 0
+    {
+        return Some(Format::Mp3);
+    }
+    iso_format(header)
+}
+
+/// 去掉 MIME 类型的参数部分并转为小写
+pub fn normalize(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Verified,
+    Corrected,
+    Spoofed,
+    Unverified,
+}
+
+/// 内容检查的结果
+#[derive(Serialize, Debug, Clone)]
+pub struct ContentCheck {
+    pub status: CheckStatus,
+    pub declared: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detected: Option<&'static str>,
+}
+
+impl ContentCheck {
+    /// 检查后应使用的 MIME 类型
+    pub fn content_type(&self) -> &str {
+        match (self.status, self.detected) {
+            (CheckStatus::Corrected | CheckStatus::Spoofed, Some(detected)) => detected,
+            _ => &self.declared,
+        }
+    }
+
+    pub fn is_spoofed(&self) -> bool {
+        self.status == CheckStatus::Spoofed
+    }
+}
+
+/// 文件内容与声明的类型不符且无法识别
+#[derive(Debug, Clone)]
+pub struct ContentMismatch {
+    pub declared: String,
+}
+
+impl fmt::Display for ContentMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "文件内容与声明的类型 {} 不符", self.declared)
+    }
+}
+
+impl std::error::Error for ContentMismatch {}
+
+/// 比对文件头和声明的类型
+pub fn check_content(header: &[u8], declared: &str) -> Result<ContentCheck, ContentMismatch> {
+    let normalized = normalize(declared);
+    let detected = detect(header);
+
+    let status = match detected {
+        Some(format) if format.matches(&normalized) => CheckStatus::Verified,
+        Some(format) if format.media_type() == media_type_from_content_type(&normalized) => {
+            CheckStatus::Corrected
+        }
+        Some(_) => CheckStatus::Spoofed,
+        None if matches!(
+            media_type_from_content_type(&normalized),
+            "image" | "video" | "audio"
+        ) || ALL_FORMATS.iter().any(|format| format.matches(&normalized)) =>
+        {
+            return Err(ContentMismatch {
+                declared: declared.to_string(),
+            });
+        }
+        None => CheckStatus::Unverified,
+    };
+
+    Ok(ContentCheck {
+        status,
+        declared: declared.to_string(),
+        detected: detected.map(Format::mime),
+    })
+}
+
+/// 读取对象开头用于识别格式的字节
+pub async fn read_header(
+    storage: &SharedStorage,
+    key: &str,
+    size: u64,
+) -> Result<Vec<u8>, StorageError> {
+    read_prefix(storage, key, size, SNIFF_LEN).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JPEG: &[u8] = &[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F'];
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    const PDF: &[u8] = b"%PDF-1.7\n";
+
+    fn ftyp(brand: &[u8; 4]) -> Vec<u8> {
+        let mut header = vec![0, 0, 0, 0x18];
+        header.extend_from_slice(b"ftyp");
+        header.extend_from_slice(brand);
+        header.extend_from_slice(&[0, 0, 0, 0]);
+        header.extend_from_slice(b"isommp41");
+        header
+    }
+
+    fn ebml(doc_type: &[u8]) -> Vec<u8> {
+        let mut header = vec![0x1A, 0x45, 0xDF, 0xA3, 0x9F, 0x42, 0x82];
+        header.push(0x80 | doc_type.len() as u8);
+        header.extend_from_slice(doc_type);
+        header
+    }
+
+    #[test]
+    fn detects_images() {
+        assert_eq!(detect(JPEG), Some(Format::Jpeg));
+        assert_eq!(detect(PNG), Some(Format::Png));
+        assert_eq!(detect(b"GIF89a\x01\0"), Some(Format::Gif));
+        assert_eq!(detect(b"RIFF\0\0\0\0WEBPVP8 "), Some(Format::WebP));
+        assert_eq!(detect(&ftyp(b"heic")), Some(Format::Heic));
+        assert_eq!(detect(&ftyp(b"avif")), Some(Format::Avif));
+    }
+
+    #[test]
+    fn detects_iso_brands() {
+        assert_eq!(detect(&ftyp(b"isom")), Some(Format::Mp4));
+        assert_eq!(detect(&ftyp(b"qt  ")), Some(Format::QuickTime));
+        assert_eq!(detect(&ftyp(b"M4A ")), Some(Format::M4a));
+        assert_eq!(detect(b"\0\0\0\x08wide"), Some(Format::QuickTime));
+        assert_eq!(detect(b"\0\0\0\x08abcd"), None);
+    }
+
+    #[test]
+    fn detects_matroska_doc_type() {
+        assert_eq!(detect(&ebml(b"webm")), Some(Format::WebM));
+        assert_eq!(detect(&ebml(b"matroska")), Some(Format::Matroska));
+    }
+
+    #[test]
+    fn detects_audio() {
+        assert_eq!(detect(b"ID3\x04\0"), Some(Format::Mp3));
+        assert_eq!(detect(&[0xFF, 0xFB, 0x90, 0x64]), Some(Format::Mp3));
+        assert_eq!(detect(b"fLaC\0\0\0\x22"), Some(Format::Flac));
+        assert_eq!(detect(b"RIFF\0\0\0\0WAVEfmt "), Some(Format::Wav));
+        assert_eq!(detect(b"OggS\0\x02"), Some(Format::Ogg));
+        // 层号为 0 的同步字不是 MPEG 音频
+        assert_eq!(detect(&[0xFF, 0xF1, 0x50, 0x80]), None);
+    }
+
+    #[test]
+    fn rejects_unknown_or_short_headers() {
+        assert_eq!(detect(b""), None);
+        assert_eq!(detect(b"RIFF\0\0"), None);
+        assert_eq!(detect(b"#EXTM3U\n"), None);
+        assert_eq!(detect(b"<svg xmlns="), None);
+        assert_eq!(detect(PDF), Some(Format::Pdf));
+    }
+
+    #[test]
+    fn normalizes_content_type() {
+        assert_eq!(normalize(" Image/JPEG ; charset=binary"), "image/jpeg");
+        assert_eq!(normalize(""), "");
+    }
+
+    #[test]
+    fn verifies_matching_type() {
+        let check = check_content(JPEG, "image/jpg").unwrap();
+        assert_eq!(check.status, CheckStatus::Verified);
+        assert_eq!(check.content_type(), "image/jpg");
+    }
+
+    #[test]
+    fn corrects_subtype() {
+        let check = check_content(JPEG, "image/png").unwrap();
+        assert_eq!(check.status, CheckStatus::Corrected);
+        assert_eq!(check.content_type(), "image/jpeg");
+    }
+
+    #[test]
+    fn flags_spoofed_media_type() {
+        let check = check_content(PDF, "image/png").unwrap();
+        assert!(check.is_spoofed());
+        assert_eq!(check.content_type(), "application/pdf");
+    }
+
+    #[test]
+    fn rejects_unrecognized_media() {
+        assert!(check_content(b"<svg onload=alert(1)>", "image/svg+xml").is_err());
+        assert!(check_content(b"#EXTM3U\n", "video/mp4").is_err());
+        assert!(check_content(b"#EXTM3U\n", "application/vnd.apple.mpegurl; x=1").is_ok());
+        assert!(check_content(b"garbage", "audio/x-unknown").is_err());
+        assert!(check_content(b"garbage", "Audio/MPEG").is_err());
+    }
+
+    #[test]
+    fn leaves_other_types_unverified() {
+        let check = check_content(b"hello", "text/plain").unwrap();
+        assert_eq!(check.status, CheckStatus::Unverified);
+        assert_eq!(check.detected, None);
+        assert_eq!(check.content_type(), "text/plain");
+    }
+}
@@ -0,0 +1,25 @@
+use axum::extract::FromRef;
+
+use crate::database::Database;
+use crate::storage::SharedStorage;
+
+/// 服务器共享状态
+///
+/// 处理函数可以直接提取 `State<Database>` 或 `State<SharedStorage>`
+#[derive(Clone)]
+pub struct AppState {
+    pub database: Database,
+    pub storage: SharedStorage,
+}
+
+impl FromRef<AppState> for Database {
+    fn from_ref(state: &AppState) -> Self {
+        state.database.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedStorage {
+    fn from_ref(state: &AppState) -> Self {
+        state.storage.clone()
+    }
+}
@@ -0,0 +1,508 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use cos_rust_sdk::sts::{GetCredentialsRequest, Policy, StsClient};
+use cos_rust_sdk::{
+    Auth, BucketClient, Config, CosClient, CosError, ListObjectsV2Options, ObjectClient,
+};
+use futures::StreamExt;
+use reqwest::{Method, StatusCode};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::multipart::{MultipartUpload, PART_SIZE, PartReader};
+use super::{
+    ByteRange, ByteStream, ObjectMeta, PresignMethod, StorageBackend, StorageError, StorageResult,
+    StoredObject, UploadCredentials, UploadCredentialsRequest, encode_key,
+};
+
+/// 服务端自身请求使用的签名有效期
+const INTERNAL_SIGN_EXPIRES: Duration = Duration::from_secs(300);
+
+/// 腾讯云COS存储后端
+#[derive(Debug)]
+pub struct CosStorage {
+    bucket: String,
+    region: String,
+    domain: Option<String>,
+    credentials: Option<(String, String)>,
+    object_client: Option<ObjectClient>,
+    bucket_client: Option<BucketClient>,
+    /// SDK 不支持范围读取、流式读取和分片上传，这些请求使用预签名URL直接发送
+    http_client: reqwest::Client,
+}
+
+impl CosStorage {
+    /// 从环境变量读取COS配置
+    ///
+    /// 密钥缺失时服务仍可启动，具体操作会返回配置错误
+    pub fn from_env() -> Self {
+        let bucket = std::env::var("COS_BUCKET").unwrap_or_default();
+        let region = std::env::var("COS_REGION").unwrap_or_else(|_| "ap-beijing".to_string());
+        let domain = std::env::var("COS_DOMAIN").ok();
+
+        let credentials = match (
+            std::env::var("COS_SECRET_ID"),
+            std::env::var("COS_SECRET_KEY"),
+        ) {
+            (Ok(id), Ok(key)) => Some((id, key)),
+            _ => None,
+        };
+
+        let (object_client, bucket_client) = match &credentials {
+            Some((secret_id, secret_key)) if !bucket.is_empty() => {
+                let config = Config::new(secret_id, secret_key, &region, &bucket)
+                    .with_timeout(Duration::from_secs(30));
+                match CosClient::new(config) {
+                    Ok(client) => (
+                        Some(ObjectClient::new(client.clone())),
+                        Some(BucketClient::new(client)),
+                    ),
+                    Err(e) => {
+                        crate::log_with_storage!(error, "COS客户端创建失败: {}", e);
+                        (None, None)
+                    }
+                }
+            }
+            _ => (None, None),
+        };
+
+        // 流式读取的总时长取决于对象大小，只限制连接和每次读取的等待时间
+        let http_client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .read_timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_else(|e| {
+                crate::log_with_storage!(error, "HTTP客户端创建失败: {}", e);
+                reqwest::Client::new()
+            });
+
+        CosStorage {
+            bucket,
+            region,
+            domain,
+            credentials,
+            object_client,
+            bucket_client,
+            http_client,
+        }
+    }
+
+    fn object_client(&self) -> StorageResult<&ObjectClient> {
+        self.object_client
+            .as_ref()
+            .ok_or_else(|| self.missing_config())
+    }
+
+    fn bucket_client(&self) -> StorageResult<&BucketClient> {
+        self.bucket_client
+            .as_ref()
+            .ok_or_else(|| self.missing_config())
+    }
+
+    fn credentials(&self) -> StorageResult<&(String, String)> {
+        self.credentials
+            .as_ref()
+            .ok_or_else(|| self.missing_config())
+    }
+
+    fn missing_config(&self) -> StorageError {
+        if self.bucket.is_empty() {
+            StorageError::Config("未找到环境变量 COS_BUCKET".to_string())
+        } else {
+            StorageError::Config("未找到环境变量 COS_SECRET_ID/COS_SECRET_KEY".to_string())
+        }
+    }
+
+    fn bucket_url(&self) -> String {
+        format!("https://{}.cos.{}.myqcloud.com", self.bucket, self.region)
+    }
+
+    /// 生成查询字符串签名的URL，`params` 中的参数同样参与签名
+    /// `headers` 为需要签名的请求头，请求必须携带相同的值
+    fn signed_url(
+        &self,
+        method: &str,
+        key: &str,
+        params: &[(&str, String)],
+        headers: &[(&str, String)],
+        expires_in: Duration,
+    ) -> StorageResult<String> {
+        let (secret_id, secret_key) = self.credentials()?;
+        let auth = Auth::new(secret_id.as_str(), secret_key.as_str());
+
+        let start_time = Utc::now();
+        let end_time = start_time
+            + chrono::Duration::from_std(expires_in).unwrap_or(chrono::Duration::hours(1));
+
+        let signed_params: HashMap<String, String> = params
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        let signed_headers: HashMap<String, String> = headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        let authorization = auth
+            .sign(
+                method,
+                &format!("/{}", key),
+                &signed_headers,
+                &signed_params,
+                start_time,
+                end_time,
+            )
+            .map_err(|e| StorageError::Backend(format!("COS签名失败: {}", e)))?;
+
+        // COS 支持将签名参数直接放在查询字符串中
+        let mut url = format!("{}/{}?", self.bucket_url(), encode_key(key));
+        for (name, value) in params {
+            url.push_str(&format!(
+                "{}={}&",
+                name,
+                encode_key(value).replace('/', "%2F")
+            ));
+        }
+        url.push_str(&authorization);
+        Ok(url)
+    }
+}
+
+/// 将SDK错误转换为存储错误，404 视为对象不存在
+fn map_cos_error(key: &str, e: CosError) -> StorageError {
+    let message = e.to_string();
+    if message.contains("404") || message.contains("NoSuchKey") {
+        StorageError::NotFound(key.to_string())
+    } else {
+        StorageError::Backend(format!("COS请求失败: {}", message))
+    }
+}
+
+fn non_empty(value: String) -> Option<String> {
+    if value.is_empty() { None } else { Some(value) }
+}
+
+/// 上传凭证的策略：只允许单个请求上传 `prefix` 下不超过 `max_size` 字节的对象
+///
+/// 分片上传的每个请求只能限制分片大小，无法限制对象的总大小，因此不允许分片上传
+fn upload_policy(bucket: &str, prefix: &str, max_size: u64) -> Policy {
+    let mut policy = Policy::allow_put_object(bucket, Some(prefix));
+    for statement in &mut policy.statement {
+        statement.action = vec!["name/cos:PutObject".to_string()];
+        statement.condition = Some(HashMap::from([(
+            "numeric_less_than_equal".to_string(),
+            HashMap::from([(
+                "cos:content-length".to_string(),
+                serde_json::json!(max_size),
+            )]),
+        )]));
+    }
+    policy
+}
+
+#[async_trait]
+impl StorageBackend for CosStorage {
+    fn name(&self) -> &'static str {
+        "cos"
+    }
+
+    fn bucket(&self) -> String {
+        self.bucket.clone()
+    }
+
+    fn region(&self) -> String {
+        self.region.clone()
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        match &self.domain {
+            Some(domain) => format!("https://{}/{}", domain, encode_key(key)),
+            None => format!("{}/{}", self.bucket_url(), encode_key(key)),
+        }
+    }
+
+    fn upload_protocol(&self) -> &'static str {
+        "sts"
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> StorageResult<ObjectMeta> {
+        let size = data.len() as u64;
+        let response = self
+            .object_client()?
+            .put_object(key, data, content_type)
+            .await
+            .map_err(|e| map_cos_error(key, e))?;
+
+        Ok(ObjectMeta {
+            key: key.to_string(),
+            size,
+            content_type: content_type.map(|s| s.to_string()),
+            etag: non_empty(response.etag),
+            last_modified: None,
+        })
+    }
+
+    /// 内容超过一个分片时使用分片上传，内存中最多保留一个分片
+    async fn put_stream(
+        &self,
+        key: &str,
+        stream: ByteStream<'_>,
+        content_type: Option<&str>,
+    ) -> StorageResult<ObjectMeta> {
+        let mut parts = PartReader::new(stream);
+        let first = parts.next_part().await?.unwrap_or_default();
+        if first.len() < PART_SIZE {
+            return self.put(key, Vec::from(first), content_type).await;
+        }
+
+        let upload = MultipartUpload {
+            http_client: &self.http_client,
+            sign: |method: &Method, params: &[(&str, String)]| {
+                self.signed_url(method.as_str(), key, params, &[], INTERNAL_SIGN_EXPIRES)
+            },
+            key,
+            content_type,
+        };
+        upload.upload(first, &mut parts).await
+    }
+
+    async fn get(&self, key: &str) -> StorageResult<StoredObject> {
+        let response = self
+            .object_client()?
+            .get_object(key)
+            .await
+            .map_err(|e| map_cos_error(key, e))?;
+
+        Ok(StoredObject {
+            meta: ObjectMeta {
+                key: key.to_string(),
+                size: response.data.len() as u64,
+                content_type: Some(response.content_type),
+                etag: non_empty(response.etag),
+                last_modified: response.last_modified,
+            },
+            data: response.data,
+        })
+    }
+
+    async fn get_stream(
+        &self,
+        key: &str,
+        range: Option<ByteRange>,
+    ) -> StorageResult<ByteStream<'static>> {
+        let url = self
+            .presign(key, PresignMethod::Get, INTERNAL_SIGN_EXPIRES)
+            .await?;
+        let mut request = self.http_client.get(&url);
+        if let Some(range) = range {
+            request = request.header(
+                reqwest::header::RANGE,
+                format!("bytes={}-{}", range.start, range.end),
+            );
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| StorageError::Backend(format!("COS请求失败: {}", e)))?;
+        match response.status() {
+            status if status.is_success() => {}
+            StatusCode::NOT_FOUND => return Err(StorageError::NotFound(key.to_string())),
+            status => {
+                let text = response.text().await.unwrap_or_default();
+                return Err(StorageError::Backend(format!(
+                    "COS返回错误 {}: {}",
+                    status, text
+                )));
+            }
+        }
+
+        Ok(Box::pin(
+            response
+                .bytes_stream()
+                .map(|chunk| chunk.map_err(std::io::Error::other)),
+        ))
+    }
+
+    async fn head(&self, key: &str) -> StorageResult<ObjectMeta> {
+        let response = self
+            .object_client()?
+            .head_object(key)
+            .await
+            .map_err(|e| map_cos_error(key, e))?;
+
+        Ok(ObjectMeta {
+            key: key.to_string(),
+            size: response.content_length,
+            content_type: Some(response.content_type),
+            etag: non_empty(response.etag),
+            last_modified: response.last_modified,
+        })
+    }
+
+    async fn delete(&self, key: &str) -> StorageResult<()> {
+        match self.object_client()?.delete_object(key).await {
+            Ok(_) => {
+                crate::log_with_storage!(info, "成功从COS删除文件: {}", key);
+                Ok(())
+            }
+            Err(e) => match map_cos_error(key, e) {
+                // 如果是404错误（文件不存在），也视为成功
+                StorageError::NotFound(_) => {
+                    crate::log_with_storage!(info, "COS文件不存在，视为删除成功: {}", key);
+                    Ok(())
+                }
+                other => Err(other),
+            },
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> StorageResult<Vec<ObjectMeta>> {
+        let client = self.bucket_client()?;
+        let mut objects = Vec::new();
+        let mut continuation_token = None;
+
+        loop {
+            let response = client
+                .list_objects_v2(Some(ListObjectsV2Options {
+                    prefix: Some(prefix.to_string()),
+                    continuation_token: continuation_token.take(),
+                    max_keys: Some(1000),
+                    ..Default::default()
+                }))
+                .await
+                .map_err(|e| map_cos_error(prefix, e))?;
+
+            objects.extend(response.contents.into_iter().map(|object| ObjectMeta {
+                key: object.key,
+                size: object.size,
+                content_type: None,
+                etag: non_empty(object.etag),
+                last_modified: non_empty(object.last_modified),
+            }));
+
+            if !response.is_truncated || response.next_continuation_token.is_empty() {
+                break;
+            }
+            continuation_token = Some(response.next_continuation_token);
+        }
+
+        Ok(objects)
+    }
+
+    async fn presign(
+        &self,
+        key: &str,
+        method: PresignMethod,
+        expires_in: Duration,
+    ) -> StorageResult<String> {
+        self.signed_url(method.as_str(), key, &[], &[], expires_in)
+    }
+
+    async fn presign_upload(
+        &self,
+        key: &str,
+        content_length: u64,
+        expires_in: Duration,
+    ) -> StorageResult<String> {
+        self.signed_url(
+            "PUT",
+            key,
+            &[],
+            &[("content-length", content_length.to_string())],
+            expires_in,
+        )
+    }
+
+    async fn presign_download(
+        &self,
+        key: &str,
+        expires_in: Duration,
+        content_disposition: Option<&str>,
+    ) -> StorageResult<String> {
+        let url = self.presign(key, PresignMethod::Get, expires_in).await?;
+
+        // COS 只校验签名中列出的参数，response-* 参数可以直接附加
+        Ok(match content_disposition {
+            Some(value) => format!(
+                "{}&response-content-disposition={}",
+                url,
+                encode_key(value).replace('/', "%2F")
+            ),
+            None => url,
+        })
+    }
+
+    async fn issue_upload_credentials(
+        &self,
+        request: UploadCredentialsRequest,
+    ) -> StorageResult<UploadCredentials> {
+        if self.bucket.is_empty() {
+            return Err(StorageError::Config("未配置腾讯云存储桶名称".to_string()));
+        }
+        let (secret_id, secret_key) = self.credentials()?;
+
+        let policy = if let Some(custom_policy) = request.custom_policy.as_deref() {
+            // 如果提供了自定义策略，尝试解析 JSON
+            serde_json::from_str::<Policy>(custom_policy)
+                .map_err(|e| StorageError::InvalidRequest(format!("自定义Policy解析失败: {}", e)))?
+        } else {
+            upload_policy(&self.bucket, &request.prefix, request.max_object_size)
+        };
+
+        crate::log_with_storage!(info, "使用Policy: {:?}", policy);
+
+        let sts_client = StsClient::new(secret_id.clone(), secret_key.clone(), self.region.clone());
+        let response = sts_client
+            .get_credentials(GetCredentialsRequest {
+                name: Some("media-hub-temp-credentials".to_string()),
+                policy,
+                duration_seconds: Some(request.duration_seconds),
+            })
+            .await
+            .map_err(|e| StorageError::Backend(format!("获取临时凭证失败: {}", e)))?;
+
+        let expiration = response
+            .expired_time
+            .map(|t| {
+                chrono::DateTime::from_timestamp(t as i64, 0)
+                    .unwrap_or_else(chrono::Utc::now)
+                    .to_rfc3339()
+            })
+            .unwrap_or_else(|| (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339());
+
+        Ok(UploadCredentials {
+            session_token: response.token,
+            tmp_secret_id: response.tmp_secret_id,
+            tmp_secret_key: response.tmp_secret_key,
+            expiration,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_upload_policy_to_single_put_of_declared_size() {
+        let policy = upload_policy("media-1250000000", "media/u1/a.png", 1024);
+        let json = serde_json::to_value(&policy).unwrap();
+        assert_eq!(
+            json["statement"][0]["action"],
+            serde_json::json!(["name/cos:PutObject"])
+        );
+        assert_eq!(
+            json["statement"][0]["resource"][0],
+            "qcs::cos:*:uid/1250000000:prefix//1250000000/media/media/u1/a.png*"
+        );
+        assert_eq!(
+            json["statement"][0]["condition"]["numeric_less_than_equal"]["cos:content-length"],
+            1024
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use super::{
+    ByteRange, ByteStream, ObjectMeta, PresignMethod, StorageBackend, StorageError, StorageResult,
+    StoredObject, encode_key,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 元信息目录名，位于存储根目录下，列举对象时会被跳过
+const META_DIR: &str = ".meta";
+
+/// 签名URL使用的密钥 (未配置时回退到 JWT_SECRET)，都未配置时本地存储无法启用
+static SIGNING_SECRET: Lazy<Option<String>> = Lazy::new(|| {
+    ["STORAGE_SIGNING_SECRET", "JWT_SECRET"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|secret| !secret.is_empty())
+});
+
+/// 对象的附加元信息，保存在 `.meta/{key}.json`
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct SidecarMeta {
+    content_type: Option<String>,
+}
+
+/// 本地文件系统存储后端
+///
+/// 对象保存在 `LOCAL_STORAGE_DIR/{key}`，通过服务器的 `/api/storage/{key}` 签名URL读写
+#[derive(Debug)]
+pub struct LocalStorage {
+    root: PathBuf,
+    base_url: String,
+}
+
+impl LocalStorage {
+    /// 从环境变量读取本地存储配置
+    pub fn from_env() -> StorageResult<Self> {
+        let root =
+            std::env::var("LOCAL_STORAGE_DIR").unwrap_or_else(|_| "data/storage".to_string());
+        let base_url = std::env::var("LOCAL_STORAGE_BASE_URL").unwrap_or_default();
+        if SIGNING_SECRET.is_none() {
+            return Err(StorageError::Config(
+                "本地存储需要配置 STORAGE_SIGNING_SECRET 或 JWT_SECRET 用于签名URL".to_string(),
+            ));
+        }
+
+        std::fs::create_dir_all(&root)
+            .map_err(|e| StorageError::Config(format!("无法创建本地存储目录 {}: {}", root, e)))?;
+
+        Ok(LocalStorage {
+            root: PathBuf::from(root),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// 将对象键解析为存储目录下的文件路径，拒绝越出根目录的键
+    fn object_path(&self, key: &str) -> StorageResult<PathBuf> {
+        Ok(self.root.join(validate_key(key)?))
+    }
+
+    fn meta_path(&self, key: &str) -> StorageResult<PathBuf> {
+        let relative = validate_key(key)?;
+        let mut path = self.root.join(META_DIR).join(relative);
+        let file_name = format!(
+            "{}.json",
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default()
+        );
+        path.set_file_name(file_name);
+        Ok(path)
+    }
+
+    async fn read_sidecar(&self, key: &str) -> SidecarMeta {
+        let Ok(path) = self.meta_path(key) else {
+            return SidecarMeta::default();
+        };
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+            Err(_) => SidecarMeta::default(),
+        }
+    }
+
+    async fn build_meta(&self, key: &str, path: &Path) -> StorageResult<ObjectMeta> {
+        let metadata = match tokio::fs::metadata(path).await {
+            Ok(metadata) if metadata.is_file() => metadata,
+            Ok(_) => return Err(StorageError::NotFound(key.to_string())),
+            Err(e) => return Err(map_io_error(key, e)),
+        };
+        let sidecar = self.read_sidecar(key).await;
+
+        let modified: Option<DateTime<Utc>> = metadata.modified().ok().map(DateTime::from);
+        let etag = format!(
+            "\"{:x}-{:x}\"",
+            modified.map(|t| t.timestamp_micros()).unwrap_or_default(),
+            metadata.len()
+        );
+
+        Ok(ObjectMeta {
+            key: key.to_string(),
+            size: metadata.len(),
+            content_type: sidecar.content_type,
+            etag: Some(etag),
+            last_modified: modified.map(|t| t.to_rfc3339()),
+        })
+    }
+}
+
+/// 检查对象键只包含普通路径片段
+fn validate_key(key: &str) -> StorageResult<PathBuf> {
+    let path = Path::new(key);
+    let is_safe = !key.is_empty()
+        && !key.contains('\\')
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        && path
+            .components()
+            .next()
+            .is_some_and(|first| first.as_os_str() != META_DIR);
+
+    if is_safe {
+        Ok(path.to_path_buf())
+    } else {
+        Err(StorageError::InvalidRequest(format!(
+            "非法的对象键: {}",
+            key
+        )))
+    }
+}
+
+/// 将字节流逐块写入文件
+async fn write_stream(path: &Path, mut stream: ByteStream<'_>) -> std::io::Result<()> {
+    let mut file = tokio::fs::File::create(path).await?;
+    while let Some(chunk) = stream.next().await {
+        file.write_all(&chunk?).await?;
+    }
+    file.flush().await
+}
+
+fn map_io_error(key: &str, e: std::io::Error) -> StorageError {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        StorageError::NotFound(key.to_string())
+    } else {
+        StorageError::Backend(format!("本地存储IO错误: {}", e))
+    }
+}
+
+/// 未配置签名密钥时返回 `None`
+///
+/// 上传地址的 `size` 是允许上传的对象大小，一并签名
+fn signing_mac(method: &str, key: &str, expires: i64, size: Option<u64>) -> Option<HmacSha256> {
+    let secret = SIGNING_SECRET.as_deref()?;
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC可以接受任意长度的密钥");
+    mac.update(format!("{}\n{}\n{}", method, key, expires).as_bytes());
+    if let Some(size) = size {
+        mac.update(format!("\n{}", size).as_bytes());
+    }
+    Some(mac)
+}
+
+/// 计算签名URL的签名
+fn signature(method: &str, key: &str, expires: i64, size: Option<u64>) -> StorageResult<String> {
+    let mac = signing_mac(method, key, expires, size)
+        .ok_or_else(|| StorageError::Config("未配置签名URL密钥".to_string()))?;
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+/// 校验 `/api/storage/{key}` 请求携带的签名
+pub fn verify_signature(
+    method: &str,
+    key: &str,
+    expires: i64,
+    size: Option<u64>,
+    provided: &str,
+) -> bool {
+    if expires < Utc::now().timestamp() {
+        return false;
+    }
+
+    match (
+        hex::decode(provided),
+        signing_mac(method, key, expires, size),
+    ) {
+        (Ok(provided), Some(mac)) => mac.verify_slice(&provided).is_ok(),
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn bucket(&self) -> String {
+        "local".to_string()
+    }
+
+    fn region(&self) -> String {
+        "local".to_string()
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        format!("{}/api/storage/{}", self.base_url, encode_key(key))
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> StorageResult<ObjectMeta> {
+        let stream: ByteStream =
+            Box::pin(futures::stream::once(async move { Ok(Bytes::from(data)) }));
+        self.put_stream(key, stream, content_type).await
+    }
+
+    async fn put_stream(
+        &self,
+        key: &str,
+        stream: ByteStream<'_>,
+        content_type: Option<&str>,
+    ) -> StorageResult<ObjectMeta> {
+        let path = self.object_path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| map_io_error(key, e))?;
+        }
+
+        // 先写入临时文件再重命名，避免读到写了一半的对象
+        let tmp_path = path.with_file_name(format!(
+            ".{}.{}.tmp",
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default(),
+            uuid::Uuid::new_v4()
+        ));
+        if let Err(e) = write_stream(&tmp_path, stream).await {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return Err(map_io_error(key, e));
+        }
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .map_err(|e| map_io_error(key, e))?;
+
+        let meta_path = self.meta_path(key)?;
+        if let Some(parent) = meta_path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| map_io_error(key, e))?;
+        }
+        let sidecar = SidecarMeta {
+            content_type: content_type.map(|s| s.to_string()),
+        };
+        let sidecar_bytes = serde_json::to_vec(&sidecar)
+            .map_err(|e| StorageError::Backend(format!("元信息序列化失败: {}", e)))?;
+        tokio::fs::write(&meta_path, sidecar_bytes)
+            .await
+            .map_err(|e| map_io_error(key, e))?;
+
+        self.build_meta(key, &path).await
+    }
+
+    async fn get(&self, key: &str) -> StorageResult<StoredObject> {
+        let path = self.object_path(key)?;
+        let meta = self.build_meta(key, &path).await?;
+        let data = tokio::fs::read(&path)
+            .await
+            .map_err(|e| map_io_error(key, e))?;
+
+        Ok(StoredObject { meta, data })
+    }
+
+    async fn get_stream(
+        &self,
+        key: &str,
+        range: Option<ByteRange>,
+    ) -> StorageResult<ByteStream<'static>> {
+        let path = self.object_path(key)?;
+        let mut file = tokio::fs::File::open(&path)
+            .await
+            .map_err(|e| map_io_error(key, e))?;
+
+        let stream: ByteStream<'static> = match range {
+            Some(range) => {
+                file.seek(std::io::SeekFrom::Start(range.start))
+                    .await
+                    .map_err(|e| map_io_error(key, e))?;
+                Box::pin(tokio_util::io::ReaderStream::new(file.take(range.len())))
+            }
+            None => Box::pin(tokio_util::io::ReaderStream::new(file)),
+        };
+        Ok(stream)
+    }
+
+    async fn head(&self, key: &str) -> StorageResult<ObjectMeta> {
+        let path = self.object_path(key)?;
+        self.build_meta(key, &path).await
+    }
+
+    async fn delete(&self, key: &str) -> StorageResult<()> {
+        let path = self.object_path(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {}
+            // 文件不存在视为删除成功
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(map_io_error(key, e)),
+        }
+
+        if let Ok(meta_path) = self.meta_path(key) {
+            let _ = tokio::fs::remove_file(meta_path).await;
+        }
+
+        crate::log_with_storage!(info, "成功从本地存储删除文件: {}", key);
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> StorageResult<Vec<ObjectMeta>> {
+        let mut objects = Vec::new();
+        let mut pending = vec![self.root.clone()];
+
+        while let Some(dir) = pending.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(map_io_error(prefix, e)),
+            };
+
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .map_err(|e| map_io_error(prefix, e))?
+            {
+                let path = entry.path();
+                let Ok(relative) = path.strip_prefix(&self.root) else {
+                    continue;
+                };
+                let key = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+
+                let file_type = entry.file_type().await.map_err(|e| map_io_error(&key, e))?;
+                if file_type.is_dir() {
+                    if key != META_DIR {
+                        pending.push(path);
+                    }
+                    continue;
+                }
+
+                // 跳过写入中的临时文件
+                let is_temp = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with('.') && name.ends_with(".tmp"));
+                if is_temp || !key.starts_with(prefix) {
+                    continue;
+                }
+
+                objects.push(self.build_meta(&key, &path).await?);
+            }
+        }
+
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn presign(
+        &self,
+        key: &str,
+        method: PresignMethod,
+        expires_in: Duration,
+    ) -> StorageResult<String> {
+        validate_key(key)?;
+
+        let expires = Utc::now().timestamp() + expires_in.as_secs() as i64;
+        Ok(format!(
+            "{}?expires={}&signature={}",
+            self.object_url(key),
+            expires,
+            signature(method.as_str(), key, expires, None)?
+        ))
+    }
+
+    async fn presign_upload(
+        &self,
+        key: &str,
+        content_length: u64,
+        expires_in: Duration,
+    ) -> StorageResult<String> {
+        validate_key(key)?;
+
+        let expires = Utc::now().timestamp() + expires_in.as_secs() as i64;
+        Ok(format!(
+            "{}?expires={}&size={}&signature={}",
+            self.object_url(key),
+            expires,
+            content_length,
+            signature("PUT", key, expires, Some(content_length))?
+        ))
+    }
+}
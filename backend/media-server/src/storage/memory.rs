@@ -0,0 +1,112 @@
+//! 内存存储后端，只用于测试
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::{
+    ObjectMeta, PresignMethod, SharedStorage, StorageBackend, StorageError, StorageResult,
+    StoredObject,
+};
+
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    objects: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    /// 只包含一个对象的存储
+    pub fn with_object(key: &str, data: Vec<u8>) -> SharedStorage {
+        let storage = MemoryStorage::default();
+        storage
+            .objects
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), data);
+        Arc::new(storage)
+    }
+
+    fn meta(key: &str, data: &[u8]) -> ObjectMeta {
+        ObjectMeta {
+            key: key.to_string(),
+            size: data.len() as u64,
+            content_type: None,
+            etag: None,
+            last_modified: None,
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn bucket(&self) -> String {
+        String::new()
+    }
+
+    fn region(&self) -> String {
+        String::new()
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        format!("memory://{}", key)
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        _content_type: Option<&str>,
+    ) -> StorageResult<ObjectMeta> {
+        let meta = Self::meta(key, &data);
+        self.objects.lock().unwrap().insert(key.to_string(), data);
+        Ok(meta)
+    }
+
+    async fn get(&self, key: &str) -> StorageResult<StoredObject> {
+        let data = self
+            .objects
+            .lock()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| StorageError::NotFound(key.to_string()))?;
+        Ok(StoredObject {
+            meta: Self::meta(key, &data),
+            data,
+        })
+    }
+
+    async fn head(&self, key: &str) -> StorageResult<ObjectMeta> {
+        self.get(key).await.map(|object| object.meta)
+    }
+
+    async fn delete(&self, key: &str) -> StorageResult<()> {
+        self.objects.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> StorageResult<Vec<ObjectMeta>> {
+        Ok(self
+            .objects
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, data)| Self::meta(key, data))
+            .collect())
+    }
+
+    async fn presign(
+        &self,
+        _key: &str,
+        _method: PresignMethod,
+        _expires_in: Duration,
+    ) -> StorageResult<String> {
+        Err(StorageError::Unsupported("presign"))
+    }
+}
@@ -0,0 +1,396 @@
+//! 存储后端模块
+//!
+//! 定义统一的对象存储接口 `StorageBackend`，服务器的其余部分只依赖该接口，
+//! 不再直接调用具体云厂商的 SDK：
+//! - cos: 腾讯云COS实现
+//! - local: 本地文件系统实现，适用于离线和私有化部署
+//! - s3: S3兼容实现（AWS S3、MinIO等），使用SigV4预签名URL
+//! - multipart: S3兼容存储和COS共用的分片上传
+
+pub mod cos;
+pub mod local;
+#[cfg(test)]
+pub mod memory;
+pub mod multipart;
+pub mod s3;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+pub use cos::CosStorage;
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+/// 对象元信息
+#[derive(Serialize, Debug, Clone)]
+pub struct ObjectMeta {
+    pub key: String,
+    pub size: u64,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// 读取到的对象（元信息 + 内容）
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    pub meta: ObjectMeta,
+    pub data: Vec<u8>,
+}
+
+/// 流式上传使用的字节流
+pub type ByteStream<'a> = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send + 'a>>;
+
+/// 对象的字节范围（`start` 和 `end` 均包含在内）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// 预签名URL的用途
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresignMethod {
+    Get,
+    Put,
+}
+
+impl PresignMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PresignMethod::Get => "GET",
+            PresignMethod::Put => "PUT",
+        }
+    }
+}
+
+/// 临时上传凭证请求
+#[derive(Debug, Clone)]
+pub struct UploadCredentialsRequest {
+    /// 允许上传的对象键前缀（也可以是完整的对象键）
+    pub prefix: String,
+    /// 后端特定格式的自定义策略（JSON）
+    pub custom_policy: Option<String>,
+    /// 有效期（秒）
+    pub duration_seconds: u32,
+    /// 单个对象的大小上限（字节），使用自定义策略时不生效
+    pub max_object_size: u64,
+}
+
+/// 临时上传凭证
+#[derive(Debug, Clone)]
+pub struct UploadCredentials {
+    pub session_token: String,
+    pub tmp_secret_id: String,
+    pub tmp_secret_key: String,
+    pub expiration: String,
+}
+
+/// 存储后端错误
+#[derive(Debug)]
+pub enum StorageError {
+    /// 对象不存在
+    NotFound(String),
+    /// 当前后端不支持该操作
+    Unsupported(&'static str),
+    /// 配置缺失或错误
+    Config(String),
+    /// 请求参数不合法（如自定义策略解析失败）
+    InvalidRequest(String),
+    /// 后端返回的其他错误
+    Backend(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NotFound(key) => write!(f, "对象不存在: {}", key),
+            StorageError::Unsupported(op) => write!(f, "当前存储后端不支持该操作: {}", op),
+            StorageError::Config(msg) => write!(f, "存储配置错误: {}", msg),
+            StorageError::InvalidRequest(msg) => write!(f, "存储请求不合法: {}", msg),
+            StorageError::Backend(msg) => write!(f, "存储后端错误: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+/// 对象存储后端接口
+///
+/// 所有对象键均为存储桶内的相对路径（如 `media/xxx.jpg`），不带前导斜杠
+#[async_trait]
+pub trait StorageBackend: Send + Sync + fmt::Debug {
+    /// 后端名称，会写入 `media_files.storage_backend`
+    fn name(&self) -> &'static str;
+
+    /// 存储桶（或等价的命名空间）名称
+    fn bucket(&self) -> String;
+
+    /// 存储区域
+    fn region(&self) -> String;
+
+    /// 对象的访问地址（不带签名）
+    fn object_url(&self, key: &str) -> String;
+
+    /// 前端直传使用的上传协议：`sts` 临时凭证或 `presigned_url` 预签名URL
+    fn upload_protocol(&self) -> &'static str {
+        "presigned_url"
+    }
+
+    /// 上传对象
+    async fn put(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> StorageResult<ObjectMeta>;
+
+    /// 以流的方式上传对象
+    ///
+    /// 默认实现会先在内存中收集完整内容再调用 `put`，支持流式写入的后端应覆盖此方法。
+    /// 内置的后端都已覆盖：本地存储直接写入文件，S3 兼容存储和COS使用分片上传（见 [`multipart`]）
+    async fn put_stream(
+        &self,
+        key: &str,
+        mut stream: ByteStream<'_>,
+        content_type: Option<&str>,
+    ) -> StorageResult<ObjectMeta> {
+        let mut data = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk =
+                chunk.map_err(|e| StorageError::Backend(format!("读取上传数据失败: {}", e)))?;
+            data.extend_from_slice(&chunk);
+        }
+        self.put(key, data, content_type).await
+    }
+
+    /// 读取对象
+    async fn get(&self, key: &str) -> StorageResult<StoredObject>;
+
+    /// 以流的方式读取对象，`range` 为 `None` 时读取整个对象
+    ///
+    /// 调用方需保证范围位于对象大小之内。默认实现会先读取完整内容再截取，
+    /// 支持范围读取的后端应覆盖此方法
+    async fn get_stream(
+        &self,
+        key: &str,
+        range: Option<ByteRange>,
+    ) -> StorageResult<ByteStream<'static>> {
+        let object = self.get(key).await?;
+        let data = Bytes::from(object.data);
+        let data = match range {
+            Some(range) => {
+                let end = (range.end as usize + 1).min(data.len());
+                data.slice((range.start as usize).min(end)..end)
+            }
+            None => data,
+        };
+        Ok(Box::pin(futures::stream::once(async move { Ok(data) })))
+    }
+
+    /// 获取对象元信息
+    async fn head(&self, key: &str) -> StorageResult<ObjectMeta>;
+
+    /// 删除对象，对象不存在时视为成功
+    async fn delete(&self, key: &str) -> StorageResult<()>;
+
+    /// 列出指定前缀下的所有对象
+    async fn list(&self, prefix: &str) -> StorageResult<Vec<ObjectMeta>>;
+
+    /// 生成带有效期的预签名URL
+    async fn presign(
+        &self,
+        key: &str,
+        method: PresignMethod,
+        expires_in: Duration,
+    ) -> StorageResult<String>;
+
+    /// 生成上传用的预签名URL，请求体必须恰好为 `content_length` 字节
+    ///
+    /// 大小随签名一起校验，避免凭一个地址上传任意大小的对象
+    async fn presign_upload(
+        &self,
+        _key: &str,
+        _content_length: u64,
+        _expires_in: Duration,
+    ) -> StorageResult<String> {
+        Err(StorageError::Unsupported("presign_upload"))
+    }
+
+    /// 生成下载用的预签名URL
+    ///
+    /// `content_disposition` 用于覆盖响应的 `Content-Disposition` 头，不支持的后端会忽略该参数
+    async fn presign_download(
+        &self,
+        key: &str,
+        expires_in: Duration,
+        _content_disposition: Option<&str>,
+    ) -> StorageResult<String> {
+        self.presign(key, PresignMethod::Get, expires_in).await
+    }
+
+    /// 签发前端直传使用的临时凭证，凭证只允许上传
+    async fn issue_upload_credentials(
+        &self,
+        _request: UploadCredentialsRequest,
+    ) -> StorageResult<UploadCredentials> {
+        Err(StorageError::Unsupported("issue_upload_credentials"))
+    }
+}
+
+/// 在服务器状态中共享的存储后端
+pub type SharedStorage = Arc<dyn StorageBackend>;
+
+/// 根据环境变量 `STORAGE_BACKEND` 创建存储后端（默认 `cos`）
+pub fn from_env() -> StorageResult<SharedStorage> {
+    let backend = std::env::var("STORAGE_BACKEND").unwrap_or_else(|_| "cos".to_string());
+    from_name(&backend)
+}
+
+/// 按名称创建存储后端，配置从对应的环境变量读取
+pub fn from_name(name: &str) -> StorageResult<SharedStorage> {
+    match name {
+        "cos" => Ok(Arc::new(CosStorage::from_env())),
+        "local" => Ok(Arc::new(LocalStorage::from_env()?)),
+        "s3" => Ok(Arc::new(S3Storage::from_env()?)),
+        other => Err(StorageError::Config(format!("未知的存储后端: {}", other))),
+    }
+}
+
+/// 已创建的非当前存储后端，按名称缓存
+static OTHER_BACKENDS: LazyLock<Mutex<HashMap<String, SharedStorage>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// 返回记录中 `storage_backend` 对应的存储后端
+///
+/// 切换存储后端后，旧记录的对象仍保存在原来的后端中。名称与当前后端相同时直接返回当前后端，
+/// 否则按名称创建（需要保留原后端的配置）并缓存
+pub fn for_backend(current: &SharedStorage, name: &str) -> StorageResult<SharedStorage> {
+    if current.name() == name {
+        return Ok(current.clone());
+    }
+
+    let mut backends = OTHER_BACKENDS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(storage) = backends.get(name) {
+        return Ok(storage.clone());
+    }
+    let storage = from_name(name)?;
+    backends.insert(name.to_string(), storage.clone());
+    Ok(storage)
+}
+
+/// 读取对象中从 `start` 开始最多 `len` 字节，`size` 为对象大小，超出对象的部分被截断
+pub async fn read_range(
+    storage: &SharedStorage,
+    key: &str,
+    size: u64,
+    start: u64,
+    len: u64,
+) -> StorageResult<Vec<u8>> {
+    let len = size.saturating_sub(start).min(len);
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+
+    let range = ByteRange {
+        start,
+        end: start + len - 1,
+    };
+    let mut stream = storage.get_stream(key, Some(range)).await?;
+    let mut data = Vec::with_capacity(len as usize);
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| StorageError::Backend(e.to_string()))?;
+        data.extend_from_slice(&chunk);
+    }
+    data.truncate(len as usize);
+    Ok(data)
+}
+
+/// 读取对象开头最多 `len` 字节
+pub async fn read_prefix(
+    storage: &SharedStorage,
+    key: &str,
+    size: u64,
+    len: u64,
+) -> StorageResult<Vec<u8>> {
+    read_range(storage, key, size, 0, len).await
+}
+
+/// 将对象下载到本地文件，用于需要随机访问完整文件的解码器和外部程序
+pub async fn download_to_file(
+    storage: &SharedStorage,
+    key: &str,
+    path: &std::path::Path,
+) -> StorageResult<()> {
+    let local_error = |e: std::io::Error| StorageError::Backend(format!("写入本地文件失败: {}", e));
+
+    let mut stream = storage.get_stream(key, None).await?;
+    let mut file = tokio::fs::File::create(path).await.map_err(local_error)?;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| StorageError::Backend(e.to_string()))?;
+        file.write_all(&chunk).await.map_err(local_error)?;
+    }
+    file.flush().await.map_err(local_error)?;
+    Ok(())
+}
+
+/// 生成 `Content-Disposition` 头的值，文件名按 RFC 5987 编码
+pub fn content_disposition(disposition: &str, filename: &str) -> String {
+    format!(
+        "{}; filename*=UTF-8''{}",
+        disposition,
+        encode_key(filename).replace('/', "%2F")
+    )
+}
+
+/// 对对象键进行URL路径编码（保留 `/`）
+pub fn encode_key(key: &str) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+
+    #[test]
+    fn for_backend_returns_current_backend_by_name() {
+        let current: SharedStorage = Arc::new(MemoryStorage::default());
+        let resolved = for_backend(&current, "memory").unwrap();
+        assert!(Arc::ptr_eq(&current, &resolved));
+    }
+
+    #[test]
+    fn for_backend_rejects_unknown_backend() {
+        let current: SharedStorage = Arc::new(MemoryStorage::default());
+        assert!(matches!(
+            for_backend(&current, "ftp"),
+            Err(StorageError::Config(_))
+        ));
+    }
+}
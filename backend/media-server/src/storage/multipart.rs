@@ -0,0 +1,249 @@
+//! 分片上传（S3 协议，S3 兼容存储和COS共用）
+//!
+//! 流式上传时按 [`PART_SIZE`] 从字节流中读取分片并逐个上传，内存中最多保留一个分片。
+//! 内容不足一个分片时由各后端直接上传；任何一步失败时取消分片上传，已上传的分片由存储服务丢弃
+
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use reqwest::{Method, Response};
+use serde::Deserialize;
+
+use super::{ByteStream, ObjectMeta, StorageError, StorageResult};
+
+/// 分片大小，S3 和COS要求除最后一个分片外不小于 5MB
+pub const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// 按分片大小读取字节流
+pub struct PartReader<'a> {
+    stream: ByteStream<'a>,
+    buffer: BytesMut,
+    finished: bool,
+}
+
+impl<'a> PartReader<'a> {
+    pub fn new(stream: ByteStream<'a>) -> Self {
+        PartReader {
+            stream,
+            buffer: BytesMut::new(),
+            finished: false,
+        }
+    }
+
+    /// 读取下一个分片，只有最后一个分片可能小于 [`PART_SIZE`]；读完后返回 `None`
+    pub async fn next_part(&mut self) -> StorageResult<Option<Bytes>> {
+        while !self.finished && self.buffer.len() < PART_SIZE {
+            match self.stream.next().await {
+                Some(chunk) => {
+                    let chunk = chunk
+                        .map_err(|e| StorageError::Backend(format!("读取上传数据失败: {}", e)))?;
+                    self.buffer.extend_from_slice(&chunk);
+                }
+                None => self.finished = true,
+            }
+        }
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        let len = self.buffer.len().min(PART_SIZE);
+        Ok(Some(self.buffer.split_to(len).freeze()))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct InitiateMultipartUploadResult {
+    upload_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct CompleteMultipartUploadResult {
+    #[serde(rename = "ETag")]
+    etag: Option<String>,
+}
+
+/// 一次分片上传，`sign` 为指定请求方法和查询参数生成签名URL
+pub struct MultipartUpload<'a, S> {
+    pub http_client: &'a reqwest::Client,
+    pub sign: S,
+    pub key: &'a str,
+    pub content_type: Option<&'a str>,
+}
+
+impl<S> MultipartUpload<'_, S>
+where
+    S: Fn(&Method, &[(&str, String)]) -> StorageResult<String> + Sync,
+{
+    async fn send(
+        &self,
+        method: Method,
+        params: &[(&str, String)],
+        body: Option<reqwest::Body>,
+    ) -> StorageResult<Response> {
+        let url = (self.sign)(&method, params)?;
+        let mut request = self.http_client.request(method, &url);
+        // 对象的内容类型在创建分片上传时指定
+        if let (Some(content_type), Some(("uploads", _))) = (self.content_type, params.first()) {
+            request = request.header(reqwest::header::CONTENT_TYPE, content_type);
+        }
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| StorageError::Backend(format!("分片上传请求失败: {}", e)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(StorageError::Backend(format!(
+                "分片上传返回错误 {}: {}",
+                status, text
+            )));
+        }
+        Ok(response)
+    }
+
+    async fn text(response: Response) -> StorageResult<String> {
+        response
+            .text()
+            .await
+            .map_err(|e| StorageError::Backend(format!("读取分片上传响应失败: {}", e)))
+    }
+
+    /// 上传 `first` 和 `parts` 中剩余的所有分片并合并为对象
+    pub async fn upload(
+        &self,
+        first: Bytes,
+        parts: &mut PartReader<'_>,
+    ) -> StorageResult<ObjectMeta> {
+        let response = self
+            .send(Method::POST, &[("uploads", String::new())], None)
+            .await?;
+        let created: InitiateMultipartUploadResult =
+            quick_xml::de::from_str(&Self::text(response).await?)
+                .map_err(|e| StorageError::Backend(format!("解析分片上传ID失败: {}", e)))?;
+
+        match self.upload_parts(&created.upload_id, first, parts).await {
+            Ok(meta) => Ok(meta),
+            Err(e) => {
+                let params = [("uploadId", created.upload_id.clone())];
+                if let Err(abort_error) = self.send(Method::DELETE, &params, None).await {
+                    crate::log_with_storage!(
+                        warn,
+                        "取消分片上传失败 {}: {}",
+                        self.key,
+                        abort_error
+                    );
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn upload_parts(
+        &self,
+        upload_id: &str,
+        first: Bytes,
+        parts: &mut PartReader<'_>,
+    ) -> StorageResult<ObjectMeta> {
+        let mut etags = Vec::new();
+        let mut size = 0u64;
+        let mut next = Some(first);
+        while let Some(part) = next {
+            size += part.len() as u64;
+            let params = [
+                ("partNumber", (etags.len() + 1).to_string()),
+                ("uploadId", upload_id.to_string()),
+            ];
+            let response = self.send(Method::PUT, &params, Some(part.into())).await?;
+            let etag = response
+                .headers()
+                .get(reqwest::header::ETAG)
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| StorageError::Backend("分片上传响应缺少ETag".to_string()))?
+                .to_string();
+            etags.push(etag);
+            next = parts.next_part().await?;
+        }
+
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (index, etag) in etags.iter().enumerate() {
+            body.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                index + 1,
+                quick_xml::escape::escape(etag)
+            ));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+
+        let params = [("uploadId", upload_id.to_string())];
+        let response = self.send(Method::POST, &params, Some(body.into())).await?;
+        // 合并失败时服务端可能在返回 200 之后才在响应体中给出错误
+        let text = Self::text(response).await?;
+        if text.contains("<Error>") {
+            return Err(StorageError::Backend(format!("合并分片失败: {}", text)));
+        }
+        let completed: CompleteMultipartUploadResult = quick_xml::de::from_str(&text)
+            .map_err(|e| StorageError::Backend(format!("解析分片合并结果失败: {}", e)))?;
+
+        Ok(ObjectMeta {
+            key: self.key.to_string(),
+            size,
+            content_type: self.content_type.map(|s| s.to_string()),
+            etag: completed.etag,
+            last_modified: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_of(chunks: Vec<usize>) -> ByteStream<'static> {
+        Box::pin(futures::stream::iter(
+            chunks
+                .into_iter()
+                .map(|len| Ok(Bytes::from(vec![0u8; len]))),
+        ))
+    }
+
+    async fn part_sizes(chunks: Vec<usize>) -> Vec<usize> {
+        let mut parts = PartReader::new(stream_of(chunks));
+        let mut sizes = Vec::new();
+        while let Some(part) = parts.next_part().await.unwrap() {
+            sizes.push(part.len());
+        }
+        sizes
+    }
+
+    #[tokio::test]
+    async fn splits_stream_into_parts() {
+        let chunk = 3 * 1024 * 1024;
+        assert_eq!(
+            part_sizes(vec![chunk; 7]).await,
+            vec![PART_SIZE, PART_SIZE, 7 * chunk - 2 * PART_SIZE]
+        );
+        assert_eq!(part_sizes(vec![PART_SIZE, 1]).await, vec![PART_SIZE, 1]);
+        assert_eq!(
+            part_sizes(vec![2 * PART_SIZE]).await,
+            vec![PART_SIZE, PART_SIZE]
+        );
+    }
+
+    #[tokio::test]
+    async fn handles_small_and_empty_streams() {
+        assert_eq!(part_sizes(vec![10, 0, 20]).await, vec![30]);
+        assert!(part_sizes(vec![]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_stream_errors() {
+        let stream: ByteStream<'static> = Box::pin(futures::stream::iter(vec![
+            Ok(Bytes::from_static(b"abc")),
+            Err(std::io::Error::other("connection reset")),
+        ]));
+        assert!(PartReader::new(stream).next_part().await.is_err());
+    }
+}
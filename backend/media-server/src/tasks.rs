@@ -0,0 +1,163 @@
+//! 后台任务模块
+//!
+//! 服务器启动时派生的周期性维护任务：
+//! - 清理过期的断点续传会话
+//! - 彻底删除超过保留期的回收站媒体
+//! - 重试删除队列中失败的存储对象删除
+//! - 定期扫描孤儿对象（设置 `ORPHAN_GC_INTERVAL_HOURS` 后启用）
+//! - 媒体处理任务的工作线程池，以及清理已完成的任务记录
+
+use std::time::Duration;
+
+use crate::deletion_queue::process_due_deletions;
+use crate::gc::{GcOptions, OrphanAction, collect_orphans};
+use crate::handlers::{cleanup_expired_upload_sessions, purge_expired_trash, trash_retention};
+use crate::jobs::{purge_finished_jobs, run_worker};
+use crate::state::AppState;
+
+/// 上传会话清理间隔
+const UPLOAD_CLEANUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// 回收站清理间隔
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 删除队列的处理间隔
+const DELETION_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 每次处理的删除队列记录数
+const DELETION_BATCH_SIZE: i64 = 100;
+
+/// 默认的任务工作线程数
+const DEFAULT_JOB_WORKERS: usize = 4;
+
+/// 已完成任务记录的清理间隔
+const JOB_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 成功的任务记录保留时间
+const JOB_RETENTION_DAYS: i64 = 7;
+
+/// 任务工作线程数（环境变量 `JOB_WORKERS`），为 0 时本实例不处理任务
+fn job_workers() -> usize {
+    std::env::var("JOB_WORKERS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_JOB_WORKERS)
+}
+
+/// 读取定期孤儿对象回收的配置，未设置间隔时不启用
+///
+/// - `ORPHAN_GC_INTERVAL_HOURS`: 扫描间隔（小时）
+/// - `ORPHAN_GC_ACTION`: `report`（默认）、`quarantine` 或 `delete`
+/// - `ORPHAN_GC_GRACE_HOURS`: 宽限期（小时），默认24小时
+fn orphan_gc_config() -> Option<(Duration, GcOptions)> {
+    let interval_hours: u64 = std::env::var("ORPHAN_GC_INTERVAL_HOURS")
+        .ok()?
+        .parse()
+        .ok()
+        .filter(|hours| *hours > 0)?;
+
+    let mut options = GcOptions::default();
+    if let Ok(action) = std::env::var("ORPHAN_GC_ACTION") {
+        match OrphanAction::parse(&action) {
+            Some(action) => options.action = action,
+            None => crate::log_with_storage!(warn, "未知的 ORPHAN_GC_ACTION: {}", action),
+        }
+    }
+    if let Some(hours) = std::env::var("ORPHAN_GC_GRACE_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|hours| *hours >= 0)
+    {
+        options.grace_period = chrono::Duration::hours(hours);
+    }
+
+    Some((Duration::from_secs(interval_hours * 3600), options))
+}
+
+/// 启动所有后台任务
+pub fn spawn_background_tasks(state: &AppState) {
+    let workers = job_workers();
+    let instance = uuid::Uuid::new_v4().simple().to_string();
+    for index in 0..workers {
+        let worker_id = format!("{}-{}", &instance[..8], index);
+        tokio::spawn(run_worker(
+            state.database.clone(),
+            state.storage.clone(),
+            worker_id,
+        ));
+    }
+    crate::log_with_storage!(info, "已启动 {} 个任务工作线程", workers);
+
+    let database = state.database.clone();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(JOB_CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match purge_finished_jobs(&database, chrono::Duration::days(JOB_RETENTION_DAYS)).await {
+                Ok(0) => {}
+                Ok(count) => crate::log_with_storage!(info, "已清理 {} 条已完成的任务记录", count),
+                Err(e) => crate::log_with_storage!(error, "清理任务记录失败: {}", e),
+            }
+        }
+    });
+
+    if let Some((interval, options)) = orphan_gc_config() {
+        let database = state.database.clone();
+        let storage = state.storage.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = collect_orphans(&database, &storage, options).await {
+                    crate::log_with_storage!(error, "定期孤儿对象回收失败: {}", e);
+                }
+            }
+        });
+    }
+
+    let database = state.database.clone();
+    let storage = state.storage.clone();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(UPLOAD_CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match cleanup_expired_upload_sessions(&database, &storage).await {
+                Ok(0) => {}
+                Ok(count) => crate::log_with_storage!(info, "已清理 {} 个过期上传会话", count),
+                Err(e) => crate::log_with_storage!(error, "清理过期上传会话失败: {}", e),
+            }
+        }
+    });
+
+    let database = state.database.clone();
+    let storage = state.storage.clone();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(DELETION_RETRY_INTERVAL);
+        loop {
+            interval.tick().await;
+            match process_due_deletions(&database, &storage, DELETION_BATCH_SIZE).await {
+                Ok(0) => {}
+                Ok(count) => crate::log_with_storage!(info, "删除队列已删除 {} 个对象", count),
+                Err(e) => crate::log_with_storage!(error, "处理删除队列失败: {}", e),
+            }
+        }
+    });
+
+    let database = state.database.clone();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TRASH_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match purge_expired_trash(&database, trash_retention()).await {
+                Ok(0) => {}
+                Ok(count) => crate::log_with_storage!(info, "已彻底删除 {} 个回收站媒体", count),
+                Err(e) => crate::log_with_storage!(error, "清理回收站失败: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,411 @@
+//! 图片缩略图
+//!
+//! 为图片生成最长边为 160、480、1280 像素的缩略图，按 EXIF 方向旋转后保存到
+//! `derivatives/{媒体ID}/` 下，并登记到 `media_derivatives`：
+//! - 不透明的图片编码为 JPEG
+//! - 带透明通道的图片编码为无损 WebP，保留透明度
+//!
+//! 原图小于目标尺寸时不放大。无法解码的格式（如 HEIC、AVIF）不生成缩略图，
+//! 由客户端回退到原图。视频的缩略图由抽取的封面画面生成，见 [`crate::previews`]。
+//! 媒体彻底删除或内容替换时，旧的缩略图对象通过删除队列删除
+
+use chrono::{DateTime, Utc};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ExtendedColorType, ImageDecoder, ImageError, ImageReader, Limits};
+use serde::Serialize;
+use sqlx::PgConnection;
+use std::fmt;
+use std::io::Cursor;
+use uuid::Uuid;
+
+use crate::database::Database;
+use crate::deletion_queue::{delete_or_enqueue, delete_or_enqueue_from, enqueue_deletion};
+use crate::handlers::media_handlers::MediaItem;
+use crate::previews;
+use crate::storage::{self, SharedStorage, StorageError, read_prefix};
+use crate::transcode::TranscodeError;
+
+/// 缩略图尺寸（最长边像素），从小到大
+pub const THUMBNAIL_SIZES: [u32; 3] = [160, 480, 1280];
+
+/// 派生文件的对象键前缀
+pub const DERIVATIVE_PREFIX: &str = "derivatives/";
+
+/// 派生文件类型
+pub const THUMBNAIL_KIND: &str = "thumbnail";
+
+/// 生成缩略图的原图大小上限
+const MAX_SOURCE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// 解码时允许的最大宽高和内存，防止解压炸弹
+const MAX_DIMENSION: u32 = 20_000;
+const MAX_DECODE_ALLOC: u64 = 512 * 1024 * 1024;
+
+const JPEG_QUALITY: u8 = 82;
+
+/// 登记的派生文件
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct MediaDerivative {
+    pub id: String,
+    pub media_id: String,
+    pub kind: String,
+    /// 目标尺寸（最长边像素）
+    pub size: i32,
+    /// 实际像素尺寸
+    pub width: i32,
+    pub height: i32,
+    pub content_type: String,
+    pub file_size: i64,
+    pub object_key: String,
+    pub storage_backend: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum ThumbnailError {
+    Storage(StorageError),
+    Image(ImageError),
+    Database(sqlx::Error),
+    /// 从视频中抽取画面失败
+    Transcode(TranscodeError),
+    /// 解码任务异常退出
+    Task(String),
+}
+
+impl fmt::Display for ThumbnailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThumbnailError::Storage(e) => write!(f, "读写缩略图对象失败: {}", e),
+            ThumbnailError::Image(e) => write!(f, "处理图片失败: {}", e),
+            ThumbnailError::Database(e) => write!(f, "登记缩略图失败: {}", e),
+            ThumbnailError::Transcode(e) => write!(f, "抽取视频画面失败: {}", e),
+            ThumbnailError::Task(e) => write!(f, "缩略图任务失败: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ThumbnailError {}
+
+impl From<StorageError> for ThumbnailError {
+    fn from(e: StorageError) -> Self {
+        ThumbnailError::Storage(e)
+    }
+}
+
+impl From<ImageError> for ThumbnailError {
+    fn from(e: ImageError) -> Self {
+        ThumbnailError::Image(e)
+    }
+}
+
+impl From<sqlx::Error> for ThumbnailError {
+    fn from(e: sqlx::Error) -> Self {
+        ThumbnailError::Database(e)
+    }
+}
+
+impl From<TranscodeError> for ThumbnailError {
+    fn from(e: TranscodeError) -> Self {
+        ThumbnailError::Transcode(e)
+    }
+}
+
+/// 编码后的缩略图
+struct Rendered {
+    size: u32,
+    width: u32,
+    height: u32,
+    content_type: &'static str,
+    extension: &'static str,
+    data: Vec<u8>,
+}
+
+fn encode(image: &DynamicImage, size: u32, transparent: bool) -> Result<Rendered, ImageError> {
+    let mut data = Vec::new();
+    let (content_type, extension) = if transparent {
+        let rgba = image.to_rgba8();
+        WebPEncoder::new_lossless(&mut data).encode(
+            &rgba,
+            rgba.width(),
+            rgba.height(),
+            ExtendedColorType::Rgba8,
+        )?;
+        ("image/webp", "webp")
+    } else {
+        let rgb = image.to_rgb8();
+        JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY).encode_image(&rgb)?;
+        ("image/jpeg", "jpg")
+    };
+
+    Ok(Rendered {
+        size,
+        width: image.width(),
+        height: image.height(),
+        content_type,
+        extension,
+        data,
+    })
+}
+
+/// 解码图片并按 EXIF 方向旋转，限制尺寸和内存（CPU 密集，应在阻塞线程中调用）
+pub fn decode(data: &[u8]) -> Result<DynamicImage, ImageError> {
+    let mut reader = ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    reader.limits(limits);
+
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// 解码原图并生成所有尺寸的缩略图（CPU 密集，应在阻塞线程中调用）
+fn render(data: Vec<u8>) -> Result<Vec<Rendered>, ImageError> {
+    let mut image = decode(&data)?;
+    let transparent = image.color().has_alpha();
+
+    // 从大到小依次缩放，较小的尺寸基于上一级的结果，减少计算量
+    let mut rendered = Vec::with_capacity(THUMBNAIL_SIZES.len());
+    for size in THUMBNAIL_SIZES.into_iter().rev() {
+        if image.width() > size || image.height() > size {
+            image = image.resize(size, size, FilterType::CatmullRom);
+        }
+        rendered.push(encode(&image, size, transparent)?);
+    }
+    rendered.reverse();
+    Ok(rendered)
+}
+
+/// 查询媒体已有的缩略图对象及其存储后端
+async fn existing_objects(
+    db: &Database,
+    media_id: &str,
+) -> Result<Vec<(String, String)>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT object_key, storage_backend FROM media_derivatives WHERE media_id = $1 AND kind = $2",
+    )
+        .bind(media_id)
+        .bind(THUMBNAIL_KIND)
+        .fetch_all(&db.pool)
+        .await
+}
+
+/// 在事务中登记缩略图，同一尺寸的旧记录被替换
+async fn register(
+    db: &Database,
+    storage: &SharedStorage,
+    media_id: &str,
+    uploaded: &[(String, i64, Rendered)],
+) -> Result<Vec<MediaDerivative>, sqlx::Error> {
+    let mut tx = db.pool.begin().await?;
+    let mut derivatives = Vec::with_capacity(uploaded.len());
+
+    for (key, file_size, thumbnail) in uploaded {
+        let derivative = sqlx::query_as::<_, MediaDerivative>(
+            r#"
+            INSERT INTO media_derivatives (
+                id, media_id, kind, size, width, height, content_type,
+                file_size, object_key, storage_backend, created_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (media_id, kind, size) DO UPDATE SET
+                width = EXCLUDED.width,
+                height = EXCLUDED.height,
+                content_type = EXCLUDED.content_type,
+                file_size = EXCLUDED.file_size,
+                object_key = EXCLUDED.object_key,
+                storage_backend = EXCLUDED.storage_backend,
+                created_at = EXCLUDED.created_at
+            RETURNING *
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(media_id)
+        .bind(THUMBNAIL_KIND)
+        .bind(thumbnail.size as i32)
+        .bind(thumbnail.width as i32)
+        .bind(thumbnail.height as i32)
+        .bind(thumbnail.content_type)
+        .bind(file_size)
+        .bind(key)
+        .bind(storage.name())
+        .bind(Utc::now())
+        .fetch_one(&mut *tx)
+        .await?;
+        derivatives.push(derivative);
+    }
+
+    tx.commit().await?;
+    Ok(derivatives)
+}
+
+/// 上传缩略图对象并登记，返回登记的记录；任何一步失败时清理本次上传的对象
+async fn store(
+    db: &Database,
+    storage: &SharedStorage,
+    media_id: &str,
+    rendered: Vec<Rendered>,
+) -> Result<Vec<MediaDerivative>, ThumbnailError> {
+    let generation = Uuid::new_v4().simple().to_string();
+    let mut uploaded = Vec::with_capacity(rendered.len());
+
+    let mut result = Ok(());
+    for mut thumbnail in rendered {
+        let key = format!(
+            "{}{}/{}_{}.{}",
+            DERIVATIVE_PREFIX, media_id, generation, thumbnail.size, thumbnail.extension
+        );
+        let data = std::mem::take(&mut thumbnail.data);
+        let file_size = data.len() as i64;
+        if let Err(e) = storage.put(&key, data, Some(thumbnail.content_type)).await {
+            result = Err(ThumbnailError::from(e));
+            break;
+        }
+        uploaded.push((key, file_size, thumbnail));
+    }
+
+    let result = match result {
+        Ok(()) => register(db, storage, media_id, &uploaded)
+            .await
+            .map_err(ThumbnailError::from),
+        Err(e) => Err(e),
+    };
+
+    if result.is_err() {
+        for (key, _, _) in &uploaded {
+            delete_or_enqueue(db, storage, key, Some(media_id)).await;
+        }
+    }
+    result
+}
+
+/// 为图片媒体生成缩略图，替换已有的缩略图，返回登记的记录
+///
+/// 视频由封面画面生成缩略图，同时生成拖动预览图；其他媒体（如内容被替换为音频）会移除已有的
+/// 缩略图和预览图。图片过大或格式无法解码时不生成
+pub async fn generate(
+    db: &Database,
+    storage: &SharedStorage,
+    media: &MediaItem,
+) -> Result<Vec<MediaDerivative>, ThumbnailError> {
+    if media.media_type == "video" {
+        return previews::generate(db, storage, media).await;
+    }
+    previews::remove(db, storage, &media.id).await?;
+    if media.media_type != "image" {
+        remove(db, storage, &media.id).await?;
+        return Ok(Vec::new());
+    }
+
+    let size = media.file_size.max(0) as u64;
+    if size > MAX_SOURCE_SIZE {
+        crate::log_with_storage!(
+            info,
+            "图片过大，跳过生成缩略图: {} ({} 字节)",
+            media.id,
+            size
+        );
+        return Ok(Vec::new());
+    }
+
+    let source = storage::for_backend(storage, &media.storage_backend)?;
+    let data = read_prefix(&source, &media.cos_key, size, size).await?;
+    replace(db, storage, &media.id, data).await
+}
+
+/// 由图片数据生成所有尺寸的缩略图并替换已有的缩略图，格式无法解码时不生成
+pub async fn replace(
+    db: &Database,
+    storage: &SharedStorage,
+    media_id: &str,
+    data: Vec<u8>,
+) -> Result<Vec<MediaDerivative>, ThumbnailError> {
+    let rendered = match tokio::task::spawn_blocking(move || render(data)).await {
+        Ok(Ok(rendered)) => rendered,
+        Ok(Err(ImageError::Unsupported(e))) => {
+            crate::log_with_storage!(info, "不支持的图片格式，跳过生成缩略图 {}: {}", media_id, e);
+            return Ok(Vec::new());
+        }
+        Ok(Err(e)) => return Err(e.into()),
+        Err(e) => return Err(ThumbnailError::Task(e.to_string())),
+    };
+
+    let previous = existing_objects(db, media_id).await?;
+    let derivatives = store(db, storage, media_id, rendered).await?;
+
+    // 删除被替换的旧缩略图对象
+    for (key, backend) in previous {
+        let replaced = derivatives
+            .iter()
+            .any(|d| d.object_key == key && d.storage_backend == backend);
+        if !replaced {
+            delete_or_enqueue_from(db, storage, &backend, &key, Some(media_id)).await;
+        }
+    }
+
+    Ok(derivatives)
+}
+
+/// 移除媒体的所有缩略图，对象通过删除队列删除
+pub async fn remove(
+    db: &Database,
+    storage: &SharedStorage,
+    media_id: &str,
+) -> Result<(), sqlx::Error> {
+    let removed: Vec<(String, String)> = sqlx::query_as(
+        "DELETE FROM media_derivatives WHERE media_id = $1 AND kind = $2 RETURNING object_key, storage_backend",
+    )
+    .bind(media_id)
+    .bind(THUMBNAIL_KIND)
+    .fetch_all(&db.pool)
+    .await?;
+
+    for (key, backend) in removed {
+        delete_or_enqueue_from(db, storage, &backend, &key, Some(media_id)).await;
+    }
+    Ok(())
+}
+
+/// 在彻底删除媒体的事务中移除所有派生文件记录，并登记其对象的删除
+pub async fn release_derivatives(
+    conn: &mut PgConnection,
+    media_id: &str,
+) -> Result<(), sqlx::Error> {
+    let removed: Vec<(String, String)> = sqlx::query_as(
+        "DELETE FROM media_derivatives WHERE media_id = $1 RETURNING object_key, storage_backend",
+    )
+    .bind(media_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for (key, backend) in removed {
+        enqueue_deletion(&mut *conn, &key, &backend, Some(media_id)).await?;
+    }
+    Ok(())
+}
+
+/// 查找与请求尺寸最接近的缩略图：不小于请求尺寸的最小一个，都小于时取最大的一个
+pub async fn find_thumbnail(
+    db: &Database,
+    media_id: &str,
+    size: u32,
+) -> Result<Option<MediaDerivative>, sqlx::Error> {
+    sqlx::query_as::<_, MediaDerivative>(
+        r#"
+        SELECT * FROM media_derivatives
+        WHERE media_id = $1 AND kind = $2
+        ORDER BY (size >= $3) DESC, CASE WHEN size >= $3 THEN size ELSE -size END
+        LIMIT 1
+        "#,
+    )
+    .bind(media_id)
+    .bind(THUMBNAIL_KIND)
+    .bind(size as i32)
+    .fetch_optional(&db.pool)
+    .await
+}
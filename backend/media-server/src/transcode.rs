@@ -0,0 +1,818 @@
+//! 视频转码（HLS 自适应码率）
+//!
+//! 手机拍摄的 HEVC/MOV 等视频大多无法在浏览器中直接播放。转码任务调用本地的转码程序
+//! （ffmpeg，路径由 `TRANSCODER_PATH` 配置，未配置时不转码）将视频转为多个码率的 H.264/AAC 版本，
+//! 每个版本为一个 HLS 播放列表和一个按字节范围分段的 MPEG-TS 文件，连同主播放列表保存到
+//! `derivatives/{媒体ID}/hls/{批次}/` 下，并登记到 `media_derivatives`：
+//! - `hls_master`: 主播放列表
+//! - `hls`: 各码率的播放列表，`size` 为短边像素
+//! - `hls_media`: 各码率的媒体文件
+//!
+//! 码率档位不高于原视频的分辨率。转码状态和进度记录在媒体记录的 `transcode_*` 字段上，
+//! 完成后通过 `GET /api/media/{id}/hls/master.m3u8` 播放
+
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::database::Database;
+use crate::deletion_queue::{delete_or_enqueue, delete_or_enqueue_from};
+use crate::handlers::media_handlers::MediaItem;
+use crate::metadata::probe;
+use crate::sniff::{self, SNIFF_LEN};
+use crate::storage::{self, SharedStorage, StorageError, download_to_file};
+use crate::thumbnails::{DERIVATIVE_PREFIX, MediaDerivative};
+
+/// 默认同时执行的转码任务数
+const DEFAULT_CONCURRENCY: i64 = 1;
+
+/// 派生文件类型
+pub const HLS_MASTER_KIND: &str = "hls_master";
+pub const HLS_PLAYLIST_KIND: &str = "hls";
+pub const HLS_MEDIA_KIND: &str = "hls_media";
+const HLS_KINDS: [&str; 3] = [HLS_MASTER_KIND, HLS_PLAYLIST_KIND, HLS_MEDIA_KIND];
+
+pub const PLAYLIST_CONTENT_TYPE: &str = "application/vnd.apple.mpegurl";
+const SEGMENT_CONTENT_TYPE: &str = "video/mp2t";
+
+/// 分段时长（秒），所有码率在相同位置插入关键帧以便切换
+const SEGMENT_SECONDS: u32 = 6;
+
+/// 进度写入数据库的最小间隔和最小变化
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+const PROGRESS_STEP: f32 = 0.01;
+
+/// 转码失败时在错误信息中保留的输出行数
+const ERROR_TAIL_LINES: usize = 20;
+
+/// 收集转码程序诊断输出的上限
+const MAX_STDERR: usize = 256 * 1024;
+
+/// 转码程序路径（环境变量 `TRANSCODER_PATH`），未设置或为空时不转码，
+/// 避免没有安装转码程序的部署为每个视频留下失败的任务
+static TRANSCODER_PATH: Lazy<Option<String>> = Lazy::new(|| {
+    std::env::var("TRANSCODER_PATH")
+        .ok()
+        .map(|path| path.trim().to_string())
+        .filter(|path| !path.is_empty())
+});
+
+/// 同时执行的转码任务数上限（环境变量 `TRANSCODE_CONCURRENCY`），对所有实例生效
+static CONCURRENCY: Lazy<i64> = Lazy::new(|| {
+    std::env::var("TRANSCODE_CONCURRENCY")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_CONCURRENCY)
+});
+
+/// 是否启用视频转码
+pub fn enabled() -> bool {
+    TRANSCODER_PATH.is_some()
+}
+
+/// 同时执行的转码任务数上限
+pub fn concurrency() -> i64 {
+    *CONCURRENCY
+}
+
+/// 码率档位
+struct Rung {
+    /// 短边像素
+    height: u32,
+    video_kbps: u32,
+    audio_kbps: u32,
+}
+
+/// 码率档位，从高到低
+const LADDER: [Rung; 4] = [
+    Rung {
+        height: 1080,
+        video_kbps: 5000,
+        audio_kbps: 160,
+    },
+    Rung {
+        height: 720,
+        video_kbps: 2800,
+        audio_kbps: 128,
+    },
+    Rung {
+        height: 480,
+        video_kbps: 1400,
+        audio_kbps: 96,
+    },
+    Rung {
+        height: 360,
+        video_kbps: 800,
+        audio_kbps: 96,
+    },
+];
+
+/// 选择不高于原视频分辨率的档位；原视频小于最低档或分辨率未知时分别使用最低档和所有档位
+fn select_ladder(short_side: Option<u32>) -> Vec<&'static Rung> {
+    let Some(short_side) = short_side else {
+        return LADDER.iter().collect();
+    };
+    let rungs: Vec<&Rung> = LADDER
+        .iter()
+        .filter(|rung| rung.height <= short_side)
+        .collect();
+    if rungs.is_empty() {
+        LADDER.last().into_iter().collect()
+    } else {
+        rungs
+    }
+}
+
+#[derive(Debug)]
+pub enum TranscodeError {
+    Storage(StorageError),
+    Database(sqlx::Error),
+    Io(std::io::Error),
+    /// 转码程序不存在或无法执行，重试也不会成功
+    Unavailable(String),
+    /// 转码程序执行失败，包含其输出的最后几行
+    Failed(String),
+    /// 文件头不是可以转码的音视频格式，重试也不会成功
+    Unsupported(String),
+}
+
+impl TranscodeError {
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            TranscodeError::Unavailable(_)
+                | TranscodeError::Unsupported(_)
+                | TranscodeError::Storage(StorageError::NotFound(_))
+        )
+    }
+}
+
+impl fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscodeError::Storage(e) => write!(f, "读写转码对象失败: {}", e),
+            TranscodeError::Database(e) => write!(f, "登记转码结果失败: {}", e),
+            TranscodeError::Io(e) => write!(f, "读写临时文件失败: {}", e),
+            TranscodeError::Unavailable(e) => write!(f, "无法执行转码程序: {}", e),
+            TranscodeError::Failed(e) => write!(f, "转码失败: {}", e),
+            TranscodeError::Unsupported(e) => write!(f, "不支持的输入格式: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TranscodeError {}
+
+impl From<StorageError> for TranscodeError {
+    fn from(e: StorageError) -> Self {
+        TranscodeError::Storage(e)
+    }
+}
+
+impl From<sqlx::Error> for TranscodeError {
+    fn from(e: sqlx::Error) -> Self {
+        TranscodeError::Database(e)
+    }
+}
+
+impl From<std::io::Error> for TranscodeError {
+    fn from(e: std::io::Error) -> Self {
+        TranscodeError::Io(e)
+    }
+}
+
+/// 转码完成的一个码率版本
+struct Rendition {
+    height: u32,
+    width: u32,
+    actual_height: u32,
+    /// 峰值码率（bit/s）
+    bandwidth: u64,
+    /// 平均码率（bit/s），时长未知时为 `None`
+    average_bandwidth: Option<u64>,
+    playlist: PathBuf,
+    media: PathBuf,
+}
+
+impl Rendition {
+    fn playlist_name(&self) -> String {
+        format!("{}p.m3u8", self.height)
+    }
+
+    fn media_name(&self) -> String {
+        format!("{}p.ts", self.height)
+    }
+}
+
+/// 按时间间隔和变化幅度节流地将进度写入媒体记录
+struct ProgressReporter<'a> {
+    db: &'a Database,
+    media_id: &'a str,
+    /// 正在转码的档位序号和档位总数，总进度按档位平均
+    step: usize,
+    steps: usize,
+    reported: f32,
+    reported_at: Instant,
+}
+
+impl ProgressReporter<'_> {
+    /// 报告当前档位的进度（0~1）
+    async fn report(&mut self, fraction: f32) {
+        let progress =
+            ((self.step as f32 + fraction.clamp(0.0, 1.0)) / self.steps.max(1) as f32).min(1.0);
+        if progress - self.reported < PROGRESS_STEP
+            || self.reported_at.elapsed() < PROGRESS_INTERVAL
+        {
+            return;
+        }
+        self.reported = progress;
+        self.reported_at = Instant::now();
+        if let Err(e) = sqlx::query(
+            "UPDATE media_files SET transcode_progress = $1 WHERE id = $2 AND transcode_status = 'running'",
+        )
+        .bind(progress)
+        .bind(self.media_id)
+        .execute(&self.db.pool)
+        .await
+        {
+            crate::log_with_storage!(warn, "更新转码进度失败 {}: {}", self.media_id, e);
+        }
+    }
+}
+
+/// 缩放到目标短边，不放大，宽高保持为偶数
+fn scale_filter(height: u32) -> String {
+    format!(
+        "scale=w='if(gt(iw,ih),-2,trunc(min({h},iw)/2)*2)':h='if(gt(iw,ih),trunc(min({h},ih)/2)*2,-2)'",
+        h = height
+    )
+}
+
+/// 读取本地输入文件的参数
+///
+/// 按文件头识别的格式固定解复用器，并且只允许读取本地文件：否则上传的 HLS 或 concat
+/// 播放列表会被自动识别，让转码程序读取服务器上的其他文件或访问网络
+pub async fn input_args(input: &Path) -> Result<Vec<String>, TranscodeError> {
+    let mut header = Vec::with_capacity(SNIFF_LEN as usize);
+    tokio::fs::File::open(input)
+        .await?
+        .take(SNIFF_LEN)
+        .read_to_end(&mut header)
+        .await?;
+    let format = sniff::detect(&header)
+        .ok_or_else(|| TranscodeError::Unsupported("无法识别的文件头".to_string()))?;
+    let demuxer = format
+        .demuxer()
+        .ok_or_else(|| TranscodeError::Unsupported(format.mime().to_string()))?;
+
+    Ok([
+        "-protocol_whitelist",
+        "file",
+        "-f",
+        demuxer,
+        "-i",
+        &input.to_string_lossy(),
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect())
+}
+
+/// `input` 为 [`input_args`] 返回的输入参数
+fn transcoder_args(input: &[String], dir: &Path, rung: &Rung) -> Vec<String> {
+    let video_kbps = rung.video_kbps;
+    let mut args: Vec<String> = ["-hide_banner", "-nostdin", "-y"]
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    args.extend_from_slice(input);
+    args.extend(
+        [
+            "-map",
+            "0:v:0",
+            "-map",
+            "0:a:0?",
+            "-vf",
+            &scale_filter(rung.height),
+            "-c:v",
+            "libx264",
+            "-preset",
+            "veryfast",
+            "-profile:v",
+            "high",
+            "-pix_fmt",
+            "yuv420p",
+            "-b:v",
+            &format!("{}k", video_kbps),
+            "-maxrate",
+            &format!("{}k", video_kbps * 107 / 100),
+            "-bufsize",
+            &format!("{}k", video_kbps * 3 / 2),
+            "-force_key_frames",
+            &format!("expr:gte(t,n_forced*{})", SEGMENT_SECONDS),
+            "-c:a",
+            "aac",
+            "-b:a",
+            &format!("{}k", rung.audio_kbps),
+            "-ac",
+            "2",
+            "-f",
+            "hls",
+            "-hls_time",
+            &SEGMENT_SECONDS.to_string(),
+            "-hls_playlist_type",
+            "vod",
+            "-hls_flags",
+            "single_file+independent_segments",
+            "-hls_segment_filename",
+            &dir.join(format!("{}p.ts", rung.height)).to_string_lossy(),
+            "-progress",
+            "pipe:1",
+            "-nostats",
+            &dir.join(format!("{}p.m3u8", rung.height)).to_string_lossy(),
+        ]
+        .into_iter()
+        .map(str::to_string),
+    );
+    args
+}
+
+/// 从转码程序的诊断输出中读取输出视频的分辨率（`Output #0` 之后的第一个视频流）
+fn output_resolution(stderr: &str) -> Option<(u32, u32)> {
+    let output = &stderr[stderr.find("Output #0")?..];
+    let line = output.lines().find(|line| line.contains("Video:"))?;
+    line.split([',', ' '])
+        .filter_map(|part| part.split_once('x'))
+        .find_map(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|(w, h)| *w > 0 && *h > 0)
+}
+
+/// 诊断输出的最后几行，作为失败原因
+fn error_tail(stderr: &str) -> String {
+    let lines: Vec<&str> = stderr
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines[lines.len().saturating_sub(ERROR_TAIL_LINES)..].join("\n")
+}
+
+/// 执行转码程序，返回诊断输出；视频封面和预览图同样使用转码程序抽取画面
+pub async fn run(args: &[String]) -> Result<String, TranscodeError> {
+    run_transcoder(args, None).await
+}
+
+/// 执行一次转码并报告进度（`progress` 为进度报告和视频时长），返回诊断输出
+async fn run_transcoder(
+    args: &[String],
+    mut progress: Option<(&mut ProgressReporter<'_>, f64)>,
+) -> Result<String, TranscodeError> {
+    let program = TRANSCODER_PATH
+        .as_deref()
+        .ok_or_else(|| TranscodeError::Unavailable("未配置转码程序".to_string()))?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound | std::io::ErrorKind::PermissionDenied => {
+                TranscodeError::Unavailable(format!("{}: {}", program, e))
+            }
+            _ => TranscodeError::Io(e),
+        })?;
+
+    // 诊断输出需要持续读取，否则缓冲区写满后转码程序会阻塞
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stderr_task = tokio::spawn(async move {
+        let mut collected = Vec::new();
+        let mut buf = [0u8; 8192];
+        loop {
+            match stderr.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) if collected.len() < MAX_STDERR => collected.extend_from_slice(&buf[..n]),
+                Ok(_) => {}
+            }
+        }
+        String::from_utf8_lossy(&collected).into_owned()
+    });
+
+    // 进度输出为 key=value 行，out_time_us（旧版本为 out_time_ms，单位同样是微秒）为已处理的时长
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut lines = BufReader::new(stdout).lines();
+    while let Some(line) = lines.next_line().await? {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if matches!(key, "out_time_us" | "out_time_ms")
+            && let (Ok(micros), Some((reporter, duration))) =
+                (value.trim().parse::<f64>(), progress.as_mut())
+        {
+            reporter
+                .report((micros / 1_000_000.0 / *duration) as f32)
+                .await;
+        }
+    }
+
+    let status = child.wait().await?;
+    let stderr = stderr_task.await.unwrap_or_default();
+    if !status.success() {
+        return Err(TranscodeError::Failed(format!(
+            "{}\n{}",
+            status,
+            error_tail(&stderr)
+        )));
+    }
+    Ok(stderr)
+}
+
+/// 生成主播放列表，码率从低到高排列
+fn master_playlist(renditions: &[Rendition]) -> String {
+    let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:4\n#EXT-X-INDEPENDENT-SEGMENTS\n");
+    for rendition in renditions.iter().rev() {
+        playlist.push_str(&format!(
+            "#EXT-X-STREAM-INF:BANDWIDTH={}",
+            rendition.bandwidth
+        ));
+        if let Some(average) = rendition.average_bandwidth {
+            playlist.push_str(&format!(",AVERAGE-BANDWIDTH={}", average));
+        }
+        if rendition.width > 0 && rendition.actual_height > 0 {
+            playlist.push_str(&format!(
+                ",RESOLUTION={}x{}",
+                rendition.width, rendition.actual_height
+            ));
+        }
+        playlist.push('\n');
+        playlist.push_str(&rendition.playlist_name());
+        playlist.push('\n');
+    }
+    playlist
+}
+
+/// 转码所有档位，返回从高到低的码率版本
+async fn render(
+    db: &Database,
+    input: &[String],
+    dir: &Path,
+    media: &MediaItem,
+    rungs: &[&Rung],
+    duration: Option<f64>,
+) -> Result<Vec<Rendition>, TranscodeError> {
+    let mut reporter = ProgressReporter {
+        db,
+        media_id: &media.id,
+        step: 0,
+        steps: rungs.len(),
+        reported: 0.0,
+        reported_at: Instant::now(),
+    };
+    let mut renditions = Vec::with_capacity(rungs.len());
+
+    for (index, rung) in rungs.iter().enumerate() {
+        reporter.step = index;
+        let args = transcoder_args(input, dir, rung);
+        let progress = duration.map(|duration| (&mut reporter, duration));
+        let stderr = run_transcoder(&args, progress).await?;
+
+        let media_path = dir.join(format!("{}p.ts", rung.height));
+        let media_size = tokio::fs::metadata(&media_path).await?.len();
+        let (width, actual_height) = output_resolution(&stderr).unwrap_or((0, 0));
+        renditions.push(Rendition {
+            height: rung.height,
+            width,
+            actual_height,
+            bandwidth: u64::from(rung.video_kbps * 107 / 100 + rung.audio_kbps) * 1000,
+            average_bandwidth: duration.map(|duration| (media_size as f64 * 8.0 / duration) as u64),
+            playlist: dir.join(format!("{}p.m3u8", rung.height)),
+            media: media_path,
+        });
+    }
+    Ok(renditions)
+}
+
+/// 待上传和登记的转码输出文件
+struct Output {
+    kind: &'static str,
+    size: u32,
+    width: u32,
+    height: u32,
+    content_type: &'static str,
+    path: PathBuf,
+    name: String,
+}
+
+fn outputs(renditions: &[Rendition], master: PathBuf) -> Vec<Output> {
+    let mut outputs = Vec::with_capacity(renditions.len() * 2 + 1);
+    for rendition in renditions {
+        outputs.push(Output {
+            kind: HLS_MEDIA_KIND,
+            size: rendition.height,
+            width: rendition.width,
+            height: rendition.actual_height,
+            content_type: SEGMENT_CONTENT_TYPE,
+            path: rendition.media.clone(),
+            name: rendition.media_name(),
+        });
+        outputs.push(Output {
+            kind: HLS_PLAYLIST_KIND,
+            size: rendition.height,
+            width: rendition.width,
+            height: rendition.actual_height,
+            content_type: PLAYLIST_CONTENT_TYPE,
+            path: rendition.playlist.clone(),
+            name: rendition.playlist_name(),
+        });
+    }
+    // 主播放列表最后上传，登记后才能播放
+    let top = renditions.first();
+    outputs.push(Output {
+        kind: HLS_MASTER_KIND,
+        size: 0,
+        width: top.map(|r| r.width).unwrap_or(0),
+        height: top.map(|r| r.actual_height).unwrap_or(0),
+        content_type: PLAYLIST_CONTENT_TYPE,
+        path: master,
+        name: "master.m3u8".to_string(),
+    });
+    outputs
+}
+
+/// 在事务中替换媒体的 HLS 记录并将转码状态标记为完成，返回被替换的旧对象及其存储后端
+///
+/// 内容在转码期间被替换时不登记，返回 `None`
+async fn register(
+    db: &Database,
+    storage: &SharedStorage,
+    media: &MediaItem,
+    uploaded: &[(String, i64, &Output)],
+) -> Result<Option<Vec<(String, String)>>, sqlx::Error> {
+    let mut tx = db.pool.begin().await?;
+
+    let updated = sqlx::query(
+        r#"
+        UPDATE media_files
+        SET transcode_status = 'ready', transcode_progress = 1, transcode_error = NULL
+        WHERE id = $1 AND cos_key = $2
+        "#,
+    )
+    .bind(&media.id)
+    .bind(&media.cos_key)
+    .execute(&mut *tx)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let previous: Vec<(String, String)> = sqlx::query_as(
+        "DELETE FROM media_derivatives WHERE media_id = $1 AND kind = ANY($2) RETURNING object_key, storage_backend",
+    )
+    .bind(&media.id)
+    .bind(&HLS_KINDS[..])
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for (key, file_size, output) in uploaded {
+        sqlx::query(
+            r#"
+            INSERT INTO media_derivatives (
+                id, media_id, kind, size, width, height, content_type,
+                file_size, object_key, storage_backend, created_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(&media.id)
+        .bind(output.kind)
+        .bind(output.size as i32)
+        .bind(output.width as i32)
+        .bind(output.height as i32)
+        .bind(output.content_type)
+        .bind(file_size)
+        .bind(key)
+        .bind(storage.name())
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(Some(previous))
+}
+
+/// 上传转码输出并登记；任何一步失败或内容已被替换时清理本次上传的对象
+async fn store(
+    db: &Database,
+    storage: &SharedStorage,
+    media: &MediaItem,
+    outputs: &[Output],
+) -> Result<(), TranscodeError> {
+    let generation = Uuid::new_v4().simple().to_string();
+    let mut uploaded = Vec::with_capacity(outputs.len());
+
+    let mut result = Ok(());
+    for output in outputs {
+        let key = format!(
+            "{}{}/hls/{}/{}",
+            DERIVATIVE_PREFIX, media.id, generation, output.name
+        );
+        let upload = async {
+            let file = tokio::fs::File::open(&output.path).await?;
+            let file_size = file.metadata().await?.len() as i64;
+            storage
+                .put_stream(
+                    &key,
+                    Box::pin(ReaderStream::new(file)),
+                    Some(output.content_type),
+                )
+                .await?;
+            Ok::<_, TranscodeError>(file_size)
+        };
+        match upload.await {
+            Ok(file_size) => uploaded.push((key, file_size, output)),
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+
+    let previous = match result {
+        Ok(()) => register(db, storage, media, &uploaded)
+            .await
+            .map_err(TranscodeError::from),
+        Err(e) => Err(e),
+    };
+
+    let discard = !matches!(previous, Ok(Some(_)));
+    if discard {
+        for (key, _, _) in &uploaded {
+            delete_or_enqueue(db, storage, key, Some(&media.id)).await;
+        }
+    }
+    if let Ok(None) = previous {
+        crate::log_with_storage!(info, "视频内容已被替换，丢弃转码结果: {}", media.id);
+    }
+
+    // 删除被替换的旧版本
+    for (key, backend) in previous?.unwrap_or_default() {
+        delete_or_enqueue_from(db, storage, &backend, &key, Some(&media.id)).await;
+    }
+    Ok(())
+}
+
+/// 将视频转码为 HLS，替换已有的转码结果
+///
+/// 非视频媒体（如内容被替换为图片）会移除已有的转码结果并清空转码状态
+pub async fn transcode(
+    db: &Database,
+    storage: &SharedStorage,
+    media: &MediaItem,
+) -> Result<(), TranscodeError> {
+    if media.media_type != "video" {
+        remove(db, storage, &media.id).await?;
+        return Ok(());
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE media_files SET transcode_status = 'running', transcode_progress = 0
+        WHERE id = $1 AND cos_key = $2
+        "#,
+    )
+    .bind(&media.id)
+    .bind(&media.cos_key)
+    .execute(&db.pool)
+    .await?;
+
+    // 原文件可能位于切换前的存储后端，转码结果写入当前后端
+    let source = storage::for_backend(storage, &media.storage_backend)?;
+    let size = media.file_size.max(0) as u64;
+    let probe = probe::probe(&source, &media.cos_key, size).await?;
+    let duration = probe.as_ref().and_then(|probe| probe.duration);
+    let short_side = probe
+        .as_ref()
+        .and_then(|probe| probe.video.as_ref())
+        .and_then(|video| Some(video.width?.min(video.height?)));
+    let rungs = select_ladder(short_side);
+
+    let dir = tempfile::Builder::new().prefix("transcode-").tempdir()?;
+    let input = dir.path().join("source");
+    download_to_file(&source, &media.cos_key, &input).await?;
+    let input = input_args(&input).await?;
+
+    let renditions = render(db, &input, dir.path(), media, &rungs, duration).await?;
+
+    let master = dir.path().join("master.m3u8");
+    tokio::fs::write(&master, master_playlist(&renditions)).await?;
+    store(db, storage, media, &outputs(&renditions, master)).await?;
+
+    crate::log_with_storage!(
+        info,
+        "视频转码完成 {}: {}",
+        media.id,
+        renditions
+            .iter()
+            .map(|r| format!("{}p", r.height))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(())
+}
+
+/// 记录转码失败：仍会重试时状态回到 `pending`，否则为 `failed`
+pub async fn record_failure(
+    db: &Database,
+    media_id: &str,
+    error: &str,
+    will_retry: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE media_files SET transcode_status = $1, transcode_error = $2
+        WHERE id = $3 AND transcode_status IS NOT NULL
+        "#,
+    )
+    .bind(if will_retry { "pending" } else { "failed" })
+    .bind(error)
+    .bind(media_id)
+    .execute(&db.pool)
+    .await?;
+    Ok(())
+}
+
+/// 移除媒体的转码结果并清空转码状态，对象通过删除队列删除
+pub async fn remove(
+    db: &Database,
+    storage: &SharedStorage,
+    media_id: &str,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.pool.begin().await?;
+    let removed: Vec<(String, String)> = sqlx::query_as(
+        "DELETE FROM media_derivatives WHERE media_id = $1 AND kind = ANY($2) RETURNING object_key, storage_backend",
+    )
+    .bind(media_id)
+    .bind(&HLS_KINDS[..])
+    .fetch_all(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        UPDATE media_files
+        SET transcode_status = NULL, transcode_progress = NULL, transcode_error = NULL
+        WHERE id = $1
+        "#,
+    )
+    .bind(media_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    for (key, backend) in removed {
+        delete_or_enqueue_from(db, storage, &backend, &key, Some(media_id)).await;
+    }
+    Ok(())
+}
+
+/// 解析 HLS 文件名：`master.m3u8`、`{短边}p.m3u8` 或 `{短边}p.ts`，返回派生文件类型和档位
+pub fn parse_hls_file(name: &str) -> Option<(&'static str, i32)> {
+    if name == "master.m3u8" {
+        return Some((HLS_MASTER_KIND, 0));
+    }
+    let (stem, kind) = if let Some(stem) = name.strip_suffix(".m3u8") {
+        (stem, HLS_PLAYLIST_KIND)
+    } else {
+        (name.strip_suffix(".ts")?, HLS_MEDIA_KIND)
+    };
+    let height = stem.strip_suffix('p')?;
+    if height.is_empty() || !height.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((kind, height.parse().ok()?))
+}
+
+/// 查找媒体的 HLS 文件
+pub async fn find_hls_object(
+    db: &Database,
+    media_id: &str,
+    kind: &str,
+    size: i32,
+) -> Result<Option<MediaDerivative>, sqlx::Error> {
+    sqlx::query_as::<_, MediaDerivative>(
+        "SELECT * FROM media_derivatives WHERE media_id = $1 AND kind = $2 AND size = $3",
+    )
+    .bind(media_id)
+    .bind(kind)
+    .bind(size)
+    .fetch_optional(&db.pool)
+    .await
+}
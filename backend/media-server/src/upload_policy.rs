@@ -0,0 +1,379 @@
+//! 上传策略
+//!
+//! 允许的文件类型、扩展名和大小上限统一由上传策略决定，校验接口、配置接口和所有服务端上传路径
+//! 使用同一份策略。策略按以下顺序加载，后者覆盖前者：
+//! 1. 内置默认值：图片、视频和音频，单个文件 100MB，断点续传 10GB
+//! 2. `UPLOAD_POLICY_FILE` 指向的 JSON 文件中的 `default`
+//! 3. 环境变量 `UPLOAD_MAX_FILE_SIZE`、`UPLOAD_MAX_RESUMABLE_FILE_SIZE`、`UPLOAD_ALLOWED_TYPES`、
+//!    `UPLOAD_ALLOWED_EXTENSIONS`、`UPLOAD_BLOCKED_EXTENSIONS`
+//!
+//! 策略文件的 `roles` 可以按用户角色覆盖部分字段，未覆盖的字段沿用默认策略：
+//!
+//! ```json
+//! {
+//!   "default": { "allowed_types": ["image/", "video/", "audio/", "application/pdf"] },
+//!   "roles": { "admin": { "max_file_size": 1073741824, "allowed_types": ["*"] } }
+//! }
+//! ```
+//!
+//! 允许 `application/pdf` 等非音视频类型后，对应媒体的 `media_type` 为 `document`
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::credentials::UserRepository;
+use crate::database::Database;
+use crate::sniff;
+
+static UPLOAD_POLICIES: OnceCell<UploadPolicies> = OnceCell::new();
+
+/// 一组上传限制
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct UploadPolicy {
+    /// 单个文件的最大字节数（直传、预签名和流式上传）
+    pub max_file_size: u64,
+    /// 断点续传的最大字节数
+    pub max_resumable_file_size: u64,
+    /// 允许的 MIME 类型：以 `/` 结尾的为前缀（如 `image/`），`*` 表示不限制
+    pub allowed_types: Vec<String>,
+    /// 允许的扩展名（小写，不带点），为空表示不限制
+    pub allowed_extensions: Vec<String>,
+    /// 禁止的扩展名，优先于 `allowed_extensions`
+    pub blocked_extensions: Vec<String>,
+}
+
+impl Default for UploadPolicy {
+    fn default() -> Self {
+        UploadPolicy {
+            max_file_size: 100 * 1024 * 1024,
+            max_resumable_file_size: 10 * 1024 * 1024 * 1024,
+            allowed_types: vec!["image/".into(), "video/".into(), "audio/".into()],
+            allowed_extensions: Vec::new(),
+            blocked_extensions: Vec::new(),
+        }
+    }
+}
+
+/// 策略文件或环境变量中出现的字段，未出现的字段保持原值
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+struct PolicyOverride {
+    max_file_size: Option<u64>,
+    max_resumable_file_size: Option<u64>,
+    allowed_types: Option<Vec<String>>,
+    allowed_extensions: Option<Vec<String>>,
+    blocked_extensions: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    default: PolicyOverride,
+    #[serde(default)]
+    roles: HashMap<String, PolicyOverride>,
+}
+
+/// 上传不符合策略的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyViolation {
+    TooLarge { max_size: u64 },
+    TypeNotAllowed { content_type: String },
+    ExtensionNotAllowed { extension: String },
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyViolation::TooLarge { max_size } => {
+                write!(f, "文件大小超过限制，最大允许{}", format_size(*max_size))
+            }
+            PolicyViolation::TypeNotAllowed { content_type } => {
+                write!(f, "不支持的文件类型: {}", content_type)
+            }
+            PolicyViolation::ExtensionNotAllowed { extension } if extension.is_empty() => {
+                write!(f, "不支持没有扩展名的文件")
+            }
+            PolicyViolation::ExtensionNotAllowed { extension } => {
+                write!(f, "不支持的文件扩展名: .{}", extension)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+#[derive(Debug)]
+pub enum PolicyError {
+    Read(String, std::io::Error),
+    Parse(String, serde_json::Error),
+    InvalidEnv(&'static str, String),
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyError::Read(path, e) => write!(f, "读取上传策略文件 {} 失败: {}", path, e),
+            PolicyError::Parse(path, e) => write!(f, "解析上传策略文件 {} 失败: {}", path, e),
+            PolicyError::InvalidEnv(name, value) => {
+                write!(f, "环境变量 {} 的值无效: {}", name, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// 以 GB、MB 或 KB 显示大小上限
+fn format_size(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = 1024 * KB;
+    const GB: u64 = 1024 * MB;
+    if bytes >= GB && bytes.is_multiple_of(GB) {
+        format!("{}GB", bytes / GB)
+    } else if bytes >= MB {
+        format!("{}MB", bytes / MB)
+    } else if bytes >= KB {
+        format!("{}KB", bytes / KB)
+    } else {
+        format!("{}字节", bytes)
+    }
+}
+
+fn normalize_extension(extension: &str) -> String {
+    extension
+        .trim()
+        .trim_start_matches('.')
+        .to_ascii_lowercase()
+}
+
+fn file_extension(filename: &str) -> String {
+    std::path::Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(normalize_extension)
+        .unwrap_or_default()
+}
+
+impl UploadPolicy {
+    fn apply(mut self, overrides: &PolicyOverride) -> Self {
+        if let Some(size) = overrides.max_file_size {
+            self.max_file_size = size;
+        }
+        if let Some(size) = overrides.max_resumable_file_size {
+            self.max_resumable_file_size = size;
+        }
+        if let Some(types) = &overrides.allowed_types {
+            self.allowed_types = types
+                .iter()
+                .map(|t| sniff::normalize(t))
+                .filter(|t| !t.is_empty())
+                .collect();
+        }
+        if let Some(extensions) = &overrides.allowed_extensions {
+            self.allowed_extensions = extensions.iter().map(|e| normalize_extension(e)).collect();
+        }
+        if let Some(extensions) = &overrides.blocked_extensions {
+            self.blocked_extensions = extensions.iter().map(|e| normalize_extension(e)).collect();
+        }
+        self
+    }
+
+    /// 检查 MIME 类型是否允许上传
+    pub fn allows_type(&self, content_type: &str) -> bool {
+        // 与内容嗅探使用同一规则，避免两处对 MIME 类型的判断不一致
+        let content_type = sniff::normalize(content_type);
+        self.allowed_types.iter().any(|allowed| {
+            match allowed.as_str() {
+                "*" | "*/*" => true,
+                // `image/` 和 `image/*` 都表示前缀
+                prefix if prefix.ends_with("/*") => {
+                    content_type.starts_with(&prefix[..prefix.len() - 1])
+                }
+                prefix if prefix.ends_with('/') => content_type.starts_with(prefix),
+                exact => content_type == exact,
+            }
+        })
+    }
+
+    /// 检查文件扩展名是否允许上传
+    pub fn allows_extension(&self, filename: &str) -> bool {
+        let extension = file_extension(filename);
+        !self.blocked_extensions.contains(&extension)
+            && (self.allowed_extensions.is_empty() || self.allowed_extensions.contains(&extension))
+    }
+
+    fn check_with_limit(
+        &self,
+        filename: Option<&str>,
+        file_size: Option<u64>,
+        content_type: &str,
+        max_size: u64,
+    ) -> Result<(), PolicyViolation> {
+        if file_size.is_some_and(|size| size > max_size) {
+            return Err(PolicyViolation::TooLarge { max_size });
+        }
+        if !self.allows_type(content_type) {
+            return Err(PolicyViolation::TypeNotAllowed {
+                content_type: content_type.to_string(),
+            });
+        }
+        if let Some(filename) = filename
+            && !self.allows_extension(filename)
+        {
+            return Err(PolicyViolation::ExtensionNotAllowed {
+                extension: file_extension(filename),
+            });
+        }
+        Ok(())
+    }
+
+    /// 检查单个文件上传是否符合策略
+    ///
+    /// 流式上传时大小可能未知，此时只检查类型和扩展名，大小在写入过程中另行限制
+    pub fn check(
+        &self,
+        filename: Option<&str>,
+        file_size: Option<u64>,
+        content_type: &str,
+    ) -> Result<(), PolicyViolation> {
+        self.check_with_limit(filename, file_size, content_type, self.max_file_size)
+    }
+
+    /// 检查断点续传是否符合策略
+    pub fn check_resumable(
+        &self,
+        filename: Option<&str>,
+        file_size: Option<u64>,
+        content_type: &str,
+    ) -> Result<(), PolicyViolation> {
+        self.check_with_limit(
+            filename,
+            file_size,
+            content_type,
+            self.max_resumable_file_size,
+        )
+    }
+
+    /// 允许的类型，格式与 `<input accept>` 相同（如 `image/*,video/*`）
+    pub fn accept(&self) -> String {
+        self.allowed_types
+            .iter()
+            .map(|allowed| match allowed.as_str() {
+                "*" => "*/*".to_string(),
+                prefix if prefix.ends_with('/') => format!("{}*", prefix),
+                other => other.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// 默认策略和按角色覆盖的策略
+#[derive(Debug, Clone, Default)]
+pub struct UploadPolicies {
+    default: UploadPolicy,
+    roles: HashMap<String, UploadPolicy>,
+}
+
+/// 读取环境变量，未设置或为空时返回 `None`
+fn env_value(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn env_size(name: &'static str) -> Result<Option<u64>, PolicyError> {
+    env_value(name)
+        .map(|value| {
+            value
+                .parse::<u64>()
+                .map_err(|_| PolicyError::InvalidEnv(name, value))
+        })
+        .transpose()
+}
+
+fn env_list(name: &str) -> Option<Vec<String>> {
+    env_value(name).map(|value| {
+        value
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    })
+}
+
+impl UploadPolicies {
+    /// 从策略文件和环境变量加载
+    pub fn load() -> Result<Self, PolicyError> {
+        let file = match env_value("UPLOAD_POLICY_FILE") {
+            Some(path) => {
+                let content = std::fs::read_to_string(&path)
+                    .map_err(|e| PolicyError::Read(path.clone(), e))?;
+                serde_json::from_str::<PolicyFile>(&content)
+                    .map_err(|e| PolicyError::Parse(path, e))?
+            }
+            None => PolicyFile::default(),
+        };
+
+        let env_overrides = PolicyOverride {
+            max_file_size: env_size("UPLOAD_MAX_FILE_SIZE")?,
+            max_resumable_file_size: env_size("UPLOAD_MAX_RESUMABLE_FILE_SIZE")?,
+            allowed_types: env_list("UPLOAD_ALLOWED_TYPES"),
+            allowed_extensions: env_list("UPLOAD_ALLOWED_EXTENSIONS"),
+            blocked_extensions: env_list("UPLOAD_BLOCKED_EXTENSIONS"),
+        };
+
+        let default = UploadPolicy::default()
+            .apply(&file.default)
+            .apply(&env_overrides);
+        let roles = file
+            .roles
+            .iter()
+            .map(|(role, overrides)| (role.clone(), default.clone().apply(overrides)))
+            .collect();
+
+        Ok(UploadPolicies { default, roles })
+    }
+
+    /// 指定角色的策略，没有单独配置的角色使用默认策略
+    pub fn for_role(&self, role: Option<&str>) -> &UploadPolicy {
+        role.and_then(|role| self.roles.get(role))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// 加载上传策略，应在启动时调用一次
+pub fn init() -> Result<&'static UploadPolicies, PolicyError> {
+    let policies = UploadPolicies::load()?;
+    crate::log_with_storage!(
+        info,
+        "上传策略: 允许类型 {}, 单文件上限 {}, 角色策略 {} 个",
+        policies.default.accept(),
+        format_size(policies.default.max_file_size),
+        policies.roles.len()
+    );
+    Ok(UPLOAD_POLICIES.get_or_init(|| policies))
+}
+
+/// 当前的上传策略，未调用 `init` 时使用内置默认值
+pub fn policies() -> &'static UploadPolicies {
+    UPLOAD_POLICIES.get_or_init(UploadPolicies::default)
+}
+
+/// 查询用户适用的上传策略，每次读取角色以便角色变更立即生效
+pub async fn policy_for_user(
+    db: &Database,
+    user_id: &str,
+) -> Result<&'static UploadPolicy, sqlx::Error> {
+    let policies = policies();
+    if policies.roles.is_empty() {
+        return Ok(&policies.default);
+    }
+
+    let role = UserRepository::get_role(&db.pool, user_id).await?;
+    Ok(policies.for_role(role.as_deref()))
+}
@@ -0,0 +1,516 @@
+//! 音频波形
+//!
+//! 在服务器上解码音频（symphonia，纯 Rust 实现，支持 WAV、FLAC、MP3、OGG Vorbis 和 AAC），
+//! 混合为单声道后按固定的采样数计算每个像素的最小值和最大值，以 audiowaveform 的二进制格式
+//! （版本 2，8 位）保存到 `derivatives/{媒体ID}/` 下，并登记为 `waveform`。
+//!
+//! `GET /api/media/{id}/waveform` 返回 audiowaveform 的 JSON 或二进制格式，
+//! 可以直接用于 peaks.js、wavesurfer.js 等组件，浏览器不需要下载和解码整个文件
+
+use chrono::Utc;
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CODEC_TYPE_NULL, DecoderOptions};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use uuid::Uuid;
+
+use crate::database::Database;
+use crate::deletion_queue::{delete_or_enqueue, delete_or_enqueue_from};
+use crate::handlers::media_handlers::MediaItem;
+use crate::storage::{self, SharedStorage, StorageError, download_to_file, read_prefix};
+use crate::thumbnails::{DERIVATIVE_PREFIX, MediaDerivative};
+
+/// 派生文件类型
+pub const WAVEFORM_KIND: &str = "waveform";
+
+/// audiowaveform 二进制格式的版本和文件头长度
+const FORMAT_VERSION: i32 = 2;
+const HEADER_LEN: usize = 24;
+
+/// 文件头中的标志位：8 位数据
+const FLAG_8_BIT: u32 = 1;
+
+/// 每个像素对应的最少采样数
+const MIN_SAMPLES_PER_PIXEL: u32 = 256;
+
+/// 波形的最大像素数，超出时相邻像素两两合并
+const MAX_PIXELS: usize = 20_000;
+
+/// 生成波形的音频大小上限
+const MAX_SOURCE_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// 读取已保存的波形的大小上限
+const MAX_WAVEFORM_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug)]
+pub enum WaveformError {
+    Storage(StorageError),
+    Database(sqlx::Error),
+    Io(std::io::Error),
+    /// 格式无法识别、没有音轨或解码失败，重试也不会成功
+    Decode(String),
+    /// 解码任务异常退出
+    Task(String),
+}
+
+impl WaveformError {
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            WaveformError::Decode(_) | WaveformError::Storage(StorageError::NotFound(_))
+        )
+    }
+}
+
+impl fmt::Display for WaveformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaveformError::Storage(e) => write!(f, "读写波形对象失败: {}", e),
+            WaveformError::Database(e) => write!(f, "登记波形失败: {}", e),
+            WaveformError::Io(e) => write!(f, "读取临时文件失败: {}", e),
+            WaveformError::Decode(e) => write!(f, "解码音频失败: {}", e),
+            WaveformError::Task(e) => write!(f, "波形任务失败: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WaveformError {}
+
+impl From<StorageError> for WaveformError {
+    fn from(e: StorageError) -> Self {
+        WaveformError::Storage(e)
+    }
+}
+
+impl From<sqlx::Error> for WaveformError {
+    fn from(e: sqlx::Error) -> Self {
+        WaveformError::Database(e)
+    }
+}
+
+impl From<std::io::Error> for WaveformError {
+    fn from(e: std::io::Error) -> Self {
+        WaveformError::Io(e)
+    }
+}
+
+/// 解码时读取的是已下载的临时文件，读取失败说明文件被截断或损坏，同样不重试
+impl From<SymphoniaError> for WaveformError {
+    fn from(e: SymphoniaError) -> Self {
+        WaveformError::Decode(e.to_string())
+    }
+}
+
+/// 单声道 8 位波形：每个像素一对最小值和最大值
+#[derive(Debug, Clone)]
+pub struct Waveform {
+    pub sample_rate: u32,
+    pub samples_per_pixel: u32,
+    /// 依次为每个像素的最小值和最大值
+    pub data: Vec<i8>,
+}
+
+/// audiowaveform 的 JSON 格式
+#[derive(Serialize, Debug)]
+pub struct WaveformJson {
+    pub version: i32,
+    pub channels: u32,
+    pub sample_rate: u32,
+    pub samples_per_pixel: u32,
+    pub bits: u32,
+    pub length: usize,
+    pub data: Vec<i8>,
+}
+
+impl Waveform {
+    /// 像素数
+    pub fn pixels(&self) -> usize {
+        self.data.len() / 2
+    }
+
+    /// 编码为 audiowaveform 二进制格式（小端序）
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.data.len());
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&FLAG_8_BIT.to_le_bytes());
+        out.extend_from_slice(&(self.sample_rate as i32).to_le_bytes());
+        out.extend_from_slice(&(self.samples_per_pixel as i32).to_le_bytes());
+        out.extend_from_slice(&(self.pixels() as u32).to_le_bytes());
+        out.extend_from_slice(&1i32.to_le_bytes());
+        out.extend(self.data.iter().map(|value| *value as u8));
+        out
+    }
+
+    /// 解析本模块保存的 audiowaveform 二进制格式
+    pub fn from_binary(data: &[u8]) -> Option<Self> {
+        let header = data.get(..HEADER_LEN)?;
+        let field = |index: usize| -> [u8; 4] {
+            header[index * 4..index * 4 + 4]
+                .try_into()
+                .expect("header field is 4 bytes")
+        };
+        if i32::from_le_bytes(field(0)) != FORMAT_VERSION
+            || u32::from_le_bytes(field(1)) & FLAG_8_BIT == 0
+            || i32::from_le_bytes(field(5)) != 1
+        {
+            return None;
+        }
+        let length = u32::from_le_bytes(field(4)) as usize;
+        let body = data.get(HEADER_LEN..HEADER_LEN + length * 2)?;
+        Some(Waveform {
+            sample_rate: u32::try_from(i32::from_le_bytes(field(2))).ok()?,
+            samples_per_pixel: u32::try_from(i32::from_le_bytes(field(3))).ok()?,
+            data: body.iter().map(|value| *value as i8).collect(),
+        })
+    }
+
+    /// 将相邻的 `factor` 个像素合并为一个
+    pub fn downsample(&self, factor: usize) -> Self {
+        let factor = factor.max(1);
+        let data = self
+            .data
+            .chunks(factor * 2)
+            .flat_map(|chunk| {
+                let min = chunk.iter().step_by(2).copied().min().unwrap_or(0);
+                let max = chunk.iter().skip(1).step_by(2).copied().max().unwrap_or(0);
+                [min, max]
+            })
+            .collect();
+        Waveform {
+            sample_rate: self.sample_rate,
+            samples_per_pixel: self.samples_per_pixel.saturating_mul(factor as u32),
+            data,
+        }
+    }
+
+    pub fn to_json(&self) -> WaveformJson {
+        WaveformJson {
+            version: FORMAT_VERSION,
+            channels: 1,
+            sample_rate: self.sample_rate,
+            samples_per_pixel: self.samples_per_pixel,
+            bits: 8,
+            length: self.pixels(),
+            data: self.data.clone(),
+        }
+    }
+}
+
+/// 逐个采样累积每个像素的最小值和最大值，像素数超过上限时两两合并
+struct PeakBuilder {
+    samples_per_pixel: u32,
+    data: Vec<i8>,
+    count: u32,
+    min: f32,
+    max: f32,
+}
+
+impl PeakBuilder {
+    fn new(samples_per_pixel: u32) -> Self {
+        PeakBuilder {
+            samples_per_pixel,
+            data: Vec::new(),
+            count: 0,
+            min: f32::MAX,
+            max: f32::MIN,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+        self.count += 1;
+        if self.count >= self.samples_per_pixel {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.count == 0 {
+            return;
+        }
+        let quantize = |value: f32| (value * 127.0).round().clamp(-128.0, 127.0) as i8;
+        self.data.push(quantize(self.min));
+        self.data.push(quantize(self.max));
+        self.count = 0;
+        self.min = f32::MAX;
+        self.max = f32::MIN;
+
+        if self.data.len() / 2 >= MAX_PIXELS * 2 {
+            let merged = Waveform {
+                sample_rate: 0,
+                samples_per_pixel: self.samples_per_pixel,
+                data: std::mem::take(&mut self.data),
+            }
+            .downsample(2);
+            self.samples_per_pixel = merged.samples_per_pixel;
+            self.data = merged.data;
+        }
+    }
+}
+
+/// 解码音频文件并计算波形（CPU 密集，应在阻塞线程中调用）
+fn compute(path: &Path, extension: Option<&str>) -> Result<Waveform, WaveformError> {
+    let file = std::fs::File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = extension {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| WaveformError::Decode("没有可解码的音轨".to_string()))?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or_else(|| WaveformError::Decode("未知的采样率".to_string()))?;
+
+    // 按总采样数选择精度，使波形不超过像素上限
+    let samples_per_pixel = track
+        .codec_params
+        .n_frames
+        .map(|frames| frames.div_ceil(MAX_PIXELS as u64) as u32)
+        .unwrap_or(0)
+        .max(MIN_SAMPLES_PER_PIXEL);
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut builder = PeakBuilder::new(samples_per_pixel);
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // 跳过损坏的数据包
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            // 文件末尾的数据包不完整
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        let required = decoded.capacity() * channels;
+        if buffer
+            .as_ref()
+            .is_none_or(|buffer| buffer.capacity() < required)
+        {
+            buffer = Some(SampleBuffer::new(decoded.capacity() as u64, spec));
+        }
+        let buffer = buffer.as_mut().expect("buffer is allocated");
+        buffer.copy_interleaved_ref(decoded);
+
+        for frame in buffer.samples().chunks(channels) {
+            builder.push(frame.iter().sum::<f32>() / channels as f32);
+        }
+    }
+    builder.flush();
+
+    if builder.data.is_empty() {
+        return Err(WaveformError::Decode("没有解码出音频数据".to_string()));
+    }
+    Ok(Waveform {
+        sample_rate,
+        samples_per_pixel: builder.samples_per_pixel,
+        data: builder.data,
+    })
+}
+
+/// 上传波形并替换已有的记录；任何一步失败时清理本次上传的对象
+async fn store(
+    db: &Database,
+    storage: &SharedStorage,
+    media: &MediaItem,
+    waveform: &Waveform,
+) -> Result<(), WaveformError> {
+    let key = format!(
+        "{}{}/{}_waveform.dat",
+        DERIVATIVE_PREFIX,
+        media.id,
+        Uuid::new_v4().simple()
+    );
+    let data = waveform.to_binary();
+    let file_size = data.len() as i64;
+    storage
+        .put(&key, data, Some("application/octet-stream"))
+        .await?;
+
+    let registered = async {
+        let mut tx = db.pool.begin().await?;
+        let previous: Vec<(String, String)> = sqlx::query_as(
+            "DELETE FROM media_derivatives WHERE media_id = $1 AND kind = $2 RETURNING object_key, storage_backend",
+        )
+        .bind(&media.id)
+        .bind(WAVEFORM_KIND)
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO media_derivatives (
+                id, media_id, kind, size, width, height, content_type,
+                file_size, object_key, storage_backend, created_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(&media.id)
+        .bind(WAVEFORM_KIND)
+        .bind(waveform.samples_per_pixel as i32)
+        .bind(waveform.pixels() as i32)
+        .bind(0)
+        .bind("application/octet-stream")
+        .bind(file_size)
+        .bind(&key)
+        .bind(storage.name())
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(previous)
+    }
+    .await;
+
+    match registered {
+        Ok(previous) => {
+            for (old, backend) in previous {
+                delete_or_enqueue_from(db, storage, &backend, &old, Some(&media.id)).await;
+            }
+            Ok(())
+        }
+        Err(e) => {
+            delete_or_enqueue(db, storage, &key, Some(&media.id)).await;
+            Err(e.into())
+        }
+    }
+}
+
+/// 为音频生成波形，替换已有的波形
+///
+/// 非音频媒体（如内容被替换为视频）会移除已有的波形；文件过大时不生成
+pub async fn generate(
+    db: &Database,
+    storage: &SharedStorage,
+    media: &MediaItem,
+) -> Result<Option<Waveform>, WaveformError> {
+    if media.media_type != "audio" {
+        remove(db, storage, &media.id).await?;
+        return Ok(None);
+    }
+
+    let size = media.file_size.max(0) as u64;
+    if size > MAX_SOURCE_SIZE {
+        crate::log_with_storage!(info, "音频过大，跳过生成波形: {} ({} 字节)", media.id, size);
+        return Ok(None);
+    }
+
+    let dir = tempfile::Builder::new().prefix("waveform-").tempdir()?;
+    let input = dir.path().join("source");
+    let source = storage::for_backend(storage, &media.storage_backend)?;
+    download_to_file(&source, &media.cos_key, &input).await?;
+
+    let extension = media
+        .original_filename
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    let waveform =
+        match tokio::task::spawn_blocking(move || compute(&input, extension.as_deref())).await {
+            Ok(result) => result?,
+            Err(e) => return Err(WaveformError::Task(e.to_string())),
+        };
+
+    store(db, storage, media, &waveform).await?;
+    Ok(Some(waveform))
+}
+
+/// 移除媒体的波形，对象通过删除队列删除
+pub async fn remove(
+    db: &Database,
+    storage: &SharedStorage,
+    media_id: &str,
+) -> Result<(), sqlx::Error> {
+    let removed: Vec<(String, String)> = sqlx::query_as(
+        "DELETE FROM media_derivatives WHERE media_id = $1 AND kind = $2 RETURNING object_key, storage_backend",
+    )
+    .bind(media_id)
+    .bind(WAVEFORM_KIND)
+    .fetch_all(&db.pool)
+    .await?;
+
+    for (key, backend) in removed {
+        delete_or_enqueue_from(db, storage, &backend, &key, Some(media_id)).await;
+    }
+    Ok(())
+}
+
+/// 媒体是否有已生成的波形，内容被替换为其他类型时需要登记任务将其移除
+pub async fn exists<'e, E>(executor: E, media_id: &str) -> Result<bool, sqlx::Error>
+where
+    E: sqlx::PgExecutor<'e>,
+{
+    sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM media_derivatives WHERE media_id = $1 AND kind = $2)",
+    )
+    .bind(media_id)
+    .bind(WAVEFORM_KIND)
+    .fetch_one(executor)
+    .await
+}
+
+/// 读取媒体已保存的波形，尚未生成时返回 `None`
+pub async fn load(
+    db: &Database,
+    storage: &SharedStorage,
+    media_id: &str,
+) -> Result<Option<Waveform>, WaveformError> {
+    let derivative = sqlx::query_as::<_, MediaDerivative>(
+        "SELECT * FROM media_derivatives WHERE media_id = $1 AND kind = $2",
+    )
+    .bind(media_id)
+    .bind(WAVEFORM_KIND)
+    .fetch_optional(&db.pool)
+    .await?;
+    let Some(derivative) = derivative else {
+        return Ok(None);
+    };
+
+    let size = derivative.file_size.max(0) as u64;
+    if size > MAX_WAVEFORM_SIZE {
+        return Err(WaveformError::Decode(format!(
+            "波形文件过大: {} 字节",
+            size
+        )));
+    }
+    let source = storage::for_backend(storage, &derivative.storage_backend)?;
+    let data = read_prefix(&source, &derivative.object_key, size, size).await?;
+    Waveform::from_binary(&data)
+        .map(Some)
+        .ok_or_else(|| WaveformError::Decode("波形文件格式错误".to_string()))
+}
@@ -17,9 +17,114 @@ export interface Media {
   cos_region: string
   media_type: 'video' | 'audio' | 'image' | 'document'
   status: string
-  metadata?: any
+  metadata?: MediaMetadata
+  content_hash?: string
+  // 视频转码（HLS）状态：pending / running / ready / failed，未转码时为空
+  transcode_status?: string | null
+  transcode_progress?: number | null
+  transcode_error?: string | null
+  // 图片的 BlurHash 和主色调（#rrggbb），缩略图加载前用作占位
+  blurhash?: string | null
+  dominant_color?: string | null
   created_at: string
   updated_at: string
+  // 仅媒体详情接口返回
+  previews?: MediaPreviews
+}
+
+// 媒体详情中的预览地址，尚未生成的为空
+export interface MediaPreviews {
+  thumbnail_url?: string | null
+  sprite_url?: string | null
+  hls_url?: string | null
+  waveform_url?: string | null
+}
+
+// audiowaveform JSON 格式的音频波形，data 依次为每个像素的最小值和最大值
+export interface MediaWaveform {
+  version: number
+  channels: number
+  sample_rate: number
+  samples_per_pixel: number
+  bits: number
+  length: number
+  data: number[]
+}
+
+// 视频拖动预览轨道中的一帧：时间段及其在雪碧图中的位置
+export interface SpriteCue {
+  start: number
+  end: number
+  x: number
+  y: number
+  w: number
+  h: number
+}
+
+// 解析 WebVTT 时间戳（hh:mm:ss.mmm 或 mm:ss.mmm）
+const parseVttTime = (value: string): number => {
+  return value.trim().split(':').reduce((total, part) => total * 60 + parseFloat(part), 0);
+};
+
+// 解析 WebVTT 缩略图轨道，每个时间段的内容为 sprite.jpg#xywh=x,y,w,h
+const parseSpriteTrack = (text: string): SpriteCue[] => {
+  const cues: SpriteCue[] = [];
+  const lines = text.split(/\r?\n/);
+  lines.forEach((line, index) => {
+    if (!line.includes('-->')) { return; }
+    const [start, end] = line.split('-->');
+    const match = lines[index + 1]?.match(/#xywh=(\d+),(\d+),(\d+),(\d+)/);
+    if (!match) { return; }
+    const [x, y, w, h] = match.slice(1).map(Number);
+    cues.push({ start: parseVttTime(start), end: parseVttTime(end), x, y, w, h });
+  });
+  return cues;
+};
+
+// 服务器从图片中提取的信息
+export interface ImageMetadata {
+  width?: number
+  height?: number
+  orientation?: number
+  make?: string
+  model?: string
+  taken_at?: string
+  taken_at_offset?: string
+  gps?: {
+    latitude: number
+    longitude: number
+    altitude?: number
+  }
+}
+
+// 服务器从音视频容器中解析的信息
+export interface MediaProbe {
+  container: string
+  duration?: number
+  bitrate?: number
+  video?: {
+    codec: string
+    width?: number
+    height?: number
+    frame_rate?: number
+  }
+  audio?: {
+    codec: string
+    sample_rate?: number
+    channels?: number
+    bits_per_sample?: number
+  }
+  tags?: {
+    title?: string
+    artist?: string
+    album?: string
+  }
+}
+
+export interface MediaMetadata {
+  image?: ImageMetadata
+  probe?: MediaProbe
+  [key: string]: any
 }
 
 export interface MediaListResponse {
@@ -42,6 +147,88 @@ export interface CreateMediaRequest {
   cos_region: string
   media_type: 'video' | 'audio' | 'image' | 'document'
   metadata?: any
+  etag?: string
+  content_hash?: string
+}
+
+export interface TrashItem extends Media {
+  deleted_at: string
+}
+
+export interface TrashListResponse {
+  items: TrashItem[]
+  total: number
+  page: number
+  per_page: number
+  retention_days: number
+}
+
+export interface MediaTypeUsage {
+  media_type: string
+  bytes: number
+  items: number
+}
+
+export interface StorageUsage {
+  bytes: number
+  items: number
+  trash_bytes: number
+  trash_items: number
+  quota: {
+    max_bytes: number | null
+    max_items: number | null
+  }
+  remaining_bytes: number | null
+  remaining_items: number | null
+  by_media_type: MediaTypeUsage[]
+}
+
+// 媒体的后台处理任务（元数据提取、缩略图等）
+export interface MediaJob {
+  id: string
+  media_id?: string
+  job_type: string
+  status: 'pending' | 'running' | 'succeeded' | 'dead'
+  attempts: number
+  max_attempts: number
+  last_error?: string
+  run_at: string
+  created_at: string
+  updated_at: string
+  finished_at?: string
+}
+
+export interface MediaJobsResponse {
+  media_id: string
+  status: string
+  jobs: MediaJob[]
+}
+
+// 画面相似的媒体，distance 为感知哈希的汉明距离（0 到 64，越小越相似）
+export interface SimilarMedia extends Media {
+  distance: number
+}
+
+export interface SimilarMediaResponse {
+  items: SimilarMedia[]
+  max_distance: number
+}
+
+// 可能重复的一对媒体
+export interface DuplicatePair {
+  distance: number
+  first: Media
+  second: Media
+}
+
+export interface DuplicateReportResponse {
+  pairs: DuplicatePair[]
+  max_distance: number
+}
+
+export interface SimilarityParams {
+  max_distance?: number
+  limit?: number
 }
 
 export interface MediaQueryParams {
@@ -49,6 +236,9 @@ export interface MediaQueryParams {
   per_page?: number
   media_type?: string
   q?: string
+  sort?: 'created_at' | 'taken_at'
+  camera?: string
+  has_location?: boolean
 }
 
 export interface UpdateMediaRequest {
@@ -90,7 +280,7 @@ export const mediaAPI = {
             console.log('📡 获取COS配置和STS凭证...');
             const [cosConfig, stsCredentials] = await Promise.all([
                 mediaAPI.getCosConfig(),
-                mediaAPI.getStsCredentials()
+                mediaAPI.getStsCredentials(file.size)
             ]);
             console.log('✅ COS配置获取成功:', cosConfig);
             console.log('✅ STS凭证获取成功');
@@ -180,20 +370,74 @@ export const mediaAPI = {
         return response.data;
     },
 
-    // 删除媒体
+    // 删除媒体（移入回收站）
     deleteMedia: async (id: string): Promise<void> => {
         await apiClient.delete(`/media/${id}`);
     },
 
+    // 获取回收站中的媒体
+    getTrash: async (params?: { page?: number; per_page?: number }): Promise<TrashListResponse> => {
+        const response = await apiClient.get('/media/trash', { params });
+        return response.data;
+    },
+
+    // 从回收站恢复媒体
+    restoreMedia: async (id: string): Promise<Media> => {
+        const response = await apiClient.post(`/media/${id}/restore`);
+        return response.data;
+    },
+
+    // 从回收站彻底删除媒体
+    purgeMedia: async (id: string): Promise<void> => {
+        await apiClient.delete(`/media/trash/${id}`);
+    },
+
+    // 按内容摘要（SHA-256）查找已有媒体，不存在时返回 null
+    getMediaByHash: async (hash: string): Promise<Media | null> => {
+        try {
+            const response = await apiClient.get(`/media/by-hash/${hash}`);
+            return response.data;
+        } catch (error) {
+            if (axios.isAxiosError(error) && error.response?.status === 404) {
+                return null;
+            }
+            throw error;
+        }
+    },
+
     // 搜索媒体
     searchMedia: async (params: MediaQueryParams): Promise<MediaListResponse> => {
         const response = await apiClient.get('/media/search', { params });
         return response.data;
     },
 
-    // 获取腾讯云 STS 临时凭证
-    getStsCredentials: async () => {
-        const response = await apiClient.get('/cos/sts');
+    // 获取媒体的处理任务状态
+    getMediaJobs: async (id: string): Promise<MediaJobsResponse> => {
+        const response = await apiClient.get(`/media/${id}/jobs`);
+        return response.data;
+    },
+
+    // 在自己的媒体中查找与指定图片或视频画面相似的媒体
+    getSimilarMedia: async (id: string, params?: SimilarityParams): Promise<SimilarMediaResponse> => {
+        const response = await apiClient.get(`/media/${id}/similar`, { params });
+        return response.data;
+    },
+
+    // 获取可能重复的媒体报告（连拍、重新编码或缩放后的副本），按距离从近到远排序
+    getPossibleDuplicates: async (params?: SimilarityParams): Promise<DuplicateReportResponse> => {
+        const response = await apiClient.get('/media/duplicates', { params });
+        return response.data;
+    },
+
+    // 获取当前用户的存储用量和配额
+    getUsage: async (): Promise<StorageUsage> => {
+        const response = await apiClient.get('/me/usage');
+        return response.data;
+    },
+
+    // 获取腾讯云 STS 临时凭证，凭证只能上传不超过 fileSize 字节的对象
+    getStsCredentials: async (fileSize: number) => {
+        const response = await apiClient.get('/cos/sts', { params: { file_size: fileSize } });
         return response.data;
     },
 
@@ -209,50 +453,54 @@ export const mediaAPI = {
         return response.data;
     },
 
-    // 获取媒体文件下载链接（直接使用 COS URL）
+    // 获取媒体文件下载链接（服务器重定向到短期签名URL）
     getMediaDownloadUrl: (media: Media): string => {
-        return media.cos_url;
+        return `/api/media/${media.id}/download`;
     },
 
     // 获取媒体文件预览链接
     getMediaPreviewUrl: (media: Media): string => {
-        return media.cos_url;
+        return `/api/media/${media.id}/stream`;
+    },
+
+    // 获取图片缩略图链接，size 为最长边像素
+    getMediaThumbnailUrl: (media: Media, size: number): string => {
+        return `/api/media/${media.id}/thumbnail?size=${size}`;
+    },
+
+    // 获取视频拖动预览轨道，尚未生成时请求失败
+    getMediaSpriteTrack: async (id: string): Promise<SpriteCue[]> => {
+        const response = await apiClient.get(`/media/${id}/sprite.vtt`, { responseType: 'text' });
+        return parseSpriteTrack(response.data);
+    },
+
+    // 获取视频拖动预览的雪碧图链接
+    getMediaSpriteImageUrl: (media: Media): string => {
+        return `/api/media/${media.id}/sprite.jpg`;
+    },
+
+    // 获取音频波形，pixels 为需要的像素数（按相邻像素合并，不会超过已生成的精度）
+    getMediaWaveform: async (id: string, pixels?: number): Promise<MediaWaveform> => {
+        const response = await apiClient.get(`/media/${id}/waveform`, { params: { pixels } });
+        return response.data;
     },
 
-    // 上传媒体文件
+    // 获取转码后的 HLS 主播放列表链接，仅在 transcode_status 为 ready 时可用
+    getMediaHlsUrl: (media: Media): string => {
+        return `/api/media/${media.id}/hls/master.m3u8`;
+    },
+
+    // 上传新文件替换媒体内容，服务器校验内容和配额后才更新记录
     uploadMediaFile: async (
         mediaId: string,
         file: File,
         onProgress?: (progress: number) => void
     ): Promise<Media> => {
         try {
-            // 1. 获取STS临时凭证
-            const stsCredentials = await mediaAPI.getStsCredentials();
-      
-            // 2. 获取COS配置
-            const cosConfig = await mediaAPI.getCosConfig();
-      
-            // 3. 生成文件key
-            const fileExtension = file.name.split('.').pop();
-            const cosKey = `media/${Date.now()}_${Math.random().toString(36).substr(2, 9)}.${fileExtension}`;
-      
-            // 4. 上传文件到COS
-            const formData = new FormData();
-            formData.append('key', cosKey);
-            formData.append('policy', stsCredentials.policy);
-            formData.append('q-sign-algorithm', 'sha1');
-            formData.append('q-ak', stsCredentials.credentials.tmpSecretId);
-            formData.append('q-key-time', stsCredentials.keyTime);
-            formData.append('q-signature', stsCredentials.signature);
-            formData.append('q-sign-time', stsCredentials.keyTime);
-            formData.append('file', file);
-      
-            const uploadUrl = `https://${cosConfig.bucket}.cos.${cosConfig.region}.myqcloud.com/`;
-      
-            // 使用axios来支持进度回调
-            await axios.post(uploadUrl, formData, {
+            const response = await apiClient.put(`/media/${mediaId}/content`, file, {
+                params: { filename: file.name },
                 headers: {
-                    'Content-Type': 'multipart/form-data',
+                    'Content-Type': file.type || 'application/octet-stream',
                 },
                 onUploadProgress: (progressEvent) => {
                     if (progressEvent.total && onProgress) {
@@ -262,26 +510,6 @@ export const mediaAPI = {
                 },
                 timeout: 300000, // 5分钟超时
             });
-      
-            // 5. 更新媒体记录
-            const cosUrl = `https://${cosConfig.bucket}.cos.${cosConfig.region}.myqcloud.com/${cosKey}`;
-      
-            const updateData = {
-                filename: cosKey,
-                original_filename: file.name,
-                file_size: file.size,
-                content_type: file.type,
-                cos_key: cosKey,
-                cos_url: cosUrl,
-                cos_bucket: cosConfig.bucket,
-                cos_region: cosConfig.region,
-                media_type: file.type.startsWith('image/') ? 'image' as const :
-                    file.type.startsWith('video/') ? 'video' as const :
-                        file.type.startsWith('audio/') ? 'audio' as const : 'document' as const
-            };
-      
-            // 调用后端API更新媒体记录
-            const response = await apiClient.put(`/media/${mediaId}/upload`, updateData);
             return response.data;
       
         } catch (error) {
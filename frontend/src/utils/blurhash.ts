@@ -0,0 +1,95 @@
+// BlurHash 解码：把服务器计算的 BlurHash 还原为模糊的小图，缩略图加载前作为占位图显示
+
+const BASE83 = '0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~';
+
+const decode83 = (value: string): number => {
+  let result = 0;
+  for (const char of value) {
+    const digit = BASE83.indexOf(char);
+    if (digit < 0) { throw new Error('无效的 BlurHash'); }
+    result = result * 83 + digit;
+  }
+  return result;
+};
+
+const srgbToLinear = (value: number): number => {
+  const v = value / 255;
+  return v <= 0.04045 ? v / 12.92 : Math.pow((v + 0.055) / 1.055, 2.4);
+};
+
+const linearToSrgb = (value: number): number => {
+  const v = Math.max(0, Math.min(1, value));
+  return Math.round((v <= 0.0031308 ? v * 12.92 : 1.055 * Math.pow(v, 1 / 2.4) - 0.055) * 255);
+};
+
+const signPow = (value: number, exp: number): number => Math.sign(value) * Math.pow(Math.abs(value), exp);
+
+// 解码为 width x height 的 RGBA 像素
+export const decodeBlurhash = (hash: string, width: number, height: number): Uint8ClampedArray => {
+  const sizeFlag = decode83(hash[0]);
+  const componentsY = Math.floor(sizeFlag / 9) + 1;
+  const componentsX = (sizeFlag % 9) + 1;
+  if (hash.length !== 4 + 2 * componentsX * componentsY) { throw new Error('无效的 BlurHash'); }
+
+  const maximumValue = (decode83(hash[1]) + 1) / 166;
+  const colors: number[][] = [];
+  const dc = decode83(hash.substring(2, 6));
+  colors.push([srgbToLinear(dc >> 16), srgbToLinear((dc >> 8) & 255), srgbToLinear(dc & 255)]);
+  for (let i = 1; i < componentsX * componentsY; i++) {
+    const ac = decode83(hash.substring(4 + i * 2, 6 + i * 2));
+    colors.push([
+      signPow((Math.floor(ac / (19 * 19)) - 9) / 9, 2) * maximumValue,
+      signPow(((Math.floor(ac / 19) % 19) - 9) / 9, 2) * maximumValue,
+      signPow(((ac % 19) - 9) / 9, 2) * maximumValue,
+    ]);
+  }
+
+  const pixels = new Uint8ClampedArray(width * height * 4);
+  for (let y = 0; y < height; y++) {
+    for (let x = 0; x < width; x++) {
+      let r = 0, g = 0, b = 0;
+      for (let j = 0; j < componentsY; j++) {
+        for (let i = 0; i < componentsX; i++) {
+          const basis = Math.cos((Math.PI * x * i) / width) * Math.cos((Math.PI * y * j) / height);
+          const color = colors[i + j * componentsX];
+          r += color[0] * basis;
+          g += color[1] * basis;
+          b += color[2] * basis;
+        }
+      }
+      const offset = 4 * (x + y * width);
+      pixels[offset] = linearToSrgb(r);
+      pixels[offset + 1] = linearToSrgb(g);
+      pixels[offset + 2] = linearToSrgb(b);
+      pixels[offset + 3] = 255;
+    }
+  }
+  return pixels;
+};
+
+// 解码后的占位图按 BlurHash 缓存，避免列表刷新时重复绘制
+const dataUrlCache = new Map<string, string | null>();
+
+// 将 BlurHash 绘制为 data URL，无效时返回 null
+export const blurhashToDataUrl = (hash: string, width = 32, height = 32): string | null => {
+  const cached = dataUrlCache.get(hash);
+  if (cached !== undefined) { return cached; }
+
+  let url: string | null = null;
+  try {
+    const canvas = document.createElement('canvas');
+    canvas.width = width;
+    canvas.height = height;
+    const context = canvas.getContext('2d');
+    if (context) {
+      const image = context.createImageData(width, height);
+      image.data.set(decodeBlurhash(hash, width, height));
+      context.putImageData(image, 0, 0);
+      url = canvas.toDataURL();
+    }
+  } catch {
+    url = null;
+  }
+  dataUrlCache.set(hash, url);
+  return url;
+};
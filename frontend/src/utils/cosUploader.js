@@ -55,7 +55,8 @@ export class COSUploader {
           Region: region,
           Key: key,
           Body: file,
-          SliceSize: 1024 * 1024 * 5, // 大于5MB的文件使用分片上传
+          // STS凭证只允许简单上传，不使用分片上传（单个请求最大5GB）
+          SliceSize: 1024 * 1024 * 1024 * 5,
           onProgress: (progressData) => {
             if (onProgress) {
               const percent = Math.round(progressData.percent * 100);
@@ -90,6 +91,55 @@ export class COSUploader {
     });
   }
 
+  /**
+   * 通过预签名URL上传文件（S3兼容存储、本地存储）
+   * @param {Object} options - 上传选项
+   * @param {File} options.file - 要上传的文件
+   * @param {string} options.url - 服务端签发的预签名PUT地址
+   * @param {string} options.key - 对象键
+   * @param {string} options.objectUrl - 对象的访问地址
+   * @param {Function} options.onProgress - 进度回调函数
+   * @returns {Promise} 上传结果
+   */
+  uploadWithPresignedUrl({ file, url, key, objectUrl, onProgress }) {
+    return new Promise((resolve, reject) => {
+      const xhr = new XMLHttpRequest();
+      const startTime = Date.now();
+
+      xhr.open('PUT', url);
+      if (file.type) {
+        xhr.setRequestHeader('Content-Type', file.type);
+      }
+
+      xhr.upload.onprogress = (event) => {
+        if (onProgress && event.lengthComputable) {
+          const elapsedSeconds = (Date.now() - startTime) / 1000;
+          onProgress({
+            percent: Math.round((event.loaded / event.total) * 100),
+            loaded: event.loaded,
+            total: event.total,
+            speed: elapsedSeconds > 0 ? event.loaded / elapsedSeconds : 0,
+          });
+        }
+      };
+
+      xhr.onload = () => {
+        if (xhr.status >= 200 && xhr.status < 300) {
+          resolve({
+            key,
+            etag: xhr.getResponseHeader('ETag'),
+            url: objectUrl,
+          });
+        } else {
+          reject(new Error(`上传失败: HTTP ${xhr.status}`));
+        }
+      };
+
+      xhr.onerror = () => reject(new Error('上传失败: 网络错误'));
+      xhr.send(file);
+    });
+  }
+
   /**
    * 生成预签名URL
    * @param {Object} options - 选项